@@ -23,7 +23,7 @@
 
 #![warn(missing_docs)]
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use jsonrpsee::RpcModule;
 use iris_runtime::{opaque::Block, AccountId, Balance, Index, BlockNumber, Hash};
@@ -33,24 +33,41 @@ use sp_block_builder::BlockBuilder;
 use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
 
 pub use sc_rpc_api::DenyUnsafe;
+use sc_client_api::BlockchainEvents;
+use sc_rpc::SubscriptionTaskExecutor;
+use sp_core::offchain::OffchainStorage;
+
+/// The number of (signer, asset) decryption keys the node holds in memory
+const DECRYPTION_CACHE_SIZE: usize = 256;
+
+/// How long a cached decryption key may be used after it is recovered
+const DECRYPTION_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// The largest plaintext, in bytes, the node will encrypt in a single call
+const MAX_PLAINTEXT_SIZE: usize = 4 * 1024 * 1024;
 
 /// Full client dependencies.
-pub struct FullDeps<C, P> {
+pub struct FullDeps<C, P, S> {
 	/// The client instance to use.
 	pub client: Arc<C>,
 	/// Transaction pool instance.
 	pub pool: Arc<P>,
 	/// Whether to deny unsafe calls
 	pub deny_unsafe: DenyUnsafe,
+	/// Executor to drive the subscriptions
+	pub subscription_executor: SubscriptionTaskExecutor,
+	/// The node's offchain storage, if offchain workers are enabled
+	pub offchain_storage: Option<S>,
 }
 
 /// Instantiate all full RPC extensions.
-pub fn create_full<C, P>(
-	deps: FullDeps<C, P>,
+pub fn create_full<C, P, S>(
+	deps: FullDeps<C, P, S>,
 ) -> Result<RpcModule<()>, Box<dyn std::error::Error + Send + Sync>>
 where
 	C: ProvideRuntimeApi<Block>,
 	C: HeaderBackend<Block> + HeaderMetadata<Block, Error = BlockChainError> + 'static,
+	C: BlockchainEvents<Block>,
 	C: Send + Sync + 'static,
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
@@ -58,20 +75,32 @@ where
 	C::Api: encryption_rpc::EncryptionRuntimeApi<Block, Balance>,
 	C::Api: BlockBuilder<Block>,
 	P: TransactionPool + 'static,
+	S: OffchainStorage + 'static,
 {
 	use pallet_contracts_rpc::{Contracts, ContractsApiServer};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApiServer};
 	use substrate_frame_rpc_system::{System, SystemApiServer};
-	use encryption_rpc::{Encryption, EncryptionApiServer};
+	use encryption_rpc::{
+		Encryption, EncryptionApiServer, IngestionControl, IngestionControlApiServer,
+	};
 
 	let mut module = RpcModule::new(());
-	let FullDeps { client, pool, deny_unsafe } = deps;
+	let FullDeps { client, pool, deny_unsafe, subscription_executor, offchain_storage } = deps;
 
 	module.merge(System::new(client.clone(), pool, deny_unsafe).into_rpc())?;
 	module.merge(TransactionPayment::new(client.clone()).into_rpc())?;
 	// Contracts RPC API extension
 	module.merge(Contracts::new(client.clone()).into_rpc())?;
 	// Ipfs RPC API extension
-	module.merge(Encryption::new(client).into_rpc())?;
+	module.merge(
+		Encryption::new(client, subscription_executor)
+			.with_decryption_cache(DECRYPTION_CACHE_SIZE, DECRYPTION_CACHE_TTL)
+			.with_max_plaintext_size(MAX_PLAINTEXT_SIZE)
+			.into_rpc()
+	)?;
+	// Node-local ingestion controls
+	if let Some(storage) = offchain_storage {
+		module.merge(IngestionControl::new(storage, deny_unsafe).into_rpc())?;
+	}
 	Ok(module)
 }
@@ -658,6 +658,27 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
 		Ok(())
 	}
 
+	/// Change the minimum balance of an existing asset.
+	///
+	/// * `id`: The asset whose minimum balance should change.
+	/// * `min_balance`: The new minimum balance. The minimum balance of a sufficient asset can't
+	///   change, and it can only be raised while no account holds the asset, so that no balance
+	///   is left below it.
+	pub fn do_set_min_balance(id: T::AssetId, min_balance: T::Balance) -> DispatchResult {
+		ensure!(!min_balance.is_zero(), Error::<T, I>::MinBalanceZero);
+		Asset::<T, I>::try_mutate(id, |maybe_details| -> DispatchResult {
+			let details = maybe_details.as_mut().ok_or(Error::<T, I>::Unknown)?;
+			ensure!(!details.is_sufficient, Error::<T, I>::NoPermission);
+			ensure!(
+				min_balance < details.min_balance || details.accounts == 0,
+				Error::<T, I>::NoPermission
+			);
+			details.min_balance = min_balance;
+			Self::deposit_event(Event::AssetStatusChanged { asset_id: id });
+			Ok(())
+		})
+	}
+
 	/// Destroy an existing asset.
 	///
 	/// * `id`: The asset you want to destroy.
@@ -36,9 +36,9 @@ pub struct AssetDetails<Balance, AccountId, DepositBalance> {
 	/// Can mint tokens.
 	pub(super) issuer: AccountId,
 	/// Can thaw tokens, force transfers and burn tokens from any account.
-	pub(super) admin: AccountId,
+	pub admin: AccountId,
 	/// Can freeze tokens.
-	pub(super) freezer: AccountId,
+	pub freezer: AccountId,
 	/// The total supply across all accounts.
 	pub(super) supply: Balance,
 	/// The balance deposited for this asset. This pays for the data stored here.
@@ -66,6 +66,11 @@ impl<Balance, AccountId, DepositBalance> AssetDetails<Balance, AccountId, Deposi
 			approvals: self.approvals,
 		}
 	}
+
+	/// Whether the asset is frozen for non-admin transfers.
+	pub fn is_frozen(&self) -> bool {
+		self.is_frozen
+	}
 }
 
 /// Data concerning an approval.
@@ -62,6 +62,7 @@ use sp_std::{
 };
 use sp_core::crypto::KeyTypeId;
 use frame_system::{
+	ensure_root,
 	ensure_signed,
 	offchain::{
 		SendSignedTransaction,
@@ -72,10 +73,7 @@ use frame_system::{
 use crypto_box::{
 	SecretKey as BoxSecretKey,
 };
-use rand_chacha::{
-	ChaCha20Rng,
-	rand_core::SeedableRng,
-};
+use rand_chacha::{ChaCha20Rng, rand_core::SeedableRng};
 
 pub const LOG_TARGET: &str = "runtime::authorities";
 // TODO: should a new KeyTypeId be defined? e.g. b"iris"
@@ -228,6 +226,37 @@ pub mod pallet {
 		ValueQuery,
 	>;
 
+	/// maps every x25519 public key a proxy has registered to the proxy. Keys stay mapped
+	/// after the proxy rotates to a new key, so that anything encrypted with an old key
+	/// can still be attributed
+	#[pallet::storage]
+	#[pallet::getter(fn box_key_to_proxy)]
+	pub type BoxKeyToProxy<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		Vec<u8>,
+		T::AccountId,
+		OptionQuery,
+	>;
+
+	/// the number of times each proxy has registered a new x25519 public key,
+	/// used to tell apart data encrypted with successive box keys
+	#[pallet::storage]
+	#[pallet::getter(fn box_key_version)]
+	pub type BoxKeyVersion<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		u32,
+		ValueQuery,
+	>;
+
+	/// the block until which offences are not reported, e.g. while a runtime upgrade
+	/// or a known network disruption settles. Offences are reported again from this block on.
+	#[pallet::storage]
+	#[pallet::getter(fn offence_grace_period)]
+	pub type OffenceGracePeriod<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -237,6 +266,8 @@ pub mod pallet {
 		ValidatorRemovalInitiated(T::AccountId),
 		/// Validator published their ipfs public key and maddrs
 		PublishedIdentity(T::AccountId),
+		/// Offences will not be reported until the given block
+		OffenceGracePeriodSet(T::BlockNumber),
 	}
 
 	// Errors inform users that something went wrong.
@@ -330,7 +361,26 @@ pub mod pallet {
 			public_key: Vec<u8>,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			X25519PublicKeys::<T>::insert(who, public_key);
+			Self::register_box_key(who, public_key);
+			Ok(())
+		}
+
+		/// Stop reporting offences until a block, to avoid mass removals during a
+		/// known disruption such as a runtime upgrade. Setting a past block ends the
+		/// grace period.
+		///
+		/// * `until`: The first block at which offences are reported again
+		///
+		/// The origin must be root.
+		///
+		#[pallet::weight(100)]
+		pub fn set_offence_grace_period(
+			origin: OriginFor<T>,
+			until: T::BlockNumber,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			<OffenceGracePeriod<T>>::put(until);
+			Self::deposit_event(Event::OffenceGracePeriodSet(until));
 			Ok(())
 		}
 	}
@@ -338,6 +388,34 @@ pub mod pallet {
 
 impl<T: Config> Pallet<T> {
 
+	/// Whether offences are currently suppressed by the offence grace period
+	pub fn in_offence_grace_period() -> bool {
+		<frame_system::Pallet<T>>::block_number() < <OffenceGracePeriod<T>>::get()
+	}
+
+	/// Set the x25519 public key of an account, keeping any key it previously
+	/// registered mapped to it in `BoxKeyToProxy`. Registering a different key
+	/// bumps the account's box key version.
+	/// 
+	/// * `who`: The account registering the key
+	/// * `public_key`: The x25519 public key
+	/// 
+	pub fn register_box_key(who: T::AccountId, public_key: Vec<u8>) {
+		if X25519PublicKeys::<T>::get(who.clone()) != public_key {
+			BoxKeyVersion::<T>::mutate(who.clone(), |version| *version = version.saturating_add(1));
+		}
+		X25519PublicKeys::<T>::insert(who.clone(), public_key.clone());
+		BoxKeyToProxy::<T>::insert(public_key, who);
+	}
+
+	/// Find the proxy that registered an x25519 public key, if any
+	/// 
+	/// * `public_key`: The x25519 public key
+	/// 
+	pub fn proxy_of_box_key(public_key: &[u8]) -> Option<T::AccountId> {
+		BoxKeyToProxy::<T>::get(public_key.to_vec())
+	}
+
 	fn initialize_validators(validators: &[T::AccountId]) {
 		log::info!("Initializing validators defined in the chain spec.");
 		assert!(validators.len() > 1, "At least 2 validators should be initialized");
@@ -418,9 +496,31 @@ impl<T: Config> Pallet<T> {
 		<OfflineValidators<T>>::put(Vec::<T::AccountId>::new());
 	}
 
-	pub fn update_x25519() {
+	/// Read this node's x25519 public key from offchain local storage,
+	/// generating and storing a new keypair if none exists yet.
+	/// Must be called from an offchain context.
+	/// 
+	/// * `seed`: Secure entropy to generate a new keypair from, e.g. `sp_io::offchain::random_seed`
+	/// 
+	pub fn local_x25519_public_key(seed: [u8; 32]) -> Vec<u8> {
+		let local_storage = StorageValueRef::persistent(b"iris::x25519");
+		if let Ok(Some(local_sk)) = local_storage.get::<[u8;32]>() {
+			return BoxSecretKey::from(local_sk).public_key().as_bytes().to_vec();
+		}
+		let mut rng = ChaCha20Rng::from_seed(seed);
+		let secret_key = BoxSecretKey::generate(&mut rng);
+		local_storage.set(&secret_key.as_bytes());
+		secret_key.public_key().as_bytes().to_vec()
+	}
+
+	/// Generate a new x25519 keypair, store it in offchain local storage and publish its public key.
+	/// Must be called from an offchain context.
+	/// 
+	/// * `seed`: Secure entropy to generate the keypair from, e.g. `sp_io::offchain::random_seed`
+	/// 
+	pub fn update_x25519(seed: [u8; 32]) {
 		// generate a new keypair
-		let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+		let mut rng = ChaCha20Rng::from_seed(seed);
 		let secret_key = BoxSecretKey::generate(&mut rng);
 		let pk: Vec<u8> = secret_key.public_key().as_bytes().to_vec();
 
@@ -527,6 +627,10 @@ impl<T: Config, O: Offence<(T::AccountId, T::AccountId)>>
 	ReportOffence<T::AccountId, (T::AccountId, T::AccountId), O> for Pallet<T>
 {
 	fn report_offence(_reporters: Vec<T::AccountId>, offence: O) -> Result<(), OffenceError> {
+		if Self::in_offence_grace_period() {
+			log::info!(target: LOG_TARGET, "Ignoring an offence reported during the grace period.");
+			return Ok(());
+		}
 		let offenders = offence.offenders();
 
 		for (v, _) in offenders.into_iter() {
@@ -124,3 +124,30 @@ fn duplicate_check() {
 		});
 	});
 }
+
+#[test]
+fn box_keys_stay_mapped_to_proxy_after_rotation() {
+	TEST_CONSTANTS.with(|test_data| {
+		let v = test_data.validators.clone();
+		new_test_ext(vec![v[0].clone(), v[1].clone(), v[2].clone()]).execute_with(|| {
+			let old_key = vec![1u8; 32];
+			let new_key = vec![2u8; 32];
+			// Given: a proxy has registered a box key
+			assert_ok!(Authorities::insert_key(Origin::signed(v[0].0.clone()), old_key.clone()));
+			assert_eq!(Some(v[0].0.clone()), Authorities::proxy_of_box_key(&old_key));
+			// When: the proxy rotates to a new box key
+			assert_ok!(Authorities::insert_key(Origin::signed(v[0].0.clone()), new_key.clone()));
+			// Then: the new key is its current key
+			assert_eq!(new_key.clone(), Authorities::x25519_public_keys(v[0].0.clone()));
+			// And: both keys are attributed to the proxy
+			assert_eq!(Some(v[0].0.clone()), Authorities::proxy_of_box_key(&old_key));
+			assert_eq!(Some(v[0].0.clone()), Authorities::proxy_of_box_key(&new_key));
+			// And: an unknown key is not attributed to anyone
+			assert_eq!(None, Authorities::proxy_of_box_key(&vec![3u8; 32]));
+			// And: the box key version was bumped once per new key
+			assert_eq!(2, Authorities::box_key_version(v[0].0.clone()));
+			assert_ok!(Authorities::insert_key(Origin::signed(v[0].0.clone()), new_key.clone()));
+			assert_eq!(2, Authorities::box_key_version(v[0].0.clone()));
+		});
+	});
+}
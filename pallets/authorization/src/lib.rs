@@ -33,6 +33,8 @@ use sp_std::{
 use frame_support::traits::ValidatorSetWithIdentification;
 use core::convert::TryInto;
 use frame_system::ensure_signed;
+use codec::Encode;
+use iris_primitives::AssetPreview;
 
 use pallet_data_assets::MetadataProvider;
 pub use pallet::*;
@@ -97,6 +99,17 @@ pub mod pallet {
         ValueQuery,
     >;
 
+    /// map the asset_id -> the number of blocks access to it lasts once granted
+    /// assets without a duration grant access indefinitely
+    #[pallet::storage]
+    #[pallet::getter(fn access_duration)]
+    pub(super) type AccessDuration<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        T::BlockNumber,
+    >;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -110,6 +123,8 @@ pub mod pallet {
         NoSuchAssetClass,
         InsufficientBalance,
         InvalidRuleExecutor,
+        /// the consumer's signature over its box public key is invalid
+        InvalidBoxKeyProof,
 	}
 
 	#[pallet::call]
@@ -135,6 +150,27 @@ pub mod pallet {
             Ok(())
         }
 
+        /// Limit how long access to your asset class lasts once granted. Consumers granted
+        /// access must be authorized again after it expires.
+        /// 
+        /// * `id`: The asset id to set the access duration for
+        /// * `duration`: The number of blocks access lasts, or None if it doesn't expire
+        /// 
+        #[pallet::weight(100)]
+        pub fn set_access_duration(
+            origin: OriginFor<T>,
+            #[pallet::compact] id: T::AssetId,
+            duration: Option<T::BlockNumber>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            ensure!(
+                Self::check_asset_class_ownership(who, id), 
+                Error::<T>::NoSuchOwnedAssetClass
+            );
+            <AccessDuration::<T>>::mutate_exists(id, |d| *d = duration);
+            Ok(())
+        }
+
         /// A function callable by contracts
         /// which allows them to submit results of execution
         /// 
@@ -148,6 +184,9 @@ pub mod pallet {
         /// * `execution_result`: The result of the execute function as reported
         ///                       by the calling contract. A 'true' value implies
         ///                       access is granted, a 'false' implies it is not.
+        /// * `box_key_proof`: The data consumer's signature over `BOX_KEY_PROOF_CONTEXT` followed by
+        ///                    `data_consumer_ephemeral_pk`, proving it controls the key.
+        ///                    Required when access is granted.
         /// TODO: Cleanup, break into smaller functions as needed
         /// leaving weight as 0 since otherwise contracts need to be funded
         #[pallet::weight(0)]
@@ -157,12 +196,19 @@ pub mod pallet {
             data_consumer_address: T::AccountId,
             execution_result: bool,
             data_consumer_ephemeral_pk: Vec<u8>,
+            box_key_proof: Vec<u8>,
         ) -> DispatchResult {
             let who = ensure_signed(origin)?;
             // verify the caller is the registered rule executor contract
             match <Registry::<T>>::get(asset_id) {
                 Some(addr) => {
                     ensure!(addr == who, Error::<T>::InvalidRuleExecutor);
+                    ensure!(
+                        !execution_result || <pallet_iris_proxy::Pallet<T>>::verify_box_key_proof(
+                            &data_consumer_address, &data_consumer_ephemeral_pk, &box_key_proof,
+                        ),
+                        Error::<T>::InvalidBoxKeyProof
+                    );
                     // TODO: locks should expire after some number of blocks
                     // is there any way we can use the vesting schedule approach to facilitate this?
                     // needed? Probably not any longer
@@ -171,10 +217,14 @@ pub mod pallet {
                         match <T as pallet::Config>::MetadataProvider::get(asset_id) {
                             Some(metadata) => {
                                 // use the metadata to get the associated public key used to encrypt the data
+                                let expires_at = <AccessDuration::<T>>::get(asset_id)
+                                    .map(|d| <frame_system::Pallet<T>>::block_number() + d);
                                 <pallet_iris_proxy::Pallet<T>>::add_kfrag_request(
                                     data_consumer_address, 
+                                    asset_id,
                                     metadata.public_key,
-                                    data_consumer_ephemeral_pk
+                                    data_consumer_ephemeral_pk,
+                                    expires_at,
                                 );
                                 Self::deposit_event(Event::ExecutionSuccess);
                             },
@@ -199,6 +249,32 @@ pub mod pallet {
 }
 
 impl<T: Config> Pallet<T> {
+    /// Preview an asset class without retrieving or decrypting it.
+    /// This only reads the asset metadata, the asset class owner and the lock
+    /// for the given address, so it performs no IPFS or crypto I/O.
+    /// 
+    /// * `asset_id`: The asset id to preview
+    /// * `who`: The address to check authorization for
+    /// 
+    pub fn peek(
+        asset_id: T::AssetId,
+        who: T::AccountId,
+    ) -> Option<AssetPreview> {
+        let metadata = <T as pallet::Config>::MetadataProvider::get(asset_id)?;
+        let asset = <pallet_assets::Pallet<T>>::asset(asset_id)?;
+        Some(AssetPreview {
+            cid: metadata.ciphertext_cid,
+            owner: asset.owner.encode(),
+            authorized: <Lock::<T>>::get(&who, asset_id),
+            name: metadata.name,
+            description: metadata.description,
+            providers: metadata.providers,
+            access_count: <pallet_iris_proxy::Pallet<T>>::access_count(asset_id),
+            extra_cids: metadata.extra_cids,
+            ciphertext_size: metadata.ciphertext_size,
+        })
+    }
+
     /// Check if an address is the owner of an asset id
     /// if not the owner or dne, then return false
     /// if owner, return true
@@ -22,7 +22,7 @@ use crate::{self as pallet_authorization, Config};
 use frame_support::{
 	parameter_types,
 	construct_runtime,
-	traits::ConstU32,
+	traits::{ConstBool, ConstU32, ConstU64},
 };
 use frame_system::EnsureRoot;
 use sp_runtime::{
@@ -183,6 +183,22 @@ impl pallet_data_assets::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = ConstBool<true>;
+	type SetAssetMetadata = ConstBool<true>;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<16>;
+	type GatewayPricing = ();
+	type GatewayProvider = ();
+	type ProxyPrefs = ();
+	type PeerIdProvider = ();
 }
 
 /// Balance of an account.
@@ -241,6 +257,12 @@ impl pallet_iris_proxy::Config for Test {
 	type QueueManager = DataAssets;
 	type MetadataProvider = DataAssets;
 	type Randomness = RandomnessCollectiveFlip;
+	type FragmentTTL = ConstU64<10>;
+	type MaxExpiredFragmentsPerBlock = ConstU32<10>;
+	type MaxFragmentsPerRequest = ConstU32<3>;
+	type MaxFragmentBatch = ConstU32<4>;
+	type AssetHistory = DataAssets;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
 }
 
 thread_local! {
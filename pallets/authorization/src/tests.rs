@@ -26,6 +26,14 @@ use rand_chacha::{
 use crypto_box::{
 	SecretKey as BoxSecretKey,
 };
+use pallet_data_assets::{QueueManager, ResultsHandler};
+use pallet_iris_proxy::BOX_KEY_PROOF_CONTEXT;
+
+/// sign a box public key with a consumer's account key
+fn box_key_proof(consumer: &sp_core::sr25519::Pair, sk: &BoxSecretKey) -> Vec<u8> {
+	let message = [BOX_KEY_PROOF_CONTEXT, sk.public_key().as_bytes()].concat();
+	consumer.sign(&message).0.to_vec()
+}
 
 #[test]
 fn can_register_rule_executor_when_caller_is_asset_owner() {
@@ -73,6 +81,40 @@ fn cant_register_rules_when_not_owned() {
 	});
 }
 
+#[test]
+fn only_asset_owner_can_set_access_duration() {
+	// Given: I am a valid node with a positive balance
+	let (p, _) = sp_core::sr25519::Pair::generate();
+	let (q, _) = sp_core::sr25519::Pair::generate();
+	let pairs = vec![(p.clone().public(), 10), (q.clone().public(), 10)];
+	let id = 1;
+	let balance = 1;
+
+	new_test_ext_funded(pairs).execute_with(|| {
+		// AND: I own some asset class
+		assert_ok!(Assets::create(
+			Origin::signed(p.clone().public()), id.clone(), p.public().clone(), balance,
+		));
+		// WHEN: someone else tries to limit access to it
+		// THEN: they receive an error
+		assert_err!(Authorization::set_access_duration(
+			Origin::signed(q.clone().public()), id.clone(), Some(10),
+		), crate::Error::<Test>::NoSuchOwnedAssetClass);
+		// WHEN: I limit access to it
+		assert_ok!(Authorization::set_access_duration(
+			Origin::signed(p.clone().public()), id.clone(), Some(10),
+		));
+		// THEN: access lasts for the given number of blocks
+		assert_eq!(Some(10), Authorization::access_duration(id));
+		// WHEN: I lift the limit
+		assert_ok!(Authorization::set_access_duration(
+			Origin::signed(p.clone().public()), id.clone(), None,
+		));
+		// THEN: access no longer expires
+		assert_eq!(None, Authorization::access_duration(id));
+	});
+}
+
 #[test]
 fn can_submit_execution_results() {
 	// Given: I am a valid node with a positive balance
@@ -112,6 +154,7 @@ fn can_submit_execution_results() {
 			p.public().clone(),
 			true,
 			sk.public_key().as_bytes().to_vec(),
+			box_key_proof(&p, &sk),
 		));
 
 		// THEN: A new entry is added to the lock
@@ -123,6 +166,120 @@ fn can_submit_execution_results() {
 	});
 }
 
+#[test]
+fn can_peek_asset_without_decryption() {
+	// Given: I am a valid node with a positive balance
+	let (p, _) = sp_core::sr25519::Pair::generate();
+	let (contract_address, _) = sp_core::sr25519::Pair::generate();
+	// enough to vest the reserve with myself and still pay the asset class deposit
+	let pairs = vec![(p.clone().public(), 2 * MILLICENTS)];
+	let id: u32 = 1;
+	let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+	let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+	let sk = BoxSecretKey::generate(&mut rng);
+
+	new_test_ext_funded(pairs).execute_with(|| {
+		// AND: an asset class does not exist
+		// THEN: there is nothing to preview
+		assert_eq!(Authorization::peek(id.clone(), p.public().clone()), None);
+		// AND: I own some data asset class
+		assert_ok!(DataAssets::create_request(
+			Origin::signed(p.clone().public()),
+			p.public().clone(),
+			MILLICENTS,
+			cid.clone(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			1_000,
+			1,
+			1,
+			1,
+			None,
+		));
+		let cmd = <DataAssets as QueueManager<_, _>>::ingestion_requests(p.public().clone())[0].clone();
+		assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+			p.public().clone(), sk.public_key().as_bytes().to_vec(),
+		));
+		assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+			Origin::signed(p.clone().public()), cmd, id.clone(), 1024,
+		));
+		// WHEN: I preview the asset before being authorized
+		let preview = Authorization::peek(id.clone(), p.public().clone()).unwrap();
+		// THEN: I see the metadata but am not authorized
+		assert_eq!(preview.cid, cid.clone());
+		assert_eq!(preview.owner, p.public().encode());
+		assert_eq!(preview.authorized, false);
+		assert_eq!(preview.name, None);
+		assert!(preview.providers.is_empty());
+		assert_eq!(preview.access_count, 0);
+		// AND: I see the size of the ciphertext
+		assert_eq!(preview.ciphertext_size, 1024);
+		// AND: when a rule executor grants me access
+		assert_ok!(Authorization::register_rule(
+			Origin::signed(p.clone().public()),
+			id.clone(),
+			contract_address.public().clone(),
+		));
+		assert_ok!(Authorization::submit_execution_results(
+			Origin::signed(contract_address.public().clone()),
+			id.clone(),
+			p.public().clone(),
+			true,
+			sk.public_key().as_bytes().to_vec(),
+			box_key_proof(&p, &sk),
+		));
+		// THEN: the preview shows that I am authorized
+		let preview = Authorization::peek(id.clone(), p.public().clone()).unwrap();
+		assert_eq!(preview.authorized, true);
+	});
+}
+
+#[test]
+fn cant_submit_execution_results_with_forged_box_key_proof() {
+	// Given: I own an asset class with a registered rule executor
+	let (p, _) = sp_core::sr25519::Pair::generate();
+	let (attacker, _) = sp_core::sr25519::Pair::generate();
+	let (contract_address, _) = sp_core::sr25519::Pair::generate();
+	let pairs = vec![(p.clone().public(), 10)];
+	let id: u32 = 1;
+	let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+	let attacker_sk = BoxSecretKey::generate(&mut rng);
+
+	new_test_ext_funded(pairs).execute_with(|| {
+		assert_ok!(Assets::create(
+			Origin::signed(p.clone().public()),
+			id.clone(),
+			p.public().clone(),
+			1,
+		));
+		assert_ok!(Authorization::register_rule(
+			Origin::signed(p.clone().public()),
+			id.clone(),
+			contract_address.public().clone(),
+		));
+		// WHEN: execution results grant me access with a box key that an attacker signed
+		// THEN: they are rejected
+		assert_err!(Authorization::submit_execution_results(
+			Origin::signed(contract_address.public().clone()),
+			id.clone(),
+			p.public().clone(),
+			true,
+			attacker_sk.public_key().as_bytes().to_vec(),
+			box_key_proof(&attacker, &attacker_sk),
+		), crate::Error::<Test>::InvalidBoxKeyProof);
+		// AND: no access is granted
+		assert_eq!(false, crate::Lock::<Test>::get(p.public().clone(), id.clone()));
+		// AND: a malformed proof is rejected too
+		assert_err!(Authorization::submit_execution_results(
+			Origin::signed(contract_address.public().clone()),
+			id.clone(),
+			p.public().clone(),
+			true,
+			attacker_sk.public_key().as_bytes().to_vec(),
+			vec![0u8; 3],
+		), crate::Error::<Test>::InvalidBoxKeyProof);
+	});
+}
+
 #[test]
 fn cant_submit_execution_results_when_contract_not_registered_for_asset() {
 	// Given: I am a valid node with a positive balance
@@ -156,6 +313,7 @@ fn cant_submit_execution_results_when_contract_not_registered_for_asset() {
 			p.public().clone(),
 			true,
 			sk.public_key().as_bytes().to_vec(),
+			box_key_proof(&p, &sk),
 		));
 		// THEN: the lock does not exist
 		let result = crate::Lock::<Test>::get(
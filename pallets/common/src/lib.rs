@@ -26,13 +26,42 @@ use crypto_box::{
     aead::{Aead, AeadCore, Payload},
 	SalsaBox, PublicKey as BoxPublicKey, SecretKey as BoxSecretKey,
 };
-use rand_chacha::{
-	ChaCha20Rng,
-	rand_core::SeedableRng,
-};
+use rand_chacha::rand_core::{CryptoRng, RngCore};
+#[cfg(test)]
+use rand_chacha::{ChaCha20Rng, rand_core::SeedableRng};
+
+/// the persistent offchain storage key of the node-local flag that pauses ingestion processing
+pub const INGESTION_PAUSED_KEY: &[u8] = b"iris::ingestion-paused";
+
+/// the persistent offchain storage key of the node-local flag that asks the offchain worker
+/// to process the ingestion queue on its next run, whatever the block number
+pub const PROCESS_QUEUE_NOW_KEY: &[u8] = b"iris::process-queue-now";
+
+/// the persistent offchain storage key of the node-local account id the offchain worker
+/// signs its transactions with, for nodes holding several keys
+pub const OFFCHAIN_SIGNER_KEY: &[u8] = b"iris::offchain-signer";
+
+/// the persistent offchain storage key of the cids the node's ipfs daemon had pinned when
+/// the offchain worker last listed them, or None if the daemon couldn't be reached
+pub const LOCAL_PINS_KEY: &[u8] = b"iris::local-pins";
+
+/// the persistent offchain storage key of the ingestions the node has fetched and reported as
+/// completed but that are still queued on chain, as (command id, size in bytes, block reported at)
+pub const IN_FLIGHT_INGESTIONS_KEY: &[u8] = b"iris::in-flight-ingestions";
+
+/// the size of a serialized umbral public key (a compressed secp256k1 point)
+pub const UMBRAL_PUBLIC_KEY_SIZE: usize = 33;
+
+/// the size of a plaintext checksum attested by an asset's owner (a sha-256 digest)
+pub const PLAINTEXT_CHECKSUM_SIZE: usize = 32;
+
+/// the number of bytes in a gb, as reported by ipfs
+pub const BYTES_PER_GB: u128 = 1_000_000_000;
 
 #[derive(Eq, Ord, PartialOrd, Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
 pub struct IngestionCommand<AccountId, Balance> {
+    /// a stable identifier for the command, assigned when the request is created
+    pub id: [u8; 32],
     /// the owner of the data to be ingested (i.e. the caller)
     pub owner: AccountId,
     /// the CID of the data to be ingested
@@ -41,27 +70,367 @@ pub struct IngestionCommand<AccountId, Balance> {
     pub multiaddress: Vec<u8>,
     /// the balance used to create an asset class and pay a proxy node
     pub balance: Balance,
+    /// the block number at which the command was queued
+    pub queued_at: u32,
+}
+
+/// preferences for a proxy node
+#[derive(PartialEq, Eq, Clone, Encode, Decode, RuntimeDebug, TypeInfo, Default)]
+pub struct GatewayPrefs {
+    pub max_mbps: u32,
+    /// the storage the gateway offers, in bytes
+    pub storage_max: u128,
+    /// the reserve the gateway expects per size unit of data ingested (0 if pricing is not set)
+    pub reserve_per_unit: u128,
+}
+
+/// ingestion statistics for a gateway
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone, Default)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct GatewayStats {
+    /// the number of ingestion commands the gateway has completed
+    pub completed: u32,
+    /// the average number of blocks between a command being queued and completed
+    pub average_latency: u32,
+}
+
+/// the capsule fragments delivered by a proxy that consumers reported as valid or invalid
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone, Default)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProxyStats {
+    /// the number of capsule fragments that passed verification
+    pub valid_fragments: u32,
+    /// the number of capsule fragments that failed verification
+    pub invalid_fragments: u32,
+}
+
+/// the balances reserved toward a gateway by the ingestion commands queued with it
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone, Default)]
+pub struct PendingReserves<Balance> {
+    /// the sum of the balances of all queued commands
+    pub total: Balance,
+    /// the balance of each queued command: (command id, balance)
+    pub commands: Vec<([u8; 32], Balance)>,
+}
+
+/// a reencryption request queued with a proxy that it has not yet served
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+pub struct PendingReencryption {
+    /// the id of the request, the hash of the encoded (caller, data public key)
+    pub request_id: [u8; 32],
+    /// the encoded account id of the caller
+    pub caller: Vec<u8>,
+    /// the asset the caller was authorized to decrypt
+    pub asset_id: u32,
+}
+
+/// a transition in the lifecycle of a data asset
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum AssetLifecycleEvent {
+    /// a gateway ingested the data and the asset class was created
+    Created,
+    /// a proxy generated reencryption keys for a consumer
+    Rekeyed,
+    /// a consumer was delivered enough capsule fragments to decrypt the asset
+    Accessed,
+    /// a gateway ejected its replica of the asset
+    Ejected,
+    /// the asset was frozen because no gateway pins it anymore
+    Frozen,
+    /// the asset was thawed after a replica was restored
+    Thawed,
+}
+
+/// an entry in the lifecycle history of a data asset
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+pub struct AssetHistoryEntry {
+    /// the block at which the transition happened
+    pub block: u32,
+    /// the transition
+    pub event: AssetLifecycleEvent,
+    /// the encoded account id of the gateway or consumer involved, if any
+    pub account: Option<Vec<u8>>,
+}
+
+/// a gateway assigned an ingestion command
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+pub struct CommandAssignment {
+    /// the encoded account id of the gateway
+    pub gateway: Vec<u8>,
+    /// whether the gateway has ingested the data
+    pub completed: bool,
+}
+
+/// the progress of an ingestion request
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum RequestStatus {
+    /// no gateway has pinned the data yet
+    Queued,
+    /// some, but not all, of the requested replicas are pinned
+    Ingesting { pinned: u32, replication: u32 },
+    /// every requested replica is pinned and the asset class exists
+    Complete { asset_id: u32 },
+}
+
+impl RequestStatus {
+    /// true if the status will not change again
+    pub fn is_final(&self) -> bool {
+        matches!(self, RequestStatus::Complete { .. })
+    }
+}
+
+/// a lightweight view of a data asset, readable without retrieving or decrypting it
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssetPreview {
+    /// the CID of the (encrypted) data
+    pub cid: Vec<u8>,
+    /// the (encoded) account id of the asset class owner
+    pub owner: Vec<u8>,
+    /// true if the account previewing the asset has been authorized to decrypt it
+    pub authorized: bool,
+    /// the utf8 name of the asset, if its admin set one
+    pub name: Option<Vec<u8>>,
+    /// the utf8 description of the asset, if its admin set one
+    pub description: Option<Vec<u8>>,
+    /// the ipfs peer ids of the gateways that pin the data
+    pub providers: Vec<Vec<u8>>,
+    /// the number of times access to the asset was granted
+    pub access_count: u64,
+    /// the CIDs of the dataset's other files, pinned alongside the (encrypted) data
+    pub extra_cids: Vec<Vec<u8>>,
+    /// the size of the (encrypted) data in bytes, or 0 if it wasn't recorded
+    pub ciphertext_size: u128,
 }
 
+/// everything a consumer needs to decrypt a data asset: the capsule stored on chain and
+/// the location and size of the ciphertext pinned in IPFS
 #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecryptionContext {
+    /// the umbral capsule created when the data was encrypted
+    pub capsule: Vec<u8>,
+    /// the CID of the ciphertext
+    pub ciphertext_cid: Vec<u8>,
+    /// the size of the ciphertext in bytes
+    pub ciphertext_size: u128,
+    /// the sha-256 checksum of the plaintext attested by the asset's owner, if they set one
+    pub plaintext_checksum: Option<Vec<u8>>,
+}
+
+/// whether a consumer has everything on chain needed to decrypt a data asset.
+/// Fragments can only be counted here: verifying them takes the consumer's secret key.
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone, Default)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecryptionReadiness {
+    /// the asset class exists
+    pub asset_exists: bool,
+    /// the capsule created when the data was encrypted is stored on chain
+    pub capsule_present: bool,
+    /// a proxy has published the reencryption artifact for the consumer
+    pub reencryption_artifact_present: bool,
+    /// the consumer was granted access to the asset and the grant hasn't expired
+    pub authorized: bool,
+    /// the number of capsule fragments delivered to the consumer and not yet expired
+    pub fragments_delivered: u32,
+    /// the number of capsule fragments needed to decrypt
+    pub fragments_needed: u32,
+    /// at least one gateway pins the asset, i.e. it hasn't been ejected by all of them
+    pub pinned: bool,
+    /// the asset is frozen
+    pub frozen: bool,
+    /// all of the above checks pass
+    pub ready: bool,
+}
+
+/// the timing parameters that govern ingestion requests and gateways, in blocks
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct DelayInfo {
+    /// the number of blocks after which a request's gateway reserve vests
+    pub delay: u32,
+    /// the number of blocks between each sync of a gateway's ipfs config
+    pub config_sync_interval: u32,
+}
+
+/// the output of encrypting data: everything an owner needs to ingest the ciphertext
+/// and to let consumers request reencryption
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncryptionResult {
+    /// the encrypted data
+    pub ciphertext: Vec<u8>,
+    /// the umbral public key the data was encrypted with, which identifies the data
+    /// when requesting reencryption
+    pub public_key: Vec<u8>,
+    /// the umbral capsule created when the data was encrypted
+    pub capsule: Vec<u8>,
+}
+
+/// the output of encrypting data for `encrypt_and_request`, which stages nothing on chain:
+/// the owner stages it along with their ingestion request in a single transaction
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnstagedEncryption {
+    /// the ciphertext, data public key and capsule
+    pub result: EncryptionResult,
+    /// the data secret key, encrypted for the proxy
+    pub encrypted_sk_box: EncryptedBox,
+}
+
+/// why data could not be encrypted
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncryptionFailure {
+    /// no entropy was provided to seed the data keys
+    MissingSeed,
+    /// the signature does not match the signer and message
+    InvalidSignature,
+    /// the proxy has no x25519 public key to encrypt the data key for
+    NoProxyKey,
+    /// umbral could not encrypt the data
+    EncryptionFailed,
+    /// the encryption artifacts could not be submitted
+    SubmissionFailed,
+}
+
+/// the scheme an asset's data was encrypted with, which decides how it is decrypted
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncryptionScheme {
+    /// umbral threshold proxy reencryption, with the data secret key sealed for the
+    /// proxy in an x25519 salsa box
+    UmbralSalsaBox,
+}
+
+impl Default for EncryptionScheme {
+    fn default() -> Self {
+        EncryptionScheme::UmbralSalsaBox
+    }
+}
+
+/// why data could not be decrypted
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecryptionFailure {
+    /// fewer verified capsule fragments are available than the threshold requires
+    NotEnoughFragments { have: u32, need: u32 },
+    /// the ciphertext failed authentication, i.e. it doesn't match the capsule or the recovered key
+    DecryptionFailed,
+    /// the secret key is not a 32 byte x25519 secret key
+    InvalidSecretKey,
+    /// the asset was encrypted with a scheme this runtime can't decrypt
+    UnsupportedScheme,
+    /// the caller asked to decrypt with fewer capsule fragments than the threshold requires
+    TooFewFragmentsRequested { requested: u32, need: u32 },
+}
+
+/// the key material a consumer recovers from its capsule fragments, which is enough to
+/// decrypt an asset's ciphertext without opening and verifying the fragments again
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, TypeInfo, Clone)]
+pub struct DecryptionKey {
+    /// the secret key the proxy generated for the consumer
+    pub secret_key: Vec<u8>,
+    /// the consumer's verified capsule fragments
+    pub capsule_fragments: Vec<Vec<u8>>,
+    /// a hash of the on-chain reencryption artifacts the key was recovered from,
+    /// so the key is rejected once the consumer is issued new ones
+    pub fingerprint: [u8; 32],
+}
+
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
 pub struct EncryptedBox {
     pub nonce: Vec<u8>,
     pub ciphertext: Vec<u8>,
     pub public_key: Vec<u8>,
 }
 
+/// Decides which proxies process ingestion commands and which proxies hold the key
+/// fragments of reencryption requests
+pub trait AssignmentStrategy<AccountId> {
+    /// Choose the gateway that should ingest a command, if any
+    /// 
+    /// * `command`: The ingestion command
+    /// * `proxies`: The proxies that have the command queued
+    /// 
+    fn assign_ingestion<Balance>(
+        command: &IngestionCommand<AccountId, Balance>,
+        proxies: Vec<AccountId>,
+    ) -> Option<AccountId>;
+
+    /// Choose the proxies that should each hold a key fragment of a reencryption request.
+    /// Returns fewer than `count` proxies if there aren't enough candidates.
+    /// 
+    /// * `request_id`: The id of the reencryption request
+    /// * `proxies`: The candidate proxies
+    /// * `count`: The number of key fragments to assign
+    /// 
+    fn assign_reencryption(
+        request_id: &[u8; 32],
+        proxies: Vec<AccountId>,
+        count: usize,
+    ) -> Vec<AccountId>;
+}
+
+/// The default assignment strategy.
+/// An ingestion command is assigned to the proxy at index `h mod n` of the proxies sorted by
+/// account id, where `h` is the first 8 bytes (little endian) of `blake2_256(command.id)` and
+/// `n` is the number of proxies. The key fragments of a reencryption request are assigned to
+/// `count` consecutive candidates, in the order they are given, starting from the candidate at
+/// index `r mod n`, where `r` is the first 8 bytes (little endian) of the request id, and
+/// wrapping around to the first candidate.
+pub struct HashAssignment;
+
+impl<AccountId: Ord + Clone> AssignmentStrategy<AccountId> for HashAssignment {
+    fn assign_ingestion<Balance>(
+        command: &IngestionCommand<AccountId, Balance>,
+        mut proxies: Vec<AccountId>,
+    ) -> Option<AccountId> {
+        if proxies.is_empty() {
+            return None;
+        }
+        proxies.sort();
+        let hash = sp_io::hashing::blake2_256(&command.id);
+        let mut h = [0u8; 8];
+        h.copy_from_slice(&hash[..8]);
+        let index = u64::from_le_bytes(h) % proxies.len() as u64;
+        Some(proxies.swap_remove(index as usize))
+    }
+
+    fn assign_reencryption(
+        request_id: &[u8; 32],
+        mut proxies: Vec<AccountId>,
+        count: usize,
+    ) -> Vec<AccountId> {
+        if proxies.is_empty() {
+            return proxies;
+        }
+        // spread the requests across the candidates rather than always loading the first ones
+        let mut r = [0u8; 8];
+        r.copy_from_slice(&request_id[..8]);
+        let start = u64::from_le_bytes(r) % proxies.len() as u64;
+        proxies.rotate_left(start as usize);
+        proxies.truncate(count);
+        proxies
+    }
+}
+
 ///
 /// Encrypt the bytes with an ephemeral secret key and your provided public key.
+/// The ephemeral key and nonce are drawn from `rng`, which must be securely seeded.
 ///
-pub fn encrypt_x25519(
+pub fn encrypt_x25519<R: CryptoRng + RngCore>(
     public_key: BoxPublicKey, 
     plaintext: Vec<u8>,
+    rng: &mut R,
 ) -> EncryptedBox {
-    let mut rng = ChaCha20Rng::seed_from_u64(31u64);
-    let ephemeral_secret_key = BoxSecretKey::generate(&mut rng);
+    let ephemeral_secret_key = BoxSecretKey::generate(&mut *rng);
 
     let salsa_box = SalsaBox::new(&public_key, &ephemeral_secret_key);
-    let nonce = SalsaBox::generate_nonce(&mut rng);
+    let nonce = SalsaBox::generate_nonce(&mut *rng);
     // TODO: should probably use encrypt_in_place for safety?
     let ciphertext: Vec<u8> = salsa_box.encrypt(&nonce, &plaintext[..]).unwrap().to_vec();
 
@@ -103,11 +472,10 @@ pub fn decrypt_x25519(
 */
 
 /// Convert a public key encoded as a vector of u8
-/// to a BoxPublicKey type required for encryption and decryption
-pub fn vec_to_box_public_key(pk_vec: &[u8]) -> BoxPublicKey {
-    // TODO: error handling
-    let pk_array = slice_to_array_32(pk_vec).unwrap();
-    BoxPublicKey::from(*pk_array)
+/// to a BoxPublicKey type required for encryption and decryption.
+/// Returns None unless the key is 32 bytes long.
+pub fn vec_to_box_public_key(pk_vec: &[u8]) -> Option<BoxPublicKey> {
+    slice_to_array_32(pk_vec).map(|pk_array| BoxPublicKey::from(*pk_array))
 }
 
 /// Convert a slice of u8 to an array of u8 of size 32
@@ -123,10 +491,199 @@ pub fn slice_to_array_32(slice: &[u8]) -> Option<&[u8; 32]> {
     }
 }
 
+/// Errors that can occur when parsing a multiaddress
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum MultiaddressError {
+    /// the multiaddress is empty
+    Empty,
+    /// the multiaddress is not valid utf8
+    InvalidUtf8,
+    /// the multiaddress does not start with a '/'
+    MissingLeadingSlash,
+    /// a protocol is not one a multiaddress may name here: (protocol)
+    UnknownProtocol(Vec<u8>),
+    /// a protocol that takes a value is the last component: (protocol)
+    MissingValue(Vec<u8>),
+    /// the value of a protocol is malformed: (protocol)
+    InvalidValue(Vec<u8>),
+}
+
+/// A multiaddress split into its components
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ParsedMultiaddress {
+    /// each protocol in order, with its value if it takes one
+    pub components: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    /// the peer id the multiaddress addresses, i.e. the value of its last `p2p` (or legacy `ipfs`)
+    /// component, unless a `p2p-circuit` follows it, in which case it is the relay's
+    pub peer_id: Option<Vec<u8>>,
+}
+
+/// the kinds of value a multiaddress protocol takes
+enum MultiaddressValue {
+    None,
+    Ip4,
+    Ip6,
+    Port,
+    Domain,
+    PeerId,
+    Multibase,
+}
+
+/// the protocols a multiaddress may name, with the kind of value each takes
+const MULTIADDRESS_PROTOCOLS: &[(&str, MultiaddressValue)] = &[
+    ("ip4", MultiaddressValue::Ip4),
+    ("ip6", MultiaddressValue::Ip6),
+    ("dns", MultiaddressValue::Domain),
+    ("dns4", MultiaddressValue::Domain),
+    ("dns6", MultiaddressValue::Domain),
+    ("dnsaddr", MultiaddressValue::Domain),
+    ("sni", MultiaddressValue::Domain),
+    ("tcp", MultiaddressValue::Port),
+    ("udp", MultiaddressValue::Port),
+    ("p2p", MultiaddressValue::PeerId),
+    ("ipfs", MultiaddressValue::PeerId),
+    ("certhash", MultiaddressValue::Multibase),
+    ("p2p-circuit", MultiaddressValue::None),
+    ("quic", MultiaddressValue::None),
+    ("quic-v1", MultiaddressValue::None),
+    ("tls", MultiaddressValue::None),
+    ("noise", MultiaddressValue::None),
+    ("ws", MultiaddressValue::None),
+    ("wss", MultiaddressValue::None),
+    ("http", MultiaddressValue::None),
+    ("https", MultiaddressValue::None),
+    ("webtransport", MultiaddressValue::None),
+    ("webrtc", MultiaddressValue::None),
+    ("webrtc-direct", MultiaddressValue::None),
+];
+
+/// Whether a value is well formed for the kind of value its protocol takes
+/// 
+/// * `kind`: The kind of value the protocol takes
+/// * `value`: The value
+/// 
+fn valid_multiaddress_value(kind: &MultiaddressValue, value: &str) -> bool {
+    match kind {
+        MultiaddressValue::None => true,
+        MultiaddressValue::Ip4 => {
+            let octets: Vec<&str> = value.split('.').collect();
+            octets.len() == 4 && octets.iter().all(|o| !o.is_empty() && o.len() <= 3
+                && o.bytes().all(|b| b.is_ascii_digit()) && o.parse::<u8>().is_ok())
+        },
+        MultiaddressValue::Ip6 => value.contains(':')
+            && value.bytes().all(|b| b.is_ascii_hexdigit() || b == b':' || b == b'.'),
+        MultiaddressValue::Port => !value.is_empty()
+            && value.bytes().all(|b| b.is_ascii_digit()) && value.parse::<u16>().is_ok(),
+        MultiaddressValue::Domain => !value.is_empty()
+            && value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.'),
+        MultiaddressValue::PeerId => !value.is_empty()
+            && value.bytes().all(|b| b.is_ascii_alphanumeric()),
+        MultiaddressValue::Multibase => !value.is_empty()
+            && value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'),
+    }
+}
+
+/// Parse a multiaddress in its string form, e.g. /ip4/127.0.0.1/tcp/4001/p2p/12D3KooW...,
+/// checking that each protocol is known and that its value is well formed. A single trailing
+/// '/' is ignored. `create_request` rejects multiaddresses this fails to parse.
+/// 
+/// * `multiaddress`: The multiaddress
+/// 
+pub fn parse_multiaddress(multiaddress: &[u8]) -> Result<ParsedMultiaddress, MultiaddressError> {
+    if multiaddress.is_empty() {
+        return Err(MultiaddressError::Empty);
+    }
+    let multiaddress = sp_std::str::from_utf8(multiaddress).map_err(|_| MultiaddressError::InvalidUtf8)?;
+    let rest = multiaddress.strip_prefix('/').ok_or(MultiaddressError::MissingLeadingSlash)?;
+    let rest = rest.strip_suffix('/').unwrap_or(rest);
+    let mut parts = rest.split('/');
+    let mut components = Vec::new();
+    let mut peer_id = None;
+    while let Some(protocol) = parts.next() {
+        let (_, kind) = MULTIADDRESS_PROTOCOLS.iter()
+            .find(|(name, _)| *name == protocol)
+            .ok_or_else(|| MultiaddressError::UnknownProtocol(protocol.as_bytes().to_vec()))?;
+        let value = match kind {
+            MultiaddressValue::None => None,
+            _ => {
+                let value = parts.next()
+                    .ok_or_else(|| MultiaddressError::MissingValue(protocol.as_bytes().to_vec()))?;
+                if !valid_multiaddress_value(kind, value) {
+                    return Err(MultiaddressError::InvalidValue(protocol.as_bytes().to_vec()));
+                }
+                Some(value.as_bytes().to_vec())
+            },
+        };
+        match kind {
+            MultiaddressValue::PeerId => peer_id = value.clone(),
+            // the components before a circuit belong to the relay
+            _ if protocol == "p2p-circuit" => peer_id = None,
+            _ => {},
+        }
+        components.push((protocol.as_bytes().to_vec(), value));
+    }
+    Ok(ParsedMultiaddress { components, peer_id })
+}
+
 /*
 TESTS
 */
 
+#[test]
+fn hash_assignment_assigns_ingestion_regardless_of_proxy_order() {
+	// Given: an ingestion command and three proxies
+	let command = IngestionCommand {
+		id: [7u8; 32],
+		owner: 0u64,
+		cid: Vec::new(),
+		multiaddress: Vec::new(),
+		balance: 1u64,
+		queued_at: 0,
+	};
+	// When: I assign it with the proxies in different orders
+	let first = <HashAssignment as AssignmentStrategy<u64>>::assign_ingestion(&command, vec![1, 2, 3]);
+	let second = <HashAssignment as AssignmentStrategy<u64>>::assign_ingestion(&command, vec![3, 1, 2]);
+	// Then: the same proxy is chosen
+	assert!(first.is_some());
+	assert_eq!(first, second);
+	// And: nothing is assigned without proxies
+	assert_eq!(None, <HashAssignment as AssignmentStrategy<u64>>::assign_ingestion(&command, vec![]));
+}
+
+#[test]
+fn hash_assignment_assigns_reencryption_to_consecutive_candidates() {
+	// Given: three candidates
+	let candidates = vec![3u64, 1, 2];
+	// When: I assign two key fragments of a request whose id starts at the first candidate
+	let holders = <HashAssignment as AssignmentStrategy<u64>>::assign_reencryption(&[0u8; 32], candidates.clone(), 2);
+	// Then: the first two candidates hold them
+	assert_eq!(vec![3, 1], holders);
+	// When: the id of the request starts at the last candidate
+	let mut request_id = [0u8; 32];
+	request_id[0] = 2;
+	let holders = <HashAssignment as AssignmentStrategy<u64>>::assign_reencryption(&request_id, candidates.clone(), 2);
+	// Then: the assignment wraps around to the first candidate
+	assert_eq!(vec![2, 3], holders);
+	// And: no more candidates than are available are assigned
+	assert_eq!(vec![2, 3, 1], <HashAssignment as AssignmentStrategy<u64>>::assign_reencryption(&request_id, candidates, 5));
+	// And: nothing is assigned without candidates
+	assert!(<HashAssignment as AssignmentStrategy<u64>>::assign_reencryption(&request_id, vec![], 2).is_empty());
+}
+
+#[test]
+fn hash_assignment_spreads_reencryption_across_candidates() {
+	// Given: five candidates
+	let candidates = vec![1u64, 2, 3, 4, 5];
+	// When: I assign a single key fragment for each of five requests
+	let mut holders = (0..5u8).map(|i| {
+		<HashAssignment as AssignmentStrategy<u64>>::assign_reencryption(&[i; 32], candidates.clone(), 1)[0]
+	}).collect::<Vec<_>>();
+	// Then: each request's fragment is held by a different candidate
+	holders.sort();
+	holders.dedup();
+	assert_eq!(candidates, holders);
+}
+
 #[test]
 fn can_encrypt_x25519() {
 	let test_vec = "test".as_bytes().to_vec();
@@ -134,12 +691,39 @@ fn can_encrypt_x25519() {
 	let sk = BoxSecretKey::generate(&mut rng);
 	let pk = sk.public_key();
 
-	let encrypted_frag = encrypt_x25519(pk, test_vec);
+	let encrypted_frag = encrypt_x25519(pk, test_vec, &mut rng);
 	assert_eq!(true, encrypted_frag.nonce.len() > 0);
 	assert_eq!(true, encrypted_frag.ciphertext.len() > 0);
 	assert_eq!(true, encrypted_frag.public_key.len() > 0);
 }
 
+#[test]
+fn only_32_byte_public_keys_convert_to_box_public_keys() {
+	// Given: a box public key
+	let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+	let pk = BoxSecretKey::generate(&mut rng).public_key();
+	// Then: its bytes convert back to it
+	assert_eq!(Some(pk.clone()), vec_to_box_public_key(pk.as_bytes()));
+	// And: keys of any other length are refused instead of panicking
+	assert_eq!(None, vec_to_box_public_key(&pk.as_bytes()[..31]));
+	assert_eq!(None, vec_to_box_public_key(&[]));
+}
+
+#[test]
+fn encrypting_the_same_plaintext_twice_gives_different_ciphertexts() {
+	// Given: some plaintext and a recipient
+	let plaintext = "test".as_bytes().to_vec();
+	let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+	let pk = BoxSecretKey::generate(&mut rng).public_key();
+	// When: I encrypt it twice
+	let first = encrypt_x25519(pk.clone(), plaintext.clone(), &mut rng);
+	let second = encrypt_x25519(pk, plaintext, &mut rng);
+	// Then: each encryption uses a fresh ephemeral key and nonce
+	assert_ne!(first.public_key, second.public_key);
+	assert_ne!(first.nonce, second.nonce);
+	assert_ne!(first.ciphertext, second.ciphertext);
+}
+
 #[test]
 fn test_can_decrypt_x25519_using_output_of_encrypt_x25519() {
     // Given: I am a valid node with a positive balance
@@ -148,7 +732,7 @@ fn test_can_decrypt_x25519_using_output_of_encrypt_x25519() {
     let sk = BoxSecretKey::generate(&mut rng);
     let pk = sk.public_key();
 
-    let encrypted = encrypt_x25519(pk.clone(), plaintext.clone());
+    let encrypted = encrypt_x25519(pk.clone(), plaintext.clone(), &mut rng);
 
     let pk_tmp = encrypted.public_key.clone();
     let pk_slice = slice_to_array_32(&pk_tmp).unwrap();
@@ -164,4 +748,39 @@ fn test_can_decrypt_x25519_using_output_of_encrypt_x25519() {
             panic!("{:?}", e);
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn multiaddresses_parse_into_their_components() {
+	// Given: a tcp multiaddress addressing a peer
+	let multiaddress = b"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp";
+	// When: I parse it
+	let parsed = parse_multiaddress(multiaddress).unwrap();
+	// Then: each protocol is listed with its value
+	assert_eq!(vec![
+		(b"ip4".to_vec(), Some(b"127.0.0.1".to_vec())),
+		(b"tcp".to_vec(), Some(b"4001".to_vec())),
+		(b"p2p".to_vec(), Some(b"12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".to_vec())),
+	], parsed.components);
+	// And: the peer id is reported
+	assert_eq!(Some(b"12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".to_vec()), parsed.peer_id);
+	// When: I parse a circuit address without a peer id after the relay
+	let parsed = parse_multiaddress(b"/dns4/relay.example.com/udp/443/quic-v1/p2p/QmRelay/p2p-circuit").unwrap();
+	// Then: it has no peer id
+	assert_eq!(None, parsed.peer_id);
+	assert_eq!((b"p2p-circuit".to_vec(), None), parsed.components[4]);
+}
+
+#[test]
+fn malformed_multiaddresses_are_rejected() {
+	assert_eq!(Err(MultiaddressError::Empty), parse_multiaddress(b""));
+	assert_eq!(Err(MultiaddressError::InvalidUtf8), parse_multiaddress(&[b'/', 0xff]));
+	assert_eq!(Err(MultiaddressError::MissingLeadingSlash), parse_multiaddress(b"ip4/127.0.0.1"));
+	assert_eq!(Err(MultiaddressError::UnknownProtocol(b"test space".to_vec())), parse_multiaddress(b"/test space"));
+	assert_eq!(Err(MultiaddressError::MissingValue(b"tcp".to_vec())), parse_multiaddress(b"/ip4/127.0.0.1/tcp"));
+	assert_eq!(Err(MultiaddressError::InvalidValue(b"ip4".to_vec())), parse_multiaddress(b"/ip4/256.0.0.1"));
+	assert_eq!(Err(MultiaddressError::InvalidValue(b"tcp".to_vec())), parse_multiaddress(b"/ip4/127.0.0.1/tcp/65536"));
+	// a single trailing slash is ignored, but not an empty component
+	assert!(parse_multiaddress(b"/ip6/::1/tcp/4001/").is_ok());
+	assert_eq!(Err(MultiaddressError::UnknownProtocol(Vec::new())), parse_multiaddress(b"/ip4/127.0.0.1//tcp/4001"));
+}
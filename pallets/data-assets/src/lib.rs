@@ -43,10 +43,12 @@ use scale_info::TypeInfo;
 use codec::{Encode, Decode};
 use frame_support::{
     pallet_prelude::*,
-    traits::{Currency, LockableCurrency},
+    traits::{Currency, ExistenceRequirement, LockableCurrency, VestingSchedule},
+    transactional,
 };
 use frame_system::{
     self as system, 
+    ensure_root,
     ensure_signed, 
     pallet_prelude::*,
     offchain::{
@@ -60,22 +62,102 @@ use sp_runtime::{
     traits::{
         Convert,
         StaticLookup,
+        One,
+        Saturating,
+        UniqueSaturatedInto,
+        Zero,
     },
 };
 use sp_std::{
+    collections::btree_set::BTreeSet,
     prelude::*,
 };
 use core::convert::TryInto;
-// use pallet_vesting::VestingInfo;
-use iris_primitives::IngestionCommand;
+use pallet_vesting::VestingInfo;
+use iris_primitives::{
+    parse_multiaddress, AssetLifecycleEvent, EncryptionScheme, GatewayPrefs, GatewayStats, IngestionCommand, PendingReserves,
+    BYTES_PER_GB, PLAINTEXT_CHECKSUM_SIZE, UMBRAL_PUBLIC_KEY_SIZE,
+};
+
+/// the ticker symbol set in pallet_assets for every Iris asset class
+pub const ASSET_SYMBOL: &[u8] = b"IRIS";
+
+/// the number of blocks covered by each bucket of `AssetsByBlock`
+pub const ASSET_INDEX_BUCKET_BLOCKS: u32 = 100;
+
+/// the widest block range searched by `assets_created_between`
+pub const MAX_ASSET_INDEX_SPAN: u32 = 100_000;
+
+/// the most asset ids returned by a single `assets_created_between` call
+pub const MAX_ASSET_INDEX_PAGE_SIZE: u32 = 1_000;
+
+/// the maximum number of queued commands `compact_queue` inspects in a single call
+pub const MAX_COMPACTION_BATCH: u32 = 500;
+
+/// the reason code recorded when a gateway's content validator rejects the data of a request
+pub const REJECTED_CONTENT: u8 = 1;
 
 /// struct to store metadata of an asset class
 #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
 pub struct AssetMetadata {
-    /// the cid of some data
-    pub cid: Vec<u8>,
+    /// the cid of the ciphertext (the encrypted data as it is pinned in IPFS), never the
+    /// cid of the plaintext
+    pub ciphertext_cid: Vec<u8>,
+    /// the size of the ciphertext in bytes, as reported by the gateway that ingested it
+    pub ciphertext_size: u128,
     /// the public key associated with the encryption artifacts (capsule and fragments)
     pub public_key: Vec<u8>,
+    /// a short utf8 name set by the asset class admin, at most `MaxAssetNameLength` bytes
+    pub name: Option<Vec<u8>>,
+    /// a utf8 description set by the asset class admin, at most `MaxAssetDescriptionLength` bytes
+    pub description: Option<Vec<u8>>,
+    /// the ipfs peer ids of the gateways that pin the data
+    pub providers: Vec<Vec<u8>>,
+    /// the cids of the dataset's other files, pinned alongside the ciphertext
+    pub extra_cids: Vec<Vec<u8>>,
+    /// the scheme the data was encrypted with
+    pub encryption_scheme: EncryptionScheme,
+    /// the sha-256 checksum of the plaintext attested by the owner, for consumers to verify
+    /// the data they decrypt
+    pub plaintext_checksum: Option<Vec<u8>>,
+}
+
+/// the replication status of an ingestion request
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+pub struct ReplicationStatus<AccountId, AssetId> {
+    /// the number of distinct gateways that should pin the data
+    pub replication: u32,
+    /// the gateways that have completed ingestion
+    pub pinned_by: Vec<AccountId>,
+    /// the asset class created by the first completed ingestion
+    pub asset_id: Option<AssetId>,
+}
+
+/// the failed attempts to ingest a command, used to back off from flaky providers
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone, Default)]
+pub struct IngestionAttempts {
+    /// the number of failed attempts
+    pub attempts: u32,
+    /// the block before which the command should not be attempted again
+    pub next_attempt: u32,
+}
+
+/// what happens to the commands queued with a gateway when it deregisters
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, Eq, Clone, Copy, TypeInfo)]
+pub enum OrphanedCommandPolicy {
+    /// drop the commands, releasing each request's reserve once none of its replicas remain queued
+    Refund,
+    /// move each command to another gateway, or refund it if there is none
+    Reroute,
+}
+
+/// the result of completing an ingestion command
+#[derive(RuntimeDebug, PartialEq, Clone)]
+pub struct IngestionOutcome {
+    /// the number of blocks between the command being queued and completed
+    pub latency: u32,
+    /// true if a new asset class was created, false if the data was pinned as a replica
+    pub asset_created: bool,
 }
 
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"iris");
@@ -116,6 +198,8 @@ type BalanceOf<T> =
 
 pub use pallet::*;
 
+pub mod migrations;
+
 #[cfg(test)]
 mod mock;
 
@@ -148,13 +232,77 @@ pub mod pallet {
 		type Currency: LockableCurrency<Self::AccountId>;
         /// the authority id used for sending signed txs
         type AuthorityId: AppCrypto<Self::Public, Self::Signature>;
+        /// the maximum number of pending requests an account can queue with a single gateway
+        #[pallet::constant]
+        type MaxPendingRequests: Get<u32>;
+        /// provides the price gateways charge to ingest data
+        type GatewayPricing: crate::GatewayPricing<Self::AccountId, BalanceOf<Self>>;
+        /// provides the gateways that replicas of an ingestion request can be routed to
+        type GatewayProvider: crate::GatewayProvider<Self::AccountId>;
+        /// reads the preferences gateways declare, to validate requests against them
+        type ProxyPrefs: crate::ProxyPrefsReader<Self::AccountId>;
+        /// provides the ipfs peer id of each gateway
+        type PeerIdProvider: crate::PeerIdProvider<Self::AccountId>;
+        /// the maximum number of assets a gateway can report on in a single pin report
+        #[pallet::constant]
+        type MaxPinReportSize: Get<u32>;
+        /// the number of failed attempts after which an ingestion command is abandoned
+        #[pallet::constant]
+        type MaxIngestionAttempts: Get<u32>;
+        /// the number of blocks to wait after a first failed ingestion attempt,
+        /// doubled after each further failed attempt
+        #[pallet::constant]
+        type IngestionBackoff: Get<u32>;
+        /// the maximum length of an asset name, in bytes
+        #[pallet::constant]
+        type MaxAssetNameLength: Get<u32>;
+        /// the maximum length of an asset description, in bytes
+        #[pallet::constant]
+        type MaxAssetDescriptionLength: Get<u32>;
+        /// whether assets are frozen while no gateway pins them, and thawed once one does again
+        #[pallet::constant]
+        type FreezeUnavailableAssets: Get<bool>;
+        /// whether an account may name itself as the gateway of its own ingestion requests.
+        /// this should only be enabled for single-node development chains
+        #[pallet::constant]
+        type AllowSelfGateway: Get<bool>;
+        /// whether the name and symbol of each asset class are set in pallet_assets,
+        /// so that wallets can label it
+        #[pallet::constant]
+        type SetAssetMetadata: Get<bool>;
+        /// the maximum number of files a dataset ingestion request can hold
+        #[pallet::constant]
+        type MaxDatasetFiles: Get<u32>;
+        /// the number of blocks for which a gateway's claim on a request keeps the other
+        /// gateways it is replicated to from completing it
+        #[pallet::constant]
+        type ClaimDuration: Get<u32>;
+        /// the number of lifecycle events kept for each asset, older events are dropped first
+        #[pallet::constant]
+        type MaxAssetHistory: Get<u32>;
 	}
 
+    /// the current storage version
+    const STORAGE_VERSION: StorageVersion = StorageVersion::new(6);
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
+    #[pallet::storage_version(STORAGE_VERSION)]
     #[pallet::without_storage_info]
 	pub struct Pallet<T>(_);
 
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_runtime_upgrade() -> Weight {
+            migrations::v1::migrate::<T>()
+                .saturating_add(migrations::v2::migrate::<T>())
+                .saturating_add(migrations::v3::migrate::<T>())
+                .saturating_add(migrations::v4::migrate::<T>())
+                .saturating_add(migrations::v5::migrate::<T>())
+                .saturating_add(migrations::v6::migrate::<T>())
+        }
+    }
+
     #[pallet::storage]
     #[pallet::getter(fn ingestion_commands)]
     pub(super) type IngestionCommands<T: Config> = StorageMap<
@@ -165,6 +313,201 @@ pub mod pallet {
         ValueQuery,
     >;
 
+    /// a nonce used to derive unique ingestion command ids
+    #[pallet::storage]
+    pub(super) type IngestionCommandNonce<T: Config> = StorageValue<_, u64, ValueQuery>;
+
+    /// maps each ingestion command id to the id of the request it replicates
+    #[pallet::storage]
+    pub(super) type ReplicaOf<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        [u8; 32],
+        OptionQuery,
+    >;
+
+    /// the replicas of each ingestion request that are still queued: (gateway, command id)
+    #[pallet::storage]
+    pub(super) type RequestReplicas<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        Vec<(T::AccountId, [u8; 32])>,
+        ValueQuery,
+    >;
+
+    /// the number of each owner's ingestion requests that are neither ingested nor released
+    #[pallet::storage]
+    pub(super) type PendingRequests<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        u32,
+        ValueQuery,
+    >;
+
+    /// the replication status of each ingestion request
+    #[pallet::storage]
+    #[pallet::getter(fn replication)]
+    pub type Replication<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        ReplicationStatus<T::AccountId, T::AssetId>,
+        OptionQuery,
+    >;
+
+    /// the failed attempts to ingest each command, keyed by command id
+    #[pallet::storage]
+    #[pallet::getter(fn ingestion_attempts)]
+    pub type IngestionAttemptsOf<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        IngestionAttempts,
+        ValueQuery,
+    >;
+
+    /// the gateway fetching the data of each claimed request and the block at which its claim
+    /// expires, keyed by request id
+    #[pallet::storage]
+    #[pallet::getter(fn command_claim)]
+    pub type CommandClaims<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        (T::AccountId, u32),
+        OptionQuery,
+    >;
+
+    /// the most recent lifecycle events of each asset, oldest first:
+    /// (block, event, gateway or consumer involved)
+    #[pallet::storage]
+    #[pallet::getter(fn asset_history)]
+    pub type AssetHistory<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        Vec<(u32, AssetLifecycleEvent, Option<T::AccountId>)>,
+        ValueQuery,
+    >;
+
+    /// the position in each gateway's queue at which the next `compact_queue` call resumes
+    #[pallet::storage]
+    pub(super) type CompactionCursor<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        u32,
+        ValueQuery,
+    >;
+
+    /// when true, ingestion and reencryption are halted (reads still work)
+    #[pallet::storage]
+    #[pallet::getter(fn halted)]
+    pub type Halted<T: Config> = StorageValue<_, bool, ValueQuery>;
+
+    /// the command each ingestion request was created with, used to queue replacement replicas
+    #[pallet::storage]
+    pub(super) type RequestCommand<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        IngestionCommand<T::AccountId, T::Balance>,
+        OptionQuery,
+    >;
+
+    /// the ids of the ingestion requests in `RequestCommand`, keyed by (owner, cid) of their command
+    #[pallet::storage]
+    pub(super) type RequestsByCid<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Blake2_128Concat,
+        Vec<u8>,
+        Vec<[u8; 32]>,
+        ValueQuery,
+    >;
+
+    /// the files of each dataset ingestion request besides the one in its command: (cid, multiaddress)
+    #[pallet::storage]
+    #[pallet::getter(fn dataset_files)]
+    pub type DatasetFiles<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        Vec<(Vec<u8>, Vec<u8>)>,
+        ValueQuery,
+    >;
+
+    /// the share of its reserve each ingestion request set aside for each gateway
+    #[pallet::storage]
+    #[pallet::getter(fn request_reserve)]
+    pub type RequestReserve<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        BalanceOf<T>,
+        ValueQuery,
+    >;
+
+    /// the block at which the shares of each ingestion request's reserve start vesting with its
+    /// gateways, used to find the vesting schedule of a share that is refunded
+    #[pallet::storage]
+    pub type ReserveVestsAt<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        T::BlockNumber,
+        OptionQuery,
+    >;
+
+    /// the asset id each ingestion request asked for, used in place of a generated one
+    #[pallet::storage]
+    #[pallet::getter(fn requested_asset_id)]
+    pub type RequestedAssetId<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        T::AssetId,
+        OptionQuery,
+    >;
+
+    /// the ingestion request each requested asset id is reserved for
+    #[pallet::storage]
+    #[pallet::getter(fn reserved_asset_id)]
+    pub type ReservedAssetIds<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        [u8; 32],
+        OptionQuery,
+    >;
+
+    /// whether a gateway last reported that it pins an asset, keyed by (gateway, asset id)
+    #[pallet::storage]
+    #[pallet::getter(fn pin_status)]
+    pub type PinStatus<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Blake2_128Concat,
+        T::AssetId,
+        bool,
+        OptionQuery,
+    >;
+
+    /// maps each asset id to the ingestion request that created it
+    #[pallet::storage]
+    pub(super) type AssetRequest<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        [u8; 32],
+        OptionQuery,
+    >;
+
     #[pallet::storage]
     #[pallet::getter(fn next_asset_id)]
     pub(super) type NextAssetId<T: Config> = StorageValue<_, T::AssetId, ValueQuery>;
@@ -189,7 +532,43 @@ pub mod pallet {
         ValueQuery,
     >;
 
+    /// The asset classes created in each bucket of `ASSET_INDEX_BUCKET_BLOCKS` blocks,
+    /// as (block created, asset id) in creation order
+    #[pallet::storage]
+    #[pallet::getter(fn assets_by_block)]
+    pub type AssetsByBlock<T: Config> = StorageMap<
+        _,
+        Twox64Concat,
+        u32,
+        Vec<(u32, T::AssetId)>,
+        ValueQuery,
+    >;
+
+    /// The asset ids of the asset classes each gateway has ingested
+    #[pallet::storage]
+    #[pallet::getter(fn ingested_by_gateway)]
+    pub type IngestedByGateway<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Vec<T::AssetId>,
+        ValueQuery,
+    >;
+
+    /// Ingestion statistics for each gateway, used for SLA monitoring
+    #[pallet::storage]
+    #[pallet::getter(fn gateway_stats)]
+    pub type IngestionStats<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        GatewayStats,
+        ValueQuery,
+    >;
+
+    /// the number of blocks after which a request's gateway reserve vests
     #[pallet::storage]
+    #[pallet::getter(fn delay)]
     pub type Delay<T: Config> = StorageValue<_, u32, ValueQuery>;
 
     /// The staging map maps account ids to the public key that 
@@ -206,18 +585,191 @@ pub mod pallet {
         OptionQuery,
     >;
 
+    /// The reverse of the staging map: the owner each staged public key belongs to.
+    /// A public key can only be staged by one owner at a time.
+    #[pallet::storage]
+    #[pallet::getter(fn staged_key_owner)]
+    pub type StagedKeyOwner<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        Vec<u8>,
+        T::AccountId,
+        OptionQuery,
+    >;
+
+    /// Assets frozen because no gateway pins them. Only these are thawed automatically,
+    /// so an asset frozen by its freezer stays frozen when a replica is restored.
+    #[pallet::storage]
+    #[pallet::getter(fn frozen_unavailable)]
+    pub type FrozenUnavailable<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        bool,
+        ValueQuery,
+    >;
+
+    /// The number of distinct gateways that must complete a request before its asset class
+    /// is created, for requests that asked for more than one
+    #[pallet::storage]
+    #[pallet::getter(fn confirmation_quorum)]
+    pub type ConfirmationQuorum<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        u32,
+        OptionQuery,
+    >;
+
+    /// The gateways that completed a request whose asset class awaits its confirmation quorum
+    #[pallet::storage]
+    #[pallet::getter(fn ingestion_confirmations)]
+    pub type IngestionConfirmations<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        Vec<T::AccountId>,
+        ValueQuery,
+    >;
+
+    /// Commands queued by `repin_asset`, mapped to the asset they restore
+    #[pallet::storage]
+    #[pallet::getter(fn repin_of)]
+    pub type RepinOf<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        [u8; 32],
+        T::AssetId,
+        OptionQuery,
+    >;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
-        /// A request to add bytes was queued
-        CreatedIngestionRequest,
+        /// A request to add bytes was queued: (request id, block at which the reserve vests)
+        CreatedIngestionRequest([u8; 32], u32),
+        /// A request was killed before it was ingested: (request id)
+        RequestKilled([u8; 32]),
+        /// A gateway pinned a replica of an asset: (asset id, gateway, replicas pinned, replication)
+        ReplicaPinned(T::AssetId, T::AccountId, u32, u32),
+        /// An asset was pinned by as many distinct gateways as requested
+        AssetDurable(T::AssetId),
+        /// The admin of an asset class changed: (asset id, old admin, new admin)
+        AssetAdminTransferred(T::AssetId, T::AccountId, T::AccountId),
+        /// The minimum balance of an asset class changed: (asset id, new minimum balance)
+        AssetMinBalanceSet(T::AssetId, T::Balance),
+        /// A gateway reported its pins: (gateway, assets pinned, assets dropped)
+        PinReportSubmitted(T::AccountId, u32, u32),
+        /// A gateway dropped its replica of an asset: (asset id, gateway)
+        ReplicaDropped(T::AssetId, T::AccountId),
+        /// A replacement replica of an asset was queued with a gateway: (asset id, gateway)
+        RepinQueued(T::AssetId, T::AccountId),
+        /// A gateway failed to ingest a command: (command id, gateway, attempts, next attempt block)
+        IngestionAttemptFailed([u8; 32], T::AccountId, u32, u32),
+        /// A command was removed from a gateway's queue after too many failed attempts: (command id, gateway)
+        CommandAbandoned([u8; 32], T::AccountId),
+        /// A gateway declined to service a request: (gateway, owner, cid, reason code)
+        RequestRejected(T::AccountId, T::AccountId, Vec<u8>, Option<u8>),
+        /// Ingestion and reencryption were halted (true) or resumed (false)
+        HaltStatusChanged(bool),
+        /// The name and description of an asset were updated: (asset id)
+        AssetInfoUpdated(T::AssetId),
+        /// An asset was frozen because no gateway pins it anymore: (asset id)
+        AssetFrozen(T::AssetId),
+        /// An asset frozen while unavailable was thawed after a replica was restored: (asset id)
+        AssetThawed(T::AssetId),
+        /// A gateway pinned an asset re-pinned on its admin's request: (asset id, gateway)
+        AssetRepinned(T::AssetId, T::AccountId),
+        /// The owner of an asset attested the checksum of its plaintext: (asset id)
+        PlaintextChecksumSet(T::AssetId),
+        /// A command queued with a deregistered gateway was dropped: (command id, owner)
+        CommandRefunded([u8; 32], T::AccountId),
+        /// A command queued with a deregistered gateway was moved to another gateway:
+        /// (command id, deregistered gateway, new gateway)
+        CommandRerouted([u8; 32], T::AccountId, T::AccountId),
+        /// A gateway claimed a request: (request id, gateway, block at which the claim expires)
+        CommandClaimed([u8; 32], T::AccountId, u32),
+        /// A gateway's share of a request's reserve was returned to the request's owner before
+        /// it vested: (request id, gateway, amount)
+        ReserveRefunded([u8; 32], T::AccountId, BalanceOf<T>),
+        /// A gateway's claim on a request expired before it completed its command:
+        /// (request id, gateway)
+        ClaimExpired([u8; 32], T::AccountId),
+        /// Stale commands were dropped from a gateway's queue: (gateway, number of commands removed)
+        QueueCompacted(T::AccountId, u32),
+        /// A gateway completed a request awaiting its confirmation quorum:
+        /// (request id, gateway, confirmations, quorum)
+        IngestionConfirmed([u8; 32], T::AccountId, u32, u32),
 	}
 
 	#[pallet::error]
 	pub enum Error<T> {
+        /// the caller can't vest the reserve with the gateways
         InsufficientBalance,
         /// could not create a new asset
         CantCreateAssetClass,
+        /// the caller already has the maximum number of pending requests queued with the gateway
+        TooManyPendingRequests,
+        /// the ingestion command is not queued with the gateway
+        NoSuchIngestionCommand,
+        /// the reserve is below the gateway's price
+        ReserveTooLow,
+        /// each gateway's share of the reserve is below the minimum vested transfer
+        ReserveBelowMinimum,
+        /// the replication factor must be at least 1
+        InvalidReplication,
+        /// there are not enough registered gateways to satisfy the replication factor
+        InsufficientGateways,
+        /// the minimum balance of the asset class must be positive
+        InvalidMinBalance,
+        /// there is no pending ingestion request with the given id
+        NoSuchRequest,
+        /// only the owner of a request can kill it
+        NotRequestOwner,
+        /// the data has already been ingested by a gateway
+        RequestAlreadyIngested,
+        /// the asset class does not exist
+        NoSuchAsset,
+        /// only the admin of an asset class can transfer it
+        NotAssetAdmin,
+        /// the pin report contains more than `MaxPinReportSize` entries
+        PinReportTooLarge,
+        /// the command is backing off and should not have been attempted yet
+        AttemptTooEarly,
+        /// ingestion and reencryption are halted
+        Halted,
+        /// the asset name is longer than `MaxAssetNameLength`
+        AssetNameTooLong,
+        /// the asset description is longer than `MaxAssetDescriptionLength`
+        AssetDescriptionTooLong,
+        /// the asset name or description is not valid utf8
+        InvalidUtf8,
+        /// the owner has no encrypted data staged for ingestion
+        NoStagedData,
+        /// the public key is not a serialized umbral public key
+        InvalidPublicKey,
+        /// the caller named itself as the gateway while `AllowSelfGateway` is disabled
+        SelfGatewayNotAllowed,
+        /// the requested asset id belongs to an existing asset class or is reserved by another request
+        AssetIdTaken,
+        /// the public key is already staged by another owner
+        KeyAlreadyStaged,
+        /// a dataset must hold at least one file
+        EmptyDataset,
+        /// the dataset holds more than `MaxDatasetFiles` files
+        TooManyDatasetFiles,
+        /// the asset is pinned, or queued to be pinned, by as many gateways as requested
+        RepinNotNeeded,
+        /// the named gateway has not declared itself a gateway
+        NotAGateway,
+        /// the plaintext checksum is not a sha-256 digest
+        InvalidChecksumLength,
+        /// another gateway holds an unexpired claim on the command
+        AlreadyClaimed,
+        /// the confirmation quorum must be between 1 and the replication factor
+        InvalidConfirmationQuorum,
+        /// a multiaddress the data is fetched from is malformed
+        InvalidMultiaddress,
 	}
 
 
@@ -253,46 +805,78 @@ pub mod pallet {
         /// submits an on-chain request to fetch data and add it to iris 
         /// 
         /// * `gateway`: The gateway node that should verify the data.
+        /// * `gateway_reserve`: The reserve offered to the gateways, split evenly between them and vested
+        ///                      with each gateway until the request's target block. Each share
+        ///                      must be at least the receiving gateway's price for `estimated_size`.
+        ///                      The remainder of an uneven split is not transferred and stays with the caller
         /// * `multiaddress`: the multiaddress where the data exists
         ///       example: /ip4/192.168.1.170/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp
         /// * `cid`: the cid to fetch from the multiaddress
         ///       example: QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9
+        /// * `estimated_size`: the size of the data in bytes, which gateways price the request by
         /// * `dataspace_id`: The asset id of the dataspace to associate the newly created asset class with
-        /// * `id`: (temp) the unique id of the asset class -> should be generated instead
         /// * `balance`: the balance the owner is willing to use to back the asset class which will be created
+        /// * `replication`: the number of distinct gateways that should pin the data. The command is sent to
+        ///                  `gateway` and `replication - 1` other registered gateways
+        /// * `confirmation_quorum`: the number of distinct gateways that must complete ingestion before
+        ///                          the asset class is created, between 1 and `replication`
+        /// * `requested_asset_id`: the id to give the asset class, which is reserved until the request is
+        ///                         ingested or killed. When None, the ingesting gateway generates an id
         ///
         #[pallet::weight(100)]
         pub fn create_request(
             origin: OriginFor<T>,
             gateway: <T::Lookup as StaticLookup>::Source,
-            _gateway_reserve: BalanceOf<T>,
+            gateway_reserve: BalanceOf<T>,
             cid: Vec<u8>,
             multiaddress: Vec<u8>,
+            estimated_size: u128,
             #[pallet::compact] min_asset_balance: T::Balance,
+            replication: u32,
+            confirmation_quorum: u32,
+            requested_asset_id: Option<T::AssetId>,
         ) -> DispatchResult {
             let who = ensure_signed(origin)?;
-            let g = T::Lookup::lookup(gateway)?; 
-            // first ensure that the caller has sufficent funds
-            // let current_block_number = <frame_system::Pallet<T>>::block_number();
-            // let target_block = current_block_number + Delay::<T>::get().into();
-            // let new_origin = system::RawOrigin::Signed(who.clone()).into();
-            // <pallet_vesting::Pallet<T>>::vested_transfer(
-            //     new_origin, gateway, 
-            //     VestingInfo::new(gateway_reserve, gateway_reserve, target_block),
-            // ).map_err(|_| Error::<T>::InsufficientBalance)?;
-            // issue the command
-            let mut commands = IngestionCommands::<T>::get(g.clone());
-            let cmd = IngestionCommand {
-                owner: who,
-                cid,
-                multiaddress,
-                balance: min_asset_balance,
-            };
-            commands.push(cmd);
-            IngestionCommands::<T>::insert(g, commands);
-            Self::deposit_event(Event::CreatedIngestionRequest);
-			Ok(())
+            Self::do_create_request(
+                who, gateway, gateway_reserve, cid, multiaddress, Vec::new(), estimated_size,
+                min_asset_balance, replication, confirmation_quorum, requested_asset_id,
+            )
+        }
+
+        /// submits an on-chain request to fetch a dataset of several files and add it to iris
+        /// as a single asset class. The gateways only complete ingestion once they have fetched
+        /// every file. The first file is the dataset's primary ciphertext, whose capsule is staged
+        /// as for `create_request`, so it should hold whatever consumers need to decrypt the others.
+        /// 
+        /// * `gateway`: The gateway node that should verify the data.
+        /// * `gateway_reserve`: As for `create_request`
+        /// * `files`: the (cid, multiaddress) of each file, at most `MaxDatasetFiles`
+        /// * `estimated_size`: the total size of the files in bytes
+        /// * `min_asset_balance`: the balance the owner is willing to use to back the asset class
+        /// * `replication`: As for `create_request`
+        /// * `requested_asset_id`: As for `create_request`
+        ///
+        #[pallet::weight(100)]
+        pub fn create_dataset_request(
+            origin: OriginFor<T>,
+            gateway: <T::Lookup as StaticLookup>::Source,
+            gateway_reserve: BalanceOf<T>,
+            files: Vec<(Vec<u8>, Vec<u8>)>,
+            estimated_size: u128,
+            #[pallet::compact] min_asset_balance: T::Balance,
+            replication: u32,
+            requested_asset_id: Option<T::AssetId>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            ensure!(files.len() as u32 <= T::MaxDatasetFiles::get(), Error::<T>::TooManyDatasetFiles);
+            let mut files = files.into_iter();
+            let (cid, multiaddress) = files.next().ok_or(Error::<T>::EmptyDataset)?;
+            Self::do_create_request(
+                who, gateway, gateway_reserve, cid, multiaddress, files.collect(), estimated_size,
+                min_asset_balance, replication, 1, requested_asset_id,
+            )
         }
+
         // /// increase the balance vested in the request 
         // /// sent to a gateway
         // #[pallet::weight(100)]
@@ -304,34 +888,1178 @@ pub mod pallet {
         //     Ok(())
         // }
 
-        // /// if a request has not been processed, 'unvest' total balance
-        // #[pallet::weight(100)]
-        // pub fn kill_request(
-        //     origin: OriginFor<T>,
-        // ) -> DispatchResult {
-        //     // TODO
-        //     Ok(())
-        // }
-    }
-}
+        /// Kill an ingestion request that no gateway has ingested yet, removing its commands
+        /// from the queues of the gateways it is replicated to. The caller's staged public key
+        /// is removed too, unless another of their requests is still waiting to be ingested.
+        /// The shares of the reserve vested with the gateways are returned to the caller.
+        /// 
+        /// * `request_id`: The id announced in the `CreatedIngestionRequest` event
+        /// 
+        #[pallet::weight(
+            T::DbWeight::get().reads_writes(4, 10).saturating_add(
+                T::DbWeight::get().reads_writes(1, 3)
+                    .saturating_mul(RequestReplicas::<T>::decode_len(request_id).unwrap_or(0) as u64)
+            )
+        )]
+        pub fn kill_request(
+            origin: OriginFor<T>,
+            request_id: [u8; 32],
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let status = Replication::<T>::get(request_id).ok_or(Error::<T>::NoSuchRequest)?;
+            ensure!(status.asset_id.is_none(), Error::<T>::RequestAlreadyIngested);
+            let owner = RequestCommand::<T>::get(request_id)
+                .map(|cmd| cmd.owner)
+                .ok_or(Error::<T>::NoSuchRequest)?;
+            ensure!(owner == who, Error::<T>::NotRequestOwner);
 
-impl<T: Config> Pallet<T> {
+            for (gateway, cmd_id) in RequestReplicas::<T>::get(request_id) {
+                Self::refund_share(request_id, &gateway);
+                IngestionCommands::<T>::mutate(gateway, |cmds| cmds.retain(|c| c.id != cmd_id));
+                ReplicaOf::<T>::remove(cmd_id);
+                IngestionAttemptsOf::<T>::remove(cmd_id);
+            }
+            Self::release_request(request_id);
+            // a single dataset is staged per account, so it may belong to another pending request
+            if PendingRequests::<T>::get(&who) == 0 {
+                Self::clear_ingestion_staging(&who);
+            }
+            Self::deposit_event(Event::RequestKilled(request_id));
+            Ok(())
+        }
 
-}
+        /// Decline to service an ingestion request queued with the caller (a gateway), e.g. for
+        /// content policy or capacity reasons, removing the command from its queue. The caller's
+        /// share of the reserve is returned to the owner. Once no replica of a request that was
+        /// never ingested remains queued, the request is released along with its asset id, so
+        /// the owner can route it to another gateway. The owner's staged data is kept for that purpose.
+        /// 
+        /// * `owner`: The owner of the request
+        /// * `cid`: The cid of the data to be ingested
+        /// * `reason`: An optional code explaining why the request was rejected
+        /// 
+        #[pallet::weight(T::DbWeight::get().reads_writes(8, 16))]
+        pub fn reject_request(
+            origin: OriginFor<T>,
+            owner: T::AccountId,
+            cid: Vec<u8>,
+            reason: Option<u8>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cmd = IngestionCommands::<T>::get(who.clone()).into_iter()
+                .find(|c| c.owner == owner && c.cid == cid)
+                .ok_or(Error::<T>::NoSuchIngestionCommand)?;
+            Self::do_reject_command(who, cmd, reason);
+            Ok(())
+        }
 
-/// 
-pub trait MetadataProvider<AssetId> {
-    fn get(asset_id: AssetId) -> Option<AssetMetadata>;
-}
+        /// A gateway claims the request of a command queued with it before fetching its data,
+        /// so that none of the other gateways the request is replicated to fetch the same data
+        /// at once or complete their command for `ClaimDuration` blocks. The claim is released
+        /// once the claimer completes, fails or drops its command. Once the claim expires, any
+        /// gateway the request is queued with can claim it, and `ClaimExpired` is emitted.
+        /// A gateway may renew its own claim at any time.
+        /// 
+        /// * `owner`: The owner of the data
+        /// * `cid`: The cid of the data to be ingested
+        /// 
+        #[pallet::weight(100)]
+        pub fn claim_command(
+            origin: OriginFor<T>,
+            owner: T::AccountId,
+            cid: Vec<u8>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cmd = IngestionCommands::<T>::get(who.clone()).into_iter()
+                .find(|c| c.owner == owner && c.cid == cid)
+                .ok_or(Error::<T>::NoSuchIngestionCommand)?;
+            Self::do_claim_command(who, cmd.id)
+        }
 
-impl<T: Config> MetadataProvider<T::AssetId> for Pallet<T> {
-    fn get(asset_id: T::AssetId) -> Option<AssetMetadata> {
-        Metadata::<T>::get(asset_id)
-    }
-}
+        /// A gateway drops stale commands from its queue: duplicates of a command queued
+        /// earlier and commands whose request no longer exists, e.g. because it was released.
+        /// At most `limit` commands (capped at `MAX_COMPACTION_BATCH`) are inspected per call,
+        /// and each call resumes where the previous one stopped, wrapping around at the end
+        /// of the queue.
+        /// 
+        /// * `limit`: The maximum number of commands to inspect
+        /// 
+        #[pallet::weight(100)]
+        pub fn compact_queue(
+            origin: OriginFor<T>,
+            limit: u32,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let removed = Self::compact_commands(&who, limit.min(MAX_COMPACTION_BATCH));
+            Self::deposit_event(Event::QueueCompacted(who, removed));
+            Ok(())
+        }
 
-// Implementation of Convert trait for mapping ValidatorId with AccountId.
-pub struct ValidatorOf<T>(sp_std::marker::PhantomData<T>);
+        /// Transfer the admin rights of an asset class to a new account, e.g. to recover
+        /// an asset class whose owner is moving to a new key. The new admin also becomes
+        /// the issuer and freezer of the asset class and takes over its Iris ownership.
+        /// 
+        /// * `asset_id`: The asset class to transfer
+        /// * `new_admin`: The account to become the admin of the asset class
+        /// 
+        #[pallet::weight(100)]
+        pub fn transfer_asset_admin(
+            origin: OriginFor<T>,
+            #[pallet::compact] asset_id: T::AssetId,
+            new_admin: <T::Lookup as StaticLookup>::Source,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let new_admin = T::Lookup::lookup(new_admin)?;
+            let details = <pallet_assets::Pallet<T>>::asset(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            ensure!(details.admin == who, Error::<T>::NotAssetAdmin);
+            // pallet_assets only lets the owner (the gateway that created the asset class) change the team
+            let owner_origin = system::RawOrigin::Signed(details.owner.clone()).into();
+            let team = T::Lookup::unlookup(new_admin.clone());
+            <pallet_assets::Pallet<T>>::set_team(owner_origin, asset_id, team.clone(), team.clone(), team)?;
+            AssetClassOwnership::<T>::mutate(who.clone(), |ids| ids.retain(|id| *id != asset_id));
+            AssetClassOwnership::<T>::mutate(new_admin.clone(), |ids| ids.push(asset_id));
+            Self::deposit_event(Event::AssetAdminTransferred(asset_id, who, new_admin));
+            Ok(())
+        }
+
+        /// Change the minimum balance of an asset class, which is fixed from the request's
+        /// `min_asset_balance` when the asset class is created. The new minimum must be positive,
+        /// and can only be raised while no account holds the asset, so no balance is left below it.
+        /// 
+        /// * `asset_id`: The asset class
+        /// * `min_balance`: The new minimum balance
+        /// 
+        /// The origin must be the admin of the asset class.
+        /// 
+        #[pallet::weight(100)]
+        pub fn set_asset_min_balance(
+            origin: OriginFor<T>,
+            #[pallet::compact] asset_id: T::AssetId,
+            #[pallet::compact] min_balance: T::Balance,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let details = <pallet_assets::Pallet<T>>::asset(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            ensure!(details.admin == who, Error::<T>::NotAssetAdmin);
+            <pallet_assets::Pallet<T>>::do_set_min_balance(asset_id, min_balance)?;
+            Self::deposit_event(Event::AssetMinBalanceSet(asset_id, min_balance));
+            Ok(())
+        }
+
+        /// Re-pin an asset that fewer gateways pin than requested, e.g. after its only replica
+        /// was ejected and no other gateway could take it over. A command to fetch and pin the
+        /// data from its recorded cid and multiaddress is queued with a gateway that neither pins
+        /// the asset nor has a replica of it queued, and is ingested like any other. Once the
+        /// gateway pins it, `AssetRepinned` is emitted and an asset frozen while unavailable is thawed.
+        /// 
+        /// * `asset_id`: The asset class to re-pin
+        /// 
+        /// The origin must be the admin of the asset class.
+        /// 
+        #[pallet::weight(100)]
+        pub fn repin_asset(
+            origin: OriginFor<T>,
+            #[pallet::compact] asset_id: T::AssetId,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            ensure!(!Halted::<T>::get(), Error::<T>::Halted);
+            let details = <pallet_assets::Pallet<T>>::asset(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            ensure!(details.admin == who, Error::<T>::NotAssetAdmin);
+            let request_id = AssetRequest::<T>::get(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            let status = Replication::<T>::get(request_id).ok_or(Error::<T>::NoSuchAsset)?;
+            let queued = RequestReplicas::<T>::decode_len(request_id).unwrap_or(0) as u32;
+            ensure!(
+                (status.pinned_by.len() as u32).saturating_add(queued) < status.replication,
+                Error::<T>::RepinNotNeeded
+            );
+            let cmd_id = Self::queue_repin(asset_id, request_id, &status, None)
+                .ok_or(Error::<T>::InsufficientGateways)?;
+            RepinOf::<T>::insert(cmd_id, asset_id);
+            Ok(())
+        }
+
+        /// Set a human readable name and description for an asset class, for display.
+        /// Passing `None` clears the field.
+        /// 
+        /// * `asset_id`: The asset class to describe
+        /// * `name`: A utf8 name, at most `MaxAssetNameLength` bytes
+        /// * `description`: A utf8 description, at most `MaxAssetDescriptionLength` bytes
+        /// 
+        /// The origin must be the admin of the asset class.
+        ///
+        #[pallet::weight(100)]
+        pub fn set_asset_info(
+            origin: OriginFor<T>,
+            #[pallet::compact] asset_id: T::AssetId,
+            name: Option<Vec<u8>>,
+            description: Option<Vec<u8>>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let details = <pallet_assets::Pallet<T>>::asset(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            ensure!(details.admin == who, Error::<T>::NotAssetAdmin);
+            if let Some(name) = &name {
+                ensure!(name.len() as u32 <= T::MaxAssetNameLength::get(), Error::<T>::AssetNameTooLong);
+                ensure!(core::str::from_utf8(name).is_ok(), Error::<T>::InvalidUtf8);
+            }
+            if let Some(description) = &description {
+                ensure!(
+                    description.len() as u32 <= T::MaxAssetDescriptionLength::get(),
+                    Error::<T>::AssetDescriptionTooLong
+                );
+                ensure!(core::str::from_utf8(description).is_ok(), Error::<T>::InvalidUtf8);
+            }
+            let display_name = Metadata::<T>::try_mutate(asset_id, |metadata| -> Result<Vec<u8>, DispatchError> {
+                let metadata = metadata.as_mut().ok_or(Error::<T>::NoSuchAsset)?;
+                metadata.name = name;
+                metadata.description = description;
+                Ok(metadata.name.clone().unwrap_or_else(|| metadata.ciphertext_cid.clone()))
+            })?;
+            Self::set_assets_metadata(asset_id, &display_name);
+            Self::deposit_event(Event::AssetInfoUpdated(asset_id));
+            Ok(())
+        }
+
+        /// Attest the checksum of an asset's plaintext, so consumers can verify the data they
+        /// decrypt independently of the ciphertext's cid. Setting it again replaces it.
+        /// 
+        /// * `asset_id`: The asset class whose plaintext was checksummed
+        /// * `checksum`: The sha-256 digest of the plaintext, `PLAINTEXT_CHECKSUM_SIZE` bytes
+        /// 
+        /// The origin must be the owner (admin) of the asset class.
+        ///
+        #[pallet::weight(100)]
+        pub fn set_plaintext_checksum(
+            origin: OriginFor<T>,
+            #[pallet::compact] asset_id: T::AssetId,
+            checksum: Vec<u8>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let details = <pallet_assets::Pallet<T>>::asset(asset_id).ok_or(Error::<T>::NoSuchAsset)?;
+            ensure!(details.admin == who, Error::<T>::NotAssetAdmin);
+            ensure!(checksum.len() == PLAINTEXT_CHECKSUM_SIZE, Error::<T>::InvalidChecksumLength);
+            Metadata::<T>::try_mutate(asset_id, |metadata| -> DispatchResult {
+                let metadata = metadata.as_mut().ok_or(Error::<T>::NoSuchAsset)?;
+                metadata.plaintext_checksum = Some(checksum);
+                Ok(())
+            })?;
+            Self::deposit_event(Event::PlaintextChecksumSet(asset_id));
+            Ok(())
+        }
+
+        /// Report whether the caller (a gateway) still pins some assets. Only assets the gateway
+        /// has previously pinned are considered, other entries are ignored. When a gateway reports
+        /// that it dropped a pin, a replacement replica is queued with another gateway if possible.
+        /// 
+        /// * `report`: A list of (asset id, pinned), at most `MaxPinReportSize` entries
+        /// 
+        #[pallet::weight(100)]
+        pub fn submit_pin_report(
+            origin: OriginFor<T>,
+            report: Vec<(T::AssetId, bool)>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            ensure!(
+                report.len() as u32 <= T::MaxPinReportSize::get(),
+                Error::<T>::PinReportTooLarge
+            );
+            let mut pinned_count = 0u32;
+            let mut dropped_count = 0u32;
+            for (asset_id, pinned) in report.into_iter() {
+                let was_pinned = match PinStatus::<T>::get(&who, asset_id) {
+                    Some(was_pinned) => was_pinned,
+                    None => continue,
+                };
+                let request_id = match AssetRequest::<T>::get(asset_id) {
+                    Some(request_id) => request_id,
+                    None => continue,
+                };
+                let mut status = match Replication::<T>::get(request_id) {
+                    Some(status) => status,
+                    None => continue,
+                };
+                if pinned {
+                    pinned_count += 1;
+                    PinStatus::<T>::insert(&who, asset_id, true);
+                    Self::update_provider(asset_id, &who, true);
+                    IngestedByGateway::<T>::mutate(who.clone(), |ids| {
+                        if !ids.contains(&asset_id) {
+                            ids.push(asset_id);
+                        }
+                    });
+                    if !was_pinned && !status.pinned_by.contains(&who) {
+                        status.pinned_by.push(who.clone());
+                        Replication::<T>::insert(request_id, status);
+                        Self::update_availability(asset_id);
+                    }
+                } else {
+                    dropped_count += 1;
+                    // a dropped pin ejects the gateway's replica
+                    Self::remove_ingested_asset(who.clone(), asset_id);
+                }
+            }
+            Self::deposit_event(Event::PinReportSubmitted(who, pinned_count, dropped_count));
+            Ok(())
+        }
+
+        /// Halt (or resume) ingestion and reencryption, e.g. when a vulnerability is found in
+        /// the crypto pipeline. While halted new requests, ingestion results and reencryption
+        /// work are rejected, but reads still work.
+        /// 
+        /// * `halted`: true to halt, false to resume
+        /// 
+        /// The origin must be root.
+        ///
+        #[pallet::weight(100)]
+        pub fn set_halted(
+            origin: OriginFor<T>,
+            halted: bool,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+            Halted::<T>::put(halted);
+            Self::deposit_event(Event::HaltStatusChanged(halted));
+            Ok(())
+        }
+    }
+}
+
+impl<T: Config> Pallet<T> {
+    /// Queue an ingestion request with `replication` gateways, as described in `create_request`
+    /// 
+    /// * `extra_files`: the (cid, multiaddress) of the dataset's files besides `cid`, if any
+    /// * `estimated_size`: the size in bytes of all of the files
+    /// 
+    #[transactional]
+    fn do_create_request(
+        who: T::AccountId,
+        gateway: <T::Lookup as StaticLookup>::Source,
+        gateway_reserve: BalanceOf<T>,
+        cid: Vec<u8>,
+        multiaddress: Vec<u8>,
+        extra_files: Vec<(Vec<u8>, Vec<u8>)>,
+        estimated_size: u128,
+        min_asset_balance: T::Balance,
+        replication: u32,
+        confirmation_quorum: u32,
+        requested_asset_id: Option<T::AssetId>,
+    ) -> DispatchResult {
+        ensure!(!Halted::<T>::get(), Error::<T>::Halted);
+        let g = T::Lookup::lookup(gateway)?; 
+        ensure!(g != who || T::AllowSelfGateway::get(), Error::<T>::SelfGatewayNotAllowed);
+        ensure!(T::ProxyPrefs::prefs(&g).is_some(), Error::<T>::NotAGateway);
+        ensure!(replication > 0, Error::<T>::InvalidReplication);
+        ensure!(
+            confirmation_quorum > 0 && confirmation_quorum <= replication,
+            Error::<T>::InvalidConfirmationQuorum
+        );
+        ensure!(min_asset_balance > Zero::zero(), Error::<T>::InvalidMinBalance);
+        ensure!(
+            parse_multiaddress(&multiaddress).is_ok()
+                && extra_files.iter().all(|(_, multiaddress)| parse_multiaddress(multiaddress).is_ok()),
+            Error::<T>::InvalidMultiaddress
+        );
+        if let Some(asset_id) = requested_asset_id {
+            ensure!(
+                <pallet_assets::Pallet<T>>::asset(asset_id).is_none()
+                    && !ReservedAssetIds::<T>::contains_key(asset_id),
+                Error::<T>::AssetIdTaken
+            );
+        }
+        let gateways = Self::select_gateways(g, replication)?;
+        // only the shares are vested, so the remainder of an uneven split stays with the caller
+        let reserve_share = gateway_reserve / BalanceOf::<T>::from(replication);
+        for gateway in gateways.iter() {
+            // each share of the reserve must cover the gateway's price for the data
+            ensure!(
+                reserve_share >= T::GatewayPricing::reserve_for(gateway.clone(), estimated_size),
+                Error::<T>::ReserveTooLow
+            );
+            let pending_count = IngestionCommands::<T>::get(gateway).iter()
+                .filter(|c| c.owner == who).count() as u32;
+            ensure!(
+                pending_count < T::MaxPendingRequests::get(),
+                Error::<T>::TooManyPendingRequests
+            );
+        }
+        let queued_at: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        let target_block = Self::compute_target_block(queued_at, Delay::<T>::get(), estimated_size);
+        // each share is vested with a gateway, which pallet_vesting refuses below its minimum
+        ensure!(
+            reserve_share >= <T as pallet_vesting::Config>::MinVestedTransfer::get(),
+            Error::<T>::ReserveBelowMinimum
+        );
+        for gateway in gateways.iter() {
+            let new_origin = system::RawOrigin::Signed(who.clone()).into();
+            <pallet_vesting::Pallet<T>>::vested_transfer(
+                new_origin,
+                T::Lookup::unlookup(gateway.clone()),
+                VestingInfo::new(reserve_share, reserve_share, target_block.into()),
+            ).map_err(|_| Error::<T>::InsufficientBalance)?;
+        }
+        // issue a command to each gateway
+        let nonce = IngestionCommandNonce::<T>::get();
+        IngestionCommandNonce::<T>::put(nonce.wrapping_add(1));
+        let request_id = sp_io::hashing::blake2_256(
+            &(who.clone(), cid.clone(), multiaddress.clone(), nonce).encode()
+        );
+        for (index, gateway) in gateways.into_iter().enumerate() {
+            let id = sp_io::hashing::blake2_256(&(request_id, index as u32).encode());
+            Self::index_replica(request_id, gateway.clone(), id);
+            IngestionCommands::<T>::mutate(gateway, |commands| {
+                commands.push(IngestionCommand {
+                    id,
+                    owner: who.clone(),
+                    cid: cid.clone(),
+                    multiaddress: multiaddress.clone(),
+                    balance: min_asset_balance,
+                    queued_at,
+                });
+            });
+        }
+        RequestCommand::<T>::insert(request_id, IngestionCommand {
+            id: request_id,
+            owner: who.clone(),
+            cid: cid.clone(),
+            multiaddress: multiaddress.clone(),
+            balance: min_asset_balance,
+            queued_at,
+        });
+        RequestsByCid::<T>::append(who.clone(), cid.clone(), request_id);
+        Replication::<T>::insert(request_id, ReplicationStatus {
+            replication,
+            pinned_by: Vec::new(),
+            asset_id: None,
+        });
+        RequestReserve::<T>::insert(request_id, reserve_share);
+        ReserveVestsAt::<T>::insert(request_id, T::BlockNumber::from(target_block));
+        PendingRequests::<T>::mutate(&who, |pending| *pending = pending.saturating_add(1));
+        if confirmation_quorum > 1 {
+            ConfirmationQuorum::<T>::insert(request_id, confirmation_quorum);
+        }
+        if let Some(asset_id) = requested_asset_id {
+            RequestedAssetId::<T>::insert(request_id, asset_id);
+            ReservedAssetIds::<T>::insert(asset_id, request_id);
+        }
+        if !extra_files.is_empty() {
+            DatasetFiles::<T>::insert(request_id, extra_files);
+        }
+        Self::deposit_event(Event::CreatedIngestionRequest(request_id, target_block));
+        Ok(())
+    }
+
+    /// The block at which the reserve of a request queued at `now` vests with its gateways.
+    /// The reserve is locked for `delay` blocks per started gb of data, so gateways have longer
+    /// to ingest larger requests.
+    /// 
+    /// * `now`: The block the request is queued at
+    /// * `delay`: The number of blocks the reserve of a request for at most a gb is locked
+    /// * `size`: The estimated size of the data in bytes
+    /// 
+    pub fn compute_target_block(now: u32, delay: u32, size: u128) -> u32 {
+        let gbs: u32 = (size.saturating_add(BYTES_PER_GB - 1) / BYTES_PER_GB)
+            .max(1)
+            .unique_saturated_into();
+        now.saturating_add(delay.saturating_mul(gbs))
+    }
+
+    /// The block at which the reserve of a request queued in the current block would vest
+    /// 
+    /// * `size`: The estimated size of the data in bytes
+    /// 
+    pub fn vesting_target_block(size: u128) -> u32 {
+        let now: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        Self::compute_target_block(now, Delay::<T>::get(), size)
+    }
+
+    /// Get a page of the asset ids a gateway has ingested
+    /// 
+    /// * `gateway`: The gateway that ingested the assets
+    /// * `page`: The index of the page to fetch, starting from 0
+    /// * `page_size`: The maximum number of asset ids in a page
+    /// 
+    pub fn ingested_assets(
+        gateway: T::AccountId,
+        page: u32,
+        page_size: u32,
+    ) -> Vec<T::AssetId> {
+        IngestedByGateway::<T>::get(gateway)
+            .into_iter()
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect()
+    }
+
+    /// Drop stale commands from a gateway's queue, inspecting at most `limit` commands
+    /// from where the last compaction stopped. Returns the number of commands removed.
+    /// 
+    /// * `gateway`: The gateway whose queue is compacted
+    /// * `limit`: The maximum number of commands to inspect
+    /// 
+    fn compact_commands(gateway: &T::AccountId, limit: u32) -> u32 {
+        let commands = IngestionCommands::<T>::get(gateway);
+        let len = commands.len();
+        let start = (CompactionCursor::<T>::get(gateway) as usize).min(len);
+        let end = start.saturating_add(limit as usize).min(len);
+        // commands before the window still count when looking for duplicates
+        let mut seen: BTreeSet<[u8; 32]> = commands[..start].iter().map(|c| c.id).collect();
+        let mut removed = 0u32;
+        let mut kept = Vec::with_capacity(len);
+        for (index, cmd) in commands.into_iter().enumerate() {
+            if index < start || index >= end {
+                kept.push(cmd);
+                continue;
+            }
+            if !seen.insert(cmd.id) {
+                removed += 1;
+                continue;
+            }
+            let live = ReplicaOf::<T>::get(cmd.id)
+                .map_or(false, |request_id| Replication::<T>::contains_key(request_id));
+            if live {
+                kept.push(cmd);
+            } else {
+                Self::release_claim(cmd.id, gateway);
+                Self::unindex_replica(cmd.id);
+                RepinOf::<T>::remove(cmd.id);
+                IngestionAttemptsOf::<T>::remove(cmd.id);
+                removed += 1;
+            }
+        }
+        if end >= len {
+            CompactionCursor::<T>::remove(gateway);
+        } else {
+            CompactionCursor::<T>::insert(gateway, (end as u32).saturating_sub(removed));
+        }
+        if removed > 0 {
+            IngestionCommands::<T>::insert(gateway, kept);
+        }
+        removed
+    }
+
+    /// Remove a command from the queue of the gateway rejecting it, refunding the gateway's
+    /// share of the reserve and releasing the request once none of its replicas remain queued
+    /// 
+    /// * `who`: The gateway rejecting the command
+    /// * `cmd`: The command queued with the gateway
+    /// * `reason`: An optional code explaining why the request was rejected
+    /// 
+    fn do_reject_command(who: T::AccountId, cmd: IngestionCommand<T::AccountId, T::Balance>, reason: Option<u8>) {
+        IngestionCommands::<T>::mutate(who.clone(), |cmds| cmds.retain(|c| c.id != cmd.id));
+        IngestionAttemptsOf::<T>::remove(cmd.id);
+        Self::release_claim(cmd.id, &who);
+        // no reserve is vested with the gateways re-pinning an asset
+        let repin = RepinOf::<T>::take(cmd.id).is_some();
+        if let Some(request_id) = Self::unindex_replica(cmd.id) {
+            if !repin {
+                Self::refund_share(request_id, &who);
+            }
+            Self::release_if_unqueued(request_id);
+        }
+        Self::deposit_event(Event::RequestRejected(who, cmd.owner, cmd.cid, reason));
+    }
+
+    /// Claim the request of a command queued with a gateway, or renew the gateway's claim
+    /// 
+    /// * `who`: The gateway claiming the request
+    /// * `cmd_id`: The id of the command queued with the gateway
+    /// 
+    fn do_claim_command(who: T::AccountId, cmd_id: [u8; 32]) -> DispatchResult {
+        ensure!(
+            IngestionCommands::<T>::get(who.clone()).iter().any(|c| c.id == cmd_id),
+            Error::<T>::NoSuchIngestionCommand
+        );
+        let request_id = ReplicaOf::<T>::get(cmd_id).ok_or(Error::<T>::NoSuchIngestionCommand)?;
+        let now: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        if let Some(claimer) = Self::expire_claim(request_id, now) {
+            ensure!(claimer == who, Error::<T>::AlreadyClaimed);
+        }
+        let expires_at = now.saturating_add(T::ClaimDuration::get());
+        CommandClaims::<T>::insert(request_id, (who.clone(), expires_at));
+        Self::deposit_event(Event::CommandClaimed(request_id, who, expires_at));
+        Ok(())
+    }
+
+    /// Remove a request's claim if it has expired, emitting `ClaimExpired`.
+    /// Returns the gateway holding the claim if it is still live.
+    /// 
+    /// * `request_id`: The claimed request
+    /// * `now`: The current block
+    /// 
+    fn expire_claim(request_id: [u8; 32], now: u32) -> Option<T::AccountId> {
+        let (claimer, expires_at) = CommandClaims::<T>::get(request_id)?;
+        if now < expires_at {
+            return Some(claimer);
+        }
+        CommandClaims::<T>::remove(request_id);
+        Self::deposit_event(Event::ClaimExpired(request_id, claimer));
+        None
+    }
+
+    /// Release the claim on a command's request if the gateway holds it, letting the other
+    /// gateways the request is replicated to fetch its data. Must be called before the
+    /// command is removed from `ReplicaOf`.
+    /// 
+    /// * `cmd_id`: The command the gateway is done with
+    /// * `gateway`: The gateway the command is queued with
+    /// 
+    fn release_claim(cmd_id: [u8; 32], gateway: &T::AccountId) {
+        if let Some(request_id) = ReplicaOf::<T>::get(cmd_id) {
+            if CommandClaims::<T>::get(request_id).map_or(false, |(claimer, _)| claimer == *gateway) {
+                CommandClaims::<T>::remove(request_id);
+            }
+        }
+    }
+
+    /// Get a page of the asset ids of the asset classes created between two blocks
+    /// (inclusive), in creation order. At most `MAX_ASSET_INDEX_SPAN` blocks are searched
+    /// from `from_block`, and pages hold at most `MAX_ASSET_INDEX_PAGE_SIZE` asset ids.
+    /// 
+    /// * `from_block`: The first block of the range
+    /// * `to_block`: The last block of the range
+    /// * `page`: The index of the page to fetch, starting from 0
+    /// * `page_size`: The maximum number of asset ids in a page
+    /// 
+    pub fn assets_created_between(
+        from_block: u32,
+        to_block: u32,
+        page: u32,
+        page_size: u32,
+    ) -> Vec<T::AssetId> {
+        let to_block = to_block.min(from_block.saturating_add(MAX_ASSET_INDEX_SPAN));
+        let page_size = page_size.min(MAX_ASSET_INDEX_PAGE_SIZE);
+        (from_block / ASSET_INDEX_BUCKET_BLOCKS..=to_block / ASSET_INDEX_BUCKET_BLOCKS)
+            .flat_map(AssetsByBlock::<T>::get)
+            .filter(|(block, _)| *block >= from_block && *block <= to_block)
+            .map(|(_, asset_id)| asset_id)
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect()
+    }
+
+    /// The reserves set aside for a gateway by the commands queued with it,
+    /// i.e. the revenue it can expect once it completes them. A replacement replica
+    /// carries the reserve share of the request it replicates.
+    /// 
+    /// * `gateway`: The gateway
+    /// 
+    pub fn pending_reserves(gateway: T::AccountId) -> PendingReserves<BalanceOf<T>> {
+        let commands: Vec<([u8; 32], BalanceOf<T>)> = IngestionCommands::<T>::get(gateway)
+            .into_iter()
+            .map(|cmd| {
+                let reserve = ReplicaOf::<T>::get(cmd.id)
+                    .map(RequestReserve::<T>::get)
+                    .unwrap_or_else(Zero::zero);
+                (cmd.id, reserve)
+            })
+            .collect();
+        let total = commands.iter()
+            .fold(Zero::zero(), |total: BalanceOf<T>, (_, reserve)| total.saturating_add(*reserve));
+        PendingReserves { total, commands }
+    }
+
+    /// The gateways assigned an owner's ingestion requests for some data, along with whether
+    /// each has completed ingestion. Gateways whose command is still queued haven't completed it,
+    /// while those that pinned the data have. Empty if the owner has no such request.
+    /// 
+    /// * `owner`: The owner of the requests
+    /// * `cid`: The cid of the data to be ingested
+    /// 
+    pub fn command_assignments(owner: T::AccountId, cid: Vec<u8>) -> Vec<(T::AccountId, bool)> {
+        let request_ids = RequestsByCid::<T>::get(&owner, &cid);
+        if request_ids.is_empty() {
+            return Vec::new();
+        }
+        let mut assignments: Vec<(T::AccountId, bool)> = request_ids.iter()
+            .filter_map(|request_id| Replication::<T>::get(request_id))
+            .flat_map(|status| status.pinned_by.into_iter())
+            .map(|gateway| (gateway, true))
+            .collect();
+        for (gateway, _) in request_ids.iter().flat_map(|request_id| RequestReplicas::<T>::get(request_id)) {
+            if !assignments.contains(&(gateway.clone(), false)) {
+                assignments.push((gateway, false));
+            }
+        }
+        assignments
+    }
+
+    /// The umbral public key staged by an owner, checked to be well formed
+    /// 
+    /// * `owner`: The owner of the staged data
+    /// 
+    pub fn staged_public_key(owner: T::AccountId) -> Result<[u8; UMBRAL_PUBLIC_KEY_SIZE], Error<T>> {
+        let public_key = IngestionStaging::<T>::get(owner).ok_or(Error::<T>::NoStagedData)?;
+        public_key.try_into().map_err(|_| Error::<T>::InvalidPublicKey)
+    }
+
+    /// Set the pallet_assets name and symbol of an asset class so that wallets can label it.
+    /// The gateway that created the asset class is its pallet_assets owner, so the metadata
+    /// is force set rather than taking a deposit from the gateway. A failure is logged
+    /// and otherwise ignored. Does nothing unless `SetAssetMetadata` is set.
+    /// 
+    /// * `asset_id`: The asset class
+    /// * `name`: The name to set, truncated to the pallet_assets string limit
+    /// 
+    fn set_assets_metadata(asset_id: T::AssetId, name: &[u8]) {
+        if !T::SetAssetMetadata::get() {
+            return;
+        }
+        let limit = <T as pallet_assets::Config>::StringLimit::get() as usize;
+        let name = name[..name.len().min(limit)].to_vec();
+        let symbol = ASSET_SYMBOL[..ASSET_SYMBOL.len().min(limit)].to_vec();
+        if let Err(e) = <pallet_assets::Pallet<T>>::force_set_metadata(
+            system::RawOrigin::Root.into(), asset_id, name, symbol, 0, false,
+        ) {
+            log::warn!("Failed to set the metadata of asset class {:?}: {:?}", asset_id, e);
+        }
+    }
+
+    /// Release a request that was never ingested once none of its replicas remain queued
+    /// 
+    /// * `request_id`: The id of the request
+    /// 
+    fn release_if_unqueued(request_id: [u8; 32]) {
+        let ingested = Replication::<T>::get(request_id)
+            .map(|status| status.asset_id.is_some())
+            .unwrap_or(false);
+        if !ingested && !RequestReplicas::<T>::contains_key(request_id) {
+            Self::release_request(request_id);
+        }
+    }
+
+    /// Move a command to the first gateway (ordered by account id) that neither pins nor
+    /// has queued a replica of its request, keeping the command's id
+    /// 
+    /// * `cmd`: The command to move
+    /// * `request_id`: The request the command is a replica of
+    /// * `from`: The gateway the command was queued with
+    /// 
+    /// Returns the new gateway, or None if no gateway can take the command.
+    /// 
+    fn reroute_command(
+        cmd: IngestionCommand<T::AccountId, T::Balance>,
+        request_id: [u8; 32],
+        from: &T::AccountId,
+    ) -> Option<T::AccountId> {
+        let mut pinned_by = Replication::<T>::get(request_id)
+            .map(|status| status.pinned_by)
+            .unwrap_or_default();
+        // gateways that confirmed a request awaiting its quorum pin the data as well
+        pinned_by.extend(IngestionConfirmations::<T>::get(request_id));
+        let replicas = RequestReplicas::<T>::get(request_id);
+        let mut candidates: Vec<T::AccountId> = T::GatewayProvider::gateways()
+            .into_iter()
+            .filter(|g| g != from && !pinned_by.contains(g))
+            .filter(|g| !replicas.iter().any(|(queued_with, _)| queued_with == g))
+            .collect();
+        candidates.sort();
+        let gateway = candidates.into_iter().next()?;
+        let cmd_id = cmd.id;
+        IngestionCommands::<T>::mutate(gateway.clone(), |commands| {
+            commands.push(IngestionCommand {
+                queued_at: <frame_system::Pallet<T>>::block_number().unique_saturated_into(),
+                ..cmd
+            });
+        });
+        RequestReplicas::<T>::insert(request_id, replicas.into_iter()
+            .map(|(queued_with, id)| if id == cmd_id { (gateway.clone(), id) } else { (queued_with, id) })
+            .collect::<Vec<_>>());
+        Some(gateway)
+    }
+
+    /// Remove an ingestion request that will never be ingested, releasing its reserve
+    /// and the asset id it asked for
+    /// 
+    /// * `request_id`: The id of the request
+    /// 
+    fn release_request(request_id: [u8; 32]) {
+        let ingested = Replication::<T>::take(request_id).map_or(true, |status| status.asset_id.is_some());
+        if let Some(cmd) = RequestCommand::<T>::take(request_id) {
+            if !ingested {
+                Self::release_pending_request(&cmd.owner);
+            }
+            let mut request_ids = RequestsByCid::<T>::get(&cmd.owner, &cmd.cid);
+            request_ids.retain(|id| *id != request_id);
+            if request_ids.is_empty() {
+                RequestsByCid::<T>::remove(&cmd.owner, &cmd.cid);
+            } else {
+                RequestsByCid::<T>::insert(&cmd.owner, &cmd.cid, request_ids);
+            }
+        }
+        CommandClaims::<T>::remove(request_id);
+        RequestReserve::<T>::remove(request_id);
+        ReserveVestsAt::<T>::remove(request_id);
+        RequestReplicas::<T>::remove(request_id);
+        DatasetFiles::<T>::remove(request_id);
+        ConfirmationQuorum::<T>::remove(request_id);
+        IngestionConfirmations::<T>::remove(request_id);
+        if let Some(asset_id) = RequestedAssetId::<T>::take(request_id) {
+            ReservedAssetIds::<T>::remove(asset_id);
+        }
+    }
+
+    /// Take a gateway's share of a request's reserve back before it vests, removing the
+    /// vesting schedule that locks it
+    /// 
+    /// * `request_id`: The id of the request
+    /// * `gateway`: The gateway the share was vested with
+    /// 
+    /// Returns the amount taken back, or None if the share has vested or was never vested with the gateway.
+    /// 
+    fn take_share(request_id: [u8; 32], gateway: &T::AccountId) -> Option<BalanceOf<T>> {
+        let starting_block = ReserveVestsAt::<T>::get(request_id)?;
+        let share = RequestReserve::<T>::get(request_id);
+        let schedules = pallet_vesting::Vesting::<T>::get(gateway)?;
+        let index = schedules.iter().position(|schedule| {
+            schedule.locked() == share
+                && schedule.per_block() == share
+                && schedule.starting_block() == starting_block
+        })?;
+        let locked = schedules[index].locked_at::<<T as pallet_vesting::Config>::BlockNumberToBalance>(
+            <frame_system::Pallet<T>>::block_number()
+        );
+        if locked.is_zero() {
+            return None;
+        }
+        <pallet_vesting::Pallet<T> as VestingSchedule<T::AccountId>>::remove_vesting_schedule(gateway, index as u32)
+            .map_err(|e| log::warn!("Failed to remove the vesting schedule of request {:?}: {:?}", request_id, e))
+            .ok()?;
+        Some(locked)
+    }
+
+    /// Return a gateway's share of a request's reserve to the request's owner, unless it has vested
+    /// 
+    /// * `request_id`: The id of the request
+    /// * `gateway`: The gateway the share was vested with
+    /// 
+    fn refund_share(request_id: [u8; 32], gateway: &T::AccountId) {
+        let owner = match RequestCommand::<T>::get(request_id) {
+            Some(cmd) => cmd.owner,
+            None => return,
+        };
+        if let Some(amount) = Self::take_share(request_id, gateway) {
+            match <T as pallet_vesting::Config>::Currency::transfer(
+                gateway, &owner, amount, ExistenceRequirement::AllowDeath,
+            ) {
+                Ok(()) => Self::deposit_event(Event::ReserveRefunded(request_id, gateway.clone(), amount)),
+                Err(e) => log::warn!("Failed to refund the reserve of request {:?}: {:?}", request_id, e),
+            }
+        }
+    }
+
+    /// Count one fewer of an owner's requests as waiting to be ingested
+    /// 
+    /// * `owner`: The owner of the request
+    /// 
+    fn release_pending_request(owner: &T::AccountId) {
+        let pending = PendingRequests::<T>::get(owner).saturating_sub(1);
+        if pending == 0 {
+            PendingRequests::<T>::remove(owner);
+        } else {
+            PendingRequests::<T>::insert(owner, pending);
+        }
+    }
+
+    /// Record a replica of a request queued with a gateway
+    /// 
+    /// * `request_id`: The request the command is a replica of
+    /// * `gateway`: The gateway the command is queued with
+    /// * `cmd_id`: The id of the command
+    /// 
+    fn index_replica(request_id: [u8; 32], gateway: T::AccountId, cmd_id: [u8; 32]) {
+        ReplicaOf::<T>::insert(cmd_id, request_id);
+        RequestReplicas::<T>::append(request_id, (gateway, cmd_id));
+    }
+
+    /// Forget a replica that is no longer queued. Returns the request it was a replica of.
+    /// 
+    /// * `cmd_id`: The id of the command
+    /// 
+    fn unindex_replica(cmd_id: [u8; 32]) -> Option<[u8; 32]> {
+        let request_id = ReplicaOf::<T>::take(cmd_id)?;
+        let mut replicas = RequestReplicas::<T>::get(request_id);
+        replicas.retain(|(_, id)| *id != cmd_id);
+        if replicas.is_empty() {
+            RequestReplicas::<T>::remove(request_id);
+        } else {
+            RequestReplicas::<T>::insert(request_id, replicas);
+        }
+        Some(request_id)
+    }
+
+    /// Remove the data staged by an owner, releasing its public key
+    /// 
+    /// * `owner`: The owner of the staged data
+    /// 
+    fn clear_ingestion_staging(owner: &T::AccountId) {
+        if let Some(public_key) = IngestionStaging::<T>::take(owner) {
+            StagedKeyOwner::<T>::remove(public_key);
+        }
+    }
+
+    /// Remove an asset from the gateway's manifest of ingested assets,
+    /// to be called when the asset is ejected
+    /// 
+    /// * `gateway`: The gateway that ingested the asset
+    /// * `asset_id`: The id of the ejected asset
+    /// 
+    pub fn remove_ingested_asset(gateway: T::AccountId, asset_id: T::AssetId) {
+        IngestedByGateway::<T>::mutate(gateway.clone(), |ids| ids.retain(|id| *id != asset_id));
+        // an ejected replica is no longer pinned
+        if PinStatus::<T>::get(&gateway, asset_id) != Some(true) {
+            return;
+        }
+        PinStatus::<T>::insert(&gateway, asset_id, false);
+        Self::update_provider(asset_id, &gateway, false);
+        let request_id = match AssetRequest::<T>::get(asset_id) {
+            Some(request_id) => request_id,
+            None => return,
+        };
+        if let Some(status) = Replication::<T>::get(request_id) {
+            Self::drop_replica(asset_id, request_id, status, &gateway);
+        }
+    }
+
+    /// Remove a gateway from the replicas of an asset, queue a replacement replica and
+    /// freeze the asset if no replicas remain
+    /// 
+    /// * `asset_id`: The asset dropped by the gateway
+    /// * `request_id`: The request the asset was ingested for
+    /// * `status`: The replication status of the request
+    /// * `gateway`: The gateway that dropped its replica
+    /// 
+    fn drop_replica(
+        asset_id: T::AssetId,
+        request_id: [u8; 32],
+        mut status: ReplicationStatus<T::AccountId, T::AssetId>,
+        gateway: &T::AccountId,
+    ) {
+        status.pinned_by.retain(|g| g != gateway);
+        Replication::<T>::insert(request_id, status.clone());
+        Self::deposit_event(Event::ReplicaDropped(asset_id, gateway.clone()));
+        Self::record_asset_event(asset_id, AssetLifecycleEvent::Ejected, Some(gateway.clone()));
+        Self::queue_repin(asset_id, request_id, &status, Some(gateway));
+        Self::update_availability(asset_id);
+    }
+
+    /// Whether an asset class was created by Iris, without decoding its metadata
+    /// 
+    /// * `asset_id`: The asset id
+    /// 
+    pub fn asset_exists(asset_id: T::AssetId) -> bool {
+        Metadata::<T>::contains_key(asset_id)
+    }
+
+    /// The number of gateways currently pinning an asset
+    /// 
+    /// * `asset_id`: The asset id
+    /// 
+    pub fn replica_count(asset_id: T::AssetId) -> u32 {
+        AssetRequest::<T>::get(asset_id)
+            .and_then(Replication::<T>::get)
+            .map(|status| status.pinned_by.len() as u32)
+            .unwrap_or(0)
+    }
+
+    /// Append an event to an asset's lifecycle history, dropping the oldest events
+    /// beyond `MaxAssetHistory`
+    /// 
+    /// * `asset_id`: The asset
+    /// * `event`: The transition
+    /// * `account`: The gateway or consumer involved, if any
+    /// 
+    pub fn record_asset_event(
+        asset_id: T::AssetId,
+        event: AssetLifecycleEvent,
+        account: Option<T::AccountId>,
+    ) {
+        let block: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        let max = T::MaxAssetHistory::get() as usize;
+        AssetHistory::<T>::mutate(asset_id, |history| {
+            history.push((block, event, account));
+            let excess = history.len().saturating_sub(max);
+            history.drain(..excess);
+        });
+    }
+
+    /// Freeze an asset once no gateway pins it, and thaw it once a replica is restored.
+    /// Does nothing unless `FreezeUnavailableAssets` is set.
+    /// 
+    /// * `asset_id`: The asset whose replicas changed
+    /// 
+    fn update_availability(asset_id: T::AssetId) {
+        if !T::FreezeUnavailableAssets::get() {
+            return;
+        }
+        let details = match <pallet_assets::Pallet<T>>::asset(asset_id) {
+            Some(details) => details,
+            None => return,
+        };
+        let frozen = FrozenUnavailable::<T>::get(asset_id);
+        let replicas = Self::replica_count(asset_id);
+        if replicas == 0 && !frozen {
+            // pallet_assets only lets the freezer freeze the asset
+            let origin = system::RawOrigin::Signed(details.freezer.clone()).into();
+            match <pallet_assets::Pallet<T>>::freeze_asset(origin, asset_id) {
+                Ok(_) => {
+                    FrozenUnavailable::<T>::insert(asset_id, true);
+                    Self::deposit_event(Event::AssetFrozen(asset_id));
+                    Self::record_asset_event(asset_id, AssetLifecycleEvent::Frozen, None);
+                }
+                Err(e) => log::warn!("Failed to freeze unavailable asset due to error: {:?}", e),
+            }
+        } else if replicas > 0 && frozen {
+            // and only the admin thaw it
+            let origin = system::RawOrigin::Signed(details.admin.clone()).into();
+            match <pallet_assets::Pallet<T>>::thaw_asset(origin, asset_id) {
+                Ok(_) => {
+                    FrozenUnavailable::<T>::remove(asset_id);
+                    Self::deposit_event(Event::AssetThawed(asset_id));
+                    Self::record_asset_event(asset_id, AssetLifecycleEvent::Thawed, None);
+                }
+                Err(e) => log::warn!("Failed to thaw restored asset due to error: {:?}", e),
+            }
+        }
+    }
+
+    /// Add (or remove) a gateway's ipfs peer id to the providers of an asset
+    /// 
+    /// * `asset_id`: The asset pinned (or dropped) by the gateway
+    /// * `gateway`: The gateway
+    /// * `pinned`: true if the gateway pins the asset
+    /// 
+    fn update_provider(asset_id: T::AssetId, gateway: &T::AccountId, pinned: bool) {
+        let peer_id = match T::PeerIdProvider::peer_id(gateway) {
+            Some(peer_id) => peer_id,
+            None => return,
+        };
+        Metadata::<T>::mutate(asset_id, |metadata| {
+            if let Some(metadata) = metadata {
+                metadata.providers.retain(|p| *p != peer_id);
+                if pinned {
+                    metadata.providers.push(peer_id);
+                }
+            }
+        });
+    }
+
+    /// Check if an asset has been pinned by as many distinct gateways as its publisher requested
+    /// 
+    /// * `asset_id`: The asset id
+    /// 
+    pub fn is_durable(asset_id: T::AssetId) -> bool {
+        AssetRequest::<T>::get(asset_id)
+            .and_then(Replication::<T>::get)
+            .map(|status| status.pinned_by.len() as u32 >= status.replication)
+            .unwrap_or(false)
+    }
+
+    /// Queue a replacement replica of an asset with a gateway that neither pins it
+    /// nor has a replica of it queued, if there is one and the asset isn't durable.
+    /// Returns the id of the queued command.
+    /// 
+    /// * `asset_id`: The asset to re-pin
+    /// * `request_id`: The ingestion request that created the asset
+    /// * `status`: The replication status of the request
+    /// * `dropped_by`: The gateway that dropped its replica, if any, which is not asked to re-pin it
+    /// 
+    fn queue_repin(
+        asset_id: T::AssetId,
+        request_id: [u8; 32],
+        status: &ReplicationStatus<T::AccountId, T::AssetId>,
+        dropped_by: Option<&T::AccountId>,
+    ) -> Option<[u8; 32]> {
+        if status.pinned_by.len() as u32 >= status.replication {
+            return None;
+        }
+        let template = RequestCommand::<T>::get(request_id)?;
+        let replicas = RequestReplicas::<T>::get(request_id);
+        let mut candidates: Vec<T::AccountId> = T::GatewayProvider::gateways()
+            .into_iter()
+            .filter(|g| Some(g) != dropped_by && !status.pinned_by.contains(g))
+            .filter(|g| !replicas.iter().any(|(queued_with, _)| queued_with == g))
+            .collect();
+        candidates.sort();
+        let gateway = candidates.into_iter().next()?;
+        let nonce = IngestionCommandNonce::<T>::get();
+        IngestionCommandNonce::<T>::put(nonce.wrapping_add(1));
+        let id = sp_io::hashing::blake2_256(&(request_id, nonce).encode());
+        Self::index_replica(request_id, gateway.clone(), id);
+        IngestionCommands::<T>::mutate(gateway.clone(), |commands| {
+            commands.push(IngestionCommand {
+                id,
+                queued_at: <frame_system::Pallet<T>>::block_number().unique_saturated_into(),
+                ..template
+            });
+        });
+        Self::deposit_event(Event::RepinQueued(asset_id, gateway));
+        Some(id)
+    }
+
+    /// Select the gateways that should pin the data: the requested gateway followed
+    /// by `replication - 1` other registered gateways, ordered by account id
+    /// 
+    /// * `primary`: The gateway chosen by the publisher
+    /// * `replication`: The number of gateways to select
+    /// 
+    fn select_gateways(
+        primary: T::AccountId,
+        replication: u32,
+    ) -> Result<Vec<T::AccountId>, Error<T>> {
+        let mut others: Vec<T::AccountId> = T::GatewayProvider::gateways()
+            .into_iter()
+            .filter(|g| *g != primary)
+            .collect();
+        ensure!(
+            others.len() as u32 >= replication.saturating_sub(1),
+            Error::<T>::InsufficientGateways
+        );
+        others.sort();
+        let mut gateways = Vec::new();
+        gateways.push(primary);
+        gateways.extend(others.into_iter().take(replication.saturating_sub(1) as usize));
+        Ok(gateways)
+    }
+
+    /// Add a completed command's latency to the gateway's running average
+    /// 
+    /// * `gateway`: The gateway that completed the command
+    /// * `latency`: The number of blocks between the command being queued and completed
+    /// 
+    fn record_latency(gateway: T::AccountId, latency: u32) {
+        IngestionStats::<T>::mutate(gateway, |stats| {
+            let completed = stats.completed as u64;
+            let total = (stats.average_latency as u64) * completed + latency as u64;
+            stats.completed = stats.completed.saturating_add(1);
+            stats.average_latency = (total / (completed + 1)) as u32;
+        });
+    }
+}
+
+/// 
+pub trait MetadataProvider<AssetId> {
+    fn get(asset_id: AssetId) -> Option<AssetMetadata>;
+    /// the number of gateways currently pinning the asset
+    fn replica_count(asset_id: AssetId) -> u32;
+    /// whether the asset class is frozen
+    fn is_frozen(asset_id: AssetId) -> bool;
+}
+
+impl<T: Config> MetadataProvider<T::AssetId> for Pallet<T> {
+    fn get(asset_id: T::AssetId) -> Option<AssetMetadata> {
+        Metadata::<T>::get(asset_id)
+    }
+
+    fn replica_count(asset_id: T::AssetId) -> u32 {
+        Self::replica_count(asset_id)
+    }
+
+    fn is_frozen(asset_id: T::AssetId) -> bool {
+        <pallet_assets::Pallet<T>>::asset(asset_id)
+            .map(|details| details.is_frozen())
+            .unwrap_or(false)
+    }
+}
+
+// Implementation of Convert trait for mapping ValidatorId with AccountId.
+pub struct ValidatorOf<T>(sp_std::marker::PhantomData<T>);
 
 impl<T: Config> Convert<T::ValidatorId, Option<T::ValidatorId>> for ValidatorOf<T> {
 	fn convert(account: T::ValidatorId) -> Option<T::ValidatorId> {
@@ -342,19 +2070,211 @@ impl<T: Config> Convert<T::ValidatorId, Option<T::ValidatorId>> for ValidatorOf<
 /// a trait to provide the ingestion queue to other modules
 pub trait QueueManager<AccountId, Balance> {
 
-    fn add_ingestion_staging(owner: AccountId, public_key: Vec<u8>);
+    /// stage the umbral public key of data the owner encrypted, rejecting malformed keys
+    fn add_ingestion_staging(owner: AccountId, public_key: Vec<u8>) -> DispatchResult;
     fn ingestion_requests(gateway: AccountId) -> Vec<IngestionCommand<AccountId, Balance>>;
+    /// the block before which a command should not be attempted again (0 if it never failed)
+    fn next_attempt(cmd_id: [u8; 32]) -> u32;
+    /// true if ingestion and reencryption are halted
+    fn is_halted() -> bool;
+    /// the files a command must fetch besides its own cid: (cid, multiaddress)
+    fn dataset_files(cmd_id: [u8; 32]) -> Vec<(Vec<u8>, Vec<u8>)>;
+    /// the number of gateways the request of a command is replicated to (0 if it has no request)
+    fn replication(cmd_id: [u8; 32]) -> u32;
+    /// the gateway claiming the request of a command and the block at which its claim expires
+    fn claim(cmd_id: [u8; 32]) -> Option<(AccountId, u32)>;
+    /// queue an ingestion request on the owner's behalf, as `create_request` does
+    fn create_request(
+        owner: AccountId,
+        gateway: AccountId,
+        gateway_reserve: Balance,
+        cid: Vec<u8>,
+        multiaddress: Vec<u8>,
+        estimated_size: u128,
+        min_asset_balance: Balance,
+        replication: u32,
+    ) -> DispatchResult;
+    /// handle the commands queued with a gateway that deregistered, as the policy decides
+    fn release_gateway_commands(gateway: AccountId, policy: OrphanedCommandPolicy);
+    /// eject the replicas pinned by a gateway that deregistered
+    fn release_gateway_assets(gateway: AccountId);
 }
 
 impl<T: Config> QueueManager<T::AccountId, T::Balance> for Pallet<T> {
 
-    fn add_ingestion_staging(owner: T::AccountId, public_key: Vec<u8>) {
+    fn add_ingestion_staging(owner: T::AccountId, public_key: Vec<u8>) -> DispatchResult {
+        ensure!(public_key.len() == UMBRAL_PUBLIC_KEY_SIZE, Error::<T>::InvalidPublicKey);
+        if let Some(staged_by) = StagedKeyOwner::<T>::get(&public_key) {
+            ensure!(staged_by == owner, Error::<T>::KeyAlreadyStaged);
+        }
+        // staging new data replaces the owner's previously staged key
+        Self::clear_ingestion_staging(&owner);
+        StagedKeyOwner::<T>::insert(public_key.clone(), owner.clone());
         IngestionStaging::<T>::insert(owner, public_key);
+        Ok(())
     }
 
     fn ingestion_requests(gateway: T::AccountId) -> Vec<IngestionCommand<T::AccountId, T::Balance>> {
         IngestionCommands::<T>::get(gateway)
     }
+
+    fn next_attempt(cmd_id: [u8; 32]) -> u32 {
+        IngestionAttemptsOf::<T>::get(cmd_id).next_attempt
+    }
+
+    fn is_halted() -> bool {
+        Halted::<T>::get()
+    }
+
+    fn dataset_files(cmd_id: [u8; 32]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        ReplicaOf::<T>::get(cmd_id)
+            .map(DatasetFiles::<T>::get)
+            .unwrap_or_default()
+    }
+
+    fn replication(cmd_id: [u8; 32]) -> u32 {
+        ReplicaOf::<T>::get(cmd_id)
+            .and_then(Replication::<T>::get)
+            .map_or(0, |status| status.replication)
+    }
+
+    fn claim(cmd_id: [u8; 32]) -> Option<(T::AccountId, u32)> {
+        ReplicaOf::<T>::get(cmd_id).and_then(CommandClaims::<T>::get)
+    }
+
+    fn create_request(
+        owner: T::AccountId,
+        gateway: T::AccountId,
+        gateway_reserve: T::Balance,
+        cid: Vec<u8>,
+        multiaddress: Vec<u8>,
+        estimated_size: u128,
+        min_asset_balance: T::Balance,
+        replication: u32,
+    ) -> DispatchResult {
+        // the reserve is paid in the native currency, whose balance type may differ from the assets'
+        let gateway_reserve: u128 = gateway_reserve.unique_saturated_into();
+        Self::do_create_request(
+            owner, T::Lookup::unlookup(gateway), gateway_reserve.unique_saturated_into(), cid, multiaddress, Vec::new(),
+            estimated_size, min_asset_balance, replication, 1, None,
+        )
+    }
+
+    /// Empty the queue of a gateway that deregistered. Under `Reroute` each command moves to
+    /// another gateway, and under `Refund` (or when no gateway can take it) it is dropped, the
+    /// gateway's unvested share of the reserve is returned to the owner, and its request is
+    /// released once none of its replicas remain queued.
+    /// 
+    /// * `gateway`: The gateway that deregistered
+    /// * `policy`: What happens to the gateway's commands
+    /// 
+    fn release_gateway_commands(gateway: T::AccountId, policy: OrphanedCommandPolicy) {
+        for cmd in IngestionCommands::<T>::take(&gateway) {
+            let cmd_id = cmd.id;
+            let owner = cmd.owner.clone();
+            IngestionAttemptsOf::<T>::remove(cmd_id);
+            Self::release_claim(cmd_id, &gateway);
+            let request_id = ReplicaOf::<T>::get(cmd_id);
+            let rerouted_to = match (policy, request_id) {
+                (OrphanedCommandPolicy::Reroute, Some(request_id)) =>
+                    Self::reroute_command(cmd, request_id, &gateway),
+                _ => None,
+            };
+            match rerouted_to {
+                Some(new_gateway) => {
+                    Self::deposit_event(Event::CommandRerouted(cmd_id, gateway.clone(), new_gateway));
+                },
+                None => {
+                    Self::unindex_replica(cmd_id);
+                    let repin = RepinOf::<T>::take(cmd_id).is_some();
+                    if let Some(request_id) = request_id {
+                        if !repin {
+                            Self::refund_share(request_id, &gateway);
+                        }
+                        Self::release_if_unqueued(request_id);
+                    }
+                    Self::deposit_event(Event::CommandRefunded(cmd_id, owner));
+                },
+            }
+        }
+    }
+
+    /// Eject every asset a gateway that deregistered ingested, as if it reported dropping
+    /// its pins: each is removed from the gateway's manifest and a replacement replica is
+    /// queued with another gateway if possible.
+    /// 
+    /// * `gateway`: The gateway that deregistered
+    /// 
+    fn release_gateway_assets(gateway: T::AccountId) {
+        for asset_id in IngestedByGateway::<T>::get(&gateway) {
+            Self::remove_ingested_asset(gateway.clone(), asset_id);
+        }
+    }
+}
+
+/// Records transitions in the lifecycle of assets that happen outside of this pallet
+pub trait AssetHistoryRecorder<AccountId, AssetId> {
+    /// append an event to the asset's lifecycle history
+    fn record(asset_id: AssetId, event: AssetLifecycleEvent, account: Option<AccountId>);
+}
+
+impl<AccountId, AssetId> AssetHistoryRecorder<AccountId, AssetId> for () {
+    fn record(_asset_id: AssetId, _event: AssetLifecycleEvent, _account: Option<AccountId>) {}
+}
+
+impl<T: Config> AssetHistoryRecorder<T::AccountId, T::AssetId> for Pallet<T> {
+    fn record(asset_id: T::AssetId, event: AssetLifecycleEvent, account: Option<T::AccountId>) {
+        Self::record_asset_event(asset_id, event, account)
+    }
+}
+
+/// Provides the set of registered gateways
+pub trait GatewayProvider<AccountId> {
+    /// the accounts of all registered gateways
+    fn gateways() -> Vec<AccountId>;
+}
+
+impl<AccountId> GatewayProvider<AccountId> for () {
+    fn gateways() -> Vec<AccountId> {
+        Vec::new()
+    }
+}
+
+/// Read-only access to the preferences gateways declare when they register
+pub trait ProxyPrefsReader<AccountId> {
+    /// the preferences declared by a gateway, or None if it is not a registered gateway
+    fn prefs(gateway: &AccountId) -> Option<GatewayPrefs>;
+}
+
+/// Places no restrictions on gateways: every account is a gateway with the default preferences
+impl<AccountId> ProxyPrefsReader<AccountId> for () {
+    fn prefs(_gateway: &AccountId) -> Option<GatewayPrefs> {
+        Some(GatewayPrefs::default())
+    }
+}
+
+/// Provides the ipfs peer ids of gateways
+pub trait PeerIdProvider<AccountId> {
+    /// the peer id of the ipfs node run by a gateway, if it has reported one
+    fn peer_id(gateway: &AccountId) -> Option<Vec<u8>>;
+}
+
+impl<AccountId> PeerIdProvider<AccountId> for () {
+    fn peer_id(_gateway: &AccountId) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Provides the price that gateways charge to ingest data
+pub trait GatewayPricing<AccountId, Balance> {
+    /// the minimum reserve the gateway accepts for an ingestion request of `size` bytes
+    fn reserve_for(gateway: AccountId, size: u128) -> Balance;
+}
+
+impl<AccountId, Balance: Zero> GatewayPricing<AccountId, Balance> for () {
+    fn reserve_for(_gateway: AccountId, _size: u128) -> Balance {
+        Zero::zero()
+    }
 }
 
 /// The result handler allows other modules to submit "execution"
@@ -364,10 +2284,31 @@ impl<T: Config> QueueManager<T::AccountId, T::Balance> for Pallet<T> {
 /// basically I'm implementing a parallel consensus mechanism to determine who gets to proxy requests
 pub trait ResultsHandler<T: frame_system::Config, AccountId, AssetId, Balance> {
 
+    /// returns the ingestion latency and whether a new asset class was created
     fn create_asset_class(
         origin: OriginFor<T>,
         cmd: IngestionCommand<AccountId, Balance>,
         asset_id: AssetId,
+        ciphertext_size: u128,
+    ) -> Result<IngestionOutcome, DispatchError>;
+
+    /// record a failed attempt to ingest a command, returns true if the command was abandoned
+    fn record_failed_attempt(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+    ) -> Result<bool, DispatchError>;
+
+    /// claim the request of a command queued with the caller before fetching its data
+    fn claim_command(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+    ) -> DispatchResult;
+
+    /// decline to service a command queued with the caller, removing it from the queue
+    fn reject_command(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+        reason: Option<u8>,
     ) -> DispatchResult;
 }
 
@@ -375,34 +2316,238 @@ impl<T: Config> ResultsHandler<T, T::AccountId, T::AssetId, T::Balance> for Pall
 
     /// Create a new data asset class
     /// 
-    /// * `cmd`: The ingestion command
-    /// * `asset_id`: The id to assign to the new asset class
+    /// * `cmd`: The ingestion command. Only its id is used, the command itself is read from
+    ///          the caller's queue
+    /// * `asset_id`: The id to assign to the new asset class, unless the request reserved one
+    /// * `ciphertext_size`: The size in bytes of the data fetched by the gateway
+    /// 
+    /// The first gateway to complete a request creates the asset class, gateways completing
+    /// other replicas of the request only record that they have pinned the data. When the
+    /// request asked for a confirmation quorum, gateways completing it before the quorum is
+    /// reached only confirm it, and the gateway reaching the quorum creates the asset class
+    /// on behalf of all of them.
+    /// 
+    /// Returns the ingestion latency (the number of blocks between the command being
+    /// queued and completed) and whether a new asset class was created. Fails with
+    /// pallet_assets' `InUse` error if the generated `asset_id` already belongs to an asset class
+    /// or is reserved by another request, and with `NoStagedData` if the owner has no public key
+    /// staged for the asset class, leaving the command queued.
     /// 
     fn create_asset_class(
         origin: OriginFor<T>,
         cmd: IngestionCommand<T::AccountId, T::Balance>,
         asset_id: T::AssetId,
-    ) -> DispatchResult {
+        ciphertext_size: u128,
+    ) -> Result<IngestionOutcome, DispatchError> {
         let who = ensure_signed(origin)?;
-        if let Some(pubkey) = IngestionStaging::<T>::get(cmd.owner.clone()) {
-            let admin = T::Lookup::unlookup(cmd.owner.clone());
-            let new_origin = system::RawOrigin::Signed(who.clone()).into();
-            <pallet_assets::Pallet<T>>::create(new_origin, asset_id, admin, cmd.balance)
-                .map_err(|e| {
-                    log::info!("Failed to create asset class due to error: {:?}", e);
-                    Error::<T>::CantCreateAssetClass
-                })?;
-            Metadata::<T>::insert(asset_id, AssetMetadata {
-                cid: cmd.cid.clone(),
-                public_key: pubkey,
-            });
-            AssetClassOwnership::<T>::mutate(cmd.owner.clone(), |ids| { ids.push(asset_id); });
-            IngestionStaging::<T>::remove(cmd.owner.clone());
-            IngestionCommands::<T>::mutate(who, |cmds| {
-                cmds.retain(|c| *c != cmd);
-            });
+        // only the id of the submitted command is trusted, everything else is read from the queue
+        let cmd = IngestionCommands::<T>::get(who.clone()).into_iter()
+            .find(|c| c.id == cmd.id)
+            .ok_or(Error::<T>::NoSuchIngestionCommand)?;
+        let queued_at = cmd.queued_at;
+        let current_block: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        let latency = current_block.saturating_sub(queued_at);
+        let request_id = ReplicaOf::<T>::get(cmd.id).ok_or(Error::<T>::NoSuchIngestionCommand)?;
+        // only the claimer may complete a replica of the request while its claim lasts
+        if let Some((claimer, expires_at)) = CommandClaims::<T>::get(request_id) {
+            ensure!(claimer == who || current_block >= expires_at, Error::<T>::AlreadyClaimed);
+        }
+        let mut status = Replication::<T>::get(request_id).ok_or(Error::<T>::NoSuchIngestionCommand)?;
+        let asset_created = status.asset_id.is_none();
+        let pinned_asset_id = match status.asset_id {
+            // another gateway already created the asset class
+            Some(existing_asset_id) => existing_asset_id,
+            None => {
+                let quorum = ConfirmationQuorum::<T>::get(request_id).unwrap_or(1);
+                let mut confirmed = IngestionConfirmations::<T>::get(request_id);
+                if !confirmed.contains(&who) {
+                    confirmed.push(who.clone());
+                }
+                let confirmations = confirmed.len() as u32;
+                if confirmations < quorum {
+                    // the gateway has pinned the data, so its command is done
+                    Self::record_latency(who.clone(), latency);
+                    IngestionCommands::<T>::mutate(who.clone(), |cmds| {
+                        cmds.retain(|c| c.id != cmd.id);
+                    });
+                    Self::release_claim(cmd.id, &who);
+                    Self::unindex_replica(cmd.id);
+                    IngestionAttemptsOf::<T>::remove(cmd.id);
+                    IngestionConfirmations::<T>::insert(request_id, confirmed);
+                    Self::deposit_event(Event::IngestionConfirmed(request_id, who.clone(), confirmations, quorum));
+                    return Ok(IngestionOutcome { latency, asset_created: false });
+                }
+                // the asset class can't be created without the owner's public key, so the
+                // command stays queued rather than being reported as ingested
+                let pubkey = Self::staged_public_key(cmd.owner.clone())?.to_vec();
+                let admin = T::Lookup::unlookup(cmd.owner.clone());
+                let new_origin = system::RawOrigin::Signed(who.clone()).into();
+                // pallet_assets requires a positive min balance, so fall back to the smallest
+                // one for any command that was queued before it was validated in create_request
+                let min_balance = if cmd.balance.is_zero() { One::one() } else { cmd.balance };
+                let requested_asset_id = RequestedAssetId::<T>::get(request_id);
+                let asset_id = match requested_asset_id {
+                    Some(requested) => requested,
+                    None => {
+                        // generated ids never take an id reserved by another request, which
+                        // counts as in use so the caller retries with a fresh one
+                        ensure!(!ReservedAssetIds::<T>::contains_key(asset_id), pallet_assets::Error::<T>::InUse);
+                        asset_id
+                    }
+                };
+                <pallet_assets::Pallet<T>>::create(new_origin, asset_id, admin, min_balance)
+                    .map_err(|e| {
+                        log::info!("Failed to create asset class due to error: {:?}", e);
+                        // a clash with a generated id is passed on so the caller can retry
+                        // with a fresh one
+                        if requested_asset_id.is_none() && e == pallet_assets::Error::<T>::InUse.into() {
+                            e
+                        } else {
+                            Error::<T>::CantCreateAssetClass.into()
+                        }
+                    })?;
+                // the asset class has no name yet, so label it with its cid
+                Self::set_assets_metadata(asset_id, &cmd.cid);
+                Metadata::<T>::insert(asset_id, AssetMetadata {
+                    ciphertext_cid: cmd.cid.clone(),
+                    ciphertext_size,
+                    public_key: pubkey,
+                    name: None,
+                    description: None,
+                    providers: Vec::new(),
+                    extra_cids: DatasetFiles::<T>::get(request_id).into_iter()
+                        .map(|(cid, _)| cid)
+                        .collect(),
+                    // ingestion stages umbral capsules, the only scheme so far
+                    encryption_scheme: EncryptionScheme::UmbralSalsaBox,
+                    plaintext_checksum: None,
+                });
+                AssetClassOwnership::<T>::mutate(cmd.owner.clone(), |ids| { ids.push(asset_id); });
+                AssetRequest::<T>::insert(asset_id, request_id);
+                AssetsByBlock::<T>::append(current_block / ASSET_INDEX_BUCKET_BLOCKS, (current_block, asset_id));
+                if RequestedAssetId::<T>::take(request_id).is_some() {
+                    ReservedAssetIds::<T>::remove(asset_id);
+                }
+                Self::clear_ingestion_staging(&cmd.owner);
+                Self::release_pending_request(&cmd.owner);
+                Self::record_asset_event(asset_id, AssetLifecycleEvent::Created, Some(who.clone()));
+                // the gateways that confirmed the request earlier pin the new asset as well
+                for gateway in IngestionConfirmations::<T>::take(request_id).into_iter().filter(|g| *g != who) {
+                    IngestedByGateway::<T>::mutate(gateway.clone(), |ids| { ids.push(asset_id); });
+                    PinStatus::<T>::insert(gateway.clone(), asset_id, true);
+                    Self::update_provider(asset_id, &gateway, true);
+                    if !status.pinned_by.contains(&gateway) {
+                        status.pinned_by.push(gateway);
+                    }
+                }
+                if ConfirmationQuorum::<T>::take(request_id).is_some() {
+                    Self::deposit_event(Event::IngestionConfirmed(request_id, who.clone(), confirmations, quorum));
+                }
+                asset_id
+            }
+        };
+        IngestedByGateway::<T>::mutate(who.clone(), |ids| { ids.push(pinned_asset_id); });
+        PinStatus::<T>::insert(who.clone(), pinned_asset_id, true);
+        Self::update_provider(pinned_asset_id, &who, true);
+        Self::record_latency(who.clone(), latency);
+        IngestionCommands::<T>::mutate(who.clone(), |cmds| {
+            cmds.retain(|c| c.id != cmd.id);
+        });
+        Self::release_claim(cmd.id, &who);
+        Self::unindex_replica(cmd.id);
+        IngestionAttemptsOf::<T>::remove(cmd.id);
+        let repinned = RepinOf::<T>::take(cmd.id).is_some();
+        // track replication progress
+        status.asset_id = Some(pinned_asset_id);
+        if !status.pinned_by.contains(&who) {
+            status.pinned_by.push(who.clone());
+        }
+        let pinned = status.pinned_by.len() as u32;
+        Self::deposit_event(Event::ReplicaPinned(pinned_asset_id, who.clone(), pinned, status.replication));
+        if pinned == status.replication {
+            Self::deposit_event(Event::AssetDurable(pinned_asset_id));
         }
+        Replication::<T>::insert(request_id, status);
+        Self::update_availability(pinned_asset_id);
+        if repinned {
+            Self::deposit_event(Event::AssetRepinned(pinned_asset_id, who));
+        }
+
+        Ok(IngestionOutcome { latency, asset_created })
+    }
+
+    /// Record a failed attempt to ingest a command queued with the caller. The command
+    /// is skipped for `IngestionBackoff * 2^(attempts - 1)` blocks, and is removed from
+    /// the queue once it has failed `MaxIngestionAttempts` times, and the gateway's share of the
+    /// reserve is returned to the owner. Abandoning the last replica of a request that was never
+    /// ingested releases the request and its asset id.
+    /// 
+    /// * `cmd_id`: The id of the command that could not be ingested
+    /// 
+    fn record_failed_attempt(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+    ) -> Result<bool, DispatchError> {
+        let who = ensure_signed(origin)?;
+        ensure!(
+            IngestionCommands::<T>::get(who.clone()).iter().any(|c| c.id == cmd_id),
+            Error::<T>::NoSuchIngestionCommand
+        );
+        let now: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        let mut record = IngestionAttemptsOf::<T>::get(cmd_id);
+        ensure!(now >= record.next_attempt, Error::<T>::AttemptTooEarly);
+        record.attempts = record.attempts.saturating_add(1);
+        // another gateway may fetch the data while this one backs off
+        Self::release_claim(cmd_id, &who);
+        if record.attempts >= T::MaxIngestionAttempts::get() {
+            IngestionCommands::<T>::mutate(who.clone(), |cmds| cmds.retain(|c| c.id != cmd_id));
+            IngestionAttemptsOf::<T>::remove(cmd_id);
+            let repin = RepinOf::<T>::take(cmd_id).is_some();
+            if let Some(request_id) = Self::unindex_replica(cmd_id) {
+                if !repin {
+                    Self::refund_share(request_id, &who);
+                }
+                // the request is released once none of its replicas can still be ingested
+                Self::release_if_unqueued(request_id);
+            }
+            Self::deposit_event(Event::CommandAbandoned(cmd_id, who));
+            return Ok(true);
+        }
+        let backoff = T::IngestionBackoff::get()
+            .saturating_mul(2u32.saturating_pow(record.attempts - 1));
+        record.next_attempt = now.saturating_add(backoff);
+        IngestionAttemptsOf::<T>::insert(cmd_id, record.clone());
+        Self::deposit_event(Event::IngestionAttemptFailed(cmd_id, who, record.attempts, record.next_attempt));
+        Ok(false)
+    }
 
+    /// Claim the request of a command queued with the caller, as `claim_command` does
+    /// 
+    /// * `cmd_id`: The id of the command whose data the caller is about to fetch
+    /// 
+    fn claim_command(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+    ) -> DispatchResult {
+        let who = ensure_signed(origin)?;
+        Self::do_claim_command(who, cmd_id)
+    }
+
+    /// Decline to service a command queued with the caller, as `reject_request` does
+    /// 
+    /// * `cmd_id`: The id of the command the caller declines
+    /// * `reason`: An optional code explaining why the request was rejected
+    /// 
+    fn reject_command(
+        origin: OriginFor<T>,
+        cmd_id: [u8; 32],
+        reason: Option<u8>,
+    ) -> DispatchResult {
+        let who = ensure_signed(origin)?;
+        let cmd = IngestionCommands::<T>::get(who.clone()).into_iter()
+            .find(|c| c.id == cmd_id)
+            .ok_or(Error::<T>::NoSuchIngestionCommand)?;
+        Self::do_reject_command(who, cmd, reason);
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Storage migrations for the data assets pallet
+
+use super::*;
+
+/// Version 1 migrates the asset metadata and ingestion commands stored before versioning.
+/// Asset classes gain their ciphertext size, descriptive fields and other dataset files,
+/// and each queued command becomes a single replica ingestion request.
+pub mod v1 {
+    use super::*;
+
+    /// the asset metadata stored before version 1
+    #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+    pub struct OldAssetMetadata {
+        pub cid: Vec<u8>,
+        pub public_key: Vec<u8>,
+    }
+
+    /// the ingestion command stored before version 1
+    #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+    pub struct OldIngestionCommand<AccountId, Balance> {
+        pub owner: AccountId,
+        pub cid: Vec<u8>,
+        pub multiaddress: Vec<u8>,
+        pub balance: Balance,
+    }
+
+    /// Migrate the metadata of every asset class and every queued ingestion command
+    /// to version 1. Existing asset classes hold a single file of unrecorded size, and
+    /// existing commands are queued at the upgrade block with no reserve.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 1 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut translated: u64 = 0;
+        Metadata::<T>::translate::<OldAssetMetadata, _>(|_, old| {
+            translated += 1;
+            Some(v2::OldAssetMetadata {
+                ciphertext_cid: old.cid,
+                ciphertext_size: 0,
+                public_key: old.public_key,
+                name: None,
+                description: None,
+                providers: Vec::new(),
+                extra_cids: Vec::new(),
+            })
+        });
+        let queued_at: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+        let mut nonce = IngestionCommandNonce::<T>::get();
+        let mut commands: u64 = 0;
+        IngestionCommands::<T>::translate::<Vec<OldIngestionCommand<T::AccountId, T::Balance>>, _>(|_, old| {
+            Some(old.into_iter().map(|old| {
+                commands += 1;
+                let request_id = sp_io::hashing::blake2_256(
+                    &(old.owner.clone(), old.cid.clone(), old.multiaddress.clone(), nonce).encode()
+                );
+                nonce = nonce.wrapping_add(1);
+                let id = sp_io::hashing::blake2_256(&(request_id, 0u32).encode());
+                ReplicaOf::<T>::insert(id, request_id);
+                RequestCommand::<T>::insert(request_id, IngestionCommand {
+                    id: request_id,
+                    owner: old.owner.clone(),
+                    cid: old.cid.clone(),
+                    multiaddress: old.multiaddress.clone(),
+                    balance: old.balance,
+                    queued_at,
+                });
+                Replication::<T>::insert(request_id, ReplicationStatus {
+                    replication: 1,
+                    pinned_by: Vec::new(),
+                    asset_id: None,
+                });
+                IngestionCommand {
+                    id,
+                    owner: old.owner,
+                    cid: old.cid,
+                    multiaddress: old.multiaddress,
+                    balance: old.balance,
+                    queued_at,
+                }
+            }).collect())
+        });
+        IngestionCommandNonce::<T>::put(nonce);
+        StorageVersion::new(1).put::<Pallet<T>>();
+        log::info!(
+            "Migrated the metadata of {:?} asset classes and {:?} ingestion commands to version 1",
+            translated, commands,
+        );
+        T::DbWeight::get().reads_writes(translated + commands + 2, translated + 3 * commands + 2)
+    }
+}
+
+/// Version 2 adds the encryption scheme to the metadata of each asset class
+pub mod v2 {
+    use super::*;
+
+    /// the asset metadata stored before version 2
+    #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+    pub struct OldAssetMetadata {
+        pub ciphertext_cid: Vec<u8>,
+        pub ciphertext_size: u64,
+        pub public_key: Vec<u8>,
+        pub name: Option<Vec<u8>>,
+        pub description: Option<Vec<u8>>,
+        pub providers: Vec<Vec<u8>>,
+        pub extra_cids: Vec<Vec<u8>>,
+    }
+
+    /// Migrate the metadata of every asset class to version 2. Existing asset
+    /// classes were all encrypted with umbral.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 2 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut translated: u64 = 0;
+        Metadata::<T>::translate::<OldAssetMetadata, _>(|_, old| {
+            translated += 1;
+            Some(v3::OldAssetMetadata {
+                ciphertext_cid: old.ciphertext_cid,
+                ciphertext_size: old.ciphertext_size,
+                public_key: old.public_key,
+                name: old.name,
+                description: old.description,
+                providers: old.providers,
+                extra_cids: old.extra_cids,
+                encryption_scheme: EncryptionScheme::UmbralSalsaBox,
+            })
+        });
+        StorageVersion::new(2).put::<Pallet<T>>();
+        log::info!("Migrated the metadata of {:?} asset classes to version 2", translated);
+        T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+    }
+}
+
+/// Version 3 adds the owner's plaintext checksum to the metadata of each asset class
+pub mod v3 {
+    use super::*;
+
+    /// the asset metadata stored before version 3
+    #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+    pub struct OldAssetMetadata {
+        pub ciphertext_cid: Vec<u8>,
+        pub ciphertext_size: u64,
+        pub public_key: Vec<u8>,
+        pub name: Option<Vec<u8>>,
+        pub description: Option<Vec<u8>>,
+        pub providers: Vec<Vec<u8>>,
+        pub extra_cids: Vec<Vec<u8>>,
+        pub encryption_scheme: EncryptionScheme,
+    }
+
+    /// Migrate the metadata of every asset class to version 3. No owner has
+    /// attested a checksum yet.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 3 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut translated: u64 = 0;
+        Metadata::<T>::translate::<OldAssetMetadata, _>(|_, old| {
+            translated += 1;
+            Some(v5::OldAssetMetadata {
+                ciphertext_cid: old.ciphertext_cid,
+                ciphertext_size: old.ciphertext_size,
+                public_key: old.public_key,
+                name: old.name,
+                description: old.description,
+                providers: old.providers,
+                extra_cids: old.extra_cids,
+                encryption_scheme: old.encryption_scheme,
+                plaintext_checksum: None,
+            })
+        });
+        StorageVersion::new(3).put::<Pallet<T>>();
+        log::info!("Migrated the metadata of {:?} asset classes to version 3", translated);
+        T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+    }
+}
+
+/// Version 4 indexes the replicas queued for each ingestion request and counts
+/// the requests each owner has waiting to be ingested
+pub mod v4 {
+    use super::*;
+
+    /// Index every queued command that replicates a request by the request's id,
+    /// along with the gateway it is queued with, and count the requests of each
+    /// owner that are not ingested yet.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 4 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut queues: u64 = 0;
+        let mut indexed: u64 = 0;
+        for (gateway, commands) in IngestionCommands::<T>::iter() {
+            queues += 1;
+            for cmd in commands {
+                if let Some(request_id) = ReplicaOf::<T>::get(cmd.id) {
+                    indexed += 1;
+                    RequestReplicas::<T>::append(request_id, (gateway.clone(), cmd.id));
+                }
+            }
+        }
+        let mut requests: u64 = 0;
+        let mut pending: u64 = 0;
+        for (request_id, status) in Replication::<T>::iter() {
+            requests += 1;
+            if status.asset_id.is_some() {
+                continue;
+            }
+            if let Some(cmd) = RequestCommand::<T>::get(request_id) {
+                pending += 1;
+                PendingRequests::<T>::mutate(cmd.owner, |count| *count = count.saturating_add(1));
+            }
+        }
+        StorageVersion::new(4).put::<Pallet<T>>();
+        log::info!(
+            "Indexed {:?} queued replicas and {:?} pending ingestion requests in version 4",
+            indexed, pending,
+        );
+        T::DbWeight::get().reads_writes(queues + indexed + requests + 2 * pending + 1, indexed + pending + 1)
+    }
+}
+
+/// Version 5 records the ciphertext size of each asset class as a u128, like every other size
+pub mod v5 {
+    use super::*;
+
+    /// the asset metadata stored before version 5
+    #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+    pub struct OldAssetMetadata {
+        pub ciphertext_cid: Vec<u8>,
+        pub ciphertext_size: u64,
+        pub public_key: Vec<u8>,
+        pub name: Option<Vec<u8>>,
+        pub description: Option<Vec<u8>>,
+        pub providers: Vec<Vec<u8>>,
+        pub extra_cids: Vec<Vec<u8>>,
+        pub encryption_scheme: EncryptionScheme,
+        pub plaintext_checksum: Option<Vec<u8>>,
+    }
+
+    /// Migrate the metadata of every asset class to version 5, widening its ciphertext size.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 5 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut translated: u64 = 0;
+        Metadata::<T>::translate::<OldAssetMetadata, _>(|_, old| {
+            translated += 1;
+            Some(AssetMetadata {
+                ciphertext_cid: old.ciphertext_cid,
+                ciphertext_size: old.ciphertext_size.into(),
+                public_key: old.public_key,
+                name: old.name,
+                description: old.description,
+                providers: old.providers,
+                extra_cids: old.extra_cids,
+                encryption_scheme: old.encryption_scheme,
+                plaintext_checksum: old.plaintext_checksum,
+            })
+        });
+        StorageVersion::new(5).put::<Pallet<T>>();
+        log::info!("Migrated the metadata of {:?} asset classes to version 5", translated);
+        T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+    }
+}
+
+/// Version 6 indexes ingestion requests by the owner and cid of their command
+pub mod v6 {
+    use super::*;
+
+    /// Index the id of every ingestion request under the owner and cid of its command.
+    /// 
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain = StorageVersion::get::<Pallet<T>>();
+        if on_chain >= 6 {
+            return T::DbWeight::get().reads(1);
+        }
+        let mut indexed: u64 = 0;
+        for (request_id, cmd) in RequestCommand::<T>::iter() {
+            indexed += 1;
+            RequestsByCid::<T>::append(cmd.owner, cmd.cid, request_id);
+        }
+        StorageVersion::new(6).put::<Pallet<T>>();
+        log::info!("Indexed {:?} ingestion requests by owner and cid in version 6", indexed);
+        T::DbWeight::get().reads_writes(indexed + 1, indexed + 1)
+    }
+}
@@ -20,7 +20,7 @@ use frame_support::{
 	BasicExternalities,
 	construct_runtime, 
 	parameter_types,
-	traits::ConstU32
+	traits::{ConstBool, ConstU32},
 };
 use frame_system::EnsureRoot;
 use sp_core::{
@@ -105,6 +105,8 @@ construct_runtime!(
 parameter_types! {
 	pub const BlockHashCount: u64 = 250;
 	pub const SS58Prefix: u8 = 42;
+	pub static AllowSelfGateway: bool = true;
+	pub static SetAssetMetadata: bool = true;
 }
 
 impl frame_system::Config for Test {
@@ -308,6 +310,22 @@ impl Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = AllowSelfGateway;
+	type SetAssetMetadata = SetAssetMetadata;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<4>;
+	type GatewayPricing = ();
+	type GatewayProvider = ();
+	type ProxyPrefs = ();
+	type PeerIdProvider = ();
 }
 
 pub fn new_test_ext(
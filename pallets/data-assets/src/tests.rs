@@ -16,24 +16,26 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use super::*;
-use frame_support::assert_ok;
+use frame_support::{assert_noop, assert_ok, traits::VestingSchedule};
 use mock::*;
 use sp_core::Pair;
 use sp_runtime::testing::UintAuthorityId;
 
 struct TestData {
 	pub p: sp_core::sr25519::Pair,
-	pub _q: sp_core::sr25519::Pair,
+	pub q: sp_core::sr25519::Pair,
 	pub cid_vec: Vec<u8>,
 	pub multiaddr_vec: Vec<u8>,
+	pub public_key: Vec<u8>,
 	pub balance: u64,
 }
 
 thread_local!(static TEST_CONSTANTS: TestData = TestData {
 	p: sp_core::sr25519::Pair::generate().0,
-	_q: sp_core::sr25519::Pair::generate().0,
+	q: sp_core::sr25519::Pair::generate().0,
 	cid_vec: "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
 	multiaddr_vec: "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+	public_key: vec![2, 7, 178, 91, 140, 23, 162, 73, 101, 16, 100, 140, 126, 128, 189, 51, 190, 43, 204, 101, 196, 187, 116, 242, 164, 135, 50, 62, 121, 21, 39, 191, 68],
 	balance: 1,
 });
 
@@ -65,6 +67,7 @@ fn data_assets_can_request_ingestion() {
 		// 	balance: test_data.balance.clone(),
 		// };
 		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
 			// When: I call to create a new ingestion request
 			assert_ok!(DataAssets::create_request(
 				Origin::signed(test_data.p.clone().public()),
@@ -72,7 +75,11 @@ fn data_assets_can_request_ingestion() {
 				test_data.balance.clone(),
 				test_data.cid_vec.clone(),
 				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
 			));
 			
 			// Then: A new entry is added to the IngestionCommands map
@@ -83,6 +90,1685 @@ fn data_assets_can_request_ingestion() {
 			assert_eq!(cmd.cid, test_data.cid_vec.clone());
 			assert_eq!(cmd.multiaddress, test_data.multiaddr_vec.clone());
 			assert_eq!(cmd.balance, test_data.balance.clone() as u32);
+			// And: the request id is announced so its progress can be tracked
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			System::assert_last_event(mock::Event::DataAssets(
+				crate::Event::CreatedIngestionRequest(request_id, 1 + DataAssets::delay())
+			));
+		});
+	})
+}
+
+#[test]
+fn data_assets_announces_the_block_at_which_the_reserve_vests() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// And: the reserve vests 25 blocks per gb after a request is queued
+			crate::Delay::<Test>::put(25);
+			System::set_block_number(7);
+			// And: a request for up to a gb queued now is estimated to vest at block 32
+			assert_eq!(32, DataAssets::vesting_target_block(1_000));
+			assert_eq!(32, DataAssets::compute_target_block(7, 25, BYTES_PER_GB));
+			// And: one for a byte over 2 gb at block 82
+			let size = 2 * BYTES_PER_GB + 1;
+			let expected = DataAssets::vesting_target_block(size);
+			assert_eq!(82, expected);
+			assert_eq!(DataAssets::compute_target_block(7, 25, size), expected);
+			// When: I create an ingestion request for that much data
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				size,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// Then: the event announces the same block the estimate reported
+			let cmd = &crate::IngestionCommands::<Test>::get(test_data.p.clone().public())[0];
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			System::assert_last_event(mock::Event::DataAssets(
+				crate::Event::CreatedIngestionRequest(request_id, expected)
+			));
+			// And: the reserve's vesting schedule ends right after that block
+			let schedule = pallet_vesting::Vesting::<Test>::get(test_data.p.clone().public()).unwrap()[0];
+			assert_eq!(expected as u64 + 1, schedule.ending_block_as_balance::<sp_runtime::traits::ConvertInto>());
+			// And: the reserve stays locked until then
+			System::set_block_number(expected as u64);
+			assert_eq!(Some(1), Vesting::vesting_balance(&test_data.p.clone().public()));
+			System::set_block_number(expected as u64 + 1);
+			assert_eq!(Some(0), Vesting::vesting_balance(&test_data.p.clone().public()));
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_exceed_max_pending_requests() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// And: I have queued the maximum number of requests with the gateway
+			let max_pending = <Test as crate::Config>::MaxPendingRequests::get();
+			for _ in 0..max_pending {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(test_data.p.clone().public()),
+					test_data.p.clone().public(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+			}
+			// When: I call to create another ingestion request
+			// Then: The request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			), Error::<Test>::TooManyPendingRequests);
+			// And: only the maximum number of commands are queued
+			let ingestion_cmds = crate::IngestionCommands::<Test>::get(test_data.p.clone().public());
+			assert_eq!(ingestion_cmds.len(), max_pending as usize);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_name_self_as_gateway_when_disallowed() {
+	// Given: I am a valid node with a positive balance on a chain that disallows self gateways
+	AllowSelfGateway::set(false);
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I name myself as the gateway of my ingestion request
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			), Error::<Test>::SelfGatewayNotAllowed);
+			// And: no command is queued
+			let ingestion_cmds = crate::IngestionCommands::<Test>::get(test_data.p.clone().public());
+			assert_eq!(ingestion_cmds.len(), 0);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_name_self_as_gateway_in_dev_mode() {
+	// Given: I am a valid node with a positive balance on a chain that allows self gateways
+	AllowSelfGateway::set(true);
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I name myself as the gateway of my ingestion request
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// Then: the command is queued with me
+			let ingestion_cmds = crate::IngestionCommands::<Test>::get(test_data.p.clone().public());
+			assert_eq!(ingestion_cmds.len(), 1);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_request_more_replicas_than_gateways() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I request a replication factor of 0
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				0,
+				1,
+				None,
+			), Error::<Test>::InvalidReplication);
+			// When: I request more replicas than there are registered gateways
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
+			), Error::<Test>::InsufficientGateways);
+		});
+	})
+}
+
+#[test]
+fn data_assets_rejects_reserve_below_minimum_vested_transfer() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: the minimum vested transfer is 1
+			assert_eq!(1, <Test as pallet_vesting::Config>::MinVestedTransfer::get());
+			// When: I offer a reserve below it
+			// Then: the request is rejected before any command is queued
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				0,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			), Error::<Test>::ReserveBelowMinimum);
+		});
+	})
+}
+
+#[test]
+fn data_assets_vests_the_reserve_with_the_gateway() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			// When: I queue a request with a reserve of 4
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				4,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// Then: the reserve is transferred to the gateway
+			assert_eq!(6, Balances::free_balance(&owner));
+			assert_eq!(14, Balances::free_balance(&gateway));
+			// And: it is locked in a vesting schedule that unlocks after the target block
+			let target_block = 1 + DataAssets::delay() as u64;
+			assert_eq!(
+				vec![VestingInfo::new(4, 4, target_block)],
+				pallet_vesting::Vesting::<Test>::get(&gateway).unwrap().to_vec(),
+			);
+			assert_eq!(10, Balances::usable_balance(&gateway));
+			assert_noop!(
+				Balances::transfer(Origin::signed(gateway.clone()), owner.clone(), 11),
+				pallet_balances::Error::<Test>::LiquidityRestrictions
+			);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_request_ingestion_without_funds_for_the_reserve() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I offer a reserve larger than my balance
+			// Then: the request is rejected and nothing is vested
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				20,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			), Error::<Test>::InsufficientBalance);
+			assert_eq!(None, pallet_vesting::Vesting::<Test>::get(&gateway));
+		});
+	})
+}
+
+#[test]
+fn data_assets_refunds_the_reserve_of_rejected_and_killed_requests() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let request = || DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				4,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			);
+			// Given: I queued a request with a reserve of 4
+			assert_ok!(request());
+			let cmd_id = crate::IngestionCommands::<Test>::get(gateway.clone())[0].id;
+			let request_id = crate::ReplicaOf::<Test>::get(cmd_id).unwrap();
+			assert_eq!(6, Balances::free_balance(&owner));
+			// When: the gateway rejects it before the reserve vests
+			assert_ok!(DataAssets::reject_request(
+				Origin::signed(gateway.clone()), owner.clone(), test_data.cid_vec.clone(), None,
+			));
+			// Then: the reserve is returned to me and no longer locked with the gateway
+			assert_eq!(10, Balances::free_balance(&owner));
+			assert_eq!(10, Balances::free_balance(&gateway));
+			assert_eq!(None, pallet_vesting::Vesting::<Test>::get(&gateway));
+			System::assert_has_event(mock::Event::DataAssets(crate::Event::ReserveRefunded(request_id, gateway.clone(), 4)));
+			// When: I queue the request again and kill it
+			assert_ok!(request());
+			let cmd_id = crate::IngestionCommands::<Test>::get(gateway.clone())[0].id;
+			let request_id = crate::ReplicaOf::<Test>::get(cmd_id).unwrap();
+			assert_eq!(6, Balances::free_balance(&owner));
+			assert_ok!(DataAssets::kill_request(Origin::signed(owner.clone()), request_id));
+			// Then: the reserve is returned to me again
+			assert_eq!(10, Balances::free_balance(&owner));
+			assert_eq!(10, Balances::free_balance(&gateway));
+			assert_eq!(None, pallet_vesting::Vesting::<Test>::get(&gateway));
+			System::assert_has_event(mock::Event::DataAssets(crate::Event::ReserveRefunded(request_id, gateway.clone(), 4)));
+		});
+	})
+}
+
+#[test]
+fn data_assets_refunds_the_reserve_of_abandoned_commands() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: I queued a request with a reserve of 4
+			System::set_block_number(1);
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				4,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd_id = crate::IngestionCommands::<Test>::get(gateway.clone())[0].id;
+			let request_id = crate::ReplicaOf::<Test>::get(cmd_id).unwrap();
+			let backoff = <Test as crate::Config>::IngestionBackoff::get();
+			// When: the gateway fails to ingest it as many times as allowed, before the reserve vests
+			for block in [1, 1 + backoff, 1 + 3 * backoff] {
+				System::set_block_number(block);
+				assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::record_failed_attempt(
+					Origin::signed(gateway.clone()), cmd_id,
+				));
+			}
+			assert!(1 + 3 * backoff < 1 + DataAssets::delay() as u64);
+			// Then: the command is abandoned and the reserve is returned to me
+			assert!(crate::IngestionCommands::<Test>::get(gateway.clone()).is_empty());
+			assert_eq!(10, Balances::free_balance(&owner));
+			assert_eq!(10, Balances::free_balance(&gateway));
+			assert_eq!(None, pallet_vesting::Vesting::<Test>::get(&gateway));
+			System::assert_has_event(mock::Event::DataAssets(crate::Event::ReserveRefunded(request_id, gateway.clone(), 4)));
+		});
+	})
+}
+
+#[test]
+fn data_assets_does_not_refund_a_vested_reserve() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: I queued a request with a reserve of 4
+			System::set_block_number(1);
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				4,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// When: the gateway rejects it after the reserve has vested
+			System::set_block_number(2 + DataAssets::delay() as u64);
+			assert_ok!(DataAssets::reject_request(
+				Origin::signed(gateway.clone()), owner.clone(), test_data.cid_vec.clone(), None,
+			));
+			// Then: the gateway keeps the reserve
+			assert_eq!(6, Balances::free_balance(&owner));
+			assert_eq!(14, Balances::free_balance(&gateway));
+		});
+	})
+}
+
+#[test]
+fn data_assets_rejects_malformed_staged_public_keys() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			// When: I stage a public key that is one byte too short
+			let short_key = test_data.public_key[1..].to_vec();
+			assert_noop!(
+				<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), short_key),
+				crate::Error::<Test>::InvalidPublicKey,
+			);
+			// Then: nothing is staged
+			assert_eq!(None, crate::IngestionStaging::<Test>::get(owner.clone()));
+			assert!(matches!(DataAssets::staged_public_key(owner.clone()), Err(crate::Error::<Test>::NoStagedData)));
+			// When: I stage a well formed public key
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				owner.clone(), test_data.public_key.clone(),
+			));
+			// Then: it can be read back as a fixed size key
+			assert_eq!(test_data.public_key.clone(), DataAssets::staged_public_key(owner.clone()).ok().unwrap().to_vec());
+		});
+	})
+}
+
+#[test]
+fn data_assets_rejects_public_keys_staged_by_another_owner() {
+	// Given: I have staged a public key
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			let other = test_data.q.clone().public();
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				owner.clone(), test_data.public_key.clone(),
+			));
+			// When: another owner stages the same public key
+			// Then: it is rejected and nothing is staged for them
+			assert_noop!(
+				<DataAssets as QueueManager<_, _>>::add_ingestion_staging(other.clone(), test_data.public_key.clone()),
+				crate::Error::<Test>::KeyAlreadyStaged,
+			);
+			assert_eq!(None, crate::IngestionStaging::<Test>::get(other.clone()));
+			// And: I can stage it again myself
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				owner.clone(), test_data.public_key.clone(),
+			));
+			assert_eq!(Some(owner.clone()), crate::StagedKeyOwner::<Test>::get(test_data.public_key.clone()));
+		});
+	})
+}
+
+#[test]
+fn data_assets_kill_request_clears_ingestion_staging() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			// And: I have staged encrypted data and queued two ingestion requests
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			for _ in 0..2 {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(owner.clone()),
+					owner.clone(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+			}
+			let cmds = crate::IngestionCommands::<Test>::get(owner.clone());
+			let first = crate::ReplicaOf::<Test>::get(cmds[0].id).unwrap();
+			let second = crate::ReplicaOf::<Test>::get(cmds[1].id).unwrap();
+			assert_eq!(2, crate::PendingRequests::<Test>::get(owner.clone()));
+			// When: I kill the first request
+			assert_ok!(DataAssets::kill_request(Origin::signed(owner.clone()), first));
+			// Then: its command is removed, but the staged data is kept for the other request
+			assert_eq!(1, crate::IngestionCommands::<Test>::get(owner.clone()).len());
+			assert_eq!(None, crate::Replication::<Test>::get(first));
+			assert!(!crate::RequestReplicas::<Test>::contains_key(first));
+			assert_eq!(1, crate::PendingRequests::<Test>::get(owner.clone()));
+			assert_eq!(Some(test_data.public_key.clone()), crate::IngestionStaging::<Test>::get(owner.clone()));
+			// When: I kill the second request
+			assert_ok!(DataAssets::kill_request(Origin::signed(owner.clone()), second));
+			// Then: the queue and the staged data are cleared
+			assert!(crate::IngestionCommands::<Test>::get(owner.clone()).is_empty());
+			assert_eq!(None, crate::IngestionStaging::<Test>::get(owner.clone()));
+			assert_eq!(None, crate::StagedKeyOwner::<Test>::get(test_data.public_key.clone()));
+			// And: the request can not be killed again
+			assert_noop!(
+				DataAssets::kill_request(Origin::signed(owner.clone()), second),
+				Error::<Test>::NoSuchRequest
+			);
+		});
+	})
+}
+
+#[test]
+fn data_assets_gateway_can_reject_request_for_rerouting() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			let gateway = validators()[0].0.clone();
+			let other_gateway = validators()[1].0.clone();
+			let request = |gateway: sp_core::sr25519::Public| {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(owner.clone()),
+					gateway,
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					Some(7),
+				));
+			};
+			// Given: I staged data and queued a request with a gateway
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			request(gateway.clone());
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			// When: another gateway tries to reject it
+			// Then: it is not queued with them
+			assert_noop!(
+				DataAssets::reject_request(Origin::signed(other_gateway.clone()), owner.clone(), test_data.cid_vec.clone(), None),
+				Error::<Test>::NoSuchIngestionCommand
+			);
+			// When: the gateway rejects it
+			assert_ok!(DataAssets::reject_request(
+				Origin::signed(gateway.clone()), owner.clone(), test_data.cid_vec.clone(), Some(1),
+			));
+			// Then: the command is removed and the request and its reserve are released
+			assert!(crate::IngestionCommands::<Test>::get(gateway.clone()).is_empty());
+			assert_eq!(None, crate::Replication::<Test>::get(request_id));
+			assert_eq!(0, crate::RequestReserve::<Test>::get(request_id));
+			assert_eq!(None, crate::ReservedAssetIds::<Test>::get(7));
+			assert!(!crate::RequestReplicas::<Test>::contains_key(request_id));
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::RequestRejected(
+				gateway.clone(), owner.clone(), test_data.cid_vec.clone(), Some(1),
+			)));
+			// And: my staged data is kept
+			assert_eq!(Some(test_data.public_key.clone()), crate::IngestionStaging::<Test>::get(owner.clone()));
+			// When: I route the request to another gateway
+			request(other_gateway.clone());
+			// Then: it is queued with them
+			assert_eq!(1, crate::IngestionCommands::<Test>::get(other_gateway.clone()).len());
+		});
+	})
+}
+
+#[test]
+fn data_assets_gateway_can_compact_its_queue() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10), (validators()[0].0.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			let gateway = validators()[0].0.clone();
+			// Given: a command is queued with me
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			// And: my queue also holds a duplicate of it and a command whose request no longer exists
+			let orphan = IngestionCommand { id: [9u8; 32], ..cmd.clone() };
+			crate::IngestionCommands::<Test>::mutate(gateway.clone(), |cmds| {
+				cmds.push(cmd.clone());
+				cmds.push(orphan.clone());
+			});
+			// When: I compact a single command
+			assert_ok!(DataAssets::compact_queue(Origin::signed(gateway.clone()), 1));
+			// Then: only the live command was inspected, so nothing is removed
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::QueueCompacted(gateway.clone(), 0)));
+			assert_eq!(3, crate::IngestionCommands::<Test>::get(gateway.clone()).len());
+			// When: I compact the rest of the queue
+			assert_ok!(DataAssets::compact_queue(Origin::signed(gateway.clone()), 10));
+			// Then: the stale commands are removed
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::QueueCompacted(gateway.clone(), 2)));
+			assert_eq!(vec![cmd.clone()], crate::IngestionCommands::<Test>::get(gateway.clone()));
+			// And: the live command can still be completed
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd.clone(), 2, 1024,
+			));
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_create_asset_class_without_staged_data() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.p.clone().public();
+		let gateway = validators()[0].0.clone();
+		let pairs = vec![(owner.clone(), 10), (gateway.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: a request was queued with a gateway but its owner has no data staged
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			// When: the gateway completes the request, reaching its quorum
+			// Then: it fails instead of reporting success without an asset class
+			assert_noop!(
+				<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+					Origin::signed(gateway.clone()), cmd.clone(), 2, 1024,
+				),
+				Error::<Test>::NoStagedData
+			);
+			// And: the command stays queued
+			assert_eq!(vec![cmd], crate::IngestionCommands::<Test>::get(gateway.clone()));
+			assert!(Assets::asset(2).is_none());
+		});
+	})
+}
+
+#[test]
+fn data_assets_reports_command_assignments() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10), (validators()[0].0.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			let gateway = validators()[0].0.clone();
+			let other_gateway = validators()[1].0.clone();
+			// Given: I have no ingestion request
+			// Then: no gateway is assigned my data
+			assert!(DataAssets::command_assignments(owner.clone(), test_data.cid_vec.clone()).is_empty());
+			// Given: I staged data and queued requests for it with two gateways
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			for g in [gateway.clone(), other_gateway.clone()] {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(owner.clone()),
+					g,
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+			}
+			// When: the first gateway completes ingestion
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd, 2, 1024,
+			));
+			// Then: it is reported as completed and the other gateway as pending
+			let assignments = DataAssets::command_assignments(owner.clone(), test_data.cid_vec.clone());
+			assert_eq!(2, assignments.len());
+			assert!(assignments.contains(&(gateway.clone(), true)));
+			assert!(assignments.contains(&(other_gateway.clone(), false)));
+			// And: other data has no assignments
+			assert!(DataAssets::command_assignments(owner.clone(), b"other".to_vec()).is_empty());
+			// When: I kill the request that is still pending
+			let other_cmd = crate::IngestionCommands::<Test>::get(other_gateway.clone())[0].clone();
+			let other_request = crate::ReplicaOf::<Test>::get(other_cmd.id).unwrap();
+			assert_ok!(DataAssets::kill_request(Origin::signed(owner.clone()), other_request));
+			// Then: only the completed gateway is reported
+			assert_eq!(
+				vec![(gateway.clone(), true)],
+				DataAssets::command_assignments(owner.clone(), test_data.cid_vec.clone()),
+			);
+			assert_eq!(1, crate::RequestsByCid::<Test>::get(owner.clone(), test_data.cid_vec.clone()).len());
+		});
+	})
+}
+
+#[test]
+fn data_assets_creates_asset_classes_from_the_queued_command() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10), (validators()[0].0.clone(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			let other_owner = test_data.q.clone().public();
+			let gateway = validators()[0].0.clone();
+			// Given: I staged data and queued a request for it
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// And: another owner has staged data too
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(other_owner.clone(), vec![2; 33]));
+			// When: the gateway completes my command, but submits it with another owner, cid and min balance
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			let tampered = IngestionCommand {
+				owner: other_owner.clone(),
+				cid: b"QmSomethingElse".to_vec(),
+				balance: 5,
+				..cmd.clone()
+			};
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), tampered, 2, 1024,
+			));
+			// Then: the asset class is created from the queued command
+			assert_eq!(owner.clone(), Assets::asset(2).unwrap().admin);
+			let metadata = crate::Metadata::<Test>::get(2).unwrap();
+			assert_eq!(test_data.cid_vec.clone(), metadata.ciphertext_cid);
+			assert_eq!(test_data.public_key.clone(), metadata.public_key);
+			assert_eq!(vec![2], crate::AssetClassOwnership::<Test>::get(owner.clone()));
+			// And: my request no longer counts as pending
+			assert_eq!(0, crate::PendingRequests::<Test>::get(owner.clone()));
+			// And: the other owner's staged data is untouched
+			assert_eq!(Some(vec![2; 33]), crate::IngestionStaging::<Test>::get(other_owner.clone()));
+			assert!(crate::AssetClassOwnership::<Test>::get(other_owner).is_empty());
+		});
+	})
+}
+
+#[test]
+fn data_assets_only_admin_can_transfer_asset_admin() {
+	// Given: a gateway created an asset class administered by a data owner
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let gateway = test_data.p.clone().public();
+			let owner = test_data.q.clone().public();
+			let new_admin = sp_core::sr25519::Public::from_raw([3; 32]);
+			assert_ok!(Assets::create(Origin::signed(gateway.clone()), 1, owner.clone(), 1));
+			crate::AssetClassOwnership::<Test>::insert(owner.clone(), vec![1]);
+			// When: someone other than the admin tries to transfer the asset class
+			// Then: the transfer is rejected
+			assert_noop!(
+				DataAssets::transfer_asset_admin(Origin::signed(gateway.clone()), 1, new_admin.clone()),
+				Error::<Test>::NotAssetAdmin
+			);
+			// When: the admin transfers the asset class
+			assert_ok!(DataAssets::transfer_asset_admin(Origin::signed(owner.clone()), 1, new_admin.clone()));
+			// Then: the new admin administers and owns the asset class
+			assert_eq!(new_admin, Assets::asset(1).unwrap().admin);
+			assert!(crate::AssetClassOwnership::<Test>::get(owner.clone()).is_empty());
+			assert_eq!(vec![1], crate::AssetClassOwnership::<Test>::get(new_admin.clone()));
+			System::assert_last_event(mock::Event::DataAssets(
+				crate::Event::AssetAdminTransferred(1, owner, new_admin)
+			));
+		});
+	})
+}
+
+#[test]
+fn data_assets_only_admin_can_set_asset_min_balance() {
+	// Given: a gateway created an asset class administered by a data owner
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let gateway = test_data.p.clone().public();
+			let owner = test_data.q.clone().public();
+			assert_ok!(Assets::create(Origin::signed(gateway.clone()), 1, owner.clone(), 2));
+			// When: someone other than the admin tries to change the minimum balance
+			// Then: the change is rejected
+			assert_noop!(
+				DataAssets::set_asset_min_balance(Origin::signed(gateway.clone()), 1, 5),
+				Error::<Test>::NotAssetAdmin
+			);
+			// When: the admin raises the minimum balance while nobody holds the asset
+			assert_ok!(DataAssets::set_asset_min_balance(Origin::signed(owner.clone()), 1, 5));
+			// Then: the minimum balance changes
+			assert_eq!(5, Assets::asset(1).unwrap().min_balance);
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::AssetMinBalanceSet(1, 5)));
+			// Given: an account holds the asset
+			assert_ok!(Assets::mint(Origin::signed(owner.clone()), 1, gateway.clone(), 10));
+			// When: the admin tries to raise the minimum balance
+			// Then: the change is rejected
+			assert_noop!(
+				DataAssets::set_asset_min_balance(Origin::signed(owner.clone()), 1, 6),
+				pallet_assets::Error::<Test>::NoPermission
+			);
+			// When: the admin lowers it
+			assert_ok!(DataAssets::set_asset_min_balance(Origin::signed(owner.clone()), 1, 3));
+			// Then: the minimum balance changes
+			assert_eq!(3, Assets::asset(1).unwrap().min_balance);
+			// And: it can't be set to zero
+			assert_noop!(
+				DataAssets::set_asset_min_balance(Origin::signed(owner.clone()), 1, 0),
+				pallet_assets::Error::<Test>::MinBalanceZero
+			);
+		});
+	})
+}
+
+#[test]
+fn data_assets_backs_off_and_abandons_failing_commands() {
+	// Given: an ingestion command is queued with a gateway
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let gateway = test_data.p.clone().public();
+			System::set_block_number(1);
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(gateway.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd_id = crate::IngestionCommands::<Test>::get(gateway.clone())[0].id;
+			let request_id = crate::ReplicaOf::<Test>::get(cmd_id).unwrap();
+			let backoff = <Test as crate::Config>::IngestionBackoff::get();
+			// When: the gateway fails to ingest the command
+			assert_eq!(Ok(false), <DataAssets as ResultsHandler<Test, _, _, _>>::record_failed_attempt(
+				Origin::signed(gateway.clone()), cmd_id,
+			));
+			// Then: the command is skipped for the base backoff
+			assert_eq!(IngestionAttempts { attempts: 1, next_attempt: 1 + backoff }, DataAssets::ingestion_attempts(cmd_id));
+			// And: another failure can't be recorded before then
+			System::set_block_number(backoff);
+			assert_noop!(
+				<DataAssets as ResultsHandler<Test, _, _, _>>::record_failed_attempt(Origin::signed(gateway.clone()), cmd_id),
+				Error::<Test>::AttemptTooEarly
+			);
+			// When: the gateway fails again once the backoff has passed
+			System::set_block_number(1 + backoff);
+			assert_eq!(Ok(false), <DataAssets as ResultsHandler<Test, _, _, _>>::record_failed_attempt(
+				Origin::signed(gateway.clone()), cmd_id,
+			));
+			// Then: the backoff doubles
+			assert_eq!(
+				IngestionAttempts { attempts: 2, next_attempt: 1 + 3 * backoff },
+				DataAssets::ingestion_attempts(cmd_id)
+			);
+			assert_eq!(1 + 3 * backoff, <DataAssets as QueueManager<_, _>>::next_attempt(cmd_id));
+			// When: the gateway fails for the last allowed time
+			System::set_block_number(1 + 3 * backoff);
+			assert_eq!(Ok(true), <DataAssets as ResultsHandler<Test, _, _, _>>::record_failed_attempt(
+				Origin::signed(gateway.clone()), cmd_id,
+			));
+			// Then: the command is abandoned
+			assert!(crate::IngestionCommands::<Test>::get(gateway.clone()).is_empty());
+			assert_eq!(IngestionAttempts::default(), DataAssets::ingestion_attempts(cmd_id));
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::CommandAbandoned(cmd_id, gateway)));
+			// And: with no replica left to ingest it, the request and its reserve are released
+			assert_eq!(None, crate::ReplicaOf::<Test>::get(cmd_id));
+			assert!(crate::Replication::<Test>::get(request_id).is_none());
+			assert!(!crate::RequestReserve::<Test>::contains_key(request_id));
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_submit_oversized_pin_report() {
+	// Given: I am a gateway
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I report on more than the maximum number of assets
+			let max = <Test as crate::Config>::MaxPinReportSize::get();
+			let report = (0..max + 1).map(|id| (id, true)).collect::<Vec<_>>();
+			// Then: the report is rejected
+			assert_noop!(
+				DataAssets::submit_pin_report(Origin::signed(test_data.p.clone().public()), report),
+				Error::<Test>::PinReportTooLarge
+			);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_request_ingestion_with_zero_min_balance() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I request an asset class with a min balance of 0
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				0,
+				1,
+				1,
+				None,
+			), Error::<Test>::InvalidMinBalance);
+			// When: I request an asset class with a positive min balance
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				1,
+				1,
+				1,
+				None,
+			));
+			// Then: the command is queued with the min balance
+			let cmds = crate::IngestionCommands::<Test>::get(test_data.p.clone().public());
+			assert_eq!(1, cmds.len());
+			assert_eq!(1, cmds[0].balance);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_request_ingestion_from_a_malformed_multiaddress() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I request ingestion from a multiaddress with an unknown protocol
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				b"/ip4/127.0.0.1/tpc/4001".to_vec(),
+				1_000,
+				1,
+				1,
+				1,
+				None,
+			), Error::<Test>::InvalidMultiaddress);
+			// When: I request a dataset one of whose files has a malformed multiaddress
+			// Then: the request is rejected
+			assert_noop!(DataAssets::create_dataset_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				vec![
+					(test_data.cid_vec.clone(), test_data.multiaddr_vec.clone()),
+					(b"QmSecond".to_vec(), b"/ip4/127.0.0.256".to_vec()),
+				],
+				1_000,
+				1,
+				1,
+				None,
+			), Error::<Test>::InvalidMultiaddress);
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_create_asset_class_if_command_not_queued() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// And: I have queued an ingestion request with a gateway
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let mut cmd = crate::IngestionCommands::<Test>::get(test_data.p.clone().public())[0].clone();
+			// When: I try to create an asset class for a command whose id is not queued
+			cmd.id = [0; 32];
+			// Then: the call fails
+			assert_noop!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(test_data.p.clone().public()), cmd, 2, 1024,
+			), Error::<Test>::NoSuchIngestionCommand);
+		});
+	})
+}
+
+#[test]
+fn data_assets_tracks_assets_ingested_by_gateway() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let gateway = test_data.p.clone().public();
+			// And: I have staged data and queued an ingestion request with a gateway
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				test_data.p.clone().public(), test_data.public_key.clone(),
+			));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			// When: the gateway completes ingestion
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd, 2, 1024,
+			));
+			// Then: the asset is added to the gateway's manifest
+			assert_eq!(vec![2], DataAssets::ingested_assets(gateway.clone(), 0, 10));
+			assert_eq!(Vec::<u32>::new(), DataAssets::ingested_assets(gateway.clone(), 1, 10));
+			// When: the gateway reports that it dropped its pin
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(gateway.clone()), vec![(2, false)]));
+			// Then: the asset is removed from its manifest
+			assert_eq!(Vec::<u32>::new(), DataAssets::ingested_assets(gateway.clone(), 0, 10));
+			// When: the gateway pins the asset again
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(gateway.clone()), vec![(2, true)]));
+			// Then: it is back in its manifest
+			assert_eq!(vec![2], DataAssets::ingested_assets(gateway.clone(), 0, 10));
+			// When: the gateway deregisters
+			<DataAssets as QueueManager<_, _>>::release_gateway_assets(gateway.clone());
+			// Then: its replica is ejected and its manifest is empty
+			assert_eq!(Vec::<u32>::new(), DataAssets::ingested_assets(gateway.clone(), 0, 10));
+			assert_eq!(0, DataAssets::replica_count(2));
+		});
+	})
+}
+
+#[test]
+fn data_assets_records_a_bounded_asset_history() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let gateway = test_data.p.clone().public();
+			let consumer = validators()[0].0.clone();
+			// Given: a gateway ingested an asset at block 1
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				test_data.p.clone().public(), test_data.public_key.clone(),
+			));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd, 2, 1024,
+			));
+			// When: the gateway ejects its only replica at block 2
+			System::set_block_number(2);
+			DataAssets::remove_ingested_asset(gateway.clone(), 2);
+			// Then: the creation, ejection and freeze are recorded in order
+			assert_eq!(vec![
+				(1, AssetLifecycleEvent::Created, Some(gateway.clone())),
+				(2, AssetLifecycleEvent::Ejected, Some(gateway.clone())),
+				(2, AssetLifecycleEvent::Frozen, None),
+			], DataAssets::asset_history(2));
+			// When: more events are recorded than the history holds
+			System::set_block_number(3);
+			for _ in 0..2 {
+				<DataAssets as AssetHistoryRecorder<_, _>>::record(
+					2, AssetLifecycleEvent::Accessed, Some(consumer.clone()),
+				);
+			}
+			// Then: only the most recent events are kept
+			let max = <Test as crate::Config>::MaxAssetHistory::get() as usize;
+			let history = DataAssets::asset_history(2);
+			assert_eq!(max, history.len());
+			assert_eq!(vec![
+				(2, AssetLifecycleEvent::Ejected, Some(gateway.clone())),
+				(2, AssetLifecycleEvent::Frozen, None),
+				(3, AssetLifecycleEvent::Accessed, Some(consumer.clone())),
+				(3, AssetLifecycleEvent::Accessed, Some(consumer.clone())),
+			], history);
+		});
+	})
+}
+
+#[test]
+fn data_assets_indexes_assets_by_creation_block() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let gateway = test_data.p.clone().public();
+			// And: a gateway ingests one asset at block 5 and another at block 250
+			for (block, asset_id) in [(5, 2), (250, 3)] {
+				System::set_block_number(block);
+				assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+					test_data.p.clone().public(), test_data.public_key.clone(),
+				));
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(test_data.p.clone().public()),
+					gateway.clone(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+				let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+				assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+					Origin::signed(gateway.clone()), cmd, asset_id, 1024,
+				));
+			}
+			// When: I query block ranges
+			// Then: only the assets created within each range are returned, in creation order
+			assert_eq!(vec![2, 3], DataAssets::assets_created_between(0, 1000, 0, 10));
+			assert_eq!(vec![2], DataAssets::assets_created_between(0, 100, 0, 10));
+			assert_eq!(vec![3], DataAssets::assets_created_between(250, 250, 0, 10));
+			assert_eq!(Vec::<u32>::new(), DataAssets::assets_created_between(6, 249, 0, 10));
+			assert_eq!(Vec::<u32>::new(), DataAssets::assets_created_between(250, 5, 0, 10));
+			// And: results are paginated
+			assert_eq!(vec![3], DataAssets::assets_created_between(0, 1000, 1, 1));
+			// And: an unbounded range is cut to the widest span searched
+			assert_eq!(vec![2, 3], DataAssets::assets_created_between(0, u32::MAX, 0, 10));
+		});
+	})
+}
+
+#[test]
+fn data_assets_creates_asset_class_with_requested_or_generated_id() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			let request = |requested_asset_id: Option<u32>| {
+				assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+					owner.clone(), test_data.public_key.clone(),
+				));
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(owner.clone()),
+					owner.clone(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					requested_asset_id,
+				));
+				crate::IngestionCommands::<Test>::get(owner.clone())[0].clone()
+			};
+			// And: I queued an ingestion request asking for asset id 7
+			let cmd = request(Some(7));
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			assert_eq!(Some(7), DataAssets::requested_asset_id(request_id));
+			assert_eq!(Some(request_id), DataAssets::reserved_asset_id(7));
+			// When: the gateway completes ingestion with a generated id
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// Then: the asset class is created with the requested id and the reservation is released
+			assert_eq!(vec![7], crate::AssetClassOwnership::<Test>::get(owner.clone()));
+			assert_eq!(None, DataAssets::requested_asset_id(request_id));
+			assert_eq!(None, DataAssets::reserved_asset_id(7));
+			// When: I queue a request without asking for an id and the gateway completes it
+			let cmd = request(None);
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// Then: the asset class is created with the generated id
+			assert_eq!(vec![7, 2], crate::AssetClassOwnership::<Test>::get(owner.clone()));
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_request_ingestion_of_multi_file_datasets() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			let file = |cid: &[u8]| (cid.to_vec(), test_data.multiaddr_vec.clone());
+			let request = |files: Vec<(Vec<u8>, Vec<u8>)>| DataAssets::create_dataset_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.balance.clone(),
+				files,
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				None,
+			);
+			// When: I request a dataset without any files
+			// Then: the request is rejected
+			assert_noop!(request(Vec::new()), Error::<Test>::EmptyDataset);
+			// When: I request a dataset with more than the maximum number of files
+			// Then: the request is rejected
+			let max = <Test as crate::Config>::MaxDatasetFiles::get() as usize;
+			let too_many = (0..=max).map(|i| file(&[i as u8])).collect();
+			assert_noop!(request(too_many), Error::<Test>::TooManyDatasetFiles);
+			// When: I request a dataset of three files
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				owner.clone(), test_data.public_key.clone(),
+			));
+			assert_ok!(request(vec![
+				file(&test_data.cid_vec), file(b"QmSecond"), file(b"QmThird"),
+			]));
+			// Then: the first file is queued as the primary ciphertext
+			let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+			assert_eq!(test_data.cid_vec.clone(), cmd.cid);
+			// And: the gateway can see the other files
+			assert_eq!(
+				vec![file(b"QmSecond"), file(b"QmThird")],
+				<DataAssets as QueueManager<_, _>>::dataset_files(cmd.id),
+			);
+			// When: the gateway completes ingestion
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// Then: the asset class lists the other files' cids
+			let metadata = crate::Metadata::<Test>::get(2).unwrap();
+			assert_eq!(test_data.cid_vec.clone(), metadata.ciphertext_cid);
+			assert_eq!(vec![b"QmSecond".to_vec(), b"QmThird".to_vec()], metadata.extra_cids);
+		});
+	})
+}
+
+#[test]
+fn data_assets_migrates_metadata_to_v1() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: an asset class's metadata was stored as (cid, public key) before versioning
+		frame_support::storage::unhashed::put(
+			&crate::Metadata::<Test>::hashed_key_for(1),
+			&(b"QmOld".to_vec(), vec![1u8, 2, 3]),
+		);
+		frame_support::traits::StorageVersion::new(0).put::<DataAssets>();
+		// When: the migration runs
+		crate::migrations::v1::migrate::<Test>();
+		// Then: the metadata is decodable and keeps its cid and public key
+		let metadata: crate::migrations::v2::OldAssetMetadata = frame_support::storage::unhashed::get(
+			&crate::Metadata::<Test>::hashed_key_for(1),
+		).unwrap();
+		assert_eq!(b"QmOld".to_vec(), metadata.ciphertext_cid);
+		assert_eq!(vec![1, 2, 3], metadata.public_key);
+		assert_eq!(0, metadata.ciphertext_size);
+		assert!(metadata.extra_cids.is_empty());
+		// And: the storage version is bumped
+		assert_eq!(1, frame_support::traits::StorageVersion::get::<DataAssets>());
+	});
+}
+
+#[test]
+fn data_assets_migrates_queued_commands_to_v1() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: a gateway's queue holds a command stored as (owner, cid, multiaddress, balance)
+		let gateway = sp_core::sr25519::Public::from_raw([1; 32]);
+		let owner = sp_core::sr25519::Public::from_raw([2; 32]);
+		frame_support::storage::unhashed::put(
+			&crate::IngestionCommands::<Test>::hashed_key_for(&gateway),
+			&vec![(owner.clone(), b"QmOld".to_vec(), b"/ip4/127.0.0.1/tcp/4001".to_vec(), 1u32)],
+		);
+		frame_support::traits::StorageVersion::new(0).put::<DataAssets>();
+		System::set_block_number(7);
+		// When: the migration runs
+		crate::migrations::v1::migrate::<Test>();
+		// Then: the command is decodable and queued at the upgrade block
+		let commands = DataAssets::ingestion_commands(gateway.clone());
+		assert_eq!(1, commands.len());
+		assert_eq!(owner, commands[0].owner);
+		assert_eq!(b"QmOld".to_vec(), commands[0].cid);
+		assert_eq!(1, commands[0].balance);
+		assert_eq!(7, commands[0].queued_at);
+		// And: it replicates a single replica request
+		let request_id = crate::ReplicaOf::<Test>::get(commands[0].id).unwrap();
+		assert_eq!(1, DataAssets::replication(request_id).unwrap().replication);
+		assert_eq!(b"QmOld".to_vec(), crate::RequestCommand::<Test>::get(request_id).unwrap().cid);
+	});
+}
+
+#[test]
+fn data_assets_migrates_baseline_storage_on_runtime_upgrade() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: an asset class's metadata was stored as (cid, public key) before versioning
+		frame_support::storage::unhashed::put(
+			&crate::Metadata::<Test>::hashed_key_for(1),
+			&(b"QmOld".to_vec(), vec![1u8, 2, 3]),
+		);
+		frame_support::traits::StorageVersion::new(0).put::<DataAssets>();
+		// When: the runtime is upgraded
+		<DataAssets as frame_support::traits::Hooks<u64>>::on_runtime_upgrade();
+		// Then: the metadata passes through every migration
+		let metadata = crate::Metadata::<Test>::get(1).unwrap();
+		assert_eq!(b"QmOld".to_vec(), metadata.ciphertext_cid);
+		assert_eq!(vec![1, 2, 3], metadata.public_key);
+		assert_eq!(iris_primitives::EncryptionScheme::UmbralSalsaBox, metadata.encryption_scheme);
+		assert_eq!(None, metadata.plaintext_checksum);
+		// And: the storage version is current
+		assert_eq!(6, frame_support::traits::StorageVersion::get::<DataAssets>());
+	});
+}
+
+#[test]
+fn data_assets_migrates_metadata_to_v2() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: an asset class's metadata was stored before it recorded an encryption scheme
+		let old = crate::migrations::v2::OldAssetMetadata {
+			ciphertext_cid: b"QmOld".to_vec(),
+			ciphertext_size: 1024,
+			public_key: vec![1, 2, 3],
+			name: None,
+			description: None,
+			providers: Vec::new(),
+			extra_cids: vec![b"QmOther".to_vec()],
+		};
+		frame_support::storage::unhashed::put(&crate::Metadata::<Test>::hashed_key_for(1), &old);
+		frame_support::traits::StorageVersion::new(1).put::<DataAssets>();
+		// When: the migration runs
+		crate::migrations::v2::migrate::<Test>();
+		// Then: the metadata is decodable and defaults to umbral
+		let metadata: crate::migrations::v3::OldAssetMetadata = frame_support::storage::unhashed::get(
+			&crate::Metadata::<Test>::hashed_key_for(1),
+		).unwrap();
+		assert_eq!(vec![b"QmOther".to_vec()], metadata.extra_cids);
+		assert_eq!(iris_primitives::EncryptionScheme::UmbralSalsaBox, metadata.encryption_scheme);
+		// And: the storage version is bumped
+		assert_eq!(2, frame_support::traits::StorageVersion::get::<DataAssets>());
+	});
+}
+
+#[test]
+fn data_assets_migrates_metadata_to_v3() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: an asset class's metadata was stored before owners could attest a checksum
+		let old = crate::migrations::v3::OldAssetMetadata {
+			ciphertext_cid: b"QmOld".to_vec(),
+			ciphertext_size: 1024,
+			public_key: vec![1, 2, 3],
+			name: Some(b"photos".to_vec()),
+			description: None,
+			providers: Vec::new(),
+			extra_cids: Vec::new(),
+			encryption_scheme: iris_primitives::EncryptionScheme::UmbralSalsaBox,
+		};
+		frame_support::storage::unhashed::put(&crate::Metadata::<Test>::hashed_key_for(1), &old);
+		frame_support::traits::StorageVersion::new(2).put::<DataAssets>();
+		// When: the migration runs
+		crate::migrations::v3::migrate::<Test>();
+		// Then: the metadata is decodable and has no checksum
+		let metadata: crate::migrations::v5::OldAssetMetadata = frame_support::storage::unhashed::get(
+			&crate::Metadata::<Test>::hashed_key_for(1),
+		).unwrap();
+		assert_eq!(Some(b"photos".to_vec()), metadata.name);
+		assert_eq!(None, metadata.plaintext_checksum);
+		// And: the storage version is bumped
+		assert_eq!(3, frame_support::traits::StorageVersion::get::<DataAssets>());
+	});
+}
+
+#[test]
+fn data_assets_migrates_metadata_to_v5() {
+	new_test_ext(validators()).execute_with(|| {
+		// Given: an asset class's metadata was stored with a u64 ciphertext size
+		let old = crate::migrations::v5::OldAssetMetadata {
+			ciphertext_cid: b"QmOld".to_vec(),
+			ciphertext_size: u64::MAX,
+			public_key: vec![1, 2, 3],
+			name: Some(b"photos".to_vec()),
+			description: None,
+			providers: Vec::new(),
+			extra_cids: Vec::new(),
+			encryption_scheme: iris_primitives::EncryptionScheme::UmbralSalsaBox,
+			plaintext_checksum: Some(vec![7; 32]),
+		};
+		frame_support::storage::unhashed::put(&crate::Metadata::<Test>::hashed_key_for(1), &old);
+		frame_support::traits::StorageVersion::new(4).put::<DataAssets>();
+		// When: the migration runs
+		crate::migrations::v5::migrate::<Test>();
+		// Then: the metadata is decodable and keeps its size and checksum
+		let metadata = crate::Metadata::<Test>::get(1).unwrap();
+		assert_eq!(u64::MAX as u128, metadata.ciphertext_size);
+		assert_eq!(Some(vec![7; 32]), metadata.plaintext_checksum);
+		// And: the storage version is bumped
+		assert_eq!(5, frame_support::traits::StorageVersion::get::<DataAssets>());
+	});
+}
+
+#[test]
+fn data_assets_migrates_request_index_to_v6() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			// Given: a request was created before requests were indexed by owner and cid
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				validators()[0].0.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(validators()[0].0.clone())[0].clone();
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			crate::RequestsByCid::<Test>::remove(owner.clone(), test_data.cid_vec.clone());
+			assert!(DataAssets::command_assignments(owner.clone(), test_data.cid_vec.clone()).is_empty());
+			frame_support::traits::StorageVersion::new(5).put::<DataAssets>();
+			// When: the migration runs
+			crate::migrations::v6::migrate::<Test>();
+			// Then: the request is indexed under its owner and cid
+			assert_eq!(vec![request_id], crate::RequestsByCid::<Test>::get(owner.clone(), test_data.cid_vec.clone()));
+			assert_eq!(
+				vec![(validators()[0].0.clone(), false)],
+				DataAssets::command_assignments(owner.clone(), test_data.cid_vec.clone()),
+			);
+			// And: the storage version is bumped
+			assert_eq!(6, frame_support::traits::StorageVersion::get::<DataAssets>());
+		});
+	})
+}
+
+#[test]
+fn data_assets_migrates_queued_replicas_to_v4() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			// Given: a request was queued with a gateway before its replicas were indexed
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				validators()[0].0.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let gateway = validators()[0].0.clone();
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			crate::RequestReplicas::<Test>::remove(request_id);
+			crate::PendingRequests::<Test>::remove(owner.clone());
+			frame_support::traits::StorageVersion::new(3).put::<DataAssets>();
+			// When: the migration runs
+			crate::migrations::v4::migrate::<Test>();
+			// Then: the replica is indexed under its request
+			assert_eq!(vec![(gateway, cmd.id)], crate::RequestReplicas::<Test>::get(request_id));
+			// And: the request counts as pending for its owner
+			assert_eq!(1, crate::PendingRequests::<Test>::get(owner.clone()));
+			// And: the storage version is bumped
+			assert_eq!(4, frame_support::traits::StorageVersion::get::<DataAssets>());
+		});
+	})
+}
+
+#[test]
+fn data_assets_can_not_request_taken_asset_id() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let owner = test_data.p.clone().public();
+			let request = |requested_asset_id: Option<u32>| DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				requested_asset_id,
+			);
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				owner.clone(), test_data.public_key.clone(),
+			));
+			// And: I queued an ingestion request asking for asset id 7
+			assert_ok!(request(Some(7)));
+			// When: I ask for asset id 7 again
+			// Then: it is rejected since the id is reserved
+			assert_noop!(request(Some(7)), Error::<Test>::AssetIdTaken);
+			// When: the first request is ingested
+			let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+			let request_id = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// Then: asset id 7 is still taken, now by the asset class
+			assert_noop!(request(Some(7)), Error::<Test>::AssetIdTaken);
+			// And: a killed request releases the id it reserved
+			assert_ok!(request(Some(8)));
+			let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+			let killed = crate::ReplicaOf::<Test>::get(cmd.id).unwrap();
+			assert_ne!(request_id, killed);
+			assert_ok!(DataAssets::kill_request(Origin::signed(owner.clone()), killed));
+			assert_eq!(None, DataAssets::reserved_asset_id(8));
+			assert_ok!(request(Some(8)));
+		});
+	})
+}
+
+#[test]
+fn data_assets_freezes_assets_without_replicas_and_thaws_restored_ones() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let gateway = test_data.p.clone().public();
+			// And: a gateway has pinned the only replica of an asset
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				test_data.p.clone().public(), test_data.public_key.clone(),
+			));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd, 2, 1024,
+			));
+			assert_eq!(1, DataAssets::replica_count(2));
+			assert_eq!(false, DataAssets::frozen_unavailable(2));
+			// When: the gateway reports that it dropped its pin
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(gateway.clone()), vec![(2, false)]));
+			// Then: no replicas remain and the asset is frozen
+			assert_eq!(0, DataAssets::replica_count(2));
+			assert_eq!(true, DataAssets::frozen_unavailable(2));
+			System::assert_has_event(mock::Event::Assets(pallet_assets::Event::AssetFrozen { asset_id: 2 }));
+			System::assert_has_event(mock::Event::DataAssets(crate::Event::AssetFrozen(2)));
+			// When: the gateway pins the asset again
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(gateway.clone()), vec![(2, true)]));
+			// Then: the asset is thawed
+			assert_eq!(1, DataAssets::replica_count(2));
+			assert_eq!(false, DataAssets::frozen_unavailable(2));
+			System::assert_has_event(mock::Event::Assets(pallet_assets::Event::AssetThawed { asset_id: 2 }));
+			System::assert_has_event(mock::Event::DataAssets(crate::Event::AssetThawed(2)));
+			// When: the gateway ejects the asset
+			DataAssets::remove_ingested_asset(gateway.clone(), 2);
+			// Then: the asset is frozen again
+			assert_eq!(0, DataAssets::replica_count(2));
+			assert_eq!(true, DataAssets::frozen_unavailable(2));
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::AssetFrozen(2)));
+		});
+	})
+}
+
+#[test]
+fn data_assets_sums_the_reserves_pending_for_a_gateway() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let gateway = test_data.p.clone().public();
+			// And: no requests are queued with the gateway
+			assert_eq!(0, DataAssets::pending_reserves(gateway.clone()).total);
+			// When: I queue two requests with different reserves
+			for reserve in [4, 6] {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(test_data.p.clone().public()),
+					gateway.clone(),
+					reserve,
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+			}
+			// Then: the pending reserves list each command and sum to the total
+			let cmds = crate::IngestionCommands::<Test>::get(gateway.clone());
+			let reserves = DataAssets::pending_reserves(gateway.clone());
+			assert_eq!(10, reserves.total);
+			assert_eq!(vec![(cmds[0].id, 4), (cmds[1].id, 6)], reserves.commands);
+		});
+	})
+}
+
+#[test]
+fn data_assets_records_ingestion_latency_per_gateway() {
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			let gateway = test_data.p.clone().public();
+			// And: I have staged data and queued an ingestion request at block 1
+			System::set_block_number(1);
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(
+				test_data.p.clone().public(), test_data.public_key.clone(),
+			));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(gateway.clone())[0].clone();
+			assert_eq!(1, cmd.queued_at);
+			// When: the gateway completes ingestion at block 5
+			System::set_block_number(5);
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(gateway.clone()), cmd, 2, 1024,
+			).unwrap();
+			// Then: the latency is the number of blocks elapsed
+			assert_eq!(4, outcome.latency);
+			// And: the gateway's stats are updated
+			let stats = DataAssets::gateway_stats(gateway.clone());
+			assert_eq!(1, stats.completed);
+			assert_eq!(4, stats.average_latency);
 		});
 	})
 }
@@ -101,12 +1787,239 @@ fn data_assets_can_not_create_request_if_funds_too_low() {
 				test_data.balance.clone(),
 				test_data.cid_vec.clone(),
 				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
 			).unwrap();
 		});
 	})
 }
 
+#[test]
+fn data_assets_admin_can_set_asset_info() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			// Given: I own an asset class
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// And: it exists, unlike an asset that was never created
+			assert!(DataAssets::asset_exists(2));
+			assert!(!DataAssets::asset_exists(3));
+			// When: I set its name and description
+			assert_ok!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, Some(b"photos".to_vec()), Some(b"my photos".to_vec()),
+			));
+			// Then: they are stored in its metadata
+			let metadata = DataAssets::metadata(2).unwrap();
+			assert_eq!(Some(b"photos".to_vec()), metadata.name);
+			assert_eq!(Some(b"my photos".to_vec()), metadata.description);
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::AssetInfoUpdated(2)));
+			// And: only the admin can set them
+			assert_noop!(DataAssets::set_asset_info(
+				Origin::signed(test_data.q.clone().public()), 2, None, None,
+			), Error::<Test>::NotAssetAdmin);
+			// And: oversized fields are rejected
+			assert_noop!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, Some(vec![b'a'; 9]), None,
+			), Error::<Test>::AssetNameTooLong);
+			assert_noop!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, None, Some(vec![b'a'; 17]),
+			), Error::<Test>::AssetDescriptionTooLong);
+			// And: fields that are not utf8 are rejected
+			assert_noop!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, Some(vec![0xff, 0xfe]), None,
+			), Error::<Test>::InvalidUtf8);
+			assert_noop!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, None, Some(vec![b'a', 0xc3]),
+			), Error::<Test>::InvalidUtf8);
+			// And: the fields can be cleared
+			assert_ok!(DataAssets::set_asset_info(Origin::signed(owner.clone()), 2, None, None));
+			assert_eq!(None, DataAssets::metadata(2).unwrap().name);
+		});
+	})
+}
+
+#[test]
+fn data_assets_owner_can_set_plaintext_checksum() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			// Given: I own an asset class
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 2, 1024,
+			));
+			// And: it has no checksum yet
+			assert_eq!(None, DataAssets::metadata(2).unwrap().plaintext_checksum);
+			// When: I attest a checksum that is not a sha-256 digest
+			// Then: it is rejected
+			assert_noop!(DataAssets::set_plaintext_checksum(
+				Origin::signed(owner.clone()), 2, vec![7; 31],
+			), Error::<Test>::InvalidChecksumLength);
+			// When: I attest the sha-256 digest of the plaintext
+			let checksum = sp_io::hashing::sha2_256(b"plaintext").to_vec();
+			assert_ok!(DataAssets::set_plaintext_checksum(
+				Origin::signed(owner.clone()), 2, checksum.clone(),
+			));
+			// Then: it is stored in the asset's metadata
+			assert_eq!(Some(checksum), DataAssets::metadata(2).unwrap().plaintext_checksum);
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::PlaintextChecksumSet(2)));
+			// And: only the owner can attest it
+			assert_noop!(DataAssets::set_plaintext_checksum(
+				Origin::signed(test_data.q.clone().public()), 2, vec![7; 32],
+			), Error::<Test>::NotAssetAdmin);
+		});
+	})
+}
+
+#[test]
+fn data_assets_sets_pallet_assets_metadata() {
+	use frame_support::traits::fungibles::metadata::Inspect;
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let owner = test_data.p.clone().public();
+			let create = |asset_id: u32| {
+				assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(owner.clone(), test_data.public_key.clone()));
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(owner.clone()),
+					owner.clone(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+				let cmd = crate::IngestionCommands::<Test>::get(owner.clone())[0].clone();
+				assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+					Origin::signed(owner.clone()), cmd, asset_id, 1024,
+				));
+			};
+			// When: an asset class is created
+			create(2);
+			// Then: it is labelled with its cid in pallet_assets
+			assert_eq!(test_data.cid_vec.clone(), <pallet_assets::Pallet<Test>>::name(2));
+			assert_eq!(crate::ASSET_SYMBOL.to_vec(), <pallet_assets::Pallet<Test>>::symbol(2));
+			// When: its admin names it
+			assert_ok!(DataAssets::set_asset_info(
+				Origin::signed(owner.clone()), 2, Some(b"photos".to_vec()), None,
+			));
+			// Then: the name is set in pallet_assets
+			assert_eq!(b"photos".to_vec(), <pallet_assets::Pallet<Test>>::name(2));
+			// When: setting the metadata is disabled and another asset class is created
+			SetAssetMetadata::set(false);
+			create(3);
+			// Then: it has no pallet_assets metadata
+			assert!(<pallet_assets::Pallet<Test>>::name(3).is_empty());
+			SetAssetMetadata::set(true);
+		});
+	})
+}
+
+#[test]
+fn data_assets_halt_blocks_requests_but_not_reads() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.p.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let gateway = test_data.p.clone().public();
+			// Given: a request is already queued with a gateway
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(gateway.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// And: only root can halt the chain
+			assert_noop!(
+				DataAssets::set_halted(Origin::signed(gateway.clone()), true),
+				sp_runtime::DispatchError::BadOrigin,
+			);
+			// When: root halts ingestion
+			assert_ok!(DataAssets::set_halted(Origin::root(), true));
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::HaltStatusChanged(true)));
+			assert!(<DataAssets as QueueManager<_, _>>::is_halted());
+			// Then: new requests are rejected
+			assert_noop!(DataAssets::create_request(
+				Origin::signed(gateway.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			), Error::<Test>::Halted);
+			// And: reads still work
+			assert_eq!(1, DataAssets::ingestion_commands(gateway.clone()).len());
+			assert_eq!(Vec::<u32>::new(), DataAssets::ingested_assets(gateway.clone(), 0, 10));
+			// When: root resumes ingestion
+			assert_ok!(DataAssets::set_halted(Origin::root(), false));
+			System::assert_last_event(mock::Event::DataAssets(crate::Event::HaltStatusChanged(false)));
+			// Then: requests can be created again
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(gateway.clone()),
+				gateway.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+		});
+	})
+}
+
 fn validators() -> Vec<(sp_core::sr25519::Public, UintAuthorityId)> {
 	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
 		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
@@ -101,12 +101,26 @@ pub mod pallet {
         DataSpaceMetadata<T::AssetId>,
     >;
 
+    /// the asset classes associated with each dataspace, keyed by (dataspace id, asset id)
+    #[pallet::storage]
+    pub(super) type AssetsByDataspace<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AssetId,
+        Blake2_128Concat,
+        T::AssetId,
+        (),
+        OptionQuery,
+    >;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
         DataSpaceCreationSuccess(T::AccountId, T::AssetId),
         DataSpaceAssetCreationSuccess(T::AccountId, T::AssetId),
         DataSpaceAssociationSuccess(T::AssetId, T::AssetId),
+        /// An asset class was removed from a dataspace (dataspace id, asset id)
+        DataSpaceDissociationSuccess(T::AssetId, T::AssetId),
 	}
 
 	#[pallet::error]
@@ -114,6 +128,8 @@ pub mod pallet {
         CantCreateAssetClass,
         CantMintAssets,
         DataSpaceNotAccessible,
+        /// the dataspace does not exist
+        NoSuchDataSpace,
 	}
 
 	#[pallet::call]
@@ -180,11 +196,12 @@ pub mod pallet {
             let balance = <pallet_assets::Pallet<T>>::balance(dataspace_id, who);
             let balance_primitive = TryInto::<u128>::try_into(balance).ok();
             ensure!(balance_primitive != Some(0), Error::<T>::DataSpaceNotAccessible);
-            let mut metadata = <Metadata::<T>>::get(dataspace_id).unwrap();
+            let mut metadata = <Metadata::<T>>::get(dataspace_id).ok_or(Error::<T>::NoSuchDataSpace)?;
             //  duplicate avoidance 
-            if !metadata.asset_ids.contains(&asset_class_id.clone()) {
+            if !<AssetsByDataspace<T>>::contains_key(dataspace_id, asset_class_id) {
                 metadata.asset_ids.push(asset_class_id);
                 <Metadata<T>>::insert(dataspace_id, metadata);
+                <AssetsByDataspace<T>>::insert(dataspace_id, asset_class_id, ());
                 Self::deposit_event(Event::DataSpaceAssociationSuccess(
                     dataspace_id, asset_class_id,
                 ));
@@ -192,9 +209,53 @@ pub mod pallet {
             
             Ok(())
         }
+
+        /// remove an asset class from a data space
+        /// 
+        /// * `dataspace_id`: The dataspace to remove the asset class from
+        /// * `asset_class_id`: The asset class to remove
+        /// 
+        #[pallet::weight(100)]
+        pub fn unbond(
+            origin: OriginFor<T>,
+            dataspace_id: T::AssetId,
+            asset_class_id: T::AssetId,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            // check that the caller has dataspace access
+            let balance = <pallet_assets::Pallet<T>>::balance(dataspace_id, who);
+            let balance_primitive = TryInto::<u128>::try_into(balance).ok();
+            ensure!(balance_primitive != Some(0), Error::<T>::DataSpaceNotAccessible);
+            let mut metadata = <Metadata::<T>>::get(dataspace_id).ok_or(Error::<T>::NoSuchDataSpace)?;
+            if <AssetsByDataspace<T>>::take(dataspace_id, asset_class_id).is_some() {
+                metadata.asset_ids.retain(|id| *id != asset_class_id);
+                <Metadata<T>>::insert(dataspace_id, metadata);
+                Self::deposit_event(Event::DataSpaceDissociationSuccess(
+                    dataspace_id, asset_class_id,
+                ));
+            }
+
+            Ok(())
+        }
 	}
 }
 
 impl<T: Config> Pallet<T> {
-
+    /// Get a page of the asset ids associated with a dataspace, in storage order.
+    /// Unknown dataspaces have no assets.
+    /// 
+    /// * `dataspace_id`: The dataspace
+    /// * `page`: The index of the page to fetch, starting from 0
+    /// * `page_size`: The maximum number of asset ids in a page
+    /// 
+    pub fn assets_in_dataspace(
+        dataspace_id: T::AssetId,
+        page: u32,
+        page_size: u32,
+    ) -> Vec<T::AssetId> {
+        <AssetsByDataspace<T>>::iter_key_prefix(dataspace_id)
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect()
+    }
 }
\ No newline at end of file
@@ -21,7 +21,7 @@ use pallet_data_assets;
 use frame_support::{
 	parameter_types,
 	construct_runtime,
-	traits::ConstU32,
+	traits::{ConstBool, ConstU32},
 };
 use frame_system::EnsureRoot;
 use sp_runtime::{
@@ -278,6 +278,22 @@ impl pallet_data_assets::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = ConstBool<true>;
+	type SetAssetMetadata = ConstBool<true>;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<16>;
+	type GatewayPricing = ();
+	type GatewayProvider = ();
+	type ProxyPrefs = ();
+	type PeerIdProvider = ();
 }
 
 type Extrinsic = TestXt<Call, ()>;
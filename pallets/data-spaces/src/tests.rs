@@ -114,3 +114,56 @@ fn data_spaces_can_associate_asset_id_with_data_space() {
 		));
 	});
 }
+
+#[test]
+fn data_spaces_can_list_and_unbond_assets() {
+	// GIVEN: I am a valid Iris node with access to a dataspace
+	let (p, _) = sp_core::sr25519::Pair::generate();
+	let pairs = vec![(p.clone().public(), 10)];
+	let balance = 1;
+	let dataspace_id = 1;
+	let name: Vec<u8> = "test space".as_bytes().to_vec();
+
+	new_test_ext_funded(pairs).execute_with(|| {
+		assert_ok!(DataSpaces::create(
+			Origin::signed(p.clone().public()),
+			p.clone().public(),
+			name.clone(),
+			dataspace_id.clone(),
+			balance.clone(),
+		));
+		assert_ok!(DataSpaces::mint(
+			Origin::signed(p.clone().public()),
+			p.clone().public(),
+			dataspace_id.clone(),
+			balance.clone(),
+		));
+		// AND: I bond three asset classes to it, one of them twice
+		for asset_id in [2, 3, 4, 3] {
+			assert_ok!(DataSpaces::bond(
+				Origin::signed(p.clone().public()),
+				dataspace_id.clone(),
+				asset_id,
+			));
+		}
+		// THEN: each asset is listed once, and pages split the listing
+		let mut all = DataSpaces::assets_in_dataspace(dataspace_id, 0, 100);
+		all.sort();
+		assert_eq!(vec![2, 3, 4], all);
+		assert_eq!(2, DataSpaces::assets_in_dataspace(dataspace_id, 0, 2).len());
+		assert_eq!(1, DataSpaces::assets_in_dataspace(dataspace_id, 1, 2).len());
+		// AND: an unknown dataspace has no assets
+		assert_eq!(Vec::<u32>::new(), DataSpaces::assets_in_dataspace(9, 0, 100));
+		// WHEN: I unbond an asset class
+		assert_ok!(DataSpaces::unbond(
+			Origin::signed(p.clone().public()),
+			dataspace_id.clone(),
+			3,
+		));
+		// THEN: it is no longer listed or in the dataspace metadata
+		let mut all = DataSpaces::assets_in_dataspace(dataspace_id, 0, 100);
+		all.sort();
+		assert_eq!(vec![2, 4], all);
+		assert_eq!(vec![2, 4], crate::Metadata::<Test>::get(dataspace_id).unwrap().asset_ids);
+	});
+}
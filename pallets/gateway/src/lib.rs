@@ -34,6 +34,8 @@
 mod mock;
 mod tests;
 
+pub mod migrations;
+
 use frame_support::{
 	ensure, parameter_types,
 	pallet_prelude::*,
@@ -46,7 +48,7 @@ use frame_support::{
 use scale_info::TypeInfo;
 pub use pallet::*;
 use sp_runtime::{
-	traits::{CheckedSub, Zero},
+	traits::{CheckedSub, SaturatedConversion, Saturating, Zero},
 };
 use sp_std::{
 	str,
@@ -64,6 +66,9 @@ pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"aura");
 
 const STAKING_ID: LockIdentifier = *b"staking ";
 
+/// the number of bytes in a gb, as reported by ipfs
+pub use iris_primitives::BYTES_PER_GB;
+
 type BalanceOf<T> =
 	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 /// Counter for the number of eras that have passed.
@@ -170,12 +175,8 @@ pub enum ProxyStatus {
 	Invalid,
 }
 
-/// preferences for a proxy node
-#[derive(PartialEq, Eq, Clone, Encode, Decode, RuntimeDebug, TypeInfo, Default)]
-pub struct GatewayPrefs {
-	pub max_mbps: u32,
-	pub storage_max_gb: u128,
-}
+/// preferences for a proxy node, defined alongside the other types shared between pallets
+pub use iris_primitives::GatewayPrefs;
 
 #[frame_support::pallet]
 pub mod pallet {
@@ -216,6 +217,15 @@ pub mod pallet {
 		/// Number of eras that staked funds must remain bonded for.
 		#[pallet::constant]
 		type BondingDuration: Get<EraIndex>;
+		/// The number of bytes in the unit that gateways price ingestion in (e.g. `BYTES_PER_GB`).
+		/// Sizes are always tracked in bytes, and reserves are prorated to the byte.
+		#[pallet::constant]
+		type SizeUnit: Get<u128>;
+		/// The minimum reserve per size unit that any gateway accepts for an ingestion request
+		#[pallet::constant]
+		type MinReservePerUnit: Get<Self::Balance>;
+		/// Provides the storage used by each proxy's ipfs node
+		type StorageReporter: StorageReporter<Self::AccountId>;
 	}
 
 	#[pallet::type_value]
@@ -223,11 +233,23 @@ pub mod pallet {
 		84u32
 	}
 
+	/// the current storage version
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(2);
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	#[pallet::without_storage_info]
 	pub struct Pallet<T>(_);
 
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_runtime_upgrade() -> Weight {
+			migrations::v1::migrate::<T>()
+				.saturating_add(migrations::v2::migrate::<T>())
+		}
+	}
+
 	/// The map from (wannabe) validator stash key to the preferences of that validator.
 	#[pallet::storage]
 	#[pallet::getter(fn proxies)]
@@ -298,6 +320,8 @@ pub mod pallet {
 		Unbonded(T::AccountId, BalanceOf<T>),
 		/// A proxy has set their preferences.
 		GatewayPrefsSet(T::AccountId, GatewayPrefs),
+		/// A gateway has updated its price per size unit. \[stash, price_per_unit\]
+		PricingUpdated(T::AccountId, u128),
 	}
 
 	// Errors inform users that something went wrong.
@@ -565,10 +589,65 @@ pub mod pallet {
 
 			Ok(())
 		}
+
+		/// Set the price per size unit that a gateway charges to ingest data.
+		/// The caller must be the controller of a declared gateway.
+		/// 
+		/// * price_per_unit: The minimum reserve per `SizeUnit` bytes the gateway accepts
+		/// 
+		#[pallet::weight(100)]
+		pub fn set_pricing(
+			origin: OriginFor<T>,
+			price_per_unit: u128,
+		) -> DispatchResult {
+			let controller = ensure_signed(origin)?;
+			let ledger = Self::ledger(&controller).ok_or(Error::<T>::NotController)?;
+			let stash = ledger.stash;
+			let mut prefs = Proxies::<T>::get(&stash).ok_or(Error::<T>::NoSuchProxy)?;
+			prefs.reserve_per_unit = price_per_unit;
+			Proxies::<T>::insert(&stash, prefs);
+			Self::deposit_event(Event::<T>::PricingUpdated(stash, price_per_unit));
+			Ok(())
+		}
 	}
 }
 
 impl<T: Config> Pallet<T> {
+	///
+	/// Estimate the minimum reserve a gateway will accept for ingesting some data.
+	/// The gateway's declared price per size unit is used, but never less than `MinReservePerUnit`.
+	/// If the gateway is unknown or hasn't set pricing, `MinReservePerUnit` is used.
+	/// 
+	/// * gateway: The gateway to estimate the reserve for
+	/// * estimated_size: The estimated size of the data to be ingested, in bytes
+	/// 
+	pub fn estimate_reserve(
+		gateway: Option<T::AccountId>,
+		estimated_size: u128,
+	) -> T::Balance {
+		let min_reserve_per_unit = T::MinReservePerUnit::get();
+		let reserve_per_unit = gateway
+			.and_then(Proxies::<T>::get)
+			.map(|prefs| prefs.reserve_per_unit.saturated_into::<T::Balance>())
+			.unwrap_or(min_reserve_per_unit)
+			.max(min_reserve_per_unit);
+		Self::prorated_reserve(reserve_per_unit, estimated_size)
+	}
+
+	///
+	/// Prorate a price per size unit to some number of bytes, rounding up so that
+	/// any data at a non-zero price costs a non-zero reserve
+	/// 
+	/// * reserve_per_unit: The reserve per `SizeUnit` bytes
+	/// * size: The size of the data, in bytes
+	/// 
+	fn prorated_reserve(reserve_per_unit: T::Balance, size: u128) -> T::Balance {
+		let unit = T::SizeUnit::get().max(1);
+		let total = reserve_per_unit.saturated_into::<u128>().saturating_mul(size);
+		let reserve = total / unit + if total % unit > 0 { 1 } else { 0 };
+		reserve.saturated_into::<T::Balance>()
+	}
+
 	///
 	/// Initialize proxies on gensis
 	/// 
@@ -623,6 +702,36 @@ impl<T: Config> Pallet<T> {
 	}
 }
 
+impl<T: Config> pallet_data_assets::GatewayPricing<T::AccountId, T::Balance> for Pallet<T> {
+	fn reserve_for(gateway: T::AccountId, size: u128) -> T::Balance {
+		Self::estimate_reserve(Some(gateway), size)
+	}
+}
+
+impl<T: Config> pallet_data_assets::ProxyPrefsReader<T::AccountId> for Pallet<T> {
+	fn prefs(gateway: &T::AccountId) -> Option<GatewayPrefs> {
+		Proxies::<T>::get(gateway)
+	}
+}
+
+impl<T: Config> pallet_data_assets::GatewayProvider<T::AccountId> for Pallet<T> {
+	fn gateways() -> Vec<T::AccountId> {
+		Proxies::<T>::iter_keys().collect()
+	}
+}
+
+/// Provides the storage used by a proxy's ipfs node
+pub trait StorageReporter<AccountId> {
+	/// the ipfs repo size reported by the proxy's node, in bytes
+	fn repo_size(acct: &AccountId) -> u128;
+}
+
+impl<AccountId> StorageReporter<AccountId> for () {
+	fn repo_size(_acct: &AccountId) -> u128 {
+		0
+	}
+}
+
 /// A trait to expose information about bonded accounts and staked amounts
 pub trait ProxyProvider<AccountId, Balance> {
 	/// get the active balance in the staking ledger
@@ -634,7 +743,15 @@ pub trait ProxyProvider<AccountId, Balance> {
 	/// most some active tokens to reserved
 	fn reserve(acct: AccountId, balance: Balance);
 	// fn unreserve(acct: AccountId, balance: Option<Balance>) -> Result<(), Error<T>>; 
+	/// get the next asset id for a gateway without consuming it
 	fn next_asset_id(acct: AccountId) -> u32;
+	/// consume the next asset id for a gateway, called once its asset class is created
+	fn commit_asset_id(acct: AccountId);
+	/// get the (stash) accounts of all registered proxies
+	fn proxies() -> Vec<AccountId>;
+	/// get the storage a proxy has left in bytes (its declared `storage_max` minus its
+	/// reported repo size), or None if the account is not a proxy
+	fn remaining_capacity(acct: AccountId) -> Option<u128>;
 }
 
 impl<T: Config> ProxyProvider<T::AccountId, T::Balance> for Pallet<T> {
@@ -677,11 +794,26 @@ impl<T: Config> ProxyProvider<T::AccountId, T::Balance> for Pallet<T> {
 
 	fn next_asset_id(acct: T::AccountId) -> u32 {
 		if let Some(slot) = Slot::<T>::get(acct.clone()) {
-			let index = CallCount::<T>::get(acct.clone()) + 1;
-			// increment callcount
-			CallCount::<T>::insert(acct, index);
+			let index = CallCount::<T>::get(acct) + 1;
 			return slot * index;
 		}
 		0
 	}
+
+	fn commit_asset_id(acct: T::AccountId) {
+		if Slot::<T>::contains_key(acct.clone()) {
+			// increment callcount
+			CallCount::<T>::mutate(acct, |count| *count += 1);
+		}
+	}
+
+	fn proxies() -> Vec<T::AccountId> {
+		Proxies::<T>::iter_keys().collect()
+	}
+
+	fn remaining_capacity(acct: T::AccountId) -> Option<u128> {
+		let prefs = Proxies::<T>::get(&acct)?;
+		let used = T::StorageReporter::repo_size(&acct);
+		Some(prefs.storage_max.saturating_sub(used))
+	}
 }
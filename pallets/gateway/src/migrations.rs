@@ -0,0 +1,92 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Storage migrations for the gateway pallet
+
+use super::*;
+
+/// Version 1 adds the price per gb to the preferences of each gateway
+pub mod v1 {
+	use super::*;
+
+	/// the gateway preferences stored before version 1
+	#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+	pub struct OldGatewayPrefs {
+		pub max_mbps: u32,
+		pub storage_max_gb: u128,
+	}
+
+	/// Migrate the preferences of every gateway to version 1. No gateway has set
+	/// its pricing yet.
+	/// 
+	pub fn migrate<T: Config>() -> Weight {
+		let on_chain = StorageVersion::get::<Pallet<T>>();
+		if on_chain >= 1 {
+			return T::DbWeight::get().reads(1);
+		}
+		let mut translated: u64 = 0;
+		Proxies::<T>::translate::<OldGatewayPrefs, _>(|_, old| {
+			translated += 1;
+			Some(v2::OldGatewayPrefs {
+				max_mbps: old.max_mbps,
+				storage_max_gb: old.storage_max_gb,
+				reserve_per_gb: 0,
+			})
+		});
+		StorageVersion::new(1).put::<Pallet<T>>();
+		log::info!("Migrated the preferences of {:?} gateways to version 1", translated);
+		T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+	}
+}
+
+/// Version 2 declares gateway storage in bytes and pricing per `SizeUnit` rather than per gb
+pub mod v2 {
+	use super::*;
+
+	/// the gateway preferences stored before version 2
+	#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+	pub struct OldGatewayPrefs {
+		pub max_mbps: u32,
+		pub storage_max_gb: u128,
+		pub reserve_per_gb: u128,
+	}
+
+	/// Migrate the preferences of every gateway to version 2. Storage is converted to
+	/// bytes, and the price per gb is converted to the configured size unit, rounding up
+	/// so that no gateway ends up charging less than it declared.
+	/// 
+	pub fn migrate<T: Config>() -> Weight {
+		let on_chain = StorageVersion::get::<Pallet<T>>();
+		if on_chain >= 2 {
+			return T::DbWeight::get().reads(1);
+		}
+		let unit = T::SizeUnit::get();
+		let mut translated: u64 = 0;
+		Proxies::<T>::translate::<OldGatewayPrefs, _>(|_, old| {
+			translated += 1;
+			let total = old.reserve_per_gb.saturating_mul(unit);
+			Some(GatewayPrefs {
+				max_mbps: old.max_mbps,
+				storage_max: old.storage_max_gb.saturating_mul(BYTES_PER_GB),
+				reserve_per_unit: total / BYTES_PER_GB + if total % BYTES_PER_GB > 0 { 1 } else { 0 },
+			})
+		});
+		StorageVersion::new(2).put::<Pallet<T>>();
+		log::info!("Migrated the preferences of {:?} gateways to version 2", translated);
+		T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+	}
+}
@@ -22,7 +22,7 @@ use crate::{self as pallet_gateway, Config};
 use pallet_data_assets;
 use frame_support::{
 	parameter_types, 
-	traits::{GenesisBuild, ConstU32},
+	traits::{GenesisBuild, ConstBool, ConstU32},
 	BasicExternalities,
 };
 
@@ -269,6 +269,22 @@ impl pallet_data_assets::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = ConstBool<true>;
+	type SetAssetMetadata = ConstBool<true>;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<16>;
+	type GatewayPricing = Gateway;
+	type GatewayProvider = Gateway;
+	type ProxyPrefs = Gateway;
+	type PeerIdProvider = ();
 }
 
 parameter_types! {
@@ -285,6 +301,8 @@ impl pallet_authorities::Config for Test {
 
 parameter_types! {
 	pub const BondingDuration: EraIndex = 3;
+	pub const SizeUnit: u128 = pallet_gateway::BYTES_PER_GB;
+	pub const MinReservePerUnit: u64 = 10;
 }
 
 impl Config for Test {
@@ -294,6 +312,9 @@ impl Config for Test {
 	type Balance = <Self as pallet_balances::Config>::Balance;
 	type BondingDuration = BondingDuration;
 	type EraProvider = Authorities;
+	type SizeUnit = SizeUnit;
+	type MinReservePerUnit = MinReservePerUnit;
+	type StorageReporter = ();
 }
 
 type Extrinsic = TestXt<Call, ()>;
@@ -20,7 +20,7 @@
 use super::*;
 use crate::mock::*;
 use frame_support::{
-	assert_ok, assert_err, bounded_vec,
+	assert_noop, assert_ok, assert_err, bounded_vec,
 };
 use sp_runtime::testing::UintAuthorityId;
 use sp_core::Pair;
@@ -228,7 +228,8 @@ fn gateway_declare_gateway_works() {
 		));
 		let proxy_prefs = crate::GatewayPrefs {
 			max_mbps: 100,
-			storage_max_gb: 100,
+			storage_max: 100 * crate::BYTES_PER_GB,
+			reserve_per_unit: 0,
 		};
 		assert_ok!(Gateway::declare_gateway(
 			Origin::signed(v0.0.clone()),
@@ -266,7 +267,8 @@ fn gateway_declare_gateway_err_when_not_controller() {
 		));
 		let proxy_prefs = crate::GatewayPrefs {
 			max_mbps: 100,
-			storage_max_gb: 100,
+			storage_max: 100 * crate::BYTES_PER_GB,
+			reserve_per_unit: 0,
 		};
 		assert_err!(Gateway::declare_gateway(
 			Origin::signed(v1.0.clone()),
@@ -275,6 +277,341 @@ fn gateway_declare_gateway_err_when_not_controller() {
 	});
 }
 
+#[test]
+fn gateway_estimate_reserve_uses_declared_pricing() {
+	// GIVEN: There is a validator node
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone()]).execute_with(|| {
+		let min_reserve_per_unit = <Test as crate::Config>::MinReservePerUnit::get();
+		// WHEN: the node has not declared itself a gateway
+		// THEN: the estimate uses the minimum reserve per size unit
+		assert_eq!(min_reserve_per_unit * 5, Gateway::estimate_reserve(Some(v0.0.clone()), 5 * crate::BYTES_PER_GB));
+		assert_eq!(min_reserve_per_unit * 5, Gateway::estimate_reserve(None, 5 * crate::BYTES_PER_GB));
+		// AND: WHEN the node declares itself a gateway with pricing
+		assert_ok!(Gateway::bond(
+			Origin::signed(v0.0.clone()),
+			v0.0.clone(),
+			1,
+		));
+		assert_ok!(Gateway::declare_gateway(
+			Origin::signed(v0.0.clone()),
+			crate::GatewayPrefs {
+				max_mbps: 100,
+				storage_max: 100 * crate::BYTES_PER_GB,
+				reserve_per_unit: (min_reserve_per_unit * 3).into(),
+			},
+		));
+		// THEN: the estimate uses the declared pricing
+		assert_eq!(min_reserve_per_unit * 3 * 5, Gateway::estimate_reserve(Some(v0.0.clone()), 5 * crate::BYTES_PER_GB));
+	});
+}
+
+#[test]
+fn gateway_prices_sub_gb_requests_by_the_byte() {
+	// GIVEN: There are two validator nodes
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	let v1: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("1")).0.public(), 
+		UintAuthorityId(1)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone(), v1.clone()]).execute_with(|| {
+		System::set_block_number(1);
+		let min_reserve_per_unit = <Test as crate::Config>::MinReservePerUnit::get();
+		// AND: the second can pay the reserves it offers
+		Balances::make_free_balance_be(&v1.0, 100);
+		// AND: the first has declared itself a gateway charging twice the minimum per gb
+		assert_ok!(Gateway::bond(
+			Origin::signed(v0.0.clone()),
+			v0.0.clone(),
+			1,
+		));
+		assert_ok!(Gateway::declare_gateway(
+			Origin::signed(v0.0.clone()),
+			crate::GatewayPrefs {
+				max_mbps: 100,
+				storage_max: 100 * crate::BYTES_PER_GB,
+				reserve_per_unit: (min_reserve_per_unit * 2).into(),
+			},
+		));
+		// WHEN: I estimate the reserve for less than a gb
+		// THEN: the price is prorated to the size, rather than rounding the size to 0 gb
+		assert_eq!(min_reserve_per_unit, Gateway::estimate_reserve(Some(v0.0.clone()), crate::BYTES_PER_GB / 2));
+		// AND: any data at all costs a non-zero reserve
+		assert_eq!(1, Gateway::estimate_reserve(Some(v0.0.clone()), 1));
+		assert_eq!(0, Gateway::estimate_reserve(Some(v0.0.clone()), 0));
+		// AND: ingestion requests for half a gb are accepted with a reserve covering half the price
+		assert_ok!(DataAssets::create_request(
+			Origin::signed(v1.0.clone()),
+			v0.0.clone(),
+			min_reserve_per_unit,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB / 2,
+			1,
+			1,
+			1,
+			None,
+		));
+		// AND: those for a single byte more are rejected
+		assert_noop!(DataAssets::create_request(
+			Origin::signed(v1.0.clone()),
+			v0.0.clone(),
+			min_reserve_per_unit,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB / 2 + 1,
+			1,
+			1,
+			1,
+			None,
+		), pallet_data_assets::Error::<Test>::ReserveTooLow);
+	});
+}
+
+#[test]
+fn gateway_migrates_prefs_to_v1() {
+	// GIVEN: There is a validator node
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone()]).execute_with(|| {
+		// AND: its prefs were stored before gateways could set pricing
+		let old = crate::migrations::v1::OldGatewayPrefs {
+			max_mbps: 100,
+			storage_max_gb: 2,
+		};
+		frame_support::storage::unhashed::put(&crate::Proxies::<Test>::hashed_key_for(&v0.0), &old);
+		frame_support::traits::StorageVersion::new(0).put::<Gateway>();
+		// WHEN: the migration runs
+		crate::migrations::v1::migrate::<Test>();
+		// THEN: its prefs are decodable and it has not set pricing
+		let prefs: crate::migrations::v2::OldGatewayPrefs = frame_support::storage::unhashed::get(
+			&crate::Proxies::<Test>::hashed_key_for(&v0.0),
+		).unwrap();
+		assert_eq!(crate::migrations::v2::OldGatewayPrefs {
+			max_mbps: 100,
+			storage_max_gb: 2,
+			reserve_per_gb: 0,
+		}, prefs);
+		// AND: the storage version is bumped
+		assert_eq!(1, frame_support::traits::StorageVersion::get::<Gateway>());
+	});
+}
+
+#[test]
+fn gateway_migrates_prefs_to_bytes() {
+	// GIVEN: There is a validator node
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone()]).execute_with(|| {
+		// AND: its prefs were stored when storage and pricing were declared per gb
+		let old = crate::migrations::v2::OldGatewayPrefs {
+			max_mbps: 100,
+			storage_max_gb: 2,
+			reserve_per_gb: 30,
+		};
+		frame_support::storage::unhashed::put(&crate::Proxies::<Test>::hashed_key_for(&v0.0), &old);
+		frame_support::traits::StorageVersion::new(1).put::<Gateway>();
+		// WHEN: the migration runs
+		crate::migrations::v2::migrate::<Test>();
+		// THEN: its storage is in bytes and its price is per size unit (a gb in the mock)
+		assert_eq!(Some(crate::GatewayPrefs {
+			max_mbps: 100,
+			storage_max: 2 * crate::BYTES_PER_GB,
+			reserve_per_unit: 30,
+		}), crate::Proxies::<Test>::get(&v0.0));
+		// AND: the storage version is bumped
+		assert_eq!(2, frame_support::traits::StorageVersion::get::<Gateway>());
+	});
+}
+
+#[test]
+fn gateway_migrates_baseline_prefs_on_runtime_upgrade() {
+	// GIVEN: There is a validator node
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone()]).execute_with(|| {
+		// AND: its prefs were encoded as the first release stored them, (max_mbps, storage_max_gb)
+		frame_support::storage::unhashed::put(&crate::Proxies::<Test>::hashed_key_for(&v0.0), &(100u32, 3u128));
+		frame_support::traits::StorageVersion::new(0).put::<Gateway>();
+		// WHEN: the runtime is upgraded
+		<Gateway as frame_support::traits::Hooks<u64>>::on_runtime_upgrade();
+		// THEN: its prefs are kept, in bytes and without pricing
+		assert_eq!(Some(crate::GatewayPrefs {
+			max_mbps: 100,
+			storage_max: 3 * crate::BYTES_PER_GB,
+			reserve_per_unit: 0,
+		}), crate::Proxies::<Test>::get(&v0.0));
+		// AND: the storage version is current
+		assert_eq!(2, frame_support::traits::StorageVersion::get::<Gateway>());
+	});
+}
+
+#[test]
+fn gateway_can_set_pricing() {
+	// GIVEN: There are two validator nodes
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	let v1: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("1")).0.public(), 
+		UintAuthorityId(1)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone(), v1.clone()]).execute_with(|| {
+		System::set_block_number(1);
+		let min_reserve_per_unit = <Test as crate::Config>::MinReservePerUnit::get();
+		// AND: the second can pay the reserves it offers
+		Balances::make_free_balance_be(&v1.0, 100);
+		let price_per_unit = (min_reserve_per_unit * 2) as u128;
+		// AND: I have declared myself a gateway
+		assert_ok!(Gateway::bond(
+			Origin::signed(v0.0.clone()),
+			v0.0.clone(),
+			1,
+		));
+		assert_ok!(Gateway::declare_gateway(
+			Origin::signed(v0.0.clone()),
+			crate::GatewayPrefs {
+				max_mbps: 100,
+				storage_max: 100 * crate::BYTES_PER_GB,
+				reserve_per_unit: 0,
+			},
+		));
+		// WHEN: I set my pricing
+		assert_ok!(Gateway::set_pricing(
+			Origin::signed(v0.0.clone()),
+			price_per_unit,
+		));
+		// THEN: my prefs are updated
+		let prefs = crate::Proxies::<Test>::get(v0.0.clone()).unwrap();
+		assert_eq!(price_per_unit, prefs.reserve_per_unit);
+		System::assert_last_event(mock::Event::Gateway(
+			crate::Event::PricingUpdated(v0.0.clone(), price_per_unit)
+		));
+		// AND: ingestion requests with a reserve below my price are rejected
+		assert_noop!(DataAssets::create_request(
+			Origin::signed(v1.0.clone()),
+			v0.0.clone(),
+			min_reserve_per_unit,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB,
+			1,
+			1,
+			1,
+			None,
+		), pallet_data_assets::Error::<Test>::ReserveTooLow);
+		// AND: ingestion requests with a reserve covering my price are accepted
+		assert_ok!(DataAssets::create_request(
+			Origin::signed(v1.0.clone()),
+			v0.0.clone(),
+			min_reserve_per_unit * 2,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB,
+			1,
+			1,
+			1,
+			None,
+		));
+	});
+}
+
+#[test]
+fn gateway_prefs_are_readable_by_data_assets() {
+	// GIVEN: There are two validator nodes
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	let v1: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("1")).0.public(), 
+		UintAuthorityId(1)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone(), v1.clone()]).execute_with(|| {
+		System::set_block_number(1);
+		let min_reserve_per_unit = <Test as crate::Config>::MinReservePerUnit::get();
+		// AND: the second can pay the reserves it offers
+		Balances::make_free_balance_be(&v1.0, 100);
+		let prefs = crate::GatewayPrefs {
+			max_mbps: 100,
+			storage_max: 100 * crate::BYTES_PER_GB,
+			reserve_per_unit: 0,
+		};
+		// AND: the first has declared itself a gateway
+		assert_ok!(Gateway::bond(
+			Origin::signed(v0.0.clone()),
+			v0.0.clone(),
+			1,
+		));
+		assert_ok!(Gateway::declare_gateway(Origin::signed(v0.0.clone()), prefs.clone()));
+		// THEN: data assets can read its prefs, but not the second's
+		assert_eq!(Some(prefs), <Gateway as pallet_data_assets::ProxyPrefsReader<_>>::prefs(&v0.0));
+		assert_eq!(None, <Gateway as pallet_data_assets::ProxyPrefsReader<_>>::prefs(&v1.0));
+		// AND: ingestion requests naming the second as their gateway are rejected
+		assert_noop!(DataAssets::create_request(
+			Origin::signed(v0.0.clone()),
+			v1.0.clone(),
+			min_reserve_per_unit,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB,
+			1,
+			1,
+			1,
+			None,
+		), pallet_data_assets::Error::<Test>::NotAGateway);
+		// AND: those naming the first are accepted
+		assert_ok!(DataAssets::create_request(
+			Origin::signed(v1.0.clone()),
+			v0.0.clone(),
+			min_reserve_per_unit * 2,
+			"QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			crate::BYTES_PER_GB,
+			1,
+			1,
+			1,
+			None,
+		));
+	});
+}
+
+#[test]
+fn gateway_set_pricing_err_when_not_gateway() {
+	// GIVEN: There is a validator node
+	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
+		sp_core::sr25519::Pair::generate_with_phrase(Some("0")).0.public(), 
+		UintAuthorityId(0)
+	);
+	new_test_ext_default_funded_validators(vec![v0.clone()]).execute_with(|| {
+		// AND: I have bonded but not declared myself a gateway
+		assert_ok!(Gateway::bond(
+			Origin::signed(v0.0.clone()),
+			v0.0.clone(),
+			1,
+		));
+		// WHEN: I try to set my pricing
+		// THEN: I receive an error
+		assert_err!(Gateway::set_pricing(
+			Origin::signed(v0.0.clone()),
+			100,
+		), crate::Error::<Test>::NoSuchProxy);
+	});
+}
+
 // TODO: test setup with genesis config
 // #[test]
 // fn proxy_bond_and_declare_gateway_err_when_max_proxy_count_exceeded() {
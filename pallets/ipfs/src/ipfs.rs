@@ -27,7 +27,7 @@ use sp_std::{
     prelude::*,
 };
 use sp_runtime::{
-    offchain::http,
+    offchain::{http, OpaqueMultiaddr},
     RuntimeDebug,
 };
 use scale_info::prelude::string::String;
@@ -65,6 +65,7 @@ pub enum Capabilities {
     Disconnect, 
     Get,
     Identity,
+    PinLs,
     Stat,
 	Other(&'static str),
 }
@@ -82,6 +83,7 @@ pub enum Capabilities {
 //             Capabilities::Disconnect => "http://127.0.0.1:5001/api/v0/swarm/disconnect?",
 //             Capabilities::Get => "http://127.0.0.1:5001/api/v0/get?",
 //             Capabilities::Identity => "http://127.0.0.1:5001/api/v0/id",
+//             Capabilities::PinLs => "http://127.0.0.1:5001/api/v0/pin/ls?type=recursive",
 //             Capabilities::Stat => "http://127.0.0.1:5001/api/v0/repo/stat",
 // 			Capabilities::Other(m) => m,
 // 		}
@@ -99,18 +101,27 @@ impl AsRef<str> for Capabilities {
             Capabilities::Disconnect => "http://host.docker.internal:5001/api/v0/swarm/disconnect?",
             Capabilities::Get => "http://host.docker.internal:5001/api/v0/get?",
             Capabilities::Identity => "http://host.docker.internal:5001/api/v0/id",
+            Capabilities::PinLs => "http://host.docker.internal:5001/api/v0/pin/ls?type=recursive",
             Capabilities::Stat => "http://host.docker.internal:5001/api/v0/repo/stat",
 			Capabilities::Other(m) => m,
 		}
 	}
 }
 
+/// an in-memory stand-in for the http functions below, for running without an ipfs daemon
+#[cfg(feature = "mock-ipfs")]
+#[path = "ipfs_stub.rs"]
+mod stub;
+#[cfg(feature = "mock-ipfs")]
+pub use stub::*;
+
 /// Get the ipfs node identity
 /// 
-pub fn identity() -> Result<http::Response, http::Error> {
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn identity() -> Result<Vec<u8>, http::Error> {
     let endpoint = Capabilities::Identity.as_ref().to_owned();
     let res = ipfs_post_request(&endpoint, None)?;
-    Ok(res)
+    Ok(res.body().collect::<Vec<u8>>())
 }
 
 /// Update the node's configuration. For the time being, we omit the optional
@@ -118,6 +129,7 @@ pub fn identity() -> Result<http::Response, http::Error> {
 /// 
 /// * config_item: The ipfs configuration to update. In general, this is a key-value pair.
 /// 
+#[cfg(not(feature = "mock-ipfs"))]
 pub fn config_update(config_item: IpfsConfigRequest) -> Result<(), http::Error> {
     let mut endpoint = Capabilities::ConfigUpdate.as_ref().to_owned();
     endpoint = add_arg(endpoint, "arg".as_bytes(), &config_item.key, true)
@@ -130,27 +142,29 @@ pub fn config_update(config_item: IpfsConfigRequest) -> Result<(), http::Error>
 
 /// Show the node's current configuration
 /// 
-pub fn config_show() -> Result<http::Response, http::Error> {
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn config_show() -> Result<Vec<u8>, http::Error> {
     let endpoint = Capabilities::ConfigShow.as_ref().to_owned();
-    let res = ipfs_post_request(&endpoint, None).unwrap();
-    Ok(res)
+    let res = ipfs_post_request(&endpoint, None)?;
+    Ok(res.body().collect::<Vec<u8>>())
 }
 
 /// Get the ipfs repo stats
 ///
+#[cfg(not(feature = "mock-ipfs"))]
 pub fn repo_stat() -> Result<serde_json::Value, http::Error> {
     let endpoint = Capabilities::Stat.as_ref().to_owned();
     let res = ipfs_post_request(&endpoint, None)?;
     let res_u8 = res.body().collect::<Vec<u8>>();
-    let body = sp_std::str::from_utf8(&res_u8).map_err(|_| http::Error::Unknown).unwrap();
-    let json = parse(body).map_err(|_| http::Error::Unknown).unwrap();
-    Ok(json)
+    let body = sp_std::str::from_utf8(&res_u8).map_err(|_| http::Error::Unknown)?;
+    parse(body).map_err(|_| http::Error::Unknown)
 }
 
 /// Connect to the given multiaddress
 /// 
 /// * multiaddress: The multiaddress to connect to
 /// 
+#[cfg(not(feature = "mock-ipfs"))]
 pub fn connect(multiaddress: &[u8]) -> Result<(), http::Error> {
     let mut endpoint = Capabilities::Connect.as_ref().to_owned();
     endpoint = add_arg(endpoint, "arg".as_bytes(), multiaddress, false)
@@ -163,6 +177,7 @@ pub fn connect(multiaddress: &[u8]) -> Result<(), http::Error> {
 /// 
 /// * multiaddress: The multiaddress to disconnect from
 /// 
+#[cfg(not(feature = "mock-ipfs"))]
 pub fn disconnect(multiaddress: &[u8]) -> Result<(), http::Error> {
     let mut endpoint = Capabilities::Disconnect.as_ref().to_owned();
     endpoint = add_arg(endpoint, "arg".as_bytes(), multiaddress, false)
@@ -176,7 +191,8 @@ pub fn disconnect(multiaddress: &[u8]) -> Result<(), http::Error> {
 /// For the initial implementation, we will ignore all optional args
 /// * ipfs_add_request: The request object containing data to add
 /// 
-pub fn add(ipfs_add_request: IpfsAddRequest) -> Result<http::Response, http::Error> {
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn add(ipfs_add_request: IpfsAddRequest) -> Result<Vec<u8>, http::Error> {
     let endpoint = Capabilities::Add.as_ref();
     // construct body
     // {"path": <file bytes>"}
@@ -204,31 +220,82 @@ pub fn add(ipfs_add_request: IpfsAddRequest) -> Result<http::Response, http::Err
         log::warn!("Unexpected status code: {}", response.code);
         return Err(http::Error::Unknown);
     }
-    Ok(response)
+    Ok(response.body().collect::<Vec<u8>>())
 }
 
 /// Fetch data from the ipfs swarm and make it available from your node
+/// Returns `FetchError::IpfsNotAvailable` if the daemon can't be reached or fails,
+/// and `FetchError::ContentNotFound` if it answers without the content
 /// 
 /// * cid: The CID to fetch.
 /// 
-pub fn get(cid: &[u8]) -> Result<http::Response, http::Error> {
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn get(cid: &[u8]) -> Result<Vec<u8>, FetchError> {
     let mut endpoint = Capabilities::Get.as_ref().to_owned();
+    // a cid that isn't valid utf8 can't name any content
     endpoint = add_arg(endpoint, "arg".as_bytes(), cid, false)
-        .map_err(|_| http::Error::Unknown).unwrap();
-    let res = ipfs_post_request(&endpoint, None).unwrap();
-    Ok(res)
+        .map_err(|_| FetchError::ContentNotFound)?;
+    log::info!("Making POST request to: {:?}", endpoint);
+    let response = http::Request::default()
+        .method(http::Method::Post)
+        .url(&endpoint)
+        .body(Vec::<&[u8]>::new())
+        .send()
+        .map_err(|e| {
+            log::error!("Unable to reach the ipfs daemon: {:?}", e);
+            FetchError::IpfsNotAvailable
+        })?
+        .wait()
+        .map_err(|e| {
+            log::error!("The ipfs daemon did not respond: {:?}", e);
+            FetchError::IpfsNotAvailable
+        })?;
+    classify_get_response(response.code, response.body().collect::<Vec<u8>>())
+}
+
+/// Errors that can occur when fetching content from ipfs
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum FetchError {
+    /// the ipfs daemon is not running, is unreachable, or failed to handle the request
+    IpfsNotAvailable,
+    /// the daemon handled the request, but the content was not found (a 404 or an empty body)
+    ContentNotFound,
+}
+
+/// Decide whether a response from the get endpoint holds the content
+/// 
+/// * code: The http status code of the response
+/// * body: The response body
+/// 
+pub fn classify_get_response(code: u16, body: Vec<u8>) -> Result<Vec<u8>, FetchError> {
+    match code {
+        200 if body.is_empty() => {
+            log::warn!("The ipfs daemon returned no content");
+            Err(FetchError::ContentNotFound)
+        },
+        200 => Ok(body),
+        404 => {
+            log::warn!("The ipfs daemon could not find the content");
+            Err(FetchError::ContentNotFound)
+        },
+        code => {
+            log::error!("Unexpected status code from the ipfs daemon: {}", code);
+            Err(FetchError::IpfsNotAvailable)
+        },
+    }
 }
 
 /// retrieve data from IPFS and return it
 /// 
 /// cid: The CID to cat
 /// 
-pub fn cat(cid: &[u8]) -> Result<http::Response, http::Error> {
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn cat(cid: &[u8]) -> Result<Vec<u8>, http::Error> {
     let mut endpoint = Capabilities::Cat.as_ref().to_owned();
     endpoint = add_arg(endpoint, "arg".as_bytes(), cid, false)
         .map_err(|_| http::Error::Unknown).unwrap();
-    let res = ipfs_post_request(&endpoint, None);
-    Ok(res.unwrap())
+    let res = ipfs_post_request(&endpoint, None)?;
+    Ok(res.body().collect::<Vec<u8>>())
 }
 
 /// Parse the input string as json
@@ -247,12 +314,181 @@ pub fn parse(input: &str) -> Result<Value, serde_json::Error> {
     }
 }
 
+/// Errors that can occur when converting between multiaddresses and strings
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum MultiaddrError {
+    /// the multiaddress bytes are not valid utf8
+    InvalidUtf8(Utf8Error),
+    /// the multiaddress is empty
+    Empty,
+}
+
+/// Convert an opaque multiaddress to a string
+/// Returns a MultiaddrError if the bytes are empty or not valid utf8
+/// 
+/// * multiaddress: The multiaddress to convert
+/// 
+pub fn multiaddr_to_string(multiaddress: &OpaqueMultiaddr) -> Result<String, MultiaddrError> {
+    let s = str::from_utf8(&multiaddress.0).map_err(MultiaddrError::InvalidUtf8)?;
+    string_to_multiaddr(s)?;
+    Ok(s.to_owned())
+}
+
+/// Convert a string to an opaque multiaddress
+/// Returns a MultiaddrError if the string is empty
+/// 
+/// * multiaddress: The multiaddress to convert, e.g. /ip4/127.0.0.1/tcp/4001
+/// 
+pub fn string_to_multiaddr(multiaddress: &str) -> Result<OpaqueMultiaddr, MultiaddrError> {
+    if multiaddress.is_empty() {
+        return Err(MultiaddrError::Empty);
+    }
+    Ok(OpaqueMultiaddr(multiaddress.as_bytes().to_vec()))
+}
+
+/// Get the peer id a multiaddress addresses, i.e. its trailing `/p2p/<peerid>` (or legacy
+/// `/ipfs/<peerid>`) component. Components before a `/p2p-circuit` belong to the relay,
+/// so a circuit address without a peer id after the relay has none.
+/// 
+/// * multiaddress: The multiaddress, e.g. /ip4/127.0.0.1/tcp/4001/p2p/12D3KooW...
+/// 
+pub fn multiaddr_peer_id(multiaddress: &str) -> Option<&str> {
+    let components: Vec<&str> = multiaddress.split('/').collect();
+    let start = components.iter()
+        .rposition(|c| *c == "p2p-circuit")
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let components = &components[start..];
+    components.iter()
+        .rposition(|c| *c == "p2p" || *c == "ipfs")
+        .and_then(|i| components.get(i + 1))
+        .copied()
+        .filter(|peer_id| !peer_id.is_empty())
+}
+
+/// the multihash code of sha2-256
+pub const SHA2_256: u64 = 0x12;
+/// the multihash code of blake2b-256
+pub const BLAKE2B_256: u64 = 0xb220;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Errors that can occur when verifying data against a cid
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum CidError {
+    /// the cid is not a base58btc CIDv0 or a base32 CIDv1
+    InvalidCid,
+    /// the cid's multihash uses a hash function we can't compute (the multihash code)
+    UnsupportedHash(u64),
+}
+
+/// Parse the multihash from a cid, returning the multihash code and the digest.
+/// CIDv0 (base58btc, starting with 'Qm') and base32 CIDv1 (starting with 'b') are supported.
+/// 
+/// * cid: The cid to parse
+/// 
+pub fn parse_multihash(cid: &[u8]) -> Result<(u64, Vec<u8>), CidError> {
+    let multihash = if cid.len() == 46 && cid.starts_with(b"Qm") {
+        decode_base58(cid).ok_or(CidError::InvalidCid)?
+    } else if cid.first() == Some(&b'b') {
+        let bytes = decode_base32(&cid[1..]).ok_or(CidError::InvalidCid)?;
+        let (version, read) = read_varint(&bytes).ok_or(CidError::InvalidCid)?;
+        if version != 1 {
+            return Err(CidError::InvalidCid);
+        }
+        // skip the content codec
+        let (_codec, codec_read) = read_varint(&bytes[read..]).ok_or(CidError::InvalidCid)?;
+        bytes[read + codec_read..].to_vec()
+    } else {
+        return Err(CidError::InvalidCid);
+    };
+    let (code, code_read) = read_varint(&multihash).ok_or(CidError::InvalidCid)?;
+    let (length, length_read) = read_varint(&multihash[code_read..]).ok_or(CidError::InvalidCid)?;
+    let digest = &multihash[code_read + length_read..];
+    if digest.len() as u64 != length {
+        return Err(CidError::InvalidCid);
+    }
+    Ok((code, digest.to_vec()))
+}
+
+/// Verify that a block of data hashes to the digest in a cid, using the hash
+/// function named by the cid's multihash. Note that the digest is computed over
+/// the block as stored by ipfs, so data added as dag-pb is wrapped in a unixfs node.
+/// 
+/// * cid: The cid of the block
+/// * block: The bytes of the block
+/// 
+pub fn verify_cid(cid: &[u8], block: &[u8]) -> Result<bool, CidError> {
+    let (code, digest) = parse_multihash(cid)?;
+    let hash = match code {
+        SHA2_256 => sp_io::hashing::sha2_256(block),
+        BLAKE2B_256 => sp_io::hashing::blake2_256(block),
+        other => return Err(CidError::UnsupportedHash(other)),
+    };
+    Ok(digest == hash)
+}
+
+/// Read an unsigned varint, returning the value and the number of bytes read
+fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, b) in bytes.iter().enumerate().take(9) {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Decode base58btc (bitcoin alphabet) encoded bytes
+fn decode_base58(input: &[u8]) -> Option<Vec<u8>> {
+    // accumulate the value as little endian bytes
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| a == c)? as u32;
+        for b in bytes.iter_mut() {
+            carry += (*b as u32) * 58;
+            *b = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    // each leading '1' encodes a leading zero byte
+    let zeros = input.iter().take_while(|c| **c == b'1').count();
+    bytes.extend(sp_std::iter::repeat(0).take(zeros));
+    bytes.reverse();
+    Some(bytes)
+}
+
+/// Decode unpadded, lowercase base32 (rfc4648) encoded bytes
+fn decode_base32(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0u32;
+    for c in input {
+        let value = BASE32_ALPHABET.iter().position(|a| a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
 /// Append a key-value argument to the endpoint.
 /// Returns a Utf8Error if we fail to convert the key or value to utf8
 /// 
 /// e.g. (endpoint?, k, v, true) => endpoint?k=v&
 ///      (endpoint?, k, v, false) => endpoint?k=v
 /// 
+#[cfg(not(feature = "mock-ipfs"))]
 fn add_arg(
     mut endpoint: String,
     key: &[u8],
@@ -278,10 +514,37 @@ fn add_arg(
     Ok(endpoint)
 }
 
+/// List the cids the ipfs daemon has pinned recursively
+/// 
+#[cfg(not(feature = "mock-ipfs"))]
+pub fn pin_ls() -> Result<Vec<Vec<u8>>, http::Error> {
+    let endpoint = Capabilities::PinLs.as_ref().to_owned();
+    let res = ipfs_post_request(&endpoint, None)?;
+    parse_pin_ls(&res.body().collect::<Vec<u8>>())
+}
+
+/// Read the cids from a response of the pin/ls endpoint, which maps each
+/// pinned cid to its pin type: `{"Keys":{"<cid>":{"Type":"recursive"}}}`
+/// 
+/// * body: The response body
+/// 
+pub fn parse_pin_ls(body: &[u8]) -> Result<Vec<Vec<u8>>, http::Error> {
+    let body = str::from_utf8(body).map_err(|_| http::Error::Unknown)?;
+    let json = parse(body).map_err(|_| http::Error::Unknown)?;
+    match json["Keys"].as_object() {
+        Some(keys) => Ok(keys.keys().map(|cid| cid.as_bytes().to_vec()).collect()),
+        None => {
+            log::error!("The pin/ls response did not contain any keys");
+            Err(http::Error::Unknown)
+        },
+    }
+}
+
 /// Make an http post request to IPFS
 /// 
 /// * `endpoint`: The IPFS endpoint to invoke
 /// 
+#[cfg(not(feature = "mock-ipfs"))]
 fn ipfs_post_request(endpoint: &str, body: Option<Vec<&[u8]>>) -> Result<http::Response, http::Error> {
     log::info!("Making POST request to: {:?}", endpoint);
     let body = match body {
@@ -0,0 +1,147 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//!
+//! An in-memory stand-in for the ipfs http api, enabled with the `mock-ipfs` feature.
+//! It lets a single node dev chain run without an ipfs daemon: the identity is a fixed
+//! peer id and added data is kept in the node's offchain storage rather than an ipfs repo.
+//!
+use super::{FetchError, IpfsAddRequest, IpfsConfigRequest};
+use sp_core::hexdisplay::HexDisplay;
+use sp_runtime::offchain::{
+    http,
+    storage::StorageValueRef,
+};
+use sp_std::vec::Vec;
+use scale_info::prelude::format;
+use serde_json::Value;
+use log;
+
+/// the peer id reported by the stub
+pub const MOCK_PEER_ID: &str = "12D3KooWMockIpfsPeerIdForLocalDevelopment";
+/// the storage capacity reported by the stub, in bytes
+pub const MOCK_STORAGE_MAX: u64 = 50_000_000_000;
+/// the prefix of the offchain storage keys the stub stores data under
+const STORAGE_PREFIX: &[u8] = b"iris::mock-ipfs::";
+/// the offchain storage key of the cids the stub has added
+const PINS_KEY: &[u8] = b"iris::mock-ipfs-pins";
+
+fn storage_key(cid: &[u8]) -> Vec<u8> {
+    let mut key = STORAGE_PREFIX.to_vec();
+    key.extend_from_slice(cid);
+    key
+}
+
+/// Get the (fixed) identity of the stub node
+///
+pub fn identity() -> Result<Vec<u8>, http::Error> {
+    let id = format!(
+        "{{\"ID\":\"{0}\",\"Addresses\":[\"/ip4/127.0.0.1/tcp/4001/p2p/{0}\"]}}",
+        MOCK_PEER_ID,
+    );
+    Ok(id.into_bytes())
+}
+
+/// Accept a configuration update without applying it
+///
+/// * config_item: The ipfs configuration to update
+///
+pub fn config_update(config_item: IpfsConfigRequest) -> Result<(), http::Error> {
+    log::info!("mock-ipfs: ignoring config update for {:?}", config_item.key);
+    Ok(())
+}
+
+/// Show the stub's configuration
+///
+pub fn config_show() -> Result<Vec<u8>, http::Error> {
+    let config = format!("{{\"Datastore\":{{\"StorageMax\":\"{}\"}}}}", MOCK_STORAGE_MAX);
+    Ok(config.into_bytes())
+}
+
+/// Get the stub's repo stats
+///
+pub fn repo_stat() -> Result<Value, http::Error> {
+    let stat = format!("{{\"RepoSize\":0,\"StorageMax\":{}}}", MOCK_STORAGE_MAX);
+    super::parse(&stat).map_err(|_| http::Error::Unknown)
+}
+
+/// Pretend to connect to the given multiaddress
+///
+/// * multiaddress: The multiaddress to connect to
+///
+pub fn connect(_multiaddress: &[u8]) -> Result<(), http::Error> {
+    Ok(())
+}
+
+/// Pretend to disconnect from the given multiaddress
+///
+/// * multiaddress: The multiaddress to disconnect from
+///
+pub fn disconnect(_multiaddress: &[u8]) -> Result<(), http::Error> {
+    Ok(())
+}
+
+/// Store some data in offchain storage, keyed by the hex encoded blake2 hash of the data
+/// which stands in for a cid. Returns a response shaped like ipfs's add response.
+///
+/// * ipfs_add_request: The request object containing data to add
+///
+pub fn add(ipfs_add_request: IpfsAddRequest) -> Result<Vec<u8>, http::Error> {
+    let hash = sp_io::hashing::blake2_256(&ipfs_add_request.bytes);
+    let cid = format!("{}", HexDisplay::from(&hash));
+    StorageValueRef::persistent(&storage_key(cid.as_bytes())).set(&ipfs_add_request.bytes);
+    let pins = StorageValueRef::persistent(PINS_KEY);
+    let mut cids = pins.get::<Vec<Vec<u8>>>().ok().flatten().unwrap_or_default();
+    if !cids.contains(&cid.as_bytes().to_vec()) {
+        cids.push(cid.as_bytes().to_vec());
+        pins.set(&cids);
+    }
+    let res = format!(
+        "{{\"Name\":\"{0}\",\"Hash\":\"{0}\",\"Size\":\"{1}\"}}",
+        cid, ipfs_add_request.bytes.len(),
+    );
+    Ok(res.into_bytes())
+}
+
+/// Fetch data previously stored with `add`. Unknown cids resolve to empty data
+/// so that ingestion of data added elsewhere still completes.
+///
+/// * cid: The CID to fetch.
+///
+pub fn get(cid: &[u8]) -> Result<Vec<u8>, FetchError> {
+    match StorageValueRef::persistent(&storage_key(cid)).get::<Vec<u8>>() {
+        Ok(Some(bytes)) => Ok(bytes),
+        _ => {
+            log::warn!("mock-ipfs: unknown cid {:?}, returning empty data", cid);
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// List the cids stored with `add`, which the stub treats as pinned
+///
+pub fn pin_ls() -> Result<Vec<Vec<u8>>, http::Error> {
+    Ok(StorageValueRef::persistent(PINS_KEY).get::<Vec<Vec<u8>>>().ok().flatten().unwrap_or_default())
+}
+
+/// Retrieve data previously stored with `add`
+///
+/// cid: The CID to cat
+///
+pub fn cat(cid: &[u8]) -> Result<Vec<u8>, http::Error> {
+    get(cid).map_err(|_| http::Error::Unknown)
+}
@@ -31,17 +31,19 @@ mod mock;
 mod tests;
 
 pub mod ipfs;
+pub mod migrations;
 
 use frame_support::{
 	ensure,
 	pallet_prelude::*,
-	traits::{ Get, LockableCurrency },
+	traits::{ Get, LockableCurrency, ValidatorSet },
 };
 use serde_json::Value;
-use sp_runtime::offchain::OpaqueMultiaddr;
+use sp_runtime::offchain::{OpaqueMultiaddr, storage::StorageValueRef};
 use scale_info::TypeInfo;
 pub use pallet::*;
 use sp_std::{
+	collections::btree_set::BTreeSet,
 	str,
 	vec::Vec,
 	prelude::*
@@ -51,19 +53,34 @@ use frame_system::{
 	self as system, 
 	ensure_signed,
 	offchain::{
-		Signer, SendSignedTransaction,
+		Account, AppCrypto, CreateSignedTransaction, Signer, SendUnsignedTransaction, SignedPayload,
+		SigningTypes, SubmitTransaction,
 	}
 };
 use scale_info::prelude::format;
-use iris_primitives::IngestionCommand;
+use sp_runtime::{
+	Perbill, Percent, RuntimeAppPublic,
+	traits::{Extrinsic as ExtrinsicT, IdentifyAccount, Saturating, UniqueSaturatedInto},
+};
+use sp_staking::{
+	SessionIndex,
+	offence::{Kind, Offence, ReportOffence},
+};
+use iris_primitives::{
+	AssignmentStrategy, GatewayStats, IngestionCommand,
+	INGESTION_PAUSED_KEY, IN_FLIGHT_INGESTIONS_KEY, LOCAL_PINS_KEY, OFFCHAIN_SIGNER_KEY, PROCESS_QUEUE_NOW_KEY,
+};
 use pallet_gateway::ProxyProvider;
-use pallet_data_assets::{ResultsHandler, QueueManager};
+use pallet_data_assets::{OrphanedCommandPolicy, ResultsHandler, QueueManager};
 use pallet_iris_proxy::OffchainKeyManager;
 
 pub const LOG_TARGET: & str = "runtime::ipfs";
 
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"aura");
 
+/// the maximum score of each component of a gateway recommendation
+const MAX_SCORE: u128 = 100;
+
 pub mod crypto {
 	use super::KEY_TYPE;
 	use sp_core::sr25519::Signature as Sr25519Signature;
@@ -111,15 +128,114 @@ pub struct Configuration {
 	pub ready: bool,
 }
 
+/// the results of an `ipfs identity` call, signed by the reporting node
+/// so that they can be submitted in an unsigned transaction
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo)]
+pub struct IdentityPayload<Public, BlockNumber> {
+	/// the ipfs node's public key
+	pub public_key: Vec<u8>,
+	/// the multiaddresses associated with the public key
+	pub multiaddresses: Vec<OpaqueMultiaddr>,
+	/// the node's current x25519 public key
+	pub x25519_public_key: Vec<u8>,
+	/// the block in which the report was made
+	pub block_number: BlockNumber,
+	/// the public key the payload is signed with
+	pub public: Public,
+}
+
+impl<T: SigningTypes> SignedPayload<T> for IdentityPayload<T::Public, T::BlockNumber> {
+	fn public(&self) -> T::Public {
+		self.public.clone()
+	}
+}
+
+/// the storage reported after an ipfs config update, signed by the reporting node
+/// so that it can be submitted in an unsigned transaction
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo)]
+pub struct ConfigPayload<Public, BlockNumber> {
+	/// the storage available to the ipfs repo, in bytes
+	pub reported_storage_size: u128,
+	/// the storage used by the ipfs repo, in bytes
+	pub reported_repo_size: u128,
+	/// the block in which the report was made
+	pub block_number: BlockNumber,
+	/// the public key the payload is signed with
+	pub public: Public,
+}
+
+impl<T: SigningTypes> SignedPayload<T> for ConfigPayload<T::Public, T::BlockNumber> {
+	fn public(&self) -> T::Public {
+		self.public.clone()
+	}
+}
+
+/// the offender reported for config drift, as (account id, full identification)
+pub type ConfigDriftOffender<T> = (<T as frame_system::Config>::AccountId, <T as frame_system::Config>::AccountId);
+
+/// An offence committed by a node whose reported ipfs storage fell short of its declared
+/// `storage_max` in `ConfigDriftThreshold` consecutive config reports
+#[derive(RuntimeDebug, TypeInfo)]
+#[cfg_attr(feature = "std", derive(Clone, PartialEq, Eq))]
+pub struct ConfigDriftOffence<Offender> {
+	/// the session in which the offence was reported
+	pub session_index: SessionIndex,
+	/// the size of the validator set in that session
+	pub validator_set_count: u32,
+	/// the drifting node
+	pub offender: Offender,
+}
+
+impl<Offender: Clone> Offence<Offender> for ConfigDriftOffence<Offender> {
+	const ID: Kind = *b"iris:configdrift";
+	type TimeSlot = SessionIndex;
+
+	fn offenders(&self) -> Vec<Offender> {
+		vec![self.offender.clone()]
+	}
+
+	fn session_index(&self) -> SessionIndex {
+		self.session_index
+	}
+
+	fn validator_set_count(&self) -> u32 {
+		self.validator_set_count
+	}
+
+	fn time_slot(&self) -> Self::TimeSlot {
+		self.session_index
+	}
+
+	fn slash_fraction(_offenders: u32, _validator_set_count: u32) -> Perbill {
+		// the offending node is removed from the validator set rather than slashed
+		Perbill::zero()
+	}
+}
+
+/// Checks the content a gateway fetched for an ingestion command before it reports the
+/// ingestion as completed, e.g. to only accept supported formats
+pub trait ContentValidator {
+	/// why content was rejected
+	type Reason: sp_std::fmt::Debug;
+	/// accept or reject the content of a single file
+	fn validate(bytes: &[u8]) -> Result<(), Self::Reason>;
+}
+
+/// accepts all content
+impl ContentValidator for () {
+	type Reason = ();
+
+	fn validate(_bytes: &[u8]) -> Result<(), Self::Reason> {
+		Ok(())
+	}
+}
+
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
 	use frame_system::{
 		pallet_prelude::*,
-		offchain::{
-			AppCrypto,
-			CreateSignedTransaction,
-		}
+		offchain::CreateSignedTransaction,
 	};
 
 	/// Configure the pallet by specifying the parameters and types on which it
@@ -151,10 +267,61 @@ pub mod pallet {
 		type NodeConfigBlockDuration: Get<u32>;
 		/// TODO: this really is a bad design.
 		type OffchainKeyManager: pallet_iris_proxy::OffchainKeyManager<Self::AccountId>;
+		/// The origin allowed to submit ingestion results, in addition to the queue membership check.
+		/// Use `frame_system::EnsureSigned` to allow any signed account (the default behavior).
+		type CompletionOrigin: EnsureOrigin<Self::Origin, Success = Self::AccountId>;
+		/// the number of times the offchain worker attempts to submit an unsigned transaction
+		/// before giving up until its next run
+		#[pallet::constant]
+		type MaxSubmissionAttempts: Get<u32>;
+		/// the number of times the offchain worker resends a signed transaction the transaction
+		/// pool rejected, with a refreshed nonce, before leaving it to its next run
+		#[pallet::constant]
+		type MaxSignedRetries: Get<u32>;
+		/// the number of times a completed ingestion skips to a gateway's next asset id when
+		/// the generated one already belongs to an asset class
+		#[pallet::constant]
+		type MaxAssetIdRetries: Get<u32>;
+		/// whether offchain workers submit their ipfs identity and config as unsigned transactions
+		/// carrying a payload signed by the node, rather than as signed transactions
+		#[pallet::constant]
+		type UnsignedSubmissions: Get<bool>;
+		/// the maximum number of ipfs-substrate associations checked against the validator set per block
+		#[pallet::constant]
+		type MaxBridgeSweepPerBlock: Get<u32>;
+		/// decides which gateway ingests each queued command
+		type AssignmentStrategy: AssignmentStrategy<Self::AccountId>;
+		/// whether the commands queued with a gateway are refunded or rerouted to other gateways
+		/// once its last ipfs identity is deregistered or pruned
+		#[pallet::constant]
+		type DeregistrationPolicy: Get<OrphanedCommandPolicy>;
+		/// checks fetched content before an ingestion is reported as completed.
+		/// Use `()` to accept everything
+		type ContentValidator: ContentValidator;
+		/// the maximum number of ipfs identities registered by a single `submit_ipfs_identities` call
+		#[pallet::constant]
+		type MaxIdentitiesPerCall: Get<u32>;
+		/// how far below its declared `storage_max` a node's reported storage may fall
+		/// before the report counts as drifting
+		#[pallet::constant]
+		type ConfigDriftTolerance: Get<Percent>;
+		/// the number of consecutive drifting config reports after which a node is reported
+		#[pallet::constant]
+		type ConfigDriftThreshold: Get<u32>;
+		/// reports nodes whose config drifts repeatedly
+		type ReportConfigDrift: ReportOffence<
+			Self::AccountId,
+			ConfigDriftOffender<Self>,
+			ConfigDriftOffence<ConfigDriftOffender<Self>>,
+		>;
 	}
 
+	/// the current storage version
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	#[pallet::without_storage_info]
 	pub struct Pallet<T>(_);
 
@@ -173,6 +340,14 @@ pub mod pallet {
 		_, Blake2_128Concat, Vec<u8>, T::AccountId,
 	>;
 
+	/// the ipfs public keys bridged to each substrate account, in registration order,
+	/// kept in sync with `SubstrateIpfsBridge`
+	#[pallet::storage]
+	#[pallet::getter(fn ipfs_identities_of)]
+	pub(super) type IpfsIdentitiesOf<T: Config> = StorageMap<
+		_, Blake2_128Concat, T::AccountId, Vec<Vec<u8>>, ValueQuery,
+	>;
+
 	/// track ipfs repo stats onchain
 	/// for now, we just map accountid to actual storage size
 	#[pallet::storage]
@@ -181,12 +356,61 @@ pub mod pallet {
 		_, Blake2_128Concat, T::AccountId, u128, ValueQuery,
 	>;
 
+	/// the ipfs repo size (the storage used) reported by each node, in bytes
+	#[pallet::storage]
+	#[pallet::getter(fn repo_size)]
+	pub(super) type RepoSize<T: Config> = StorageMap<
+		_, Blake2_128Concat, T::AccountId, u128, ValueQuery,
+	>;
+
+	/// the block in which each node last submitted an identity, config or ingestion result
+	#[pallet::storage]
+	#[pallet::getter(fn last_seen)]
+	pub(super) type LastSeen<T: Config> = StorageMap<
+		_, Blake2_128Concat, T::AccountId, T::BlockNumber, OptionQuery,
+	>;
+
+	/// the raw storage key of the last ipfs-substrate association checked by the bridge sweep,
+	/// if the sweep is partway through the map
+	#[pallet::storage]
+	pub(super) type BridgeSweepCursor<T: Config> = StorageValue<_, Vec<u8>, OptionQuery>;
+
+	/// the number of consecutive config reports in which each node's storage drifted
+	/// from its declared `storage_max`
+	#[pallet::storage]
+	#[pallet::getter(fn config_drift_count)]
+	pub(super) type ConfigDriftCount<T: Config> = StorageMap<
+		_, Blake2_128Concat, T::AccountId, u32, ValueQuery,
+	>;
+
+	/// accounts that have asked their node to re-report its ipfs identity
+	/// on the next offchain worker run
+	#[pallet::storage]
+	#[pallet::getter(fn identity_refresh_requests)]
+	pub(super) type IdentityRefreshRequests<T: Config> = StorageMap<
+		_, Blake2_128Concat, T::AccountId, bool, ValueQuery,
+	>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
-		IdentitySubmitted(T::AccountId),
+		/// A node submitted its ipfs identity along with its x25519 public key
+		IdentitySubmitted(T::AccountId, Vec<u8>),
 		ConfigurationSyncSubmitted(T::AccountId),
-		IngestionComplete(),
+		/// A gateway completed an ingestion command after the given number of blocks
+		IngestionComplete(u32),
+		IdentityRefreshRequested(T::AccountId),
+		/// The ipfs identity of an account that is no longer a validator was removed
+		BridgePruned(T::AccountId, Vec<u8>),
+		/// A node's config drifted in `ConfigDriftThreshold` consecutive reports and was reported
+		ConfigDriftReported(T::AccountId),
+		/// An ipfs public key was registered to an account in a batch of identities
+		IdentityRegistered(T::AccountId, Vec<u8>),
+		/// The bootstrap multiaddresses of an account's ipfs identity changed:
+		/// (account, the number of multiaddresses added, the number removed)
+		BootstrapNodesUpdated(T::AccountId, u32, u32),
+		/// An account deregistered one of its ipfs identities: (account, ipfs public key)
+		IdentityDeregistered(T::AccountId, Vec<u8>),
 	}
 
 	#[pallet::error]
@@ -201,16 +425,40 @@ pub mod pallet {
 		IpfsError,
 		/// an Ipfs daemon is not running or is unreachable
 		IpfsNotAvailable,
+		/// the Ipfs daemon is reachable, but the content could not be found at the provider
+		ContentNotFound,
+		/// the fetched content was rejected by the node's content validator
+		ContentRejected,
 		/// failed to parse the response body -> maybe temp 
 		ResponseParsingFailure,
 		/// failure when calling the /config endpoint to update config
 		ConfigUpdateFailure,
 		InvalidSigner,
 		NotAuthorized,
+		/// The x25519 public key is not 32 bytes
+		InvalidX25519PublicKey,
+		/// ingestion and reencryption are halted
+		Halted,
+		/// unsigned identity and config submissions are not enabled
+		UnsignedSubmissionsDisabled,
+		/// a multiaddress addresses a different peer id than the submitted ipfs public key
+		PeerIdMismatch,
+		/// a batch must contain between 1 and `MaxIdentitiesPerCall` identities
+		InvalidIdentityCount,
+		/// the account has no ipfs identity bridged to it
+		NoIpfsIdentity,
 	}
 
 	#[pallet::hooks]
 	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_idle(_block_number: T::BlockNumber, remaining_weight: Weight) -> Weight {
+			Self::sweep_bridge(remaining_weight)
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			migrations::v1::migrate::<T>()
+		}
+
 		// The offchain worker here will act as the main coordination point for all offchain functions
 		// that require a substrate acct id (as identified by ipfs pubkey)
 		fn offchain_worker(block_number: T::BlockNumber) {
@@ -218,21 +466,36 @@ pub mod pallet {
 			if sp_io::offchain::is_validator() {
 				match Self::fetch_identity_json() {
 					Ok(id_json) => {
-						let id = &id_json["ID"];
-						let pubkey = id.clone().as_str().unwrap().as_bytes().to_vec();
+						let pubkey = match id_json["ID"].as_str() {
+							Some(id) => id.as_bytes().to_vec(),
+							None => {
+								log::error!("The ipfs identity response did not contain a valid ID");
+								return;
+							}
+						};
 						match <SubstrateIpfsBridge::<T>>::get(pubkey) {
 							Some(addr) => {
 								if <pallet_authorities::Pallet<T>>::x25519_public_keys(addr.clone()).is_empty() {
 									// should only happen once
-									<pallet_authorities::Pallet<T>>::update_x25519();
+									<pallet_authorities::Pallet<T>>::update_x25519(sp_io::offchain::random_seed());
 										if let Err(e) = Self::ipfs_update_configs(addr.clone()) {
 											log::error!("Encountered an error while attempting to update ipfs node config: {:?}", e);
 										}
 								}
-								if block_number % T::NodeConfigBlockDuration::get().into() == 0u32.into() {
+								if let Err(e) = Self::refresh_identity_if_requested() {
+									log::error!("Encountered an error while attempting to reannounce ipfs node identity: {:?}", e);
+								}
+								T::OffchainKeyManager::process_self_test(addr.clone());
+								let scheduled = block_number % Self::config_sync_interval().into() == 0u32.into();
+								// the flag is always taken, so a request made on a scheduled block isn't run twice
+								let requested = Self::take_process_queue_now();
+								if scheduled || requested {
 									if let Err(e) = Self::handle_ingestion_queue(addr.clone()) {
 										log::error!("Encountered an error while attempting to process the ingestion queue: {:?}", e);
 									}
+									Self::record_local_pins();
+								}
+								if scheduled {
 									let authorities = <pallet_authorities::Pallet<T>>::validators();
 									T::OffchainKeyManager::process_reencryption_requests(addr.clone());
 									T::OffchainKeyManager::process_decryption_delegation(addr, authorities);
@@ -252,12 +515,37 @@ pub mod pallet {
 					},
 					Err(_e) => {
 						// log::error!("There is no reachable IPFS node {:?}", e);
+						StorageValueRef::persistent(LOCAL_PINS_KEY).set(&None::<Vec<Vec<u8>>>);
 					}
 				}
 			}
 		}
 	}
 
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		/// Unsigned identity and config submissions are only valid when `UnsignedSubmissions`
+		/// is enabled, and their payload must be signed by a current validator.
+		fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if !T::UnsignedSubmissions::get() {
+				return InvalidTransaction::Call.into();
+			}
+			match call {
+				Call::submit_ipfs_identity_unsigned { payload, signature } =>
+					Self::validate_signed_payload(
+						b"identity", payload, signature, &payload.public, payload.block_number,
+					),
+				Call::submit_config_complete_unsigned { payload, signature } =>
+					Self::validate_signed_payload(
+						b"config", payload, signature, &payload.public, payload.block_number,
+					),
+				_ => InvalidTransaction::Call.into(),
+			}
+		}
+	}
+
 	#[pallet::call]
 	impl<T: Config> Pallet<T> {
 
@@ -267,19 +555,102 @@ pub mod pallet {
         /// * `cid`: The cid generated by the OCW
         /// * `id`: The AssetId (passed through from the create_storage_asset call)
         /// * `balance`: The balance (passed through from the create_storage_asset call)
+        /// * `ciphertext_size`: The size in bytes of the (encrypted) data fetched from IPFS
+        /// 
+        /// The origin must satisfy `T::CompletionOrigin`.
         ///
         #[pallet::weight(100_00)]
         pub fn submit_ingestion_completed(
             origin: OriginFor<T>,
 			cmd: IngestionCommand<T::AccountId, T::Balance>,
+			ciphertext_size: u128,
         ) -> DispatchResult {
-			let who = ensure_signed(origin)?;
+			let who = T::CompletionOrigin::ensure_origin(origin)?;
+			ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
 			let queued_commands = T::QueueManager::ingestion_requests(who.clone());
-			ensure!(queued_commands.contains(&cmd), Error::<T>::NotAuthorized);
+			ensure!(queued_commands.iter().any(|c| c.id == cmd.id), Error::<T>::NotAuthorized);
+			let mut retries = 0;
+			let outcome = loop {
+				let new_origin = system::RawOrigin::Signed(who.clone()).into();
+				let new_asset_id = T::ProxyProvider::next_asset_id(who.clone());
+				match T::ResultsHandler::create_asset_class(
+					new_origin, cmd.clone(), new_asset_id.into(), ciphertext_size,
+				) {
+					// the id was taken (e.g. by a migration), so skip it for good and retry
+					Err(e) if e == pallet_assets::Error::<T>::InUse.into()
+						&& retries < T::MaxAssetIdRetries::get() => {
+						log::info!("asset id {:?} is in use, retrying with the next one", new_asset_id);
+						T::ProxyProvider::commit_asset_id(who.clone());
+						retries += 1;
+					},
+					result => break result?,
+				}
+			};
+			// only consume the asset id once the asset class has been created
+			if outcome.asset_created {
+				T::ProxyProvider::commit_asset_id(who.clone());
+			}
+			Self::record_seen(who);
+			Self::deposit_event(Event::IngestionComplete(outcome.latency));
+            Ok(())
+        }
+
+        /// Report that an ingestion command could not be ingested (e.g. the data could not be
+        /// fetched from its multiaddress). The command is retried with an exponential backoff,
+        /// and abandoned after too many failed attempts.
+        ///
+        /// * `cmd_id`: The id of the command that could not be ingested
+        /// 
+        /// The origin must satisfy `T::CompletionOrigin`.
+        ///
+        #[pallet::weight(100_00)]
+        pub fn submit_ingestion_failed(
+            origin: OriginFor<T>,
+			cmd_id: [u8; 32],
+        ) -> DispatchResult {
+			let who = T::CompletionOrigin::ensure_origin(origin)?;
 			let new_origin = system::RawOrigin::Signed(who.clone()).into();
-			let new_asset_id = T::ProxyProvider::next_asset_id(who);
-			T::ResultsHandler::create_asset_class(new_origin, cmd, new_asset_id.into())?;
-			Self::deposit_event(Event::IngestionComplete());
+			T::ResultsHandler::record_failed_attempt(new_origin, cmd_id)?;
+			Self::record_seen(who);
+            Ok(())
+        }
+
+        /// Report that the data of an ingestion command failed the node's `ContentValidator`.
+        /// Unlike a failed fetch the rejection is final, so the command is removed from the
+        /// queue at once and the gateway's share of the reserve is returned to the owner.
+        ///
+        /// * `cmd_id`: The id of the command whose content was rejected
+        /// 
+        /// The origin must satisfy `T::CompletionOrigin`.
+        ///
+        #[pallet::weight(100_00)]
+        pub fn submit_ingestion_rejected(
+            origin: OriginFor<T>,
+			cmd_id: [u8; 32],
+        ) -> DispatchResult {
+			let who = T::CompletionOrigin::ensure_origin(origin)?;
+			let new_origin = system::RawOrigin::Signed(who.clone()).into();
+			T::ResultsHandler::reject_command(new_origin, cmd_id, Some(pallet_data_assets::REJECTED_CONTENT))?;
+			Self::record_seen(who);
+            Ok(())
+        }
+
+        /// Claim the request of an ingestion command before fetching its data, so that the
+        /// other gateways the request is replicated to don't fetch it at the same time.
+        ///
+        /// * `cmd_id`: The id of the command whose data is about to be fetched
+        /// 
+        /// The origin must satisfy `T::CompletionOrigin`.
+        ///
+        #[pallet::weight(100_00)]
+        pub fn submit_command_claim(
+            origin: OriginFor<T>,
+			cmd_id: [u8; 32],
+        ) -> DispatchResult {
+			let who = T::CompletionOrigin::ensure_origin(origin)?;
+			let new_origin = system::RawOrigin::Signed(who.clone()).into();
+			T::ResultsHandler::claim_command(new_origin, cmd_id)?;
+			Self::record_seen(who);
             Ok(())
         }
 
@@ -289,34 +660,147 @@ pub mod pallet {
         /// * origin: a validator node who is the controller for some stash
         /// * public_key: The IPFS node's public key
         /// * multiaddresses: A vector of multiaddresses associate with the public key
+        /// * x25519_public_key: The node's current x25519 public key, used to encrypt data to the node
         ///
         #[pallet::weight(100_000)]
         pub fn submit_ipfs_identity(
             origin: OriginFor<T>,
             public_key: Vec<u8>,
             multiaddresses: Vec<OpaqueMultiaddr>,
+            x25519_public_key: Vec<u8>,
         ) -> DispatchResult {
 			// we assume that this is the controller
             let who = ensure_signed(origin)?;
-			if <SubstrateIpfsBridge::<T>>::contains_key(public_key.clone()) {
-				let existing_association = <SubstrateIpfsBridge::<T>>::get(public_key.clone()).unwrap();
-				ensure!(who == existing_association, Error::<T>::InvalidPublicKey);
-			}
-			<BootstrapNodes::<T>>::insert(public_key.clone(), multiaddresses);
-			<SubstrateIpfsBridge::<T>>::insert(public_key, who.clone());
-			Self::deposit_event(Event::IdentitySubmitted(who));
-            Ok(())
+			Self::do_submit_ipfs_identity(who, public_key, multiaddresses, x25519_public_key)
         }
 
+		/// Submit the identities of several ipfs daemons run by the same node.
+		/// Each identity is validated as in `submit_ipfs_identity`, and the batch is rejected
+		/// as a whole if any of them is invalid or registered to another account.
+		///
+		/// * origin: a validator node who is the controller for some stash
+		/// * identities: The public key and multiaddresses of each IPFS node
+		/// * x25519_public_key: The node's current x25519 public key, used to encrypt data to the node
+		///
+		#[pallet::weight(100_000u64.saturating_mul(identities.len() as u64))]
+		pub fn submit_ipfs_identities(
+			origin: OriginFor<T>,
+			identities: Vec<(Vec<u8>, Vec<OpaqueMultiaddr>)>,
+			x25519_public_key: Vec<u8>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				!identities.is_empty() && identities.len() <= T::MaxIdentitiesPerCall::get() as usize,
+				Error::<T>::InvalidIdentityCount
+			);
+			ensure!(x25519_public_key.len() == 32, Error::<T>::InvalidX25519PublicKey);
+			// validate every identity before registering any of them
+			for (public_key, multiaddresses) in identities.iter() {
+				Self::validate_ipfs_identity(&who, public_key, multiaddresses)?;
+			}
+			for (public_key, multiaddresses) in identities.into_iter() {
+				Self::set_bootstrap_nodes(&who, public_key.clone(), multiaddresses);
+				Self::bridge_identity(&who, public_key.clone());
+				Self::deposit_event(Event::IdentityRegistered(who.clone(), public_key));
+			}
+			<IdentityRefreshRequests::<T>>::remove(who.clone());
+			<pallet_authorities::Pallet<T>>::register_box_key(who.clone(), x25519_public_key.clone());
+			Self::record_seen(who.clone());
+			Self::deposit_event(Event::IdentitySubmitted(who, x25519_public_key));
+			Ok(())
+		}
+
+		/// Deregister one of the caller's ipfs identities, so it is no longer offered as a
+		/// bootstrap node. Once the caller has no identity left, the commands queued with it
+		/// are handled as `DeregistrationPolicy` decides.
+		///
+		/// * public_key: The public key of the IPFS node to deregister
+		///
+		#[pallet::weight(100_000)]
+		pub fn deregister_ipfs_identity(
+			origin: OriginFor<T>,
+			public_key: Vec<u8>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				<SubstrateIpfsBridge::<T>>::get(&public_key) == Some(who.clone()),
+				Error::<T>::NotAuthorized
+			);
+			Self::unbridge_identity(&who, &public_key);
+			Self::set_bootstrap_nodes(&who, public_key.clone(), Vec::new());
+			Self::deposit_event(Event::IdentityDeregistered(who.clone(), public_key));
+			Self::release_if_deregistered(&who);
+			Ok(())
+		}
+
+		/// Submit the results of an `ipfs identity` call in an unsigned transaction.
+		/// Only available when `UnsignedSubmissions` is enabled.
+		/// 
+		/// * payload: the results, signed by a validator node
+		/// * signature: the signature of the payload, checked when the transaction is validated
+		/// 
+		#[pallet::weight(100_000)]
+		pub fn submit_ipfs_identity_unsigned(
+			origin: OriginFor<T>,
+			payload: IdentityPayload<T::Public, T::BlockNumber>,
+			_signature: T::Signature,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+			ensure!(T::UnsignedSubmissions::get(), Error::<T>::UnsignedSubmissionsDisabled);
+			Self::do_submit_ipfs_identity(
+				payload.public.into_account(),
+				payload.public_key,
+				payload.multiaddresses,
+				payload.x25519_public_key,
+			)
+		}
+
+		/// Request that your node re-reports its ipfs identity on its next offchain worker run,
+		/// rather than waiting for the identity to be reported on the regular schedule.
+		/// This is useful when a node restarts its ipfs daemon with a new peer id.
+		/// 
+		/// * origin: a validator node who has previously submitted an ipfs identity
+		/// 
+		#[pallet::weight(100)]
+		pub fn reannounce_identity(
+			origin: OriginFor<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(<IpfsIdentitiesOf::<T>>::contains_key(&who), Error::<T>::NoIpfsIdentity);
+			<IdentityRefreshRequests::<T>>::insert(who.clone(), true);
+			Self::deposit_event(Event::IdentityRefreshRequested(who));
+			Ok(())
+		}
+
 		#[pallet::weight(100_000)]
 		pub fn submit_config_complete(
 			origin: OriginFor<T>,
 			reported_storage_size: u128,
+			reported_repo_size: u128,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			<Stats<T>>::insert(who.clone(), reported_storage_size);
-			Self::deposit_event(Event::ConfigurationSyncSubmitted(who));
-			Ok(())
+			Self::do_submit_config_complete(who, reported_storage_size, reported_repo_size)
+		}
+
+		/// Submit the storage reported after an ipfs config update in an unsigned transaction.
+		/// Only available when `UnsignedSubmissions` is enabled.
+		/// 
+		/// * payload: the reported storage, signed by a validator node
+		/// * signature: the signature of the payload, checked when the transaction is validated
+		/// 
+		#[pallet::weight(100_000)]
+		pub fn submit_config_complete_unsigned(
+			origin: OriginFor<T>,
+			payload: ConfigPayload<T::Public, T::BlockNumber>,
+			_signature: T::Signature,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+			ensure!(T::UnsignedSubmissions::get(), Error::<T>::UnsignedSubmissionsDisabled);
+			Self::do_submit_config_complete(
+				payload.public.into_account(),
+				payload.reported_storage_size,
+				payload.reported_repo_size,
+			)
 		}
 	}
 }
@@ -340,51 +824,232 @@ impl<T: Config> Pallet<T> {
 		// 	Ok(id_res)
 		// } else {
 		match ipfs::identity() {
-			Ok(res) => {
-				let out = res.body().collect::<Vec<u8>>();
-				Ok(out)
-			} 
+			Ok(body) => Ok(body),
 			Err(_e) => {
 				Err(Error::<T>::IpfsNotAvailable)
 			}
 		}
 	}
 
+	/// Report the node's ipfs identity again if the local account asked for it with
+	/// `reannounce_identity`. The request is cleared on chain once the identity lands.
+	/// Returns whether the identity was reported.
+	/// 
+	fn refresh_identity_if_requested() -> Result<bool, Error<T>> {
+		let requested = Self::offchain_signer()
+			.map_or(false, |public| <IdentityRefreshRequests::<T>>::get(public.into_account()));
+		if !requested {
+			return Ok(false);
+		}
+		log::info!("Reannouncing the ipfs identity as requested");
+		Self::ipfs_verify_identity()?;
+		Ok(true)
+	}
+
 	/// verify if an ipfs daemon is running and if so, report its identity on chain
 	/// 
 	fn ipfs_verify_identity() -> Result<(), Error<T>> {
 		let id_json = Self::fetch_identity_json()?;
 		// get pubkey
-		let id = &id_json["ID"];
-		let pubkey = id.clone().as_str().unwrap().as_bytes().to_vec();
+		let pubkey = id_json["ID"].as_str()
+			.ok_or(Error::<T>::ResponseParsingFailure)?
+			.as_bytes().to_vec();
 		// get multiaddresses
 		let addrs: Vec<Value> = serde_json::from_value(id_json["Addresses"].clone())
-			.map_err(|_| Error::<T>::ResponseParsingFailure).unwrap();
-		let addrs_vec: Vec<_> = addrs.iter()
-			.map(|x| OpaqueMultiaddr(x.as_str().unwrap().as_bytes().to_vec()))
-			.collect();
+			.map_err(|_| Error::<T>::ResponseParsingFailure)?;
+		let addrs_vec = addrs.iter()
+			.map(|x| x.as_str()
+				.ok_or(Error::<T>::InvalidMultiaddress)
+				.and_then(|s| ipfs::string_to_multiaddr(s).map_err(|_| Error::<T>::InvalidMultiaddress)))
+			.collect::<Result<Vec<OpaqueMultiaddr>, Error<T>>>()?;
+		// get the node's x25519 public key
+		let x25519_public_key = <pallet_authorities::Pallet<T>>::local_x25519_public_key(sp_io::offchain::random_seed());
 		// submit extrinsic
-		let signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
-		if !signer.can_sign() {
-			log::error!(
-				"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+		if T::UnsignedSubmissions::get() {
+			let block_number = <frame_system::Pallet<T>>::block_number();
+			Self::send_unsigned_with_retry(
+				|account| IdentityPayload {
+					public_key: pubkey.clone(),
+					multiaddresses: addrs_vec.clone(),
+					x25519_public_key: x25519_public_key.clone(),
+					block_number,
+					public: account.public.clone(),
+				},
+				|payload, signature| Call::submit_ipfs_identity_unsigned { payload, signature },
 			);
-		}
-		let results = signer.send_signed_transaction(|_account| { 
-			Call::submit_ipfs_identity {
+		} else {
+			Self::send_signed(|| Call::submit_ipfs_identity {
 				public_key: pubkey.clone(),
 				multiaddresses: addrs_vec.clone(),
+				x25519_public_key: x25519_public_key.clone(),
+			});
+		}
+		Ok(())
+	}
+
+	fn do_submit_ipfs_identity(
+		who: T::AccountId,
+		public_key: Vec<u8>,
+		multiaddresses: Vec<OpaqueMultiaddr>,
+		x25519_public_key: Vec<u8>,
+	) -> DispatchResult {
+		ensure!(x25519_public_key.len() == 32, Error::<T>::InvalidX25519PublicKey);
+		Self::validate_ipfs_identity(&who, &public_key, &multiaddresses)?;
+		Self::set_bootstrap_nodes(&who, public_key.clone(), multiaddresses);
+		Self::bridge_identity(&who, public_key);
+		<IdentityRefreshRequests::<T>>::remove(who.clone());
+		<pallet_authorities::Pallet<T>>::register_box_key(who.clone(), x25519_public_key.clone());
+		Self::record_seen(who.clone());
+		Self::deposit_event(Event::IdentitySubmitted(who, x25519_public_key));
+		Ok(())
+	}
+
+	/// Replace the bootstrap multiaddresses of an ipfs identity, removing the entry when there
+	/// are none, and emit `BootstrapNodesUpdated` if they changed. Only the number of added and
+	/// removed multiaddresses is emitted, so the event stays small however many there are.
+	/// 
+	/// * `who`: The account the identity is registered to
+	/// * `public_key`: The IPFS node's public key
+	/// * `multiaddresses`: The identity's new multiaddresses
+	/// 
+	fn set_bootstrap_nodes(who: &T::AccountId, public_key: Vec<u8>, multiaddresses: Vec<OpaqueMultiaddr>) {
+		let previous = <BootstrapNodes::<T>>::get(&public_key);
+		let added = multiaddresses.iter().filter(|m| !previous.contains(m)).count() as u32;
+		let removed = previous.iter().filter(|m| !multiaddresses.contains(m)).count() as u32;
+		if multiaddresses.is_empty() {
+			<BootstrapNodes::<T>>::remove(public_key);
+		} else {
+			<BootstrapNodes::<T>>::insert(public_key, multiaddresses);
+		}
+		if added > 0 || removed > 0 {
+			Self::deposit_event(Event::BootstrapNodesUpdated(who.clone(), added, removed));
+		}
+	}
+
+	/// Hand the commands queued with an account to `DeregistrationPolicy` and eject the
+	/// replicas it pins once the account has no ipfs identity left, so its queued work
+	/// and hosted data aren't silently lost
+	/// 
+	/// * `who`: The account whose identity was removed
+	/// 
+	fn release_if_deregistered(who: &T::AccountId) {
+		if <IpfsIdentitiesOf::<T>>::contains_key(who) {
+			return;
+		}
+		T::QueueManager::release_gateway_commands(who.clone(), T::DeregistrationPolicy::get());
+		T::QueueManager::release_gateway_assets(who.clone());
+	}
+
+	/// Bridge an ipfs identity to an account, recording it among the account's identities
+	/// 
+	/// * `who`: The account the identity is registered to
+	/// * `public_key`: The IPFS node's public key
+	/// 
+	fn bridge_identity(who: &T::AccountId, public_key: Vec<u8>) {
+		<IpfsIdentitiesOf::<T>>::mutate(who, |identities| {
+			if !identities.contains(&public_key) {
+				identities.push(public_key.clone());
 			}
 		});
-		for (_, res) in &results {
-			match res {
-				Ok(()) => log::info!("Submitted results successfully"),
-				Err(e) => log::error!("Failed to submit transaction: {:?}",  e),
+		<SubstrateIpfsBridge::<T>>::insert(public_key, who.clone());
+	}
+
+	/// Remove an ipfs identity from the bridge and from the identities of its account
+	/// 
+	/// * `who`: The account the identity is registered to
+	/// * `public_key`: The IPFS node's public key
+	/// 
+	fn unbridge_identity(who: &T::AccountId, public_key: &[u8]) {
+		<SubstrateIpfsBridge::<T>>::remove(public_key);
+		<IpfsIdentitiesOf::<T>>::mutate_exists(who, |identities| {
+			if let Some(keys) = identities {
+				keys.retain(|key| key.as_slice() != public_key);
+				if keys.is_empty() {
+					*identities = None;
+				}
+			}
+		});
+	}
+
+	/// Ensure that an ipfs identity may be registered to an account: each multiaddress that
+	/// carries a peer id must carry the identity's public key, and the public key must not
+	/// be registered to another account.
+	/// 
+	/// * `who`: The account registering the identity
+	/// * `public_key`: The IPFS node's public key
+	/// * `multiaddresses`: The multiaddresses associated with the public key
+	/// 
+	fn validate_ipfs_identity(
+		who: &T::AccountId,
+		public_key: &[u8],
+		multiaddresses: &[OpaqueMultiaddr],
+	) -> DispatchResult {
+		for multiaddress in multiaddresses.iter() {
+			let address = ipfs::multiaddr_to_string(multiaddress)
+				.map_err(|_| Error::<T>::InvalidMultiaddress)?;
+			// addresses without a peer id are trusted to belong to the reporting node
+			if let Some(peer_id) = ipfs::multiaddr_peer_id(&address) {
+				ensure!(peer_id.as_bytes() == public_key, Error::<T>::PeerIdMismatch);
 			}
 		}
+		if let Some(existing_association) = <SubstrateIpfsBridge::<T>>::get(public_key) {
+			ensure!(*who == existing_association, Error::<T>::InvalidPublicKey);
+		}
 		Ok(())
 	}
 
+	fn do_submit_config_complete(
+		who: T::AccountId,
+		reported_storage_size: u128,
+		reported_repo_size: u128,
+	) -> DispatchResult {
+		<Stats<T>>::insert(who.clone(), reported_storage_size);
+		<RepoSize<T>>::insert(who.clone(), reported_repo_size);
+		Self::record_seen(who.clone());
+		Self::track_config_drift(who.clone(), reported_storage_size);
+		Self::deposit_event(Event::ConfigurationSyncSubmitted(who));
+		Ok(())
+	}
+
+	/// Count consecutive config reports in which a node's storage falls more than
+	/// `ConfigDriftTolerance` below its declared `storage_max`, and report a
+	/// `ConfigDriftOffence` once the count reaches `ConfigDriftThreshold`.
+	/// A compliant report resets the count.
+	/// 
+	/// * `who`: The reporting node
+	/// * `reported_storage_size`: The storage available to its ipfs repo, in bytes
+	/// 
+	fn track_config_drift(who: T::AccountId, reported_storage_size: u128) {
+		let declared = match T::ProxyProvider::prefs(who.clone()) {
+			Some(prefs) => prefs.storage_max,
+			None => 0,
+		};
+		let allowed_shortfall = T::ConfigDriftTolerance::get().mul_floor(declared);
+		if reported_storage_size >= declared.saturating_sub(allowed_shortfall) {
+			<ConfigDriftCount<T>>::remove(who);
+			return;
+		}
+		let count = <ConfigDriftCount<T>>::get(who.clone()).saturating_add(1);
+		if count < T::ConfigDriftThreshold::get() {
+			<ConfigDriftCount<T>>::insert(who, count);
+			return;
+		}
+		<ConfigDriftCount<T>>::remove(who.clone());
+		// the count starts over, so a grace period doesn't leave offences to report as soon as it ends
+		if <pallet_authorities::Pallet<T>>::in_offence_grace_period() {
+			return;
+		}
+		let offence = ConfigDriftOffence {
+			session_index: <pallet_authorities::Pallet<T> as ValidatorSet<T::AccountId>>::session_index(),
+			validator_set_count: <pallet_authorities::Pallet<T>>::validators().len() as u32,
+			offender: (who.clone(), who.clone()),
+		};
+		if let Err(e) = T::ReportConfigDrift::report_offence(vec![], offence) {
+			log::error!(target: LOG_TARGET, "Failed to report config drift: {:?}", e);
+		}
+		Self::deposit_event(Event::ConfigDriftReported(who));
+	}
+
 /// update the running ipfs daemon's configuration to be in sync
 	/// with the latest on-chain valid configuration values
 	/// 
@@ -403,27 +1068,30 @@ impl<T: Config> Pallet<T> {
 					json: None,
 				};
 				ipfs::config_update(storage_size_config_item).map_err(|_| Error::<T>::ConfigUpdateFailure)?;
-				let stat_response = ipfs::repo_stat().map_err(|_| Error::<T>::IpfsNotAvailable).unwrap();
+				let stat_response = ipfs::repo_stat().map_err(|e| {
+					log::error!("Failed to read the repo stats of the ipfs daemon: {:?}", e);
+					Error::<T>::IpfsNotAvailable
+				})?;
 				// 2. get actual available storage space
 				if let Some(actual_storage) = stat_response["StorageMax"].clone().as_u64() {
+					let repo_size = stat_response["RepoSize"].as_u64().unwrap_or(0);
 					// 3. report result on chain
-					let signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
-					if !signer.can_sign() {
-						log::error!(
-							"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+					if T::UnsignedSubmissions::get() {
+						let block_number = <frame_system::Pallet<T>>::block_number();
+						Self::send_unsigned_with_retry(
+							|account| ConfigPayload {
+								reported_storage_size: actual_storage.into(),
+								reported_repo_size: repo_size.into(),
+								block_number,
+								public: account.public.clone(),
+							},
+							|payload, signature| Call::submit_config_complete_unsigned { payload, signature },
 						);
-					}
-					let results = signer.send_signed_transaction(|_account| { 
-						Call::submit_config_complete{
+					} else {
+						Self::send_signed(|| Call::submit_config_complete {
 							reported_storage_size: actual_storage.into(),
-						}
-					});
-
-					for (_, res) in &results {
-						match res {
-							Ok(()) => log::info!("Submitted results successfully"),
-							Err(e) => log::error!("Failed to submit transaction: {:?}",  e),
-						}
+							reported_repo_size: repo_size.into(),
+						});
 					}
 				}
 			},
@@ -447,39 +1115,565 @@ impl<T: Config> Pallet<T> {
         Ok(())
     }
 
+	/// Determine whether a gateway is assigned to process an ingestion command,
+	/// so that at most one gateway ingests the data and submits the results.
+	/// 
+	/// The candidates are the registered proxies that have the command queued, and the
+	/// command is assigned to one of them by `T::AssignmentStrategy`.
+	/// 
+	/// * `cmd`: The ingestion command
+	/// * `account`: The gateway to check
+	/// 
+	pub fn is_assigned(
+		cmd: &IngestionCommand<T::AccountId, T::Balance>,
+		account: &T::AccountId,
+	) -> bool {
+		Self::assigned_commands(sp_std::slice::from_ref(cmd), account).contains(&cmd.id)
+	}
+
+	/// The ids of the commands a gateway is assigned to process, decided for each command as
+	/// in `is_assigned`. The queue of every registered proxy is read once for all of the
+	/// commands, rather than once per command.
+	/// 
+	/// * `commands`: The ingestion commands to check
+	/// * `account`: The gateway to check
+	/// 
+	pub fn assigned_commands(
+		commands: &[IngestionCommand<T::AccountId, T::Balance>],
+		account: &T::AccountId,
+	) -> BTreeSet<[u8; 32]> {
+		let queues: Vec<(T::AccountId, BTreeSet<[u8; 32]>)> = T::ProxyProvider::proxies()
+			.into_iter()
+			.map(|p| {
+				let queued = T::QueueManager::ingestion_requests(p.clone()).into_iter().map(|c| c.id).collect();
+				(p, queued)
+			})
+			.collect();
+		commands.iter()
+			.filter(|cmd| {
+				let candidates: Vec<T::AccountId> = queues.iter()
+					.filter(|(_, queued)| queued.contains(&cmd.id))
+					.map(|(p, _)| p.clone())
+					.collect();
+				T::AssignmentStrategy::assign_ingestion(*cmd, candidates).as_ref() == Some(account)
+			})
+			.map(|cmd| cmd.id)
+			.collect()
+	}
+
+	/// Rank the proxies with enough remaining storage for some data, best first.
+	/// Each proxy is scored out of 100 on each of:
+	/// 
+	/// * capacity: its remaining storage relative to the proxy with the most remaining storage
+	/// * reliability: 100 / (1 + its average ingestion latency), or 0 if it has never completed an ingestion
+	/// * recency: how recently it was last seen on chain, decaying to 0 over `NodeConfigBlockDuration` blocks
+	/// 
+	/// Ties are broken by account id.
+	/// 
+	/// * `estimated_size`: The estimated size of the data to be ingested, in bytes
+	/// * `count`: The maximum number of proxies to return
+	/// * `gateway_stats`: Provides the ingestion stats of a proxy
+	/// 
+	pub fn recommend_gateways<F>(
+		estimated_size: u128,
+		count: u32,
+		gateway_stats: F,
+	) -> Vec<T::AccountId>
+	where
+		F: Fn(&T::AccountId) -> GatewayStats,
+	{
+		let candidates: Vec<(T::AccountId, u128)> = T::ProxyProvider::proxies()
+			.into_iter()
+			.filter_map(|g| T::ProxyProvider::remaining_capacity(g.clone()).map(|remaining| (g, remaining)))
+			.filter(|(_, remaining)| *remaining > 0 && *remaining >= estimated_size)
+			.collect();
+		let most_remaining = candidates.iter().map(|(_, r)| *r).max().unwrap_or(1);
+		let now = <frame_system::Pallet<T>>::block_number();
+		let window = Self::config_sync_interval().max(1) as u128;
+		let mut scored: Vec<(u128, T::AccountId)> = candidates
+			.into_iter()
+			.map(|(g, remaining)| {
+				let capacity = remaining.saturating_mul(MAX_SCORE) / most_remaining;
+				let stats = gateway_stats(&g);
+				let reliability = if stats.completed == 0 {
+					0
+				} else {
+					MAX_SCORE / (1 + stats.average_latency as u128)
+				};
+				let recency = Self::last_seen(&g).map(|seen| {
+					let age: u128 = now.saturating_sub(seen).unique_saturated_into();
+					MAX_SCORE.saturating_mul(window.saturating_sub(age)) / window
+				}).unwrap_or(0);
+				(capacity + reliability + recency, g)
+			})
+			.collect();
+		scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+		scored.into_iter().take(count as usize).map(|(_, g)| g).collect()
+	}
+
+	/// Whether the node operator has paused ingestion processing on this node.
+	/// The flag is node-local: it is kept in persistent offchain storage and
+	/// toggled with the `iris_pauseIngestion` rpc.
+	pub fn ingestion_paused() -> bool {
+		StorageValueRef::persistent(INGESTION_PAUSED_KEY)
+			.get::<bool>()
+			.ok()
+			.flatten()
+			.unwrap_or(false)
+	}
+
+	/// The number of blocks between the offchain worker's scheduled runs, in which gateways
+	/// sync their ipfs config and process their ingestion queue. Nodes run on blocks that
+	/// are a multiple of the interval. This is currently the `NodeConfigBlockDuration` constant.
+	pub fn config_sync_interval() -> u32 {
+		T::NodeConfigBlockDuration::get()
+	}
+
+	/// Whether the node operator has asked for the ingestion queue to be processed on
+	/// this offchain worker run, clearing the request. The flag is node-local: it is kept
+	/// in persistent offchain storage and set with the `iris_processQueueNow` rpc.
+	pub fn take_process_queue_now() -> bool {
+		let mut flag = StorageValueRef::persistent(PROCESS_QUEUE_NOW_KEY);
+		let requested = flag.get::<bool>().ok().flatten().unwrap_or(false);
+		if requested {
+			flag.clear();
+		}
+		requested
+	}
+
+	/// Record the cids the node's ipfs daemon has pinned in persistent offchain storage,
+	/// where the `iris_localPins` rpc reads them. None is recorded if the daemon can't
+	/// be reached.
+	pub fn record_local_pins() {
+		let pins = match ipfs::pin_ls() {
+			Ok(pins) => Some(pins),
+			Err(e) => {
+				log::error!("Unable to list the cids pinned by the ipfs daemon: {:?}", e);
+				None
+			}
+		};
+		StorageValueRef::persistent(LOCAL_PINS_KEY).set(&pins);
+	}
+
+	/// The ingestions the node has fetched and reported as completed but that are still
+	/// queued on chain, as (command id, size in bytes, block reported at). Their size counts
+	/// toward the node's `storage_max` until the node's reported repo size includes them.
+	/// The list is node-local and kept in persistent offchain storage.
+	pub fn in_flight_ingestions() -> Vec<([u8; 32], u128, u32)> {
+		StorageValueRef::persistent(IN_FLIGHT_INGESTIONS_KEY)
+			.get::<Vec<([u8; 32], u128, u32)>>()
+			.ok()
+			.flatten()
+			.unwrap_or_default()
+	}
+
+	/// Submit a signed transaction from the offchain signer, returning whether the transaction
+	/// pool accepted it. The pool rejects a transaction signed with a nonce that a pending
+	/// transaction of the signer already uses, e.g. one submitted earlier in the same run, so a
+	/// rejected transaction is resent up to `MaxSignedRetries` times. Each attempt re-reads the
+	/// signer's on-chain nonce and skips the nonces of the attempts before it.
+	/// 
+	/// * `call`: Builds the call to submit
+	/// 
+	fn send_signed<F>(call: F) -> bool
+	where
+		F: Fn() -> Call<T>,
+	{
+		let public = match Self::offchain_signer() {
+			Some(public) => public,
+			None => {
+				log::error!(
+					"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+				);
+				return false;
+			},
+		};
+		let account = public.clone().into_account();
+		let retries = T::MaxSignedRetries::get();
+		for attempt in 0..=retries {
+			let nonce = <frame_system::Pallet<T>>::account_nonce(&account).saturating_add(attempt.into());
+			let xt = <T as CreateSignedTransaction<Call<T>>>::create_transaction::<<T as pallet::Config>::AuthorityId>(
+				call().into(), public.clone(), account.clone(), nonce,
+			).and_then(|(call, signature)| T::Extrinsic::new(call, Some(signature)));
+			let xt = match xt {
+				Some(xt) => xt,
+				None => {
+					log::error!("Failed to sign transaction");
+					return false;
+				},
+			};
+			match SubmitTransaction::<T, Call<T>>::submit_transaction(xt) {
+				Ok(()) => {
+					log::info!("Submitted results successfully");
+					return true;
+				},
+				Err(()) => log::warn!(
+					"The transaction pool rejected the transaction signed with nonce {:?} (attempt {:?})",
+					nonce, attempt + 1,
+				),
+			}
+		}
+		log::error!("Giving up after {:?} rejected submissions, deferring the transaction to the next run", retries + 1);
+		false
+	}
+
+	/// The local account the offchain worker signs its transactions with, so a node
+	/// holding several keys submits each transaction once. Operators can designate an
+	/// account by setting its (scale encoded) account id in persistent offchain storage
+	/// under `OFFCHAIN_SIGNER_KEY`, otherwise the first local key is used. Nothing is
+	/// signed if the designated account isn't in the local keystore.
+	pub fn offchain_signer() -> Option<T::Public> {
+		let mut keys = <
+			<<T as pallet::Config>::AuthorityId as AppCrypto<T::Public, T::Signature>>::RuntimeAppPublic
+				as RuntimeAppPublic
+		>::all()
+			.into_iter()
+			.map(|key| {
+				let generic: <<T as pallet::Config>::AuthorityId as AppCrypto<T::Public, T::Signature>>::GenericPublic =
+					key.into();
+				let public: T::Public = generic.into();
+				public
+			});
+		let designated = StorageValueRef::persistent(OFFCHAIN_SIGNER_KEY)
+			.get::<T::AccountId>()
+			.ok()
+			.flatten();
+		match designated {
+			Some(account) => {
+				let public = keys.find(|public| public.clone().into_account() == account);
+				if public.is_none() {
+					log::error!("The designated offchain signer {:?} is not in the local keystore", account);
+				}
+				public
+			},
+			None => keys.next(),
+		}
+	}
+
+	/// Submit an unsigned transaction carrying a payload signed by any local account,
+	/// making up to `MaxSubmissionAttempts` attempts.
+	/// 
+	/// * `payload`: Builds the payload to sign for an account
+	/// * `call`: Builds the call from the payload and its signature
+	/// 
+	fn send_unsigned_with_retry<P, F, G>(payload: F, call: G)
+	where
+		P: SignedPayload<T>,
+		F: Fn(&Account<T>) -> P,
+		G: Fn(P, T::Signature) -> Call<T>,
+	{
+		let signer = match Self::offchain_signer() {
+			Some(public) => Signer::<T, <T as pallet::Config>::AuthorityId>::any_account()
+				.with_filter(vec![public]),
+			None => {
+				log::error!(
+					"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+				);
+				return;
+			},
+		};
+		Self::retry_submission(T::MaxSubmissionAttempts::get(), |attempt| {
+			match signer.send_unsigned_transaction(&payload, &call) {
+				Some((_, Ok(()))) => {
+					log::info!("Submitted results successfully");
+					true
+				},
+				_ => {
+					log::error!("Failed to submit unsigned transaction (attempt {:?})", attempt);
+					false
+				},
+			}
+		});
+	}
+
+	/// Check that an unsigned submission's payload is signed by a current validator.
+	/// A validator can submit each kind of payload once per block.
+	/// 
+	/// * `kind`: Distinguishes the kinds of payload
+	/// * `payload`: The signed payload
+	/// * `signature`: The signature of the payload
+	/// * `public`: The public key the payload claims to be signed with
+	/// * `block_number`: The block in which the payload was created
+	/// 
+	fn validate_signed_payload<P: SignedPayload<T>>(
+		kind: &'static [u8],
+		payload: &P,
+		signature: &T::Signature,
+		public: &T::Public,
+		block_number: T::BlockNumber,
+	) -> TransactionValidity {
+		if !payload.verify::<T::AuthorityId>(signature.clone()) {
+			return InvalidTransaction::BadProof.into();
+		}
+		let who = public.clone().into_account();
+		if !<pallet_authorities::Pallet<T>>::validators().contains(&who) {
+			return InvalidTransaction::BadSigner.into();
+		}
+		ValidTransaction::with_tag_prefix("iris-ipfs")
+			.priority(2 << 20)
+			.and_provides((kind, who, block_number))
+			.longevity(Self::config_sync_interval().into())
+			.propagate(true)
+			.build()
+	}
+
+	/// Call `submit` until it succeeds, at most `max_attempts` times (and at least once).
+	/// Returns the number of attempts made.
+	/// 
+	/// * `max_attempts`: The maximum number of attempts
+	/// * `submit`: Makes an attempt (numbered from 1), returning true if it succeeded
+	/// 
+	pub fn retry_submission<F>(max_attempts: u32, mut submit: F) -> u32
+	where
+		F: FnMut(u32) -> bool,
+	{
+		let max_attempts = max_attempts.max(1);
+		for attempt in 1..=max_attempts {
+			if submit(attempt) {
+				return attempt;
+			}
+		}
+		log::error!("Giving up after {:?} failed submission attempts", max_attempts);
+		max_attempts
+	}
+
+	/// record that a node was active in the current block
+	fn record_seen(who: T::AccountId) {
+		<LastSeen<T>>::insert(who, <frame_system::Pallet<T>>::block_number());
+	}
+
+	/// Remove the ipfs identities of accounts that are no longer validators, so that
+	/// they are not offered as bootstrap nodes. At most `MaxBridgeSweepPerBlock` associations
+	/// are checked per call, and the sweep resumes where it left off on the next call.
+	/// 
+	/// * `remaining_weight`: The weight available to the sweep
+	/// 
+	fn sweep_bridge(remaining_weight: Weight) -> Weight {
+		let db_weight = T::DbWeight::get();
+		let mut consumed = db_weight.reads(2);
+		let validators = <pallet_authorities::Pallet<T>>::validators();
+		if validators.is_empty() {
+			return consumed;
+		}
+
+		let max_entries = T::MaxBridgeSweepPerBlock::get() as usize;
+		let entry_weight = db_weight.reads_writes(2, 3);
+		let mut entries = match <BridgeSweepCursor<T>>::get() {
+			Some(cursor) => <SubstrateIpfsBridge<T>>::iter_from(cursor),
+			None => <SubstrateIpfsBridge<T>>::iter(),
+		};
+		let mut checked = 0;
+		let mut last_checked = None;
+		let mut stale = Vec::new();
+		let finished = loop {
+			if checked >= max_entries || consumed.saturating_add(entry_weight) > remaining_weight {
+				break false;
+			}
+			match entries.next() {
+				Some((public_key, who)) => {
+					consumed = consumed.saturating_add(entry_weight);
+					checked += 1;
+					if !validators.contains(&who) {
+						stale.push((public_key.clone(), who));
+					}
+					last_checked = Some(public_key);
+				},
+				None => break true,
+			}
+		};
+
+		for (public_key, who) in stale {
+			Self::unbridge_identity(&who, &public_key);
+			Self::set_bootstrap_nodes(&who, public_key.clone(), Vec::new());
+			Self::deposit_event(Event::BridgePruned(who.clone(), public_key));
+			Self::release_if_deregistered(&who);
+		}
+		if finished {
+			<BridgeSweepCursor<T>>::kill();
+		} else if let Some(public_key) = last_checked {
+			<BridgeSweepCursor<T>>::put(<SubstrateIpfsBridge<T>>::hashed_key_for(public_key));
+		}
+		consumed.saturating_add(db_weight.writes(1))
+	}
+
 	/// process requests to ingest data from offchain clients
 	/// This function fetches data from offchain clients and ingests it into IPFS
 	/// it finally sends a signed tx to create an asset class on behalf of the caller
 	fn handle_ingestion_queue(account: T::AccountId) -> Result<(), Error<T>> {
+		Self::process_ingestion_queue(account, Self::fetch_command_data)
+	}
+
+	/// Process the ingestion queue of a gateway, fetching the data of each command with `fetch`.
+	/// A command whose content can't be found is reported as failed. If the ipfs daemon isn't
+	/// available the run stops without reporting anything, since no command can be fetched
+	/// until it is back, and fails with `IpfsNotAvailable`.
+	/// 
+	/// * `account`: The gateway whose queue is processed
+	/// * `fetch`: Fetches the data of a command, returning its size in bytes
+	/// 
+	fn process_ingestion_queue(
+		account: T::AccountId,
+		fetch: impl Fn(&IngestionCommand<T::AccountId, T::Balance>) -> Result<u128, Error<T>>,
+	) -> Result<(), Error<T>> {
+		ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
 		let queued_commands = T::QueueManager::ingestion_requests(account);
+		if Self::ingestion_paused() {
+			log::info!(
+				"Ingestion processing is paused, leaving {:?} items in the ingestion queue. Resume with iris_pauseIngestion(false)",
+				queued_commands.len(),
+			);
+			return Ok(());
+		}
 		log::info!("Processing {:?} items in the ingestion queue", queued_commands.len());
+		let current_block: u32 = <frame_system::Pallet<T>>::block_number().unique_saturated_into();
+		// ingestions that left the queue are counted in the repo size the node reports, and
+		// ones still queued a sync interval after being reported are assumed to have been dropped
+		let mut in_flight = Self::in_flight_ingestions();
+		in_flight.retain(|(id, _, reported_at)| {
+			queued_commands.iter().any(|c| c.id == *id)
+				&& current_block < reported_at.saturating_add(Self::config_sync_interval())
+		});
+		// a node that hasn't declared its storage isn't capped
+		let capacity = T::ProxyProvider::prefs(account.clone())
+			.filter(|prefs| prefs.storage_max > 0)
+			.map(|prefs| prefs.storage_max);
+		let mut committed = in_flight.iter()
+			.fold(RepoSize::<T>::get(&account), |total, (_, size, _)| total.saturating_add(*size));
+		let mut stopped = false;
+		let assigned = Self::assigned_commands(&queued_commands, &account);
 		for cmd in queued_commands.iter() {
-			let cid = cmd.cid.clone();
-			ipfs::get(&cid.clone()).map_err(|_| Error::<T>::InvalidCID)?;
-			
-			let signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
-			if !signer.can_sign() {
-				log::error!(
-					"No local accounts available. Consider adding one via `author_insertKey` RPC.",
-				);
+			if !assigned.contains(&cmd.id) {
+				log::info!("Skipping ingestion command {:?}: assigned to another gateway", cmd.id);
+				continue;
 			}
-			let results = signer.send_signed_transaction(|_acct| { 
-				Call::submit_ingestion_completed {
-					cmd: cmd.clone(),
+			if in_flight.iter().any(|(id, _, _)| *id == cmd.id) {
+				log::info!("Skipping ingestion command {:?}: its completion was already reported", cmd.id);
+				continue;
+			}
+			let next_attempt = T::QueueManager::next_attempt(cmd.id);
+			if current_block < next_attempt {
+				log::info!("Skipping ingestion command {:?}: backing off until block {:?}", cmd.id, next_attempt);
+				continue;
+			}
+			// a replicated request is fetched by one gateway at a time, which claims it first
+			if T::QueueManager::replication(cmd.id) > 1 {
+				match T::QueueManager::claim(cmd.id) {
+					Some((claimer, expires_at)) if current_block < expires_at => {
+						if claimer != account {
+							log::info!("Skipping ingestion command {:?}: claimed by another gateway until block {:?}", cmd.id, expires_at);
+							continue;
+						}
+					},
+					_ => {
+						log::info!("Claiming ingestion command {:?}, fetching once the claim is on chain", cmd.id);
+						let cmd_id = cmd.id;
+						Self::send_signed(|| Call::submit_command_claim { cmd_id });
+						continue;
+					},
 				}
-			});
-		
-			for (_, res) in &results {
-				match res {
-					Ok(()) => log::info!("Submitted results successfully"),
-					Err(e) => log::error!("Failed to submit transaction: {:?}",  e),
+			}
+			let (call, reported) = match fetch(cmd) {
+				Ok(ciphertext_size) => {
+					let total = committed.saturating_add(ciphertext_size);
+					if capacity.map_or(false, |capacity| total > capacity) {
+						log::warn!(
+							"Refusing ingestion command {:?}: {:?} bytes would exceed the node's storage_max, with {:?} bytes already committed",
+							cmd.id, ciphertext_size, committed,
+						);
+						continue;
+					}
+					let call = Call::submit_ingestion_completed {
+						cmd: cmd.clone(),
+						ciphertext_size,
+					};
+					(call, Some(ciphertext_size))
+				},
+				Err(Error::<T>::ContentRejected) => {
+					log::warn!("Rejecting ingestion command {:?}: its content failed validation", cmd.id);
+					(Call::submit_ingestion_rejected { cmd_id: cmd.id }, None)
+				},
+				Err(Error::<T>::ContentNotFound) => {
+					log::warn!("Failed to ingest {:?}: its content was not found", cmd.id);
+					(Call::submit_ingestion_failed { cmd_id: cmd.id }, None)
+				},
+				Err(Error::<T>::IpfsNotAvailable) => {
+					// the failure is the node's, so it isn't held against the command
+					log::error!("Stopping ingestion: the ipfs daemon is not available");
+					stopped = true;
+					break;
+				},
+				Err(e) => {
+					log::warn!("Skipping ingestion command {:?}: {:?}", cmd.id, e);
+					continue;
+				},
+			};
+			// a rejected report is rebuilt on the next run, so only an accepted one is in flight
+			if Self::send_signed(|| call.clone()) {
+				if let Some(ciphertext_size) = reported {
+					committed = committed.saturating_add(ciphertext_size);
+					in_flight.push((cmd.id, ciphertext_size, current_block));
 				}
 			}
 		}
+		StorageValueRef::persistent(IN_FLIGHT_INGESTIONS_KEY).set(&in_flight);
+		ensure!(!stopped, Error::<T>::IpfsNotAvailable);
 		Ok(())
 	}
-}
 
+	/// Fetch the data of an ingestion command, returning its size in bytes.
+	/// A dataset is only complete once every one of its files is fetched.
+	/// 
+	/// * `cmd`: The ingestion command
+	/// 
+	fn fetch_command_data(cmd: &IngestionCommand<T::AccountId, T::Balance>) -> Result<u128, Error<T>> {
+		Self::fetch_file(&cmd.cid, &cmd.multiaddress).and_then(|size| {
+			T::QueueManager::dataset_files(cmd.id).iter().try_fold(size, |total, (cid, multiaddress)| {
+				Self::fetch_file(cid, multiaddress).map(|size| total.saturating_add(size))
+			})
+		})
+	}
+}
 
+impl<T: Config> Pallet<T> {
+	/// Fetch a file from ipfs, returning its size in bytes. Fails with `IpfsNotAvailable`
+	/// if the local daemon is down and `ContentNotFound` if the content isn't available,
+	/// so operators can tell the two apart, and with `ContentRejected` if the fetched
+	/// content fails the node's `ContentValidator`.
+	/// 
+	/// * `cid`: The cid of the file
+	/// * `multiaddress`: The multiaddress of the ipfs node where the file exists
+	/// 
+	fn fetch_file(cid: &[u8], multiaddress: &[u8]) -> Result<u128, Error<T>> {
+		ipfs::multiaddr_to_string(&OpaqueMultiaddr(multiaddress.to_vec())).map_err(|e| {
+			log::warn!("Failed to fetch {:?}: invalid multiaddress {:?}: {:?}", cid, multiaddress, e);
+			Error::<T>::InvalidMultiaddress
+		})?;
+		let data = ipfs::get(cid).map_err(|e| match e {
+			ipfs::FetchError::IpfsNotAvailable => {
+				log::error!("Failed to fetch {:?}: the ipfs daemon is not available", cid);
+				Error::<T>::IpfsNotAvailable
+			},
+			ipfs::FetchError::ContentNotFound => {
+				log::warn!("Failed to fetch {:?}: content not found at {:?}", cid, multiaddress);
+				Error::<T>::ContentNotFound
+			},
+		})?;
+		T::ContentValidator::validate(&data).map_err(|reason| {
+			log::warn!("Rejected the content of {:?}: {:?}", cid, reason);
+			Error::<T>::ContentRejected
+		})?;
+		Ok(data.len() as u128)
+	}
+}
 
+impl<T: Config> pallet_gateway::StorageReporter<T::AccountId> for Pallet<T> {
+	fn repo_size(acct: &T::AccountId) -> u128 {
+		RepoSize::<T>::get(acct)
+	}
+}
 
+impl<T: Config> pallet_data_assets::PeerIdProvider<T::AccountId> for Pallet<T> {
+	fn peer_id(gateway: &T::AccountId) -> Option<Vec<u8>> {
+		<IpfsIdentitiesOf<T>>::get(gateway).into_iter().next()
+	}
+}
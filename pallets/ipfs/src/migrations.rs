@@ -0,0 +1,43 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Storage migrations for the ipfs pallet
+
+use super::*;
+
+/// Version 1 indexes the ipfs identities bridged to each account
+pub mod v1 {
+	use super::*;
+
+	/// Migrate to version 1, recording each identity in `SubstrateIpfsBridge`
+	/// among the identities of the account it is bridged to.
+	/// 
+	pub fn migrate<T: Config>() -> Weight {
+		let on_chain = StorageVersion::get::<Pallet<T>>();
+		if on_chain >= 1 {
+			return T::DbWeight::get().reads(1);
+		}
+		let mut indexed: u64 = 0;
+		for (public_key, who) in SubstrateIpfsBridge::<T>::iter() {
+			indexed += 1;
+			IpfsIdentitiesOf::<T>::append(who, public_key);
+		}
+		StorageVersion::new(1).put::<Pallet<T>>();
+		log::info!("Indexed {:?} ipfs identities in version 1", indexed);
+		T::DbWeight::get().reads_writes(indexed + 1, indexed + 1)
+	}
+}
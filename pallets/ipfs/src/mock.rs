@@ -21,7 +21,7 @@ use super::*;
 use crate::{self as pallet_ipfs, Config};
 use frame_support::{
 	parameter_types, 
-	traits::{GenesisBuild, ConstU32},
+	traits::{GenesisBuild, ConstBool, ConstU32, ConstU64, EnsureOrigin},
 	BasicExternalities,
 };
 
@@ -212,6 +212,10 @@ impl frame_system::Config for Test {
 
 parameter_types! {
 	pub const NodeConfigBlockDuration: u32 = 10;
+	pub static UnsignedSubmissions: bool = false;
+	pub static DeregistrationPolicy: pallet_data_assets::OrphanedCommandPolicy = pallet_data_assets::OrphanedCommandPolicy::Refund;
+	pub static RejectContent: bool = false;
+	pub const ConfigDriftTolerance: Percent = Percent::from_percent(10);
 }
 
 impl Config for Test {
@@ -225,6 +229,49 @@ impl Config for Test {
 	type ResultsHandler = DataAssets;
 	type OffchainKeyManager = IrisProxy;
 	type NodeConfigBlockDuration = NodeConfigBlockDuration;
+	type CompletionOrigin = EnsureValidator;
+	type MaxSubmissionAttempts = ConstU32<3>;
+	type MaxSignedRetries = ConstU32<2>;
+	type MaxAssetIdRetries = ConstU32<2>;
+	type UnsignedSubmissions = UnsignedSubmissions;
+	type MaxBridgeSweepPerBlock = ConstU32<10>;
+	type MaxIdentitiesPerCall = ConstU32<3>;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
+	type DeregistrationPolicy = DeregistrationPolicy;
+	type ContentValidator = MockContentValidator;
+	type ConfigDriftTolerance = ConfigDriftTolerance;
+	type ConfigDriftThreshold = ConstU32<3>;
+	type ReportConfigDrift = Authorities;
+}
+
+/// accepts all content unless `RejectContent` is set
+pub struct MockContentValidator;
+impl crate::ContentValidator for MockContentValidator {
+	type Reason = &'static str;
+
+	fn validate(_bytes: &[u8]) -> Result<(), Self::Reason> {
+		if RejectContent::get() {
+			return Err("unsupported format");
+		}
+		Ok(())
+	}
+}
+
+/// only allows signed origins from current validators
+pub struct EnsureValidator;
+impl EnsureOrigin<Origin> for EnsureValidator {
+	type Success = sp_core::sr25519::Public;
+	fn try_origin(o: Origin) -> Result<Self::Success, Origin> {
+		o.into().and_then(|o| match o {
+			frame_system::RawOrigin::Signed(who) if Authorities::validators().contains(&who) => Ok(who),
+			r => Err(Origin::from(r)),
+		})
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	fn successful_origin() -> Origin {
+		Origin::from(frame_system::RawOrigin::Signed(Default::default()))
+	}
 }
 
 impl pallet_data_assets::Config for Test {
@@ -232,6 +279,22 @@ impl pallet_data_assets::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = ConstBool<true>;
+	type SetAssetMetadata = ConstBool<true>;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<16>;
+	type GatewayPricing = ();
+	type GatewayProvider = Gateway;
+	type ProxyPrefs = ();
+	type PeerIdProvider = Ipfs;
 }
 
 parameter_types! {
@@ -264,6 +327,12 @@ impl pallet_iris_proxy::Config for Test {
 	type QueueManager = DataAssets;
 	type MetadataProvider = DataAssets;
 	type Randomness = TestRandomness<Self>;
+	type FragmentTTL = ConstU64<10>;
+	type MaxExpiredFragmentsPerBlock = ConstU32<10>;
+	type MaxFragmentsPerRequest = ConstU32<3>;
+	type MaxFragmentBatch = ConstU32<4>;
+	type AssetHistory = DataAssets;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
 }
 
 parameter_types! {
@@ -322,6 +391,8 @@ impl pallet_assets::Config for Test {
 
 parameter_types! {
 	pub const BondingDuration: pallet_authorities::EraIndex = 3;
+	pub const SizeUnit: u128 = pallet_gateway::BYTES_PER_GB;
+	pub const MinReservePerUnit: u64 = 10;
 }
 
 impl pallet_gateway::Config for Test {
@@ -331,6 +402,9 @@ impl pallet_gateway::Config for Test {
 	type Balance = <Self as pallet_balances::Config>::Balance;
 	type BondingDuration = BondingDuration;
 	type EraProvider = Authorities;
+	type SizeUnit = SizeUnit;
+	type MinReservePerUnit = MinReservePerUnit;
+	type StorageReporter = Ipfs;
 }
 
 pub type Extrinsic = TestXt<Call, ()>;
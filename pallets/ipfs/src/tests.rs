@@ -19,7 +19,7 @@
 
 use super::*;
 use crate::mock::*;
-use frame_support::{assert_ok, assert_err, pallet_prelude::*};
+use frame_support::{assert_ok, assert_err, pallet_prelude::*, traits::Currency};
 use sp_runtime::{
 	RuntimeAppPublic,
 };
@@ -27,7 +27,9 @@ use sp_core::Pair;
 use sp_core::{
 	offchain::{testing, OffchainWorkerExt, TransactionPoolExt, OffchainDbExt}
 };
-use iris_primitives::{EncryptedBox, IngestionCommand};
+use iris_primitives::{EncryptedBox, IngestionCommand, BYTES_PER_GB};
+use frame_system::offchain::SignedPayload;
+use sp_runtime::transaction_validity::{InvalidTransaction, TransactionSource};
 use sp_keystore::{testing::KeyStore, KeystoreExt, SyncCryptoStore};
 use std::sync::Arc;
 
@@ -35,32 +37,28 @@ struct TestData {
 	pub p: sp_core::sr25519::Pair,
 	pub q: sp_core::sr25519::Pair,
 	pub cid_vec: Vec<u8>,
-	pub name: Vec<u8>,
+	pub multiaddr_vec: Vec<u8>,
 	pub balance: u64,
 	pub public_key: Vec<u8>,
+	pub umbral_pk: Vec<u8>,
+	pub x25519_pk: Vec<u8>,
 }
 
 thread_local!(static TEST_CONSTANTS: TestData = TestData {
 	p: sp_core::sr25519::Pair::generate().0,
 	q: sp_core::sr25519::Pair::generate().0,
 	cid_vec: "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec(),
-	name: "test space".as_bytes().to_vec(),
+	multiaddr_vec: "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
 	balance: 1,
 	public_key: "public_key".as_bytes().to_vec(),
+	umbral_pk: vec![2, 7, 178, 91, 140, 23, 162, 73, 101, 16, 100, 140, 126, 128, 189, 51, 190, 43, 204, 101, 196, 187, 116, 242, 164, 135, 50, 62, 121, 21, 39, 191, 68],
+	x25519_pk: vec![136, 127, 175, 150, 142, 160, 194, 185, 24, 43, 243, 37, 77, 126,  183, 5, 114, 157, 167, 133, 183, 81, 29, 217, 53, 237, 240, 233, 111, 29, 9, 84],
 });
 
 #[test]
 pub fn ipfs_can_submit_ingestion_complete() { 
 	// Given: I am a valid node with a positive balance
 	TEST_CONSTANTS.with(|test_data| {
-	
-		let cmd = IngestionCommand {
-			owner: test_data.p.public().clone(),
-			cid: test_data.cid_vec.clone(),
-			multiaddress: test_data.name.clone(),
-			balance: test_data.balance,
-		};
-	
 		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
 			// And: A user has encrypted data and submitted capsule/kfrags
 			let sk_box = EncryptedBox {
@@ -75,7 +73,7 @@ pub fn ipfs_can_submit_ingestion_complete() {
 				test_data.p.clone().public(),
 				test_data.p.clone().public(),
 				capsule,
-				test_data.public_key.clone(),
+				test_data.umbral_pk.clone(),
 				sk_box.clone(),
 			));
 			// And: There is an ingestion request in the queue for a gateway 
@@ -84,13 +82,19 @@ pub fn ipfs_can_submit_ingestion_complete() {
 				test_data.p.public().clone(),
 				test_data.balance.clone(),
 				test_data.cid_vec.clone(),
-				test_data.name.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
 			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
 			// WHEN: I invoke the create_storage_assets extrinsic
 			assert_ok!(Ipfs::submit_ingestion_completed(
 				Origin::signed(test_data.p.public().clone()),
 				cmd,
+				1024,
 			));
 			// // Then: A new asset class is created with asset id 1
 			let asset = Assets::asset(5);
@@ -100,15 +104,170 @@ pub fn ipfs_can_submit_ingestion_complete() {
 	});
 }
 
+#[test]
+pub fn ipfs_retries_asset_class_creation_when_the_asset_id_is_in_use() { 
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// And: A user has encrypted data and submitted capsule/kfrags
+			let sk_box = EncryptedBox {
+				nonce: vec![102, 209, 34, 179, 214, 75, 129,  24, 44, 14, 136, 104, 179, 34, 247, 161, 168, 16, 131, 113, 43, 29, 165, 49],
+				ciphertext: vec![155, 157, 182, 50, 148, 238, 223, 196, 62, 153, 134, 37, 58, 199, 71, 176, 83, 180, 73, 235, 143, 230, 221, 40, 9, 182, 4, 129, 230, 192, 13, 6, 47, 52, 14, 161, 121, 219, 204, 224, 237, 21, 139, 241, 15, 168, 189, 181], 
+				public_key: vec![136, 127, 175, 150, 142, 160, 194, 185, 24, 43, 243, 37, 77, 126,  183, 5, 114, 157, 167, 133, 183, 81, 29, 217, 53, 237, 240, 233, 111, 29, 9, 84],
+			};
+			let capsule = vec![2, 7, 178, 91, 140, 23, 162, 73, 101, 16, 100, 140, 126, 128, 189, 51, 190, 43, 204, 101, 196, 187, 116, 242, 164, 135, 50, 62, 121, 21, 39, 191, 68, 3, 10, 130, 168, 14, 115, 158, 226, 143, 244, 181, 223, 210, 201, 139, 29, 65, 97, 32, 168, 140, 68, 186, 173, 72, 150, 112, 244, 66, 162, 46, 142, 226, 82, 104, 238, 89, 28, 1, 76, 159, 68, 159, 87, 201, 28, 254, 143, 212, 222, 42, 254, 44, 100, 100, 157, 252, 43, 91, 34, 219, 192, 199, 123, 25];
+
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.p.clone().public(),
+				capsule,
+				test_data.umbral_pk.clone(),
+				sk_box.clone(),
+			));
+			// And: There is an ingestion request in the queue for a gateway 
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.public().clone()),
+				test_data.p.public().clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
+			// And: the asset id the gateway would assign is already in use
+			let next_asset_id = <Gateway as ProxyProvider<_, _>>::next_asset_id(test_data.p.public().clone());
+			assert_ok!(Assets::create(
+				Origin::signed(test_data.p.public().clone()),
+				next_asset_id,
+				test_data.p.public().clone(),
+				1,
+			));
+			// WHEN: I invoke the create_storage_assets extrinsic
+			assert_ok!(Ipfs::submit_ingestion_completed(
+				Origin::signed(test_data.p.public().clone()),
+				cmd,
+				1024,
+			));
+			// Then: the clashing id is skipped and the asset class gets the gateway's next id
+			assert_eq!(2, pallet_gateway::CallCount::<Test>::get(test_data.p.public().clone()));
+			let retried_asset_id = next_asset_id * 2;
+			assert_eq!(Some(test_data.cid_vec.clone()), DataAssets::metadata(retried_asset_id).map(|m| m.ciphertext_cid));
+			// And: the asset class that held the clashing id is left untouched
+			assert_eq!(None, DataAssets::metadata(next_asset_id));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_retries_asset_class_creation_when_the_asset_id_is_reserved() { 
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let p = test_data.p.public().clone();
+			// And: I have staged encrypted data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			// And: There is an ingestion request in the queue for a gateway
+			let request = |requested_asset_id: Option<u32>| DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				requested_asset_id,
+			);
+			assert_ok!(request(None));
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			// And: another request reserved the asset id the gateway would assign
+			let next_asset_id = <Gateway as ProxyProvider<_, _>>::next_asset_id(p.clone());
+			assert_ok!(request(Some(next_asset_id)));
+			// WHEN: the gateway completes the first request
+			assert_ok!(Ipfs::submit_ingestion_completed(
+				Origin::signed(p.clone()),
+				cmd,
+				1024,
+			));
+			// Then: the reserved id is skipped and the asset class gets the gateway's next id
+			assert_eq!(2, pallet_gateway::CallCount::<Test>::get(p.clone()));
+			let retried_asset_id = next_asset_id * 2;
+			assert_eq!(Some(test_data.cid_vec.clone()), DataAssets::metadata(retried_asset_id).map(|m| m.ciphertext_cid));
+			// And: the reserved id is still free for the request that asked for it
+			assert_eq!(None, Assets::asset(next_asset_id));
+			assert!(DataAssets::reserved_asset_id(next_asset_id).is_some());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_gives_up_on_asset_class_creation_after_max_asset_id_retries() { 
+	// Given: I am a valid node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// And: A user has encrypted data and submitted capsule/kfrags
+			let sk_box = EncryptedBox {
+				nonce: vec![102, 209, 34, 179, 214, 75, 129,  24, 44, 14, 136, 104, 179, 34, 247, 161, 168, 16, 131, 113, 43, 29, 165, 49],
+				ciphertext: vec![155, 157, 182, 50, 148, 238, 223, 196, 62, 153, 134, 37, 58, 199, 71, 176, 83, 180, 73, 235, 143, 230, 221, 40, 9, 182, 4, 129, 230, 192, 13, 6, 47, 52, 14, 161, 121, 219, 204, 224, 237, 21, 139, 241, 15, 168, 189, 181], 
+				public_key: vec![136, 127, 175, 150, 142, 160, 194, 185, 24, 43, 243, 37, 77, 126,  183, 5, 114, 157, 167, 133, 183, 81, 29, 217, 53, 237, 240, 233, 111, 29, 9, 84],
+			};
+			let capsule = vec![2, 7, 178, 91, 140, 23, 162, 73, 101, 16, 100, 140, 126, 128, 189, 51, 190, 43, 204, 101, 196, 187, 116, 242, 164, 135, 50, 62, 121, 21, 39, 191, 68, 3, 10, 130, 168, 14, 115, 158, 226, 143, 244, 181, 223, 210, 201, 139, 29, 65, 97, 32, 168, 140, 68, 186, 173, 72, 150, 112, 244, 66, 162, 46, 142, 226, 82, 104, 238, 89, 28, 1, 76, 159, 68, 159, 87, 201, 28, 254, 143, 212, 222, 42, 254, 44, 100, 100, 157, 252, 43, 91, 34, 219, 192, 199, 123, 25];
+
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.p.clone().public(),
+				capsule,
+				test_data.umbral_pk.clone(),
+				sk_box.clone(),
+			));
+			// And: There is an ingestion request in the queue for a gateway 
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.public().clone()),
+				test_data.p.public().clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
+			// And: every asset id the gateway would try is already in use
+			let next_asset_id = <Gateway as ProxyProvider<_, _>>::next_asset_id(test_data.p.public().clone());
+			let attempts = <Test as crate::Config>::MaxAssetIdRetries::get() + 1;
+			for attempt in 1..=attempts {
+				assert_ok!(Assets::create(
+					Origin::signed(test_data.p.public().clone()),
+					next_asset_id * attempt,
+					test_data.p.public().clone(),
+					1,
+				));
+			}
+			// WHEN: I invoke the create_storage_assets extrinsic
+			// Then: the asset class can't be created once the retries are exhausted
+			assert_err!(Ipfs::submit_ingestion_completed(
+				Origin::signed(test_data.p.public().clone()),
+				cmd.clone(),
+				1024,
+			), pallet_assets::Error::<Test>::InUse);
+			// And: the command stays queued so it can be completed later
+			assert!(DataAssets::ingestion_commands(test_data.p.public().clone()).contains(&cmd));
+		});
+	});
+}
+
 #[test]
 pub fn ipfs_fail_to_create_asset_class_if_no_staging_exists() {
 	TEST_CONSTANTS.with(|test_data| {
-		let cmd = IngestionCommand {
-			owner: test_data.p.public().clone(),
-			cid: test_data.cid_vec.clone(),
-			multiaddress: test_data.name.clone(),
-			balance: test_data.balance,
-		};
 		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
 			// And: There is an ingestion request in the queue for a gateway 
 			assert_ok!(DataAssets::create_request(
@@ -116,13 +275,19 @@ pub fn ipfs_fail_to_create_asset_class_if_no_staging_exists() {
 				test_data.p.public().clone(),
 				test_data.balance.clone(),
 				test_data.cid_vec.clone(),
-				test_data.name.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
 			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
 			// WHEN: I invoke the create_storage_assets extrinsic
 			assert_ok!(Ipfs::submit_ingestion_completed(
 				Origin::signed(test_data.p.public().clone()),
 				cmd,
+				1024,
 			));
 	
 			// Then: A new asset class is NOT created
@@ -140,12 +305,6 @@ pub fn ipfs_fail_to_create_asset_class_if_no_staging_exists() {
 pub fn ipfs_fail_submit_ingestion_complete_if_ingestion_cmd_not_assigned_to_you() {	
 	TEST_CONSTANTS.with(|test_data| {
 		// Given: I am a valid node with a positive balance
-		let cmd = IngestionCommand {
-			owner: test_data.p.public().clone(),
-			cid: test_data.cid_vec.clone(),
-			multiaddress: test_data.name.clone(),
-			balance: test_data.balance,
-		};
 		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
 			// And: There is an ingestion request in the queue for a gateway 
 			assert_ok!(DataAssets::create_request(
@@ -153,19 +312,89 @@ pub fn ipfs_fail_submit_ingestion_complete_if_ingestion_cmd_not_assigned_to_you(
 				test_data.q.public().clone(),
 				test_data.balance.clone(),
 				test_data.cid_vec.clone(),
-				test_data.name.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
 			));
+			let cmd = DataAssets::ingestion_commands(test_data.q.public().clone())[0].clone();
 			// WHEN: I invoke the create_storage_assets extrinsic
 			assert_err!(Ipfs::submit_ingestion_completed(
 				Origin::signed(test_data.p.public().clone()),
 				cmd,
+				1024,
 			), crate::Error::<Test>::NotAuthorized);
 		});
 	});
 	
 }
 
+#[test]
+pub fn ipfs_fail_submit_ingestion_complete_while_halted() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a valid node with a positive balance
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// And: There is an ingestion request in my queue
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.public().clone()),
+				test_data.p.public().clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
+			// And: ingestion has been halted
+			assert_ok!(DataAssets::set_halted(Origin::root(), true));
+			// WHEN: I report that I completed the command
+			// THEN: the call is rejected
+			assert_err!(Ipfs::submit_ingestion_completed(
+				Origin::signed(test_data.p.public().clone()),
+				cmd,
+				1024,
+			), crate::Error::<Test>::Halted);
+			// AND: the command stays in my queue
+			assert_eq!(1, DataAssets::ingestion_commands(test_data.p.public().clone()).len());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_fail_submit_ingestion_complete_if_origin_not_allowed() {	
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a node that is not a validator
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// And: There is an ingestion request in my queue
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.public().clone()),
+				test_data.q.public().clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(test_data.q.public().clone())[0].clone();
+			// WHEN: I invoke the create_storage_assets extrinsic
+			// THEN: the call is rejected by the completion origin
+			assert_err!(Ipfs::submit_ingestion_completed(
+				Origin::signed(test_data.q.public().clone()),
+				cmd,
+				1024,
+			), sp_runtime::DispatchError::BadOrigin);
+		});
+	});
+}
+
 #[test]
 pub fn ipfs_can_submit_ipfs_identity() {
 	// Given: I am an authorized node with a positive balance
@@ -179,6 +408,7 @@ pub fn ipfs_can_submit_ipfs_identity() {
 				Origin::signed(test_data.p.clone().public()),
 				test_data.public_key.clone(),
 				maddrs.clone(),
+				test_data.x25519_pk.clone(),
 			));
 			// Then: my multiaddresses and pk are added as bootstrap nodes
 			let bootstrap_nodes_entry = crate::BootstrapNodes::<Test>::get(test_data.public_key.clone());
@@ -186,158 +416,885 @@ pub fn ipfs_can_submit_ipfs_identity() {
 			// And: my node account id is associated with the ipfs pk
 			let mapped_acct = crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()).unwrap();
 			assert_eq!(test_data.p.public().clone(), mapped_acct);
+			// And: the ipfs pk is my peer id
+			assert_eq!(vec![test_data.public_key.clone()], Ipfs::ipfs_identities_of(test_data.p.public().clone()));
+			assert_eq!(
+				Some(test_data.public_key.clone()),
+				<Ipfs as pallet_data_assets::PeerIdProvider<_>>::peer_id(&test_data.p.public()),
+			);
+			// And: my x25519 public key is stored
+			let x25519_pk = pallet_authorities::Pallet::<Test>::x25519_public_keys(test_data.p.public().clone());
+			assert_eq!(test_data.x25519_pk.clone(), x25519_pk);
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_cannot_submit_ipfs_identity_with_another_nodes_public_key() {
-	// Given: I am an authorized node with a positive balance
+pub fn ipfs_reports_bootstrap_node_changes() {
 	TEST_CONSTANTS.with(|test_data| {
-		let mut maddrs: Vec<OpaqueMultiaddr> = Vec::new();
-		maddrs.push(OpaqueMultiaddr(test_data.public_key.clone()));
+		let p = test_data.p.public().clone();
+		let first = OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec());
+		let second = OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4002".to_vec());
 
 		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
-			// When: I call to submit my ipfs identity for the first time
+			System::set_block_number(1);
+			// When: I submit my ipfs identity with one multiaddress
 			assert_ok!(Ipfs::submit_ipfs_identity(
-				Origin::signed(test_data.p.clone().public()),
+				Origin::signed(p.clone()),
 				test_data.public_key.clone(),
-				maddrs.clone(),
+				vec![first.clone()],
+				test_data.x25519_pk.clone(),
 			));
-			// Then: I receive an error if I call again with the same pk but a different origin
-			assert_err!(Ipfs::submit_ipfs_identity(
-				Origin::signed(test_data.q.clone().public()),
+			// Then: one bootstrap node is reported as added
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::BootstrapNodesUpdated(p.clone(), 1, 0)));
+			// When: I resubmit it with another multiaddress in place of the first
+			System::reset_events();
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(p.clone()),
 				test_data.public_key.clone(),
-				maddrs.clone(),
-			), crate::Error::<Test>::InvalidPublicKey);
+				vec![second.clone()],
+				test_data.x25519_pk.clone(),
+			));
+			// Then: one is reported as added and one as removed
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::BootstrapNodesUpdated(p.clone(), 1, 1)));
+			// When: I resubmit it unchanged
+			System::reset_events();
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(p.clone()),
+				test_data.public_key.clone(),
+				vec![second.clone()],
+				test_data.x25519_pk.clone(),
+			));
+			// Then: no change is reported
+			assert!(!System::events().iter().any(|record| matches!(
+				record.event,
+				mock::Event::Ipfs(crate::Event::BootstrapNodesUpdated(..)),
+			)));
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_can_submit_config_complete() {
-	// Given I am an authorized node with a positive balance
+pub fn ipfs_refunds_commands_queued_with_a_deregistered_gateway() {
 	TEST_CONSTANTS.with(|test_data| {
-		let reported_storage_cap = 100u128;
+		let p = test_data.p.public().clone();
 		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
-			// When: I submit config complete
-			assert_ok!(Ipfs::submit_config_complete(
-				Origin::signed(test_data.p.clone().public()), 
-				reported_storage_cap.clone(),
+			System::set_block_number(1);
+			// Given: the runtime refunds the commands of deregistered gateways
+			DeregistrationPolicy::set(pallet_data_assets::OrphanedCommandPolicy::Refund);
+			// And: I registered my ipfs identity as a gateway
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(p.clone()),
+				test_data.public_key.clone(),
+				vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())],
+				test_data.x25519_pk.clone(),
+			));
+			// And: a command is queued with me
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			// And: someone else can't deregister my identity
+			assert_err!(Ipfs::deregister_ipfs_identity(
+				Origin::signed(test_data.q.public().clone()),
+				test_data.public_key.clone(),
+			), crate::Error::<Test>::NotAuthorized);
+			// When: I deregister my ipfs identity
+			assert_ok!(Ipfs::deregister_ipfs_identity(Origin::signed(p.clone()), test_data.public_key.clone()));
+			// Then: my identity and bootstrap nodes are removed
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()));
+			assert!(!crate::IpfsIdentitiesOf::<Test>::contains_key(p.clone()));
+			assert_eq!(None, <Ipfs as pallet_data_assets::PeerIdProvider<_>>::peer_id(&p));
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::IdentityDeregistered(p.clone(), test_data.public_key.clone())));
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::BootstrapNodesUpdated(p.clone(), 0, 1)));
+			// And: the command is dropped and its reserve released
+			assert!(DataAssets::ingestion_commands(p.clone()).is_empty());
+			assert_eq!(0, pallet_data_assets::RequestReserve::<Test>::iter().count());
+			System::assert_has_event(mock::Event::DataAssets(
+				pallet_data_assets::Event::CommandRefunded(cmd.id, p.clone())
 			));
-			// Then: my reported storage capacity is added on chain
-			let reported_stats = crate::Stats::<Test>::get(test_data.p.clone().public());
-			assert_eq!(reported_storage_cap, reported_stats);
 		});
 	});
 }
 
-/*
-	OFFCHAIN FUNCTIONALITY TESTS
-*/
-
 #[test]
-pub fn ipfs_offchain_can_fetch_identity_json() {
+pub fn ipfs_reroutes_commands_queued_with_a_deregistered_gateway() {
 	TEST_CONSTANTS.with(|test_data| {
-		let mut t = new_test_ext_funded(test_data.p.clone());
-		let (offchain, state) = testing::TestOffchainExt::new();
-		t.register_extension(OffchainWorkerExt::new(offchain.clone()));	
-		t.register_extension(OffchainDbExt::new(offchain.clone()));	
-		{
-			let mut state = state.write();
-			state.expect_request(testing::PendingRequest {
-				method: "POST".into(),
-				uri: "http://host.docker.internal:5001/api/v0/id".into(),
-				response: Some(ipfs_id_response_body()),
-				sent: true,
-				..Default::default()
+		let p = test_data.p.public().clone();
+		let q = test_data.q.public().clone();
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: the runtime reroutes the commands of deregistered gateways
+			DeregistrationPolicy::set(pallet_data_assets::OrphanedCommandPolicy::Reroute);
+			// And: there is another gateway
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs {
+				max_mbps: 1, storage_max: BYTES_PER_GB, reserve_per_unit: 0,
 			});
-		}
-		t.execute_with(|| {
-			let actual_identity_result = Ipfs::fetch_identity_json().unwrap();
-			let actual_id = &actual_identity_result["ID"];
-			assert_eq!("123456789abcdefgt", actual_id);
+			// And: I registered my ipfs identity as a gateway
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(p.clone()),
+				test_data.public_key.clone(),
+				vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())],
+				test_data.x25519_pk.clone(),
+			));
+			// And: a command is queued with me
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			// When: I deregister my ipfs identity
+			System::set_block_number(5);
+			assert_ok!(Ipfs::deregister_ipfs_identity(Origin::signed(p.clone()), test_data.public_key.clone()));
+			// Then: the command moves to the other gateway, keeping its id
+			assert!(DataAssets::ingestion_commands(p.clone()).is_empty());
+			let rerouted = DataAssets::ingestion_commands(q.clone());
+			assert_eq!(1, rerouted.len());
+			assert_eq!(cmd.id, rerouted[0].id);
+			assert_eq!(cmd.cid, rerouted[0].cid);
+			assert_eq!(5, rerouted[0].queued_at);
+			System::assert_has_event(mock::Event::DataAssets(
+				pallet_data_assets::Event::CommandRerouted(cmd.id, p.clone(), q.clone())
+			));
+			// And: its reserve is kept
+			assert_eq!(1, pallet_data_assets::RequestReserve::<Test>::iter().count());
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_offchain_fetch_identity_with_invalid_json() {
+pub fn ipfs_ejects_the_assets_of_a_deregistered_gateway() {
 	TEST_CONSTANTS.with(|test_data| {
-		let mut t = new_test_ext_funded(test_data.p.clone());
-		let (offchain, state) = testing::TestOffchainExt::new();
-		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
-		t.register_extension(OffchainDbExt::new(offchain.clone()));
-		{
-			let mut state = state.write();
-			state.expect_request(testing::PendingRequest {
-				method: "POST".into(),
-				uri: "http://host.docker.internal:5001/api/v0/id".into(),
-				response: Some(br#"{:}
-				"#.to_vec()),
-				sent: true,
-				..Default::default()
-			});
-		}
-		t.execute_with(|| {
-			match Ipfs::fetch_identity_json() {
-				Ok(_) => panic!("json should be unparseable"),
-				Err(_) => {
-					// as expected
-					// assert_eq!(e.kind(), crate::Error::<Test>::ResponseParsingFailure);
-				},
-			}
+		let p = test_data.p.public().clone();
+		let q = test_data.q.public().clone();
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: there is another gateway
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: I registered my ipfs identity as a gateway
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(p.clone()),
+				test_data.public_key.clone(),
+				vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())],
+				test_data.x25519_pk.clone(),
+			));
+			// And: I ingested some data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), cmd, 10, 1024,
+			));
+			assert_eq!(vec![10], DataAssets::ingested_assets(p.clone(), 0, 10));
+			// When: I deregister my ipfs identity
+			assert_ok!(Ipfs::deregister_ipfs_identity(Origin::signed(p.clone()), test_data.public_key.clone()));
+			// Then: my replica is ejected and removed from my manifest
+			assert!(DataAssets::ingested_assets(p.clone(), 0, 10).is_empty());
+			assert_eq!(Some(false), DataAssets::pin_status(p.clone(), 10));
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::ReplicaDropped(
+				10, p.clone(),
+			)));
+			// And: a replacement replica is queued with the other gateway
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::RepinQueued(
+				10, q.clone(),
+			)));
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_offchain_can_verify_identity_and_submit_tx() {
+pub fn ipfs_cannot_submit_ipfs_identity_with_invalid_x25519_public_key() {
+	// Given: I am an authorized node with a positive balance
 	TEST_CONSTANTS.with(|test_data| {
-		let mut t = new_test_ext_funded(test_data.p.clone());
-		let (offchain, state) = testing::TestOffchainExt::new();
-		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
-		let keystore = KeyStore::new();
-		const PHRASE: &str =
-			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
-		SyncCryptoStore::sr25519_generate_new(
-			&keystore,
-			crate::crypto::Public::ID,
-			Some(&format!("{}/hunter1", PHRASE)),
-		)
-		.unwrap();
-
-		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
-		t.register_extension(OffchainDbExt::new(offchain.clone()));
-		t.register_extension(TransactionPoolExt::new(pool));
-		t.register_extension(KeystoreExt(Arc::new(keystore)));
+		let mut maddrs: Vec<OpaqueMultiaddr> = Vec::new();
+		maddrs.push(OpaqueMultiaddr(test_data.public_key.clone()));
 
-		{
-			let mut state = state.write();
-			state.expect_request(testing::PendingRequest {
-				method: "POST".into(),
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I submit my ipfs identity with an x25519 public key that isn't 32 bytes
+			// Then: I receive an error
+			assert_err!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				vec![1, 2, 3],
+			), crate::Error::<Test>::InvalidX25519PublicKey);
+			// And: no identity is recorded
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_cannot_submit_ipfs_identity_with_invalid_utf8_multiaddress() {
+	// Given: I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let maddrs = vec![OpaqueMultiaddr(vec![0xff, 0xfe, 0xfd])];
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I submit my ipfs identity with a multiaddress that isn't valid utf8
+			// Then: I receive an error
+			assert_err!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			), crate::Error::<Test>::InvalidMultiaddress);
+			// And: no identity is recorded
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_submit_ipfs_identity_with_matching_peer_ids() {
+	// Given: I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let peer_id = str::from_utf8(&test_data.public_key).unwrap();
+		let maddrs = vec![
+			OpaqueMultiaddr(format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer_id).as_bytes().to_vec()),
+			OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4002".to_vec()),
+		];
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I submit my ipfs identity with addresses that carry my peer id or none
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			// Then: my multiaddresses are added as bootstrap nodes
+			assert_eq!(maddrs, crate::BootstrapNodes::<Test>::get(test_data.public_key.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_cannot_submit_ipfs_identity_with_another_peers_multiaddress() {
+	// Given: I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let peer_id = str::from_utf8(&test_data.public_key).unwrap();
+		let maddrs = vec![
+			OpaqueMultiaddr(format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer_id).as_bytes().to_vec()),
+			OpaqueMultiaddr(b"/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWOtherPeer".to_vec()),
+		];
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I submit my ipfs identity with an address that carries another peer id
+			// Then: I receive an error
+			assert_err!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			), crate::Error::<Test>::PeerIdMismatch);
+			// And: no identity is recorded
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_read_the_peer_id_of_a_multiaddress() {
+	// Given: multiaddresses with and without peer ids
+	// Then: the trailing peer id is read, if there is one
+	assert_eq!(Some("QmPeer"), ipfs::multiaddr_peer_id("/ip4/127.0.0.1/tcp/4001/p2p/QmPeer"));
+	assert_eq!(Some("QmPeer"), ipfs::multiaddr_peer_id("/ip4/127.0.0.1/tcp/4001/ipfs/QmPeer"));
+	assert_eq!(None, ipfs::multiaddr_peer_id("/ip4/127.0.0.1/tcp/4001"));
+	// And: a relay's peer id is not mistaken for the node's
+	assert_eq!(
+		Some("QmPeer"),
+		ipfs::multiaddr_peer_id("/ip4/1.2.3.4/tcp/4001/p2p/QmRelay/p2p-circuit/p2p/QmPeer"),
+	);
+	assert_eq!(None, ipfs::multiaddr_peer_id("/ip4/1.2.3.4/tcp/4001/p2p/QmRelay/p2p-circuit"));
+}
+
+#[test]
+pub fn ipfs_can_convert_multiaddresses_to_and_from_strings() {
+	// Given: a valid multiaddress string
+	let addr = "/ip4/127.0.0.1/tcp/4001";
+	// When: I convert it to a multiaddress and back
+	let maddr = ipfs::string_to_multiaddr(addr).unwrap();
+	// Then: the round trip preserves the address
+	assert_eq!(OpaqueMultiaddr(addr.as_bytes().to_vec()), maddr);
+	assert_eq!(Ok(addr.to_string()), ipfs::multiaddr_to_string(&maddr));
+	// And: invalid utf8 bytes are rejected rather than panicking
+	let invalid = OpaqueMultiaddr(vec![0xff, 0xfe, 0xfd]);
+	assert!(matches!(ipfs::multiaddr_to_string(&invalid), Err(ipfs::MultiaddrError::InvalidUtf8(_))));
+	// And: empty multiaddresses are rejected
+	assert_eq!(Err(ipfs::MultiaddrError::Empty), ipfs::string_to_multiaddr(""));
+	assert_eq!(Err(ipfs::MultiaddrError::Empty), ipfs::multiaddr_to_string(&OpaqueMultiaddr(Vec::new())));
+}
+
+#[test]
+pub fn ipfs_distinguishes_unavailable_daemons_from_missing_content() {
+	// Given: the get endpoint answered with the content
+	// Then: the content is returned
+	assert_eq!(Ok(b"hello".to_vec()), ipfs::classify_get_response(200, b"hello".to_vec()));
+	// Given: the daemon answered, but without the content
+	// Then: the content was not found
+	assert_eq!(Err(ipfs::FetchError::ContentNotFound), ipfs::classify_get_response(200, Vec::new()));
+	assert_eq!(Err(ipfs::FetchError::ContentNotFound), ipfs::classify_get_response(404, Vec::new()));
+	// Given: the daemon failed to handle the request
+	// Then: ipfs is not available
+	assert_eq!(Err(ipfs::FetchError::IpfsNotAvailable), ipfs::classify_get_response(500, b"error".to_vec()));
+	assert_eq!(Err(ipfs::FetchError::IpfsNotAvailable), ipfs::classify_get_response(502, Vec::new()));
+}
+
+#[cfg(not(feature = "mock-ipfs"))]
+#[test]
+pub fn ipfs_reports_content_not_found_when_the_daemon_returns_nothing() {
+	TEST_CONSTANTS.with(|test_data| {
+		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let mut t = sp_io::TestExternalities::default();
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		// Given: my ipfs daemon is reachable, but returns an empty body for the cid
+		state.write().expect_request(testing::PendingRequest {
+			method: "POST".into(),
+			uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+			response: Some(Vec::new()),
+			sent: true,
+			..Default::default()
+		});
+		t.execute_with(|| {
+			// When: I fetch the file
+			// Then: the content is reported as not found, not as an unavailable daemon
+			assert_eq!(
+				Err(crate::Error::<Test>::ContentNotFound),
+				Ipfs::fetch_file(&test_data.cid_vec, &multiaddr_vec),
+			);
+			// And: a file without a valid multiaddress isn't fetched at all
+			assert_eq!(
+				Err(crate::Error::<Test>::InvalidMultiaddress),
+				Ipfs::fetch_file(&test_data.cid_vec, &Vec::new()),
+			);
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_parses_pinned_cids() {
+	// Given: the pin/ls endpoint answered with two pins
+	let body = br#"{"Keys":{"QmA":{"Type":"recursive"},"QmB":{"Type":"recursive"}}}"#;
+	// Then: both cids are listed
+	assert_eq!(Ok(vec![b"QmA".to_vec(), b"QmB".to_vec()]), ipfs::parse_pin_ls(body));
+	// Given: the daemon answered with no pins
+	// Then: no cids are listed
+	assert_eq!(Ok(Vec::<Vec<u8>>::new()), ipfs::parse_pin_ls(br#"{"Keys":{}}"#));
+	// Given: the daemon answered with something else
+	// Then: the response is rejected
+	assert!(ipfs::parse_pin_ls(b"not json").is_err());
+}
+
+#[cfg(not(feature = "mock-ipfs"))]
+#[test]
+pub fn ipfs_offchain_records_local_pins() {
+	let (offchain, state) = testing::TestOffchainExt::new();
+	let mut t = sp_io::TestExternalities::default();
+	t.register_extension(OffchainDbExt::new(offchain.clone()));
+	t.register_extension(OffchainWorkerExt::new(offchain));
+	// Given: my ipfs daemon has pinned a cid
+	state.write().expect_request(testing::PendingRequest {
+		method: "POST".into(),
+		uri: "http://host.docker.internal:5001/api/v0/pin/ls?type=recursive".into(),
+		response: Some(br#"{"Keys":{"QmA":{"Type":"recursive"}}}"#.to_vec()),
+		sent: true,
+		..Default::default()
+	});
+	t.execute_with(|| {
+		// When: the offchain worker records the local pins
+		Ipfs::record_local_pins();
+		// Then: the cid is kept in persistent offchain storage for the rpc
+		let pins = sp_runtime::offchain::storage::StorageValueRef::persistent(iris_primitives::LOCAL_PINS_KEY)
+			.get::<Option<Vec<Vec<u8>>>>();
+		assert_eq!(Ok(Some(Some(vec![b"QmA".to_vec()]))), pins);
+	});
+}
+
+#[cfg(feature = "mock-ipfs")]
+#[test]
+pub fn ipfs_stub_serves_identity_and_added_data() {
+	let (offchain, _state) = testing::TestOffchainExt::new();
+	let mut t = sp_io::TestExternalities::default();
+	t.register_extension(OffchainDbExt::new(offchain.clone()));
+	t.register_extension(OffchainWorkerExt::new(offchain));
+	t.execute_with(|| {
+		// Given: the ipfs http api is replaced by the stub
+		// When: I fetch the node identity
+		let id_json = Ipfs::fetch_identity_json().unwrap();
+		// Then: the stub's peer id is reported
+		assert_eq!(ipfs::MOCK_PEER_ID, id_json["ID"].as_str().unwrap());
+		// When: I add some data
+		let res = ipfs::add(ipfs::IpfsAddRequest { bytes: b"hello".to_vec() }).unwrap();
+		let res_json = ipfs::parse(sp_std::str::from_utf8(&res).unwrap()).unwrap();
+		let cid = res_json["Hash"].as_str().unwrap().as_bytes().to_vec();
+		// Then: the data can be fetched by its cid
+		assert_eq!(b"hello".to_vec(), ipfs::get(&cid).unwrap());
+	});
+}
+
+#[test]
+pub fn ipfs_cannot_submit_ipfs_identity_with_another_nodes_public_key() {
+	// Given: I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let mut maddrs: Vec<OpaqueMultiaddr> = Vec::new();
+		maddrs.push(OpaqueMultiaddr(test_data.public_key.clone()));
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I call to submit my ipfs identity for the first time
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			// Then: I receive an error if I call again with the same pk but a different origin
+			assert_err!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.q.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			), crate::Error::<Test>::InvalidPublicKey);
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_submit_several_ipfs_identities() {
+	// Given: I am an authorized node running two ipfs daemons
+	TEST_CONSTANTS.with(|test_data| {
+		let identities = vec![
+			(b"12D3KooWFirstDaemon".to_vec(), vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())]),
+			(b"12D3KooWSecondDaemon".to_vec(), vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4002".to_vec())]),
+		];
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			// When: I submit both identities in one call
+			assert_ok!(Ipfs::submit_ipfs_identities(
+				Origin::signed(who.clone()),
+				identities.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			// Then: each identity is registered to me, in order
+			assert_eq!(
+				identities.iter().map(|(public_key, _)| public_key.clone()).collect::<Vec<_>>(),
+				Ipfs::ipfs_identities_of(who.clone()),
+			);
+			for (public_key, multiaddresses) in identities.into_iter() {
+				assert_eq!(Some(who.clone()), crate::SubstrateIpfsBridge::<Test>::get(public_key.clone()));
+				assert_eq!(multiaddresses, crate::BootstrapNodes::<Test>::get(public_key.clone()));
+				System::assert_has_event(mock::Event::Ipfs(crate::Event::IdentityRegistered(who.clone(), public_key)));
+			}
+			System::assert_last_event(mock::Event::Ipfs(crate::Event::IdentitySubmitted(who, test_data.x25519_pk.clone())));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_rejects_identity_batch_with_a_conflicting_identity() {
+	// Given: I am an authorized node and another node registered an ipfs identity
+	TEST_CONSTANTS.with(|test_data| {
+		let taken = b"12D3KooWTakenDaemon".to_vec();
+		let free = b"12D3KooWFreeDaemon".to_vec();
+		let maddrs = vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())];
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.q.clone().public()),
+				taken.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			// When: I submit a batch containing a free identity and the other node's identity
+			// Then: the batch is rejected
+			assert_err!(Ipfs::submit_ipfs_identities(
+				Origin::signed(test_data.p.clone().public()),
+				vec![(free.clone(), maddrs.clone()), (taken.clone(), maddrs.clone())],
+				test_data.x25519_pk.clone(),
+			), crate::Error::<Test>::InvalidPublicKey);
+			// And: the free identity was not registered
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(free.clone()));
+			assert_eq!(Vec::<OpaqueMultiaddr>::new(), crate::BootstrapNodes::<Test>::get(free));
+			// And: the other node keeps its identity
+			assert_eq!(Some(test_data.q.clone().public()), crate::SubstrateIpfsBridge::<Test>::get(taken));
+			// When: I submit more identities than allowed in one call
+			// Then: the batch is rejected
+			let too_many = (0..4u8)
+				.map(|i| (vec![i], maddrs.clone()))
+				.collect::<Vec<_>>();
+			assert_err!(Ipfs::submit_ipfs_identities(
+				Origin::signed(test_data.p.clone().public()),
+				too_many,
+				test_data.x25519_pk.clone(),
+			), crate::Error::<Test>::InvalidIdentityCount);
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_request_identity_reannouncement() {
+	// Given: I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let mut maddrs: Vec<OpaqueMultiaddr> = Vec::new();
+		maddrs.push(OpaqueMultiaddr(test_data.public_key.clone()));
+
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// When: I request a reannouncement before bridging an ipfs identity
+			// Then: the request is rejected
+			assert_err!(Ipfs::reannounce_identity(
+				Origin::signed(test_data.p.clone().public()),
+			), crate::Error::<Test>::NoIpfsIdentity);
+			assert_eq!(false, crate::IdentityRefreshRequests::<Test>::get(test_data.p.clone().public()));
+			// Given: I have submitted my ipfs identity
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			// When: I request my node to reannounce its identity
+			assert_ok!(Ipfs::reannounce_identity(
+				Origin::signed(test_data.p.clone().public()),
+			));
+			// Then: a refresh is flagged for my account
+			assert_eq!(true, crate::IdentityRefreshRequests::<Test>::get(test_data.p.clone().public()));
+			// And: the flag is cleared once my identity is submitted again
+			assert_ok!(Ipfs::submit_ipfs_identity(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.public_key.clone(),
+				maddrs.clone(),
+				test_data.x25519_pk.clone(),
+			));
+			assert_eq!(false, crate::IdentityRefreshRequests::<Test>::get(test_data.p.clone().public()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_submit_config_complete() {
+	// Given I am an authorized node with a positive balance
+	TEST_CONSTANTS.with(|test_data| {
+		let reported_storage_cap = 100u128;
+		let reported_repo_size = 10u128;
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(3);
+			// When: I submit config complete
+			assert_ok!(Ipfs::submit_config_complete(
+				Origin::signed(test_data.p.clone().public()), 
+				reported_storage_cap.clone(),
+				reported_repo_size.clone(),
+			));
+			// Then: my reported storage capacity and usage are added on chain
+			let reported_stats = crate::Stats::<Test>::get(test_data.p.clone().public());
+			assert_eq!(reported_storage_cap, reported_stats);
+			assert_eq!(reported_repo_size, crate::RepoSize::<Test>::get(test_data.p.clone().public()));
+			// And: I am seen in the current block
+			assert_eq!(Some(3), crate::LastSeen::<Test>::get(test_data.p.clone().public()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_reports_nodes_whose_config_drifts_repeatedly() {
+	// Given: I am a gateway that declared 1 gb of storage
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			pallet_gateway::Proxies::<Test>::insert(who.clone(), pallet_gateway::GatewayPrefs {
+				max_mbps: 1, storage_max: BYTES_PER_GB, reserve_per_unit: 0,
+			});
+			let drifting = BYTES_PER_GB / 2;
+			// When: I report half of my declared storage twice
+			for _ in 0..2 {
+				assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), drifting, 0));
+			}
+			// Then: the drift is counted but not yet reported
+			assert_eq!(2, Ipfs::config_drift_count(who.clone()));
+			assert_eq!(false, Authorities::validators_to_remove().contains(&who));
+			// When: I report it a third time
+			assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), drifting, 0));
+			// Then: I am reported and marked for removal, and the count starts over
+			System::assert_last_event(mock::Event::Ipfs(crate::Event::ConfigurationSyncSubmitted(who.clone())));
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::ConfigDriftReported(who.clone())));
+			assert!(Authorities::validators_to_remove().contains(&who));
+			assert_eq!(0, Ipfs::config_drift_count(who.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_does_not_report_config_drift_during_grace_period() {
+	// Given: I am a gateway that declared 1 gb of storage
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			pallet_gateway::Proxies::<Test>::insert(who.clone(), pallet_gateway::GatewayPrefs {
+				max_mbps: 1, storage_max: BYTES_PER_GB, reserve_per_unit: 0,
+			});
+			// And: offences are suppressed until block 10
+			assert_err!(
+				Authorities::set_offence_grace_period(Origin::signed(who.clone()), 10),
+				sp_runtime::DispatchError::BadOrigin,
+			);
+			assert_ok!(Authorities::set_offence_grace_period(Origin::root(), 10));
+			// When: my config drifts often enough to be reported
+			for _ in 0..3 {
+				assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), BYTES_PER_GB / 2, 0));
+			}
+			// Then: no offence is reported and the count starts over
+			assert!(!System::events().iter().any(|r| r.event == mock::Event::Ipfs(crate::Event::ConfigDriftReported(who.clone()))));
+			assert_eq!(false, Authorities::validators_to_remove().contains(&who));
+			assert_eq!(0, Ipfs::config_drift_count(who.clone()));
+			// When: it drifts as often again once the grace period ends
+			System::set_block_number(10);
+			for _ in 0..3 {
+				assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), BYTES_PER_GB / 2, 0));
+			}
+			// Then: I am reported
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::ConfigDriftReported(who.clone())));
+			assert!(Authorities::validators_to_remove().contains(&who));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_compliant_config_resets_drift_count() {
+	// Given: I am a gateway that declared 1 gb of storage
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let who = test_data.p.clone().public();
+			pallet_gateway::Proxies::<Test>::insert(who.clone(), pallet_gateway::GatewayPrefs {
+				max_mbps: 1, storage_max: BYTES_PER_GB, reserve_per_unit: 0,
+			});
+			// And: my last two reports drifted from my declared storage
+			for _ in 0..2 {
+				assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), BYTES_PER_GB / 2, 0));
+			}
+			assert_eq!(2, Ipfs::config_drift_count(who.clone()));
+			// When: I report storage within the tolerance
+			assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), BYTES_PER_GB * 95 / 100, 0));
+			// Then: the count is reset
+			assert_eq!(0, Ipfs::config_drift_count(who.clone()));
+			// And: a single drifting report afterwards is not reported
+			assert_ok!(Ipfs::submit_config_complete(Origin::signed(who.clone()), BYTES_PER_GB / 2, 0));
+			assert_eq!(1, Ipfs::config_drift_count(who.clone()));
+			assert_eq!(false, Authorities::validators_to_remove().contains(&who));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_can_submit_ipfs_identity_unsigned_in_unsigned_mode() {
+	// Given: the runtime accepts unsigned submissions
+	UnsignedSubmissions::set(true);
+	TEST_CONSTANTS.with(|test_data| {
+		let maddrs = vec![OpaqueMultiaddr(test_data.public_key.clone())];
+		let payload = IdentityPayload {
+			public_key: test_data.public_key.clone(),
+			multiaddresses: maddrs.clone(),
+			x25519_public_key: test_data.x25519_pk.clone(),
+			block_number: 1,
+			public: test_data.p.clone().public(),
+		};
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// And: I am a validator who signed my identity
+			let signature = test_data.p.sign(&payload.encode());
+			let call = crate::Call::submit_ipfs_identity_unsigned {
+				payload: payload.clone(),
+				signature: signature.clone(),
+			};
+			// Then: the unsigned transaction is valid
+			assert!(Ipfs::validate_unsigned(TransactionSource::External, &call).is_ok());
+			// But: it is invalid if someone else signed it
+			let forged = test_data.q.sign(&payload.encode());
+			assert_eq!(
+				Err(InvalidTransaction::BadProof.into()),
+				Ipfs::validate_unsigned(TransactionSource::External, &crate::Call::submit_ipfs_identity_unsigned {
+					payload: payload.clone(),
+					signature: forged,
+				}),
+			);
+			// When: it is executed
+			assert_ok!(Ipfs::submit_ipfs_identity_unsigned(Origin::none(), payload.clone(), signature));
+			// Then: my node account id is associated with the ipfs pk
+			let mapped_acct = crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()).unwrap();
+			assert_eq!(test_data.p.public().clone(), mapped_acct);
+			assert_eq!(maddrs, crate::BootstrapNodes::<Test>::get(test_data.public_key.clone()));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_rejects_unsigned_submissions_in_signed_mode() {
+	// Given: the runtime only accepts signed submissions
+	UnsignedSubmissions::set(false);
+	TEST_CONSTANTS.with(|test_data| {
+		let payload = ConfigPayload {
+			reported_storage_size: 100,
+			reported_repo_size: 10,
+			block_number: 1,
+			public: test_data.p.clone().public(),
+		};
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let signature = test_data.p.sign(&payload.encode());
+			let call = crate::Call::submit_config_complete_unsigned {
+				payload: payload.clone(),
+				signature: signature.clone(),
+			};
+			// Then: a validly signed unsigned transaction is still rejected
+			assert_eq!(
+				Err(InvalidTransaction::Call.into()),
+				Ipfs::validate_unsigned(TransactionSource::External, &call),
+			);
+			assert_err!(
+				Ipfs::submit_config_complete_unsigned(Origin::none(), payload.clone(), signature),
+				Error::<Test>::UnsignedSubmissionsDisabled,
+			);
+			// And: no stats are recorded
+			assert_eq!(0, crate::Stats::<Test>::get(test_data.p.clone().public()));
+		});
+	});
+}
+
+/*
+	OFFCHAIN FUNCTIONALITY TESTS
+*/
+
+#[test]
+pub fn ipfs_offchain_can_fetch_identity_json() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));	
+		t.register_extension(OffchainDbExt::new(offchain.clone()));	
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
 				uri: "http://host.docker.internal:5001/api/v0/id".into(),
 				response: Some(ipfs_id_response_body()),
 				sent: true,
 				..Default::default()
 			});
 		}
+		t.execute_with(|| {
+			let actual_identity_result = Ipfs::fetch_identity_json().unwrap();
+			let actual_id = &actual_identity_result["ID"];
+			assert_eq!("123456789abcdefgt", actual_id);
+		});
+	});
+}
 
-		let mut expected_maddrs: Vec<OpaqueMultiaddr> = Vec::new();
-		expected_maddrs.push(OpaqueMultiaddr(
-			"/ip4/127.0.0.1/tcp/4001/p2p/123456789abcdefgt".as_bytes().to_vec()
-		));
-		expected_maddrs.push(OpaqueMultiaddr(
-			"/ip4/127.0.0.1/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
-		));
-		expected_maddrs.push(OpaqueMultiaddr(
-			"/ip4/192.168.101.47/tcp/4001/p2p/123456789abcdefgt".as_bytes().to_vec()
-		));
-		expected_maddrs.push(OpaqueMultiaddr(
-			"/ip4/192.168.101.47/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
-		));
-		expected_maddrs.push(OpaqueMultiaddr(
-			"/ip4/206.176.195.179/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
+#[test]
+pub fn ipfs_offchain_fetch_identity_with_invalid_json() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/id".into(),
+				response: Some(br#"{:}
+				"#.to_vec()),
+				sent: true,
+				..Default::default()
+			});
+		}
+		t.execute_with(|| {
+			match Ipfs::fetch_identity_json() {
+				Ok(_) => panic!("json should be unparseable"),
+				Err(_) => {
+					// as expected
+					// assert_eq!(e.kind(), crate::Error::<Test>::ResponseParsingFailure);
+				},
+			}
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_can_verify_identity_and_submit_tx() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/id".into(),
+				response: Some(ipfs_id_response_body()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		let mut expected_maddrs: Vec<OpaqueMultiaddr> = Vec::new();
+		expected_maddrs.push(OpaqueMultiaddr(
+			"/ip4/127.0.0.1/tcp/4001/p2p/123456789abcdefgt".as_bytes().to_vec()
+		));
+		expected_maddrs.push(OpaqueMultiaddr(
+			"/ip4/127.0.0.1/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
+		));
+		expected_maddrs.push(OpaqueMultiaddr(
+			"/ip4/192.168.101.47/tcp/4001/p2p/123456789abcdefgt".as_bytes().to_vec()
+		));
+		expected_maddrs.push(OpaqueMultiaddr(
+			"/ip4/192.168.101.47/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
+		));
+		expected_maddrs.push(OpaqueMultiaddr(
+			"/ip4/206.176.195.179/udp/4001/quic/p2p/123456789abcdefgt".as_bytes().to_vec()
 		));
 		expected_maddrs.push(OpaqueMultiaddr(
 			"/ip6/::1/tcp/4001/p2p/123456789abcdefgt".as_bytes().to_vec()
@@ -347,23 +1304,919 @@ pub fn ipfs_offchain_can_verify_identity_and_submit_tx() {
 		));
 
 		t.execute_with(|| {
-			Ipfs::ipfs_verify_identity().unwrap();
-			// And: a signed tx is added on chain
-			let tx = pool_state.write().transactions.pop().unwrap();
-			assert!(pool_state.read().transactions.is_empty());
-			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 0);
-			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ipfs_identity { 
-				public_key: "123456789abcdefgt".as_bytes().to_vec(),
-				multiaddresses: expected_maddrs,
-			}));
+			Ipfs::ipfs_verify_identity().unwrap();
+			// And: a signed tx is added on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.signature.unwrap().0, 0);
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ipfs_identity { 
+				public_key: "123456789abcdefgt".as_bytes().to_vec(),
+				multiaddresses: expected_maddrs,
+				x25519_public_key: pallet_authorities::Pallet::<Test>::local_x25519_public_key([1u8; 32]),
+			}));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_reannounces_identity_when_requested() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/id".into(),
+				response: Some(ipfs_id_response_body()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			// Given: my node's account has not requested a reannouncement
+			// When: the offchain worker checks for refresh requests
+			// Then: nothing is reported
+			assert_eq!(false, Ipfs::refresh_identity_if_requested().unwrap());
+			assert!(pool_state.read().transactions.is_empty());
+			// Given: my node's account has requested a reannouncement
+			let account = Ipfs::offchain_signer().unwrap();
+			crate::IdentityRefreshRequests::<Test>::insert(account, true);
+			// When: the offchain worker checks for refresh requests
+			assert_eq!(true, Ipfs::refresh_identity_if_requested().unwrap());
+			// Then: the node's identity is submitted on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			match tx.call {
+				mock::Call::Ipfs(crate::Call::submit_ipfs_identity { public_key, .. }) => {
+					assert_eq!("123456789abcdefgt".as_bytes().to_vec(), public_key);
+				},
+				_ => panic!("expected an identity submission"),
+			}
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_can_update_config() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/config?arg=Datastore.StorageMax&arg=50GB".into(),
+				response: Some(ipfs_config_update_body()),
+				sent: true,
+				..Default::default()
+			});
+
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/repo/stat".into(),
+				response: Some(ipfs_config_show_body()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			// setup proxy prefs
+			assert_ok!(Gateway::declare_gateway(
+				Origin::signed(test_data.p.public().clone()),
+				pallet_gateway::GatewayPrefs {
+					max_mbps: 100,
+					storage_max: 100 * BYTES_PER_GB,
+					reserve_per_unit: 0,
+				}
+			));
+			Ipfs::ipfs_update_configs(test_data.p.clone().public()).unwrap();
+			// And: a signed tx is added on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.signature.unwrap().0, 0);
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_config_complete { 
+				reported_storage_size: 100,
+				reported_repo_size: 27898551,
+			}));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_can_update_config_with_unsigned_tx() {
+	// Given: the runtime accepts unsigned submissions
+	UnsignedSubmissions::set(true);
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		let public = SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/config?arg=Datastore.StorageMax&arg=50GB".into(),
+				response: Some(ipfs_config_update_body()),
+				sent: true,
+				..Default::default()
+			});
+
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/repo/stat".into(),
+				response: Some(ipfs_config_show_body()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			assert_ok!(Gateway::declare_gateway(
+				Origin::signed(test_data.p.public().clone()),
+				pallet_gateway::GatewayPrefs {
+					max_mbps: 100,
+					storage_max: 100 * BYTES_PER_GB,
+					reserve_per_unit: 0,
+				}
+			));
+			// When: the node updates its config
+			Ipfs::ipfs_update_configs(test_data.p.clone().public()).unwrap();
+			// Then: an unsigned tx carrying a signed payload is added to the pool
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.signature, None);
+			match tx.call {
+				mock::Call::Ipfs(crate::Call::submit_config_complete_unsigned { payload, signature }) => {
+					assert_eq!(ConfigPayload {
+						reported_storage_size: 100,
+						reported_repo_size: 27898551,
+						block_number: 1,
+						public,
+					}, payload);
+					assert!(SignedPayload::<Test>::verify::<crypto::TestAuthId>(&payload, signature));
+				},
+				_ => panic!("expected submit_config_complete_unsigned"),
+			}
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_config_update_fails_when_repo_stats_are_unreadable() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/config?arg=Datastore.StorageMax&arg=50GB".into(),
+				response: Some(ipfs_config_update_body()),
+				sent: true,
+				..Default::default()
+			});
+
+			// Given: the daemon answers the repo stats request with something that isn't json
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/repo/stat".into(),
+				response: Some(b"not json".to_vec()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			assert_ok!(Gateway::declare_gateway(
+				Origin::signed(test_data.p.public().clone()),
+				pallet_gateway::GatewayPrefs {
+					max_mbps: 100,
+					storage_max: 100 * BYTES_PER_GB,
+					reserve_per_unit: 0,
+				}
+			));
+			// When: the node updates its config
+			// Then: the failure is returned instead of panicking
+			assert_eq!(
+				Err(Error::<Test>::IpfsNotAvailable),
+				Ipfs::ipfs_update_configs(test_data.p.clone().public()),
+			);
+			// And: nothing is reported on chain
+			assert!(pool_state.read().transactions.is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_can_handle_ingestion_commands() {
+	TEST_CONSTANTS.with(|test_data| {
+		
+		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+
+
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		{
+			let mut state = state.write();
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+				response: Some(ipfs_config_show_body()),
+				sent: true,
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			// create ingestion request
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = IngestionCommand {
+				id: DataAssets::ingestion_commands(test_data.p.public().clone())[0].id,
+				owner: test_data.p.public().clone(),
+				cid: test_data.cid_vec.clone(),
+				multiaddress: vec![47, 105, 112, 52, 47, 49, 50, 55, 46, 48, 46, 48, 46, 49, 47, 116, 99, 112, 47, 52, 48, 48, 49, 47, 112, 50, 112, 47, 49, 50, 68, 51, 75, 111, 111, 87, 77, 118, 121, 118, 75, 120, 89, 99, 121, 57, 109, 106, 98, 70, 98, 88, 99, 111, 103, 70, 83, 67, 118, 69, 78, 122, 81, 54, 50, 111, 103, 82, 120, 72, 75, 90, 97, 107, 115, 70, 67, 107, 65, 112],
+				balance: test_data.balance,
+				queued_at: DataAssets::ingestion_commands(test_data.p.public().clone())[0].queued_at,
+			};
+
+			Ipfs::handle_ingestion_queue(test_data.p.clone().public()).unwrap();
+			// And: a signed tx is added on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.signature.unwrap().0, 0);
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_completed { 
+				cmd: cmd,
+				ciphertext_size: ipfs_config_show_body().len() as u128,
+			}));
+		});
+	});
+}
+
+#[cfg(not(feature = "mock-ipfs"))]
+#[test]
+pub fn ipfs_offchain_refuses_ingestions_that_would_overcommit_storage() {
+	TEST_CONSTANTS.with(|test_data| {
+		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+		// Given: my ipfs daemon serves the data of two commands
+		{
+			let mut state = state.write();
+			for _ in 0..2 {
+				state.expect_request(testing::PendingRequest {
+					method: "POST".into(),
+					uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+					response: Some(ipfs_config_show_body()),
+					sent: true,
+					..Default::default()
+				});
+			}
+		}
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			let size = ipfs_config_show_body().len() as u128;
+			// And: I declared 1 gb of storage, and have room left for one and a half commands
+			pallet_gateway::Proxies::<Test>::insert(who.clone(), pallet_gateway::GatewayPrefs {
+				max_mbps: 1, storage_max: BYTES_PER_GB, reserve_per_unit: 0,
+			});
+			crate::RepoSize::<Test>::insert(who.clone(), BYTES_PER_GB - size * 3 / 2);
+			// And: both commands are queued with my node
+			for _ in 0..2 {
+				assert_ok!(DataAssets::create_request(
+					Origin::signed(who.clone()),
+					who.clone(),
+					test_data.balance.clone(),
+					test_data.cid_vec.clone(),
+					multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					1,
+					1,
+					None,
+				));
+			}
+			let queued = DataAssets::ingestion_commands(who.clone());
+			assert_eq!(2, queued.len());
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(who.clone()));
+			// Then: only the first command is reported as completed
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_completed {
+				cmd: queued[0].clone(),
+				ciphertext_size: size as u128,
+			}));
+			// And: its size is committed until it leaves the queue
+			assert_eq!(vec![(queued[0].id, size as u128, 1)], Ipfs::in_flight_ingestions());
+			// And: the second command stays queued for a later run
+			assert_eq!(2, DataAssets::ingestion_commands(who.clone()).len());
+		});
+	});
+}
+
+#[cfg(not(feature = "mock-ipfs"))]
+#[test]
+pub fn ipfs_offchain_rejects_ingestions_whose_content_fails_validation() {
+	TEST_CONSTANTS.with(|test_data| {
+		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+		// Given: my ipfs daemon serves the data of a command
+		state.write().expect_request(testing::PendingRequest {
+			method: "POST".into(),
+			uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+			response: Some(ipfs_config_show_body()),
+			sent: true,
+			..Default::default()
+		});
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			// And: my node's content validator rejects the data
+			RejectContent::set(true);
+			// And: the command is queued with my node
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(who.clone()),
+				who.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(who.clone())[0].clone();
+			assert!(pallet_vesting::Vesting::<Test>::get(&who).is_some());
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(who.clone()));
+			// Then: a signed tx reporting the rejection is added on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_rejected { cmd_id: cmd.id }));
+			assert!(Ipfs::in_flight_ingestions().is_empty());
+			// When: the rejection is recorded
+			assert_ok!(Ipfs::submit_ingestion_rejected(Origin::signed(who.clone()), cmd.id));
+			// Then: the command leaves my queue
+			assert!(DataAssets::ingestion_commands(who.clone()).is_empty());
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::RequestRejected(
+				who.clone(), cmd.owner, cmd.cid, Some(pallet_data_assets::REJECTED_CONTENT),
+			)));
+			// And: my share of the reserve is returned to the publisher
+			assert!(pallet_vesting::Vesting::<Test>::get(&who).is_none());
+			// And: the command is not fetched again
+			assert_ok!(Ipfs::handle_ingestion_queue(who.clone()));
+			assert!(pool_state.read().transactions.is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_retries_failed_submissions_up_to_max_attempts() {
+	TEST_CONSTANTS.with(|test_data| new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+		let max_attempts = <Test as crate::Config>::MaxSubmissionAttempts::get();
+		// Given: a submission that the transaction pool always rejects
+		let mut calls = 0;
+		// When: it is submitted with retries
+		let attempts = Ipfs::retry_submission(max_attempts, |_| { calls += 1; false });
+		// Then: it stops after the configured number of attempts
+		assert_eq!(max_attempts, attempts);
+		assert_eq!(max_attempts, calls);
+		// Given: a submission that is rejected once
+		// Then: it stops retrying as soon as it succeeds
+		assert_eq!(2, Ipfs::retry_submission(max_attempts, |attempt| attempt == 2));
+		// And: it is always attempted at least once
+		assert_eq!(1, Ipfs::retry_submission(0, |_| false));
+	}));
+}
+
+#[test]
+pub fn ipfs_offchain_skips_ingestion_while_paused() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+
+		t.execute_with(|| {
+			// Given: an ingestion command is queued with my node
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			assert!(!Ipfs::ingestion_paused());
+			// And: I have paused ingestion processing
+			sp_runtime::offchain::storage::StorageValueRef::persistent(iris_primitives::INGESTION_PAUSED_KEY)
+				.set(&true);
+			assert!(Ipfs::ingestion_paused());
+			// When: my node processes its ingestion queue
+			// Then: no ipfs calls are made and no results are submitted
+			assert_ok!(Ipfs::handle_ingestion_queue(test_data.p.clone().public()));
+			assert!(pool_state.read().transactions.is_empty());
+			assert_eq!(1, DataAssets::ingestion_commands(test_data.p.public().clone()).len());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_reports_failed_ingestion_and_backs_off() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		// Given: my ipfs daemon doesn't have the data
+		state.write().expect_request(testing::PendingRequest {
+			method: "POST".into(),
+			uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+			response: Some(Vec::new()),
+			sent: true,
+			..Default::default()
+		});
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			// And: an ingestion command whose data can't be fetched is queued with my node
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd_id = DataAssets::ingestion_commands(test_data.p.public().clone())[0].id;
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(test_data.p.clone().public()));
+			// Then: a signed tx reporting the failure is added on chain
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_failed { cmd_id }));
+			// When: the failure is recorded
+			assert_ok!(Ipfs::submit_ingestion_failed(Origin::signed(test_data.p.clone().public()), cmd_id));
+			// Then: my node skips the command until its backoff has passed
+			System::set_block_number(2);
+			assert_ok!(Ipfs::handle_ingestion_queue(test_data.p.clone().public()));
+			assert!(pool_state.read().transactions.is_empty());
+		});
+	});
+}
+
+/// Queue two single replica ingestion commands with the gateway, returning their ids
+fn queue_two_commands(gateway: sp_core::sr25519::Public, balance: u64) -> Vec<[u8; 32]> {
+	for cid in [b"QmFirstFile".to_vec(), b"QmSecondFile".to_vec()] {
+		assert_ok!(DataAssets::create_request(
+			Origin::signed(gateway.clone()),
+			gateway.clone(),
+			balance,
+			cid,
+			"/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec(),
+			1_000,
+			balance.try_into().unwrap(),
+			1,
+			1,
+			None,
+		));
+	}
+	DataAssets::ingestion_commands(gateway).iter().map(|c| c.id).collect()
+}
+
+/// what a `RejectingPool` was asked to submit
+#[derive(Default)]
+struct RejectingPoolState {
+	/// whether the pool rejects every transaction
+	reject: bool,
+	/// the number of transactions submitted, accepted or not
+	submissions: u32,
+	/// the transactions the pool accepted
+	transactions: Vec<Vec<u8>>,
+}
+
+/// a transaction pool that rejects transactions signed with the nonce of a pending one,
+/// and can be made to reject every transaction
+struct RejectingPool(Arc<std::sync::Mutex<RejectingPoolState>>);
+
+/// the nonce a transaction submitted to a `RejectingPool` was signed with
+fn nonce_of(extrinsic: &[u8]) -> u64 {
+	mock::Extrinsic::decode(&mut &*extrinsic).unwrap().signature.unwrap().0
+}
+
+impl sp_core::offchain::TransactionPool for RejectingPool {
+	fn submit_transaction(&mut self, extrinsic: Vec<u8>) -> Result<(), ()> {
+		let mut state = self.0.lock().unwrap();
+		state.submissions += 1;
+		let nonce = nonce_of(&extrinsic);
+		if state.reject || state.transactions.iter().any(|pending| nonce_of(pending) == nonce) {
+			return Err(());
+		}
+		state.transactions.push(extrinsic);
+		Ok(())
+	}
+}
+
+#[test]
+pub fn ipfs_offchain_resends_rejected_submissions_with_a_refreshed_nonce() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let pool_state = Arc::new(std::sync::Mutex::new(RejectingPoolState { reject: true, ..Default::default() }));
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(RejectingPool(pool_state.clone())));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			let who = test_data.p.clone().public();
+			// Given: two ingestion commands are queued with my node
+			let cmd_ids = queue_two_commands(who.clone(), test_data.balance.clone());
+			let retries = <Test as crate::Config>::MaxSignedRetries::get();
+			// When: my node fetches both but the transaction pool rejects every report
+			assert_ok!(Ipfs::process_ingestion_queue(who.clone(), |_| Ok(1024)));
+			// Then: each report is resent at most `MaxSignedRetries` times
+			assert_eq!(2 * (1 + retries), pool_state.lock().unwrap().submissions);
+			assert!(pool_state.lock().unwrap().transactions.is_empty());
+			// And: neither ingestion counts as reported
+			assert!(Ipfs::in_flight_ingestions().is_empty());
+			// When: the pool accepts transactions again by my node's next run
+			{
+				let mut state = pool_state.lock().unwrap();
+				state.reject = false;
+				state.submissions = 0;
+			}
+			System::set_block_number(2);
+			assert_ok!(Ipfs::process_ingestion_queue(who.clone(), |_| Ok(1024)));
+			// Then: the second report, rejected for reusing the first one's nonce, is resent with the next nonce
+			assert_eq!(3, pool_state.lock().unwrap().submissions);
+			let state = pool_state.lock().unwrap();
+			assert_eq!(vec![0, 1], state.transactions.iter().map(|tx| nonce_of(tx)).collect::<Vec<_>>());
+			// And: both ingestions are reported
+			let calls: Vec<_> = state.transactions.iter()
+				.map(|tx| mock::Extrinsic::decode(&mut &**tx).unwrap().call)
+				.collect();
+			assert!(calls.iter().all(|call| matches!(
+				call, mock::Call::Ipfs(crate::Call::submit_ingestion_completed { ciphertext_size: 1024, .. })
+			)));
+			assert_eq!(cmd_ids, Ipfs::in_flight_ingestions().into_iter().map(|(id, _, _)| id).collect::<Vec<_>>());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_reports_commands_whose_content_is_not_found() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			// Given: two ingestion commands are queued with my node
+			let cmd_ids = queue_two_commands(test_data.p.clone().public(), test_data.balance.clone());
+			// When: my node processes its queue but the content of neither can be found
+			assert_ok!(Ipfs::process_ingestion_queue(
+				test_data.p.clone().public(), |_| Err(crate::Error::<Test>::ContentNotFound),
+			));
+			// Then: both commands are reported as failed
+			let calls: Vec<_> = pool_state.read().transactions.iter()
+				.map(|tx| mock::Extrinsic::decode(&mut &**tx).unwrap().call)
+				.collect();
+			assert_eq!(cmd_ids.into_iter()
+				.map(|cmd_id| mock::Call::Ipfs(crate::Call::submit_ingestion_failed { cmd_id }))
+				.collect::<Vec<_>>(), calls);
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_stops_ingesting_without_reporting_when_ipfs_is_unavailable() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			// Given: two ingestion commands are queued with my node
+			let cmd_ids = queue_two_commands(test_data.p.clone().public(), test_data.balance.clone());
+			// When: my node processes its queue while its ipfs daemon is down
+			let fetched = std::cell::Cell::new(0);
+			assert_eq!(Err(crate::Error::<Test>::IpfsNotAvailable), Ipfs::process_ingestion_queue(
+				test_data.p.clone().public(),
+				|_| {
+					fetched.set(fetched.get() + 1);
+					Err(crate::Error::<Test>::IpfsNotAvailable)
+				},
+			));
+			// Then: the run stops at the first command
+			assert_eq!(1, fetched.get());
+			// And: no failure is reported, so the commands don't back off
+			assert!(pool_state.read().transactions.is_empty());
+			for cmd_id in cmd_ids {
+				assert_eq!(0, <DataAssets as QueueManager<_, _>>::next_attempt(cmd_id));
+			}
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_submits_once_from_a_multi_key_keystore() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+		// Given: my node's keystore holds two keys
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		let first = SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter1", PHRASE)),
+		)
+		.unwrap();
+		let second = SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/hunter2", PHRASE)),
+		)
+		.unwrap();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		// And: my ipfs daemon doesn't have the data
+		state.write().expect_request(testing::PendingRequest {
+			method: "POST".into(),
+			uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+			response: Some(Vec::new()),
+			sent: true,
+			..Default::default()
+		});
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			// And: an ingestion command whose data can't be fetched is queued with my node
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(test_data.p.clone().public()));
+			// Then: the failure is submitted once, not once per key
+			assert_eq!(1, pool_state.read().transactions.len());
+			// And: without a designated signer, one of my keys signs it
+			let signer = Ipfs::offchain_signer().unwrap();
+			assert!(signer == first || signer == second);
+			// When: I designate the second key as the offchain signer
+			sp_runtime::offchain::storage::StorageValueRef::persistent(iris_primitives::OFFCHAIN_SIGNER_KEY)
+				.set(&second);
+			// Then: it signs the node's transactions
+			assert_eq!(Some(second), Ipfs::offchain_signer());
+			// When: I designate an account whose key isn't in my keystore
+			sp_runtime::offchain::storage::StorageValueRef::persistent(iris_primitives::OFFCHAIN_SIGNER_KEY)
+				.set(&test_data.q.public());
+			// Then: nothing is signed
+			assert_eq!(None, Ipfs::offchain_signer());
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_offchain_can_update_config() {
+pub fn ipfs_config_sync_interval_is_the_configured_block_duration() {
+	sp_io::TestExternalities::default().execute_with(|| {
+		// Given: a runtime that syncs gateway configs every NodeConfigBlockDuration blocks
+		// When: I read the config sync interval
+		// Then: it is the configured duration
+		assert_eq!(NodeConfigBlockDuration::get(), Ipfs::config_sync_interval());
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_takes_the_process_queue_now_flag_once() {
+	let mut t = sp_io::TestExternalities::default();
+	let (offchain, _state) = testing::TestOffchainExt::new();
+	t.register_extension(OffchainDbExt::new(offchain.clone()));
+	t.register_extension(OffchainWorkerExt::new(offchain));
+	t.execute_with(|| {
+		// Given: no one has asked for the queue to be processed
+		// Then: the offchain worker waits for its next scheduled block
+		assert!(!Ipfs::take_process_queue_now());
+		// Given: I asked for the queue to be processed now
+		sp_runtime::offchain::storage::StorageValueRef::persistent(iris_primitives::PROCESS_QUEUE_NOW_KEY)
+			.set(&true);
+		// When: the offchain worker checks the flag
+		// Then: the queue is processed and the flag is cleared
+		assert!(Ipfs::take_process_queue_now());
+		assert!(!Ipfs::take_process_queue_now());
+	});
+}
+
+#[test]
+pub fn ipfs_offchain_fails_dataset_ingestion_when_any_file_cannot_be_fetched() {
 	TEST_CONSTANTS.with(|test_data| {
+		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
 		let mut t = new_test_ext_funded(test_data.p.clone());
 		let (offchain, state) = testing::TestOffchainExt::new();
 		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
@@ -376,7 +2229,7 @@ pub fn ipfs_offchain_can_update_config() {
 			Some(&format!("{}/hunter1", PHRASE)),
 		)
 		.unwrap();
-
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
 		t.register_extension(OffchainWorkerExt::new(offchain));
 		t.register_extension(TransactionPoolExt::new(pool));
 		t.register_extension(KeystoreExt(Arc::new(keystore)));
@@ -385,54 +2238,443 @@ pub fn ipfs_offchain_can_update_config() {
 			let mut state = state.write();
 			state.expect_request(testing::PendingRequest {
 				method: "POST".into(),
-				uri: "http://host.docker.internal:5001/api/v0/config?arg=Datastore.StorageMax&arg=50GB".into(),
-				response: Some(ipfs_config_update_body()),
+				uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
+				response: Some(ipfs_config_show_body()),
 				sent: true,
 				..Default::default()
 			});
-
 			state.expect_request(testing::PendingRequest {
 				method: "POST".into(),
-				uri: "http://host.docker.internal:5001/api/v0/repo/stat".into(),
-				response: Some(ipfs_config_show_body()),
+				uri: "http://host.docker.internal:5001/api/v0/get?arg=QmSecondFile".into(),
+				response: Some(Vec::new()),
 				sent: true,
 				..Default::default()
 			});
 		}
 
 		t.execute_with(|| {
-			// setup proxy prefs
-			assert_ok!(Gateway::declare_gateway(
-				Origin::signed(test_data.p.public().clone()),
-				pallet_gateway::GatewayPrefs {
-					max_mbps: 100,
-					storage_max_gb: 100,
-				}
+			System::set_block_number(1);
+			// Given: a dataset is queued with my node whose primary file can be fetched
+			// And: whose second file can't be fetched
+			assert_ok!(DataAssets::create_dataset_request(
+				Origin::signed(test_data.p.clone().public()),
+				test_data.p.clone().public(),
+				test_data.balance.clone(),
+				vec![
+					(test_data.cid_vec.clone(), multiaddr_vec.clone()),
+					(b"QmSecondFile".to_vec(), multiaddr_vec.clone()),
+				],
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				None,
 			));
-			Ipfs::ipfs_update_configs(test_data.p.clone().public()).unwrap();
-			// And: a signed tx is added on chain
+			let cmd_id = DataAssets::ingestion_commands(test_data.p.public().clone())[0].id;
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(test_data.p.clone().public()));
+			// Then: the partially fetched dataset is reported as failed
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 0);
-			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_config_complete { 
-				reported_storage_size: 100,
-			}));
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_failed { cmd_id }));
 		});
 	});
 }
 
 #[test]
-pub fn ipfs_offchain_can_handle_ingestion_commands() {
+pub fn ipfs_recommends_gateways_with_capacity_ranked_by_score() {
+	// Given: there are two registered proxies
 	TEST_CONSTANTS.with(|test_data| {
-		
-		let multiaddr_vec = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(p.clone(), pallet_gateway::GatewayPrefs {
+				storage_max: 12 * BYTES_PER_GB, ..Default::default()
+			});
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs {
+				storage_max: 20 * BYTES_PER_GB, ..Default::default()
+			});
+			System::set_block_number(20);
+			// And: p has 10gb remaining, completes ingestion quickly, and was seen recently
+			crate::RepoSize::<Test>::insert(p.clone(), 2_000_000_000);
+			crate::LastSeen::<Test>::insert(p.clone(), 20);
+			// And: q has 20gb remaining, has never completed ingestion, and was last seen long ago
+			crate::LastSeen::<Test>::insert(q.clone(), 1);
+			let stats = |g: &sp_core::sr25519::Public| if *g == p {
+				iris_primitives::GatewayStats { completed: 3, average_latency: 1 }
+			} else {
+				Default::default()
+			};
+			// When: I ask for recommendations for 1gb of data
+			// Then: both proxies are ranked by score
+			assert_eq!(vec![p.clone(), q.clone()], Ipfs::recommend_gateways(BYTES_PER_GB, 5, stats));
+			// And: at most `count` proxies are returned
+			assert_eq!(vec![p.clone()], Ipfs::recommend_gateways(BYTES_PER_GB, 1, stats));
+			// When: I ask for recommendations for 15gb of data
+			// Then: only the proxy with enough remaining storage is recommended
+			assert_eq!(vec![q.clone()], Ipfs::recommend_gateways(15 * BYTES_PER_GB, 5, stats));
+		});
+	});
+}
 
-		let mut t = new_test_ext_funded(test_data.p.clone());
-		let (offchain, state) = testing::TestOffchainExt::new();
-		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+#[test]
+pub fn ipfs_remaining_capacity_is_declared_storage_minus_repo_size() {
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			// Given: a near-full proxy that declared 10gb and has a 9.999gb repo
+			pallet_gateway::Proxies::<Test>::insert(p.clone(), pallet_gateway::GatewayPrefs {
+				storage_max: 10 * BYTES_PER_GB, ..Default::default()
+			});
+			assert_ok!(Ipfs::submit_config_complete(Origin::signed(p.clone()), 10, 9_999_000_000));
+			// Then: only 1mb is left
+			assert_eq!(Some(1_000_000), <Gateway as ProxyProvider<_, _>>::remaining_capacity(p.clone()));
+			// And: it is not recommended for 1gb of data
+			assert!(Ipfs::recommend_gateways(BYTES_PER_GB, 5, |_| Default::default()).is_empty());
+			// And: it is recommended for data that fits, though it is less than a gb
+			assert_eq!(vec![p.clone()], Ipfs::recommend_gateways(500_000, 5, |_| Default::default()));
+			// But: not for data a single byte larger than what is left
+			assert!(Ipfs::recommend_gateways(1_000_001, 5, |_| Default::default()).is_empty());
+			// Given: an empty proxy that declared 10gb and has not reported a repo size
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs {
+				storage_max: 10 * BYTES_PER_GB, ..Default::default()
+			});
+			// Then: all of its storage is left
+			assert_eq!(Some(10_000_000_000), <Gateway as ProxyProvider<_, _>>::remaining_capacity(q.clone()));
+			// And: an account that is not a proxy has no capacity
+			pallet_gateway::Proxies::<Test>::remove(q.clone());
+			assert_eq!(None, <Gateway as ProxyProvider<_, _>>::remaining_capacity(q));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_ingestion_command_is_assigned_to_a_single_gateway() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			pallet_gateway::Proxies::<Test>::insert(
+				test_data.q.public().clone(), pallet_gateway::GatewayPrefs::default(),
+			);
+			assert_eq!(2, <Gateway as ProxyProvider<_, _>>::proxies().len());
+			// And: an ingestion command is queued with the first proxy
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(test_data.p.public().clone()),
+				test_data.p.public().clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(test_data.p.public().clone())[0].clone();
+			// When: each proxy checks if it is assigned the command
+			let assigned = vec![test_data.p.public().clone(), test_data.q.public().clone()]
+				.into_iter()
+				.filter(|g| Ipfs::is_assigned(&cmd, g))
+				.collect::<Vec<_>>();
+			// Then: exactly one proxy processes it
+			assert_eq!(vec![test_data.p.public().clone()], assigned);
+			// And: the assignments of a whole queue agree
+			let queue = DataAssets::ingestion_commands(test_data.p.public().clone());
+			assert_eq!(
+				vec![cmd.id],
+				Ipfs::assigned_commands(&queue, &test_data.p.public()).into_iter().collect::<Vec<_>>(),
+			);
+			assert!(Ipfs::assigned_commands(&queue, &test_data.q.public()).is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_replicated_ingestion_is_durable_once_every_replica_is_pinned() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: I have staged encrypted data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			// When: I request that the data is pinned by two gateways
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone() * 2,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
+			));
+			// Then: a command is queued with each gateway
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			let q_cmd = DataAssets::ingestion_commands(q.clone())[0].clone();
+			assert_ne!(p_cmd.id, q_cmd.id);
+			// When: the first gateway completes ingestion
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			).unwrap();
+			// Then: the asset class is created but the asset is not yet durable
+			assert!(outcome.asset_created);
+			assert_ne!(Assets::asset(10), None);
+			assert!(!DataAssets::is_durable(10));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::ReplicaPinned(
+				10, p.clone(), 1, 2,
+			)));
+			// When: the second gateway completes ingestion
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(q.clone()), q_cmd, 11, 1024,
+			).unwrap();
+			// Then: it pins a replica of the existing asset instead of creating a new one
+			assert!(!outcome.asset_created);
+			assert_eq!(Assets::asset(11), None);
+			assert_eq!(vec![10], DataAssets::ingested_by_gateway(q.clone()));
+			// And: the asset is durable
+			assert!(DataAssets::is_durable(10));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::AssetDurable(10)));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_asset_class_is_created_once_the_confirmation_quorum_is_reached() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: the second can pay the deposit for the asset class it creates
+			Balances::make_free_balance_be(&q, 100);
+			// And: I have staged encrypted data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			// When: I ask for a quorum of zero or larger than the replication factor
+			// Then: the request is rejected
+			for quorum in [0, 3] {
+				assert_err!(DataAssets::create_request(
+					Origin::signed(p.clone()),
+					p.clone(),
+					test_data.balance.clone() * 2,
+					test_data.cid_vec.clone(),
+					test_data.multiaddr_vec.clone(),
+					1_000,
+					test_data.balance.clone().try_into().unwrap(),
+					2,
+					quorum,
+					None,
+				), pallet_data_assets::Error::<Test>::InvalidConfirmationQuorum);
+			}
+			// When: I request that two gateways confirm the data before the asset class is created
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone() * 2,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				2,
+				None,
+			));
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			let q_cmd = DataAssets::ingestion_commands(q.clone())[0].clone();
+			let request_id = System::events().into_iter().find_map(|record| match record.event {
+				mock::Event::DataAssets(pallet_data_assets::Event::CreatedIngestionRequest(id, _)) => Some(id),
+				_ => None,
+			}).unwrap();
+			// And: the first gateway completes ingestion (quorum - 1 confirmations)
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			).unwrap();
+			// Then: its confirmation is recorded but no asset class is created
+			assert!(!outcome.asset_created);
+			assert_eq!(Assets::asset(10), None);
+			assert_eq!(vec![p.clone()], DataAssets::ingestion_confirmations(request_id));
+			assert!(DataAssets::ingestion_commands(p.clone()).is_empty());
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::IngestionConfirmed(
+				request_id, p.clone(), 1, 2,
+			)));
+			// When: the second gateway completes ingestion (quorum confirmations)
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(q.clone()), q_cmd, 10, 1024,
+			).unwrap();
+			// Then: the asset class is created
+			assert!(outcome.asset_created);
+			assert_ne!(Assets::asset(10), None);
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::IngestionConfirmed(
+				request_id, q.clone(), 2, 2,
+			)));
+			// And: both gateways pin it, so the asset is durable
+			assert_eq!(vec![10], DataAssets::ingested_by_gateway(p.clone()));
+			assert_eq!(vec![10], DataAssets::ingested_by_gateway(q.clone()));
+			assert!(DataAssets::is_durable(10));
+			assert!(DataAssets::ingestion_confirmations(request_id).is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_remainder_of_an_uneven_reserve_stays_with_the_publisher() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			Balances::make_free_balance_be(&q, 10);
+			let publisher_balance = Balances::free_balance(&p);
+			// When: I split a reserve of 5 between both of them
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				5,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
+			));
+			// Then: each gateway is vested a share of 2
+			for gateway in [&p, &q] {
+				let schedules = pallet_vesting::Vesting::<Test>::get(gateway).unwrap();
+				assert_eq!(vec![2], schedules.iter().map(|s| s.locked()).collect::<Vec<_>>());
+			}
+			assert_eq!(12, Balances::free_balance(&q));
+			// And: the remainder is not transferred, so I only pay the second gateway's share
+			assert_eq!(publisher_balance - 2, Balances::free_balance(&p));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_gateways_claim_replicated_requests_one_at_a_time() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: a request is replicated to both of them
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone() * 2,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
+			));
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			let q_cmd = DataAssets::ingestion_commands(q.clone())[0].clone();
+			let request_id = System::events().into_iter().find_map(|record| match record.event {
+				mock::Event::DataAssets(pallet_data_assets::Event::CreatedIngestionRequest(id, _)) => Some(id),
+				_ => None,
+			}).unwrap();
+			// When: the first gateway claims its command
+			assert_ok!(Ipfs::submit_command_claim(Origin::signed(p.clone()), p_cmd.id));
+			// Then: the request is claimed for the claim duration
+			let expires_at = 1 + <Test as pallet_data_assets::Config>::ClaimDuration::get();
+			assert_eq!(Some((p.clone(), expires_at)), DataAssets::command_claim(request_id));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::CommandClaimed(
+				request_id, p.clone(), expires_at,
+			)));
+			// And: the other gateway can neither claim nor complete its replica
+			assert_err!(
+				DataAssets::claim_command(Origin::signed(q.clone()), p.clone(), test_data.cid_vec.clone()),
+				pallet_data_assets::Error::<Test>::AlreadyClaimed
+			);
+			assert_err!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(q.clone()), q_cmd.clone(), 10, 1024,
+			), pallet_data_assets::Error::<Test>::AlreadyClaimed);
+			// When: the claimer completes its replica
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			));
+			// Then: the claim is released
+			assert_eq!(None, DataAssets::command_claim(request_id));
+			// And: the other gateway can claim and complete its replica
+			assert_ok!(DataAssets::claim_command(Origin::signed(q.clone()), p.clone(), test_data.cid_vec.clone()));
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(q.clone()), q_cmd, 10, 1024,
+			));
+			assert!(DataAssets::is_durable(10));
+		});
+	});
+}
 
+#[test]
+pub fn ipfs_expired_claims_can_be_taken_over() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: a request is replicated to both of them and claimed by the first
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone() * 2,
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
+			));
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			let q_cmd = DataAssets::ingestion_commands(q.clone())[0].clone();
+			let request_id = System::events().into_iter().find_map(|record| match record.event {
+				mock::Event::DataAssets(pallet_data_assets::Event::CreatedIngestionRequest(id, _)) => Some(id),
+				_ => None,
+			}).unwrap();
+			assert_ok!(Ipfs::submit_command_claim(Origin::signed(p.clone()), p_cmd.id));
+			// When: the claim expires
+			let expires_at = 1 + <Test as pallet_data_assets::Config>::ClaimDuration::get();
+			System::set_block_number(expires_at.into());
+			// Then: the other gateway can claim the request
+			assert_ok!(Ipfs::submit_command_claim(Origin::signed(q.clone()), q_cmd.id));
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::ClaimExpired(
+				request_id, p.clone(),
+			)));
+			let renewed_until = expires_at + <Test as pallet_data_assets::Config>::ClaimDuration::get();
+			assert_eq!(Some((q.clone(), renewed_until)), DataAssets::command_claim(request_id));
+			// And: the first gateway can no longer complete its replica
+			assert_err!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			), pallet_data_assets::Error::<Test>::AlreadyClaimed);
+		});
+	});
+}
 
+#[test]
+pub fn ipfs_offchain_claims_replicated_requests_before_fetching() {
+	TEST_CONSTANTS.with(|test_data| {
+		let mut t = new_test_ext_funded(test_data.p.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
 		let keystore = KeyStore::new();
 		const PHRASE: &str =
 			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
@@ -442,53 +2684,330 @@ pub fn ipfs_offchain_can_handle_ingestion_commands() {
 			Some(&format!("{}/hunter1", PHRASE)),
 		)
 		.unwrap();
-
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
 		t.register_extension(OffchainWorkerExt::new(offchain));
 		t.register_extension(TransactionPoolExt::new(pool));
 		t.register_extension(KeystoreExt(Arc::new(keystore)));
 
-		{
-			let mut state = state.write();
-			state.expect_request(testing::PendingRequest {
-				method: "POST".into(),
-				uri: "http://host.docker.internal:5001/api/v0/get?arg=QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".into(),
-				response: Some(ipfs_config_show_body()),
-				sent: true,
-				..Default::default()
-			});
-		}
-
-		let cmd = IngestionCommand {
-			owner: test_data.p.public().clone(),
-			cid: test_data.cid_vec.clone(),
-			multiaddress: vec![47, 105, 112, 52, 47, 49, 50, 55, 46, 48, 46, 48, 46, 49, 47, 116, 99, 112, 47, 52, 48, 48, 49, 47, 112, 50, 112, 47, 49, 50, 68, 51, 75, 111, 111, 87, 77, 118, 121, 118, 75, 120, 89, 99, 121, 57, 109, 106, 98, 70, 98, 88, 99, 111, 103, 70, 83, 67, 118, 69, 78, 122, 81, 54, 50, 111, 103, 82, 120, 72, 75, 90, 97, 107, 115, 70, 67, 107, 65, 112],
-			balance: test_data.balance,
-		};
-
 		t.execute_with(|| {
-			// create ingestion request
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// Given: a request is replicated to my node and another gateway
 			assert_ok!(DataAssets::create_request(
-				Origin::signed(test_data.p.clone().public()),
-				test_data.p.clone().public(),
-				test_data.balance.clone(),
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone() * 2,
 				test_data.cid_vec.clone(),
-				multiaddr_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
 				test_data.balance.clone().try_into().unwrap(),
+				2,
+				1,
+				None,
 			));
-
-			Ipfs::handle_ingestion_queue(test_data.p.clone().public()).unwrap();
-			// And: a signed tx is added on chain
+			let cmd_id = DataAssets::ingestion_commands(p.clone())[0].id;
+			let q_cmd_id = DataAssets::ingestion_commands(q.clone())[0].id;
+			// When: my node processes its ingestion queue
+			assert_ok!(Ipfs::handle_ingestion_queue(p.clone()));
+			// Then: it claims the request instead of fetching the data
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 0);
-			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_ingestion_completed { 
-				cmd: cmd,
-			}));
+			assert_eq!(tx.call, mock::Call::Ipfs(crate::Call::submit_command_claim { cmd_id }));
+			// When: the other gateway's claim lands on chain first
+			assert_ok!(Ipfs::submit_command_claim(Origin::signed(q.clone()), q_cmd_id));
+			// Then: my node leaves the command to it
+			assert_ok!(Ipfs::handle_ingestion_queue(p.clone()));
+			assert!(pool_state.read().transactions.is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_dropped_pin_is_repinned_by_another_gateway() {
+	// Given: there are two registered proxies
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			pallet_gateway::Proxies::<Test>::insert(q.clone(), pallet_gateway::GatewayPrefs::default());
+			// And: the first proxy has ingested some data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			));
+			assert!(DataAssets::is_durable(10));
+			assert_eq!(Some(true), DataAssets::pin_status(p.clone(), 10));
+			// When: the second proxy reports on an asset it never pinned
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(q.clone()), vec![(10, true)]));
+			// Then: the report is ignored
+			assert_eq!(None, DataAssets::pin_status(q.clone(), 10));
+			// When: the first proxy reports that it dropped its pin
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(p.clone()), vec![(10, false)]));
+			// Then: the asset is no longer durable
+			assert_eq!(Some(false), DataAssets::pin_status(p.clone(), 10));
+			assert!(!DataAssets::is_durable(10));
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::ReplicaDropped(
+				10, p.clone(),
+			)));
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::RepinQueued(
+				10, q.clone(),
+			)));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::PinReportSubmitted(
+				p.clone(), 0, 1,
+			)));
+			// When: the second proxy pins the replacement replica
+			let q_cmd = DataAssets::ingestion_commands(q.clone())[0].clone();
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(q.clone()), q_cmd, 11, 1024,
+			).unwrap();
+			// Then: the asset is durable again
+			assert!(!outcome.asset_created);
+			assert!(DataAssets::is_durable(10));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_ejected_asset_can_be_repinned_on_demand() {
+	// Given: there is a single registered proxy
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			let p = test_data.p.public().clone();
+			let q = test_data.q.public().clone();
+			// And: it has ingested some data
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let p_cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), p_cmd, 10, 1024,
+			));
+			// When: I try to re-pin the durable asset
+			// Then: there is nothing to restore
+			assert_err!(
+				DataAssets::repin_asset(Origin::signed(p.clone()), 10),
+				pallet_data_assets::Error::<Test>::RepinNotNeeded,
+			);
+			// When: the proxy ejects the asset
+			DataAssets::remove_ingested_asset(p.clone(), 10);
+			// Then: no other proxy can take it over, so the asset is frozen and nothing is queued
+			assert_eq!(0, DataAssets::replica_count(10));
+			assert!(DataAssets::frozen_unavailable(10));
+			assert!(DataAssets::ingestion_commands(p.clone()).is_empty());
+			// When: someone other than the asset's admin tries to re-pin it
+			// Then: the call is rejected
+			assert_err!(
+				DataAssets::repin_asset(Origin::signed(q.clone()), 10),
+				pallet_data_assets::Error::<Test>::NotAssetAdmin,
+			);
+			// When: the admin re-pins it
+			assert_ok!(DataAssets::repin_asset(Origin::signed(p.clone()), 10));
+			// Then: a fetch and pin command for the recorded cid is queued with the proxy
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			assert_eq!(test_data.cid_vec.clone(), cmd.cid);
+			assert_eq!(Some(10), DataAssets::repin_of(cmd.id));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::RepinQueued(
+				10, p.clone(),
+			)));
+			// And: it can't be re-pinned twice
+			assert_err!(
+				DataAssets::repin_asset(Origin::signed(p.clone()), 10),
+				pallet_data_assets::Error::<Test>::RepinNotNeeded,
+			);
+			// When: the proxy completes the command
+			let outcome = <DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), cmd.clone(), 11, 1024,
+			).unwrap();
+			// Then: the asset is available again and thawed
+			assert!(!outcome.asset_created);
+			assert_eq!(1, DataAssets::replica_count(10));
+			assert!(!DataAssets::frozen_unavailable(10));
+			assert_eq!(None, DataAssets::repin_of(cmd.id));
+			System::assert_has_event(mock::Event::DataAssets(pallet_data_assets::Event::AssetThawed(10)));
+			System::assert_last_event(mock::Event::DataAssets(pallet_data_assets::Event::AssetRepinned(
+				10, p.clone(),
+			)));
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_records_the_peer_ids_of_gateways_pinning_an_asset() {
+	// Given: a proxy has announced its ipfs identity
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			let p = test_data.p.public().clone();
+			crate::SubstrateIpfsBridge::<Test>::insert(test_data.public_key.clone(), p.clone());
+			crate::IpfsIdentitiesOf::<Test>::insert(p.clone(), vec![test_data.public_key.clone()]);
+			// And: it has an ingestion command queued
+			assert_ok!(<DataAssets as QueueManager<_, _>>::add_ingestion_staging(p.clone(), test_data.umbral_pk.clone()));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(p.clone()),
+				p.clone(),
+				test_data.balance.clone(),
+				test_data.cid_vec.clone(),
+				test_data.multiaddr_vec.clone(),
+				1_000,
+				test_data.balance.clone().try_into().unwrap(),
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(p.clone())[0].clone();
+			// When: the proxy pins the data
+			assert_ok!(<DataAssets as ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(p.clone()), cmd, 10, 1024,
+			));
+			// Then: its peer id is listed as a provider of the asset
+			assert_eq!(vec![test_data.public_key.clone()], DataAssets::metadata(10).unwrap().providers);
+			// When: the proxy reports that it dropped its pin
+			assert_ok!(DataAssets::submit_pin_report(Origin::signed(p.clone()), vec![(10, false)]));
+			// Then: it is no longer listed as a provider
+			assert!(DataAssets::metadata(10).unwrap().providers.is_empty());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_prunes_the_identities_of_removed_validators() {
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: two validators have announced their ipfs identities
+			let p = test_data.p.public().clone();
+			let v = NEXT_VALIDATORS.with(|l| l.borrow()[0].0.clone());
+			let v_public_key = b"v-ipfs-public-key".to_vec();
+			crate::SubstrateIpfsBridge::<Test>::insert(test_data.public_key.clone(), p.clone());
+			crate::SubstrateIpfsBridge::<Test>::insert(v_public_key.clone(), v.clone());
+			crate::IpfsIdentitiesOf::<Test>::insert(p.clone(), vec![test_data.public_key.clone()]);
+			crate::IpfsIdentitiesOf::<Test>::insert(v.clone(), vec![v_public_key.clone()]);
+			crate::BootstrapNodes::<Test>::insert(
+				v_public_key.clone(), vec![OpaqueMultiaddr(b"/ip4/127.0.0.1/tcp/4001".to_vec())],
+			);
+			// And: one of them is deregistered
+			assert_ok!(Authorities::remove_validator(Origin::root(), v.clone()));
+			// When: the bridge is swept
+			Ipfs::on_idle(1, Weight::max_value());
+			// Then: only the removed validator's identity is pruned
+			assert_eq!(Some(p.clone()), crate::SubstrateIpfsBridge::<Test>::get(test_data.public_key.clone()));
+			assert_eq!(None, crate::SubstrateIpfsBridge::<Test>::get(v_public_key.clone()));
+			assert_eq!(vec![test_data.public_key.clone()], Ipfs::ipfs_identities_of(p));
+			assert!(!crate::IpfsIdentitiesOf::<Test>::contains_key(v.clone()));
+			assert!(!crate::BootstrapNodes::<Test>::contains_key(v_public_key.clone()));
+			System::assert_last_event(mock::Event::Ipfs(crate::Event::BridgePruned(v.clone(), v_public_key)));
+			// And: its bootstrap node is reported as removed
+			System::assert_has_event(mock::Event::Ipfs(crate::Event::BootstrapNodesUpdated(v, 0, 1)));
+			// And: the sweep starts over on the next call
+			assert_eq!(None, crate::BridgeSweepCursor::<Test>::get());
+		});
+	});
+}
+
+#[test]
+pub fn ipfs_migrates_the_bridge_to_v1() {
+	TEST_CONSTANTS.with(|test_data| {
+		new_test_ext_funded(test_data.p.clone()).execute_with(|| {
+			// Given: an ipfs identity was bridged before identities were indexed by account
+			let p = test_data.p.public().clone();
+			crate::SubstrateIpfsBridge::<Test>::insert(test_data.public_key.clone(), p.clone());
+			frame_support::traits::StorageVersion::new(0).put::<Ipfs>();
+			// When: the runtime is upgraded
+			<Ipfs as frame_support::traits::Hooks<u64>>::on_runtime_upgrade();
+			// Then: the identity is indexed under its account
+			assert_eq!(vec![test_data.public_key.clone()], Ipfs::ipfs_identities_of(p.clone()));
+			assert_eq!(
+				Some(test_data.public_key.clone()),
+				<Ipfs as pallet_data_assets::PeerIdProvider<_>>::peer_id(&p),
+			);
+			// And: the storage version is bumped
+			assert_eq!(1, frame_support::traits::StorageVersion::get::<Ipfs>());
 		});
 	});
 }
 
+#[test]
+pub fn ipfs_can_verify_data_against_a_blake2b_cid() {
+	// Given: a CIDv1 of some raw data, hashed with blake2b-256
+	let data = b"hello iris".to_vec();
+	let mut cid_bytes = vec![0x01, 0x55, 0xa0, 0xe4, 0x02, 0x20];
+	cid_bytes.extend_from_slice(&sp_io::hashing::blake2_256(&data));
+	let cid = base32_cid(&cid_bytes);
+	// Then: the multihash is parsed from the cid
+	let (code, digest) = ipfs::parse_multihash(&cid).unwrap();
+	assert_eq!(ipfs::BLAKE2B_256, code);
+	assert_eq!(sp_io::hashing::blake2_256(&data).to_vec(), digest);
+	// And: the data is verified against the cid, but other data is not
+	assert_eq!(Ok(true), ipfs::verify_cid(&cid, &data));
+	assert_eq!(Ok(false), ipfs::verify_cid(&cid, b"not iris"));
+}
+
+#[test]
+pub fn ipfs_can_not_verify_data_against_an_unsupported_cid() {
+	// Given: a CIDv0, which uses sha2-256
+	TEST_CONSTANTS.with(|test_data| {
+		let (code, digest) = ipfs::parse_multihash(&test_data.cid_vec).unwrap();
+		assert_eq!(ipfs::SHA2_256, code);
+		assert_eq!(32, digest.len());
+	});
+	// And: a CIDv1 hashed with sha3-256
+	let mut cid_bytes = vec![0x01, 0x55, 0x16, 0x20];
+	cid_bytes.extend_from_slice(&[0u8; 32]);
+	let cid = base32_cid(&cid_bytes);
+	// Then: the sha3-256 cid can't be verified
+	assert_eq!(Err(ipfs::CidError::UnsupportedHash(0x16)), ipfs::verify_cid(&cid, b"data"));
+	// And: malformed cids are rejected
+	assert_eq!(Err(ipfs::CidError::InvalidCid), ipfs::verify_cid(b"not a cid", b"data"));
+}
+
+/// encode cid bytes as a base32 (multibase 'b') CIDv1
+fn base32_cid(bytes: &[u8]) -> Vec<u8> {
+	let alphabet = b"abcdefghijklmnopqrstuvwxyz234567";
+	let mut out = vec![b'b'];
+	let mut buffer = 0u32;
+	let mut bits = 0u32;
+	for b in bytes {
+		buffer = (buffer << 8) | *b as u32;
+		bits += 8;
+		while bits >= 5 {
+			bits -= 5;
+			out.push(alphabet[((buffer >> bits) & 0x1f) as usize]);
+		}
+		buffer &= (1 << bits) - 1;
+	}
+	if bits > 0 {
+		out.push(alphabet[((buffer << (5 - bits)) & 0x1f) as usize]);
+	}
+	out
+}
+
 fn ipfs_config_update_body() -> Vec<u8> {
 	br#"
 	{
@@ -28,16 +28,19 @@
 mod mock;
 mod tests;
 
+pub mod migrations;
+
 use frame_support::{
 	ensure,
 	pallet_prelude::*,
 	traits::Randomness,
+	transactional,
 };
 // use scale_info::prelude::string::ToString;
 use scale_info::TypeInfo;
 pub use pallet::*;
 use sp_runtime::{
-	traits::{TrailingZeroInput, Verify},
+	traits::{One, TrailingZeroInput, Verify},
 };
 use sp_std::{
 	str,
@@ -56,7 +59,7 @@ use frame_system::{
 };
 use sp_runtime::offchain::storage::StorageValueRef;
 use iris_primitives::*;
-use pallet_data_assets::{MetadataProvider, QueueManager};
+use pallet_data_assets::{AssetHistoryRecorder, MetadataProvider, QueueManager};
 
 use umbral_pre::*;
 
@@ -70,6 +73,18 @@ use crypto_box::{
 
 pub const LOG_TARGET: &str = "runtime::proxy";
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"aura");
+/// the number of capsule fragments required to decrypt data
+pub const KFRAG_THRESHOLD: usize = 2;
+/// the number of key fragments generated for each reencryption request
+pub const KFRAG_SHARES: usize = 3;
+/// the context a consumer prefixes to its box public key when proving it owns the key
+pub const BOX_KEY_PROOF_CONTEXT: &[u8] = b"iris::box-key-proof";
+/// the size of a salsa box nonce
+pub const BOX_NONCE_SIZE: usize = 24;
+/// the size of an x25519 public key
+pub const BOX_PUBLIC_KEY_SIZE: usize = 32;
+/// the size of an umbral secret key encrypted in a salsa box: the key and a 16 byte tag
+pub const ENCRYPTED_SECRET_KEY_SIZE: usize = 48;
 
 #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
 pub struct TPREEncryptionArtifact<AccountId> {
@@ -89,11 +104,28 @@ pub struct ReencryptionArtifact<AccountId> {
 	pub verified_kfrags: Vec<(AccountId, EncryptedBox)>,
 }
 
+/// An encrypted capsule fragment along with the block after which it can be removed
+#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo, Clone)]
+pub struct CapsuleFragment<AccountId, BlockNumber> {
+	/// the capsule fragment, encrypted for the data consumer
+	pub encrypted_cfrag: EncryptedBox,
+	/// the proxy that delivered the fragment
+	pub proxy: AccountId,
+	/// the version of the proxy's box key the fragment was encrypted with
+	pub box_key_version: u32,
+	/// the block at which the fragment expires
+	pub expires_at: BlockNumber,
+	/// whether the consumer has reported the result of verifying the fragment
+	pub reported: bool,
+}
+
 #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
-pub struct KeyFragGenerationRequest<AccountId> {
+pub struct KeyFragGenerationRequest<AccountId, AssetId> {
     pub caller: AccountId,
     pub data_public_key: Vec<u8>,
 	pub consumer_public_key: Vec<u8>,
+	/// the asset the caller was authorized to decrypt
+	pub asset_id: AssetId,
 }
 
 #[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
@@ -158,10 +190,31 @@ pub mod pallet {
 		type MetadataProvider: pallet_data_assets::MetadataProvider<u32>;
 		/// Something that provides randomness in the runtime.
 		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
+		/// The number of blocks a capsule fragment is kept before it expires
+		#[pallet::constant]
+		type FragmentTTL: Get<Self::BlockNumber>;
+		/// The maximum number of (consumer, public key) entries checked for expired fragments per block
+		#[pallet::constant]
+		type MaxExpiredFragmentsPerBlock: Get<u32>;
+		/// The maximum number of capsule fragments that can be delivered to a consumer for a public key.
+		/// Should be at least `KFRAG_THRESHOLD`, with some margin for fragments that fail verification
+		#[pallet::constant]
+		type MaxFragmentsPerRequest: Get<u32>;
+		/// The maximum number of capsule fragments a proxy can deliver in a single `submit_fragments` call
+		#[pallet::constant]
+		type MaxFragmentBatch: Get<u32>;
+		/// records when assets are rekeyed and accessed
+		type AssetHistory: pallet_data_assets::AssetHistoryRecorder<Self::AccountId, Self::AssetId>;
+		/// decides which proxies hold the key fragments of each reencryption request
+		type AssignmentStrategy: AssignmentStrategy<Self::AccountId>;
 	}
 
+	/// the current storage version
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(2);
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	#[pallet::without_storage_info]
 	pub struct Pallet<T>(_);
 
@@ -192,7 +245,7 @@ pub mod pallet {
         _,
         Blake2_128Concat,
         T::AccountId, // proxy address
-        Vec<KeyFragGenerationRequest<T::AccountId>>, // public key associated with umbral encrypted data
+        Vec<KeyFragGenerationRequest<T::AccountId, T::AssetId>>, // public key associated with umbral encrypted data
         ValueQuery,
     >;
 
@@ -203,10 +256,23 @@ pub mod pallet {
         T::AccountId,
         Blake2_128Concat,
         Vec<u8>,
-        Vec<EncryptedBox>, // <-- TODO: should rename this struct to something more generic
+        Vec<CapsuleFragment<T::AccountId, T::BlockNumber>>,
         ValueQuery,
     >;
 
+	/// the (consumer, public key) pairs whose capsule fragments or access grant expire
+	/// at each block, keyed by block and then by pair
+	#[pallet::storage]
+	pub type FragmentExpiries<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		T::BlockNumber,
+		Blake2_128Concat,
+		(T::AccountId, Vec<u8>),
+		(),
+		OptionQuery,
+	>;
+
 	#[pallet::storage]
 	pub type CapsuleFragmentGenerationRequests<T: Config> = StorageMap<
 		_,
@@ -216,6 +282,68 @@ pub mod pallet {
 		ValueQuery,
 	>;
 
+	/// maps a (consumer, public key) pair to the asset the consumer was authorized to decrypt
+	#[pallet::storage]
+	pub type GrantedAssets<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId, // the consumer
+		Blake2_128Concat,
+		Vec<u8>, // the public key
+		T::AssetId,
+		OptionQuery,
+	>;
+
+	/// maps a (consumer, public key) pair to the block at which the consumer's access
+	/// to the asset expires. Grants without an entry don't expire.
+	#[pallet::storage]
+	#[pallet::getter(fn grant_expiry)]
+	pub type GrantExpiries<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId, // the consumer
+		Blake2_128Concat,
+		Vec<u8>, // the public key
+		T::BlockNumber,
+		OptionQuery,
+	>;
+
+	/// the number of times access to each asset was granted, i.e. a consumer was
+	/// delivered enough capsule fragments to decrypt it
+	#[pallet::storage]
+	#[pallet::getter(fn access_count)]
+	pub type AccessCount<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AssetId,
+		u64,
+		ValueQuery,
+	>;
+
+	/// the number of capsule fragments each proxy delivered that consumers
+	/// reported as passing or failing verification
+	#[pallet::storage]
+	#[pallet::getter(fn proxy_reputation)]
+	pub type ProxyReputation<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		ProxyStats,
+		ValueQuery,
+	>;
+
+	/// proxies that have asked their node to run the self test
+	/// on the next offchain worker run
+	#[pallet::storage]
+	#[pallet::getter(fn self_test_requests)]
+	pub type SelfTestRequests<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		bool,
+		ValueQuery,
+	>;
+
 	#[pallet::storage]
 	pub type ProxyCodes<T: Config> = StorageDoubleMap<
 		_,
@@ -233,6 +361,25 @@ pub mod pallet {
 		EncryptionArtifactsSubmitted,
 		ReencryptionComplete,
 		ReencapsulationComplete,
+		/// Capsule fragments for a (consumer, public key) pair expired and were removed
+		FragmentsExpired(T::AccountId, Vec<u8>),
+		/// A proxy asked its node to run a self test of its crypto pipeline
+		SelfTestRequested(T::AccountId),
+		/// A proxy successfully completed a self test of its crypto pipeline
+		SelfTestPassed(T::AccountId),
+		/// A proxy's node failed a self test of its crypto pipeline
+		SelfTestFailed(T::AccountId),
+		/// A consumer cancelled a pending reencryption request for a public key
+		ReencryptionCancelled(T::AccountId, Vec<u8>),
+		/// A proxy replaced its capsule fragment for a (consumer, public key) pair
+		/// with one encrypted under its rotated box key
+		CapsuleFragmentRedelivered(T::AccountId, Vec<u8>, T::AccountId),
+		/// A consumer reported whether a proxy's capsule fragment for a public key passed verification
+		CapsuleFragmentReported(T::AccountId, Vec<u8>, T::AccountId, bool),
+		/// A consumer's access grant for a public key expired and its capsule fragments were removed
+		AccessExpired(T::AccountId, Vec<u8>),
+		/// A proxy delivered a batch of capsule fragments: (proxy, fragments stored, fragments rejected)
+		CapsuleFragmentsSubmitted(T::AccountId, u32, u32),
 	}
 
 	#[pallet::error]
@@ -240,6 +387,53 @@ pub mod pallet {
 		InsufficientAuthorities,
 		InvalidPublicKeyLength,
 		InsufficientCapsuleFrags,
+		/// a step of the proxy self test failed
+		SelfTestFailed,
+		/// there is no pending reencryption request to cancel
+		NoSuchReencryptionRequest,
+		/// capsule fragments have already been delivered for the request
+		FragmentsAlreadyDelivered,
+		/// ingestion and reencryption are halted
+		Halted,
+		/// the proxy has not delivered a capsule fragment to the caller for the public key
+		NoSuchCapsuleFragment,
+		/// the result of verifying the capsule fragment has already been reported
+		FragmentAlreadyReported,
+		/// the encryption or reencryption artifacts needed to verify the fragment are malformed
+		MalformedArtifacts,
+		/// the capsule is not a serialized umbral capsule
+		MalformedCapsule,
+		/// the data public key is not a serialized umbral public key
+		MalformedDataPublicKey,
+		/// the encrypted secret key does not have the nonce, public key and ciphertext
+		/// lengths of an umbral secret key encrypted in a salsa box
+		MalformedSecretBox,
+		/// the proxy has not published an x25519 public key
+		NoProxyKey,
+		/// the consumer's access grant for the public key has expired
+		AccessExpired,
+		/// the consumer has already been delivered the maximum number of capsule fragments for the public key
+		FragmentLimitReached,
+		/// the batch holds more than `MaxFragmentBatch` capsule fragments
+		FragmentBatchTooLarge,
+		/// the proxy has not requested a self test
+		NoSelfTestRequested,
+		/// the caller was not assigned a key fragment for the consumer's reencryption request
+		NotAKfragHolder,
+		/// the proxy has already delivered a capsule fragment to the consumer for the public key
+		FragmentAlreadyDelivered,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_idle(block_number: T::BlockNumber, remaining_weight: Weight) -> Weight {
+			Self::expire_capsule_fragments(block_number, remaining_weight)
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			migrations::v1::migrate::<T>()
+				.saturating_add(migrations::v2::migrate::<T>())
+		}
 	}
 
 	#[pallet::validate_unsigned]
@@ -261,14 +455,18 @@ pub mod pallet {
 	impl<T: Config> Pallet<T> {
 
 		/// This function allows validators to submit encrypted capsule fragments to be encoded in the runtime. 
-		/// In general, this functional should be called *only* by offchain workers, which is why the weight is left
-		/// as 0.
+		/// In general, this functional should be called *only* by offchain workers. Only the holders of the
+		/// consumer's key fragments can deliver capsule fragments, one each. If the proxy rotated its box key
+		/// since it last delivered a fragment to the consumer, the new fragment replaces the old one and
+		/// does not count towards the threshold again.
+		/// Fragments expire with the consumer's access grant if it expires before their TTL.
+		/// At most `MaxFragmentsPerRequest` fragments can be delivered for a (consumer, public key) pair.
 		/// 
 		/// `data_consumer`: The account id for which the capsule fragment has been created
 		/// `public_key`: The unique public key that identifies the encrypted data
 		/// `encrypted_cfrag_data`: The encrypted capsule fragment
 		/// 
-		#[pallet::weight(0)]
+		#[pallet::weight(100_000)]
 		pub fn submit_capsule_fragment(
 			origin: OriginFor<T>,
 			data_consumer: T::AccountId,
@@ -276,15 +474,40 @@ pub mod pallet {
 			encrypted_cfrag_data: EncryptedBox,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			// should there be any verification that this public key was generated via the encryption? probably
-			EncryptedCapsuleFrags::<T>::mutate(data_consumer, public_key.clone(), |cfrags| {
-            	cfrags.push(encrypted_cfrag_data);
-        	});
-			// cleanup
-			CapsuleFragmentGenerationRequests::<T>::mutate(who, |reqs| {
-				reqs.retain(|r| *r.data_public_key != public_key.clone());
-			});
-			Self::deposit_event(Event::ReencapsulationComplete);
+			ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
+			Self::do_submit_capsule_fragment(who, data_consumer, public_key, encrypted_cfrag_data)
+		}
+
+		/// Submit several capsule fragments in a single transaction, as proxies servicing many
+		/// reencryption requests do. Each fragment is validated and stored as by
+		/// `submit_capsule_fragment`, independently of the others: a fragment that is rejected
+		/// (e.g. because the consumer's access expired) is skipped without affecting the rest
+		/// of the batch. Only a batch of more than `MaxFragmentBatch` fragments is rejected
+		/// as a whole.
+		/// 
+		/// `fragments`: The (consumer, public key, encrypted capsule fragment) of each delivery
+		/// 
+		#[pallet::weight(100_000u64.saturating_mul(fragments.len() as u64))]
+		pub fn submit_fragments(
+			origin: OriginFor<T>,
+			fragments: Vec<(T::AccountId, Vec<u8>, EncryptedBox)>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
+			ensure!(fragments.len() as u32 <= T::MaxFragmentBatch::get(), Error::<T>::FragmentBatchTooLarge);
+			let mut accepted = 0u32;
+			let mut rejected = 0u32;
+			for (data_consumer, public_key, encrypted_cfrag_data) in fragments {
+				// fragments are validated before anything is written, so a rejected one leaves no trace
+				match Self::do_submit_capsule_fragment(who.clone(), data_consumer, public_key, encrypted_cfrag_data) {
+					Ok(()) => accepted += 1,
+					Err(e) => {
+						log::info!("Rejected a capsule fragment in a batch from {:?}: {:?}", who, e);
+						rejected += 1;
+					},
+				}
+			}
+			Self::deposit_event(Event::CapsuleFragmentsSubmitted(who, accepted, rejected));
 			Ok(())
 		}
 		
@@ -311,6 +534,7 @@ pub mod pallet {
 			encrypted_receiving_sk: EncryptedBox,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
+			ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
 			// this probably won't stay like this forever but it's fine for now I guess, makes testing easier
             for assignment in kfrag_assignments.iter() {
 				CapsuleFragmentGenerationRequests::<T>::mutate(
@@ -324,6 +548,9 @@ pub mod pallet {
 				);
             }
 
+			if let Some(asset_id) = GrantedAssets::<T>::get(consumer.clone(), delegating_public_key.clone()) {
+				T::AssetHistory::record(asset_id, AssetLifecycleEvent::Rekeyed, Some(consumer.clone()));
+			}
 			ReencryptionArtifacts::<T>::insert(
 				consumer, 
 				delegating_public_key.clone(), 
@@ -360,21 +587,268 @@ pub mod pallet {
             encrypted_sk_box: EncryptedBox,
         ) -> DispatchResult {
             // ensure_signed(origin)?;
-			EncryptionArtifacts::<T>::insert(public_key.clone(), TPREEncryptionArtifact {
-				capsule,
-				proxy: proxy.clone(),
-			});
-            ProxyCodes::<T>::insert(proxy, public_key.clone(), encrypted_sk_box);
-			T::QueueManager::add_ingestion_staging(owner, public_key);
-            Self::deposit_event(Event::EncryptionArtifactsSubmitted);
-            Ok(())
+			Self::stage_encryption_artifacts(owner, proxy, capsule, public_key, encrypted_sk_box)
         }
 
+		/// Stage the encryption artifacts returned by the `iris_encryptForRequest` rpc and
+		/// request ingestion of the ciphertext in a single transaction, so that neither is left
+		/// behind without the other. If either step fails, both are rolled back.
+		/// 
+		/// * `proxy`: The proxy the data secret key was encrypted for
+		/// * `capsule`: The capsule created when the data was encrypted
+		/// * `public_key`: The data public key
+		/// * `encrypted_sk_box`: The data secret key, encrypted for the proxy
+		/// * `gateway`: The gateway node that should verify the data
+		/// * `gateway_reserve`: As for `create_request`
+		/// * `cid`: The cid of the ciphertext
+		/// * `multiaddress`: The multiaddress where the ciphertext exists
+		/// * `estimated_size`: The size of the ciphertext in bytes
+		/// * `min_asset_balance`: As for `create_request`
+		/// * `replication`: As for `create_request`
+		/// 
+		#[pallet::weight(100)]
+		#[transactional]
+		pub fn encrypt_and_request(
+			origin: OriginFor<T>,
+			proxy: T::AccountId,
+			capsule: Vec<u8>,
+			public_key: Vec<u8>,
+			encrypted_sk_box: EncryptedBox,
+			gateway: T::AccountId,
+			gateway_reserve: T::Balance,
+			cid: Vec<u8>,
+			multiaddress: Vec<u8>,
+			estimated_size: u128,
+			#[pallet::compact] min_asset_balance: T::Balance,
+			replication: u32,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::stage_encryption_artifacts(who.clone(), proxy, capsule, public_key, encrypted_sk_box)?;
+			T::QueueManager::create_request(
+				who, gateway, gateway_reserve, cid, multiaddress, estimated_size, min_asset_balance, replication,
+			)
+		}
+
+		/// Stage encryption artifacts generated client-side, for owners that encrypt their
+		/// data themselves rather than through the `iris_encrypt` rpc. The artifacts are
+		/// validated and staged for the caller exactly as the rpc stages them, and the
+		/// ciphertext is then ingested with `create_request`.
+		/// 
+		/// * `proxy`: The proxy assigned to process reencryption requests. It must have
+		///            published an x25519 public key
+		/// * `capsule`: The umbral capsule created when the data was encrypted
+		/// * `public_key`: The umbral data public key
+		/// * `encrypted_sk_box`: The data secret key, encrypted for the proxy's x25519 public key
+		/// 
+		#[pallet::weight(100)]
+		pub fn submit_precomputed_artifacts(
+			origin: OriginFor<T>,
+			proxy: T::AccountId,
+			capsule: Vec<u8>,
+			public_key: Vec<u8>,
+			encrypted_sk_box: EncryptedBox,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Capsule::from_bytes(&capsule).map_err(|_| Error::<T>::MalformedCapsule)?;
+			PublicKey::from_bytes(public_key.clone()).map_err(|_| Error::<T>::MalformedDataPublicKey)?;
+			ensure!(
+				encrypted_sk_box.nonce.len() == BOX_NONCE_SIZE
+					&& encrypted_sk_box.public_key.len() == BOX_PUBLIC_KEY_SIZE
+					&& encrypted_sk_box.ciphertext.len() == ENCRYPTED_SECRET_KEY_SIZE,
+				Error::<T>::MalformedSecretBox
+			);
+			ensure!(
+				pallet_authorities::Pallet::<T>::x25519_public_keys(proxy.clone()).len() == BOX_PUBLIC_KEY_SIZE,
+				Error::<T>::NoProxyKey
+			);
+			Self::stage_encryption_artifacts(who, proxy, capsule, public_key, encrypted_sk_box)
+		}
+
+		/// Request that your node runs a self test of its crypto pipeline on its next offchain
+		/// worker run, to verify it works before it accepts reencryption work. The node round trips
+		/// some plaintext through its local x25519 key and through umbral encryption, kfrag generation,
+		/// reencryption and decryption, then reports the result with `submit_self_test_result`.
+		/// The caller must have published its x25519 public key.
+		/// 
+		#[pallet::weight(100)]
+		pub fn proxy_self_test(
+			origin: OriginFor<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				pallet_authorities::Pallet::<T>::x25519_public_keys(who.clone()).len() == BOX_PUBLIC_KEY_SIZE,
+				Error::<T>::SelfTestFailed
+			);
+			SelfTestRequests::<T>::insert(who.clone(), true);
+			Self::deposit_event(Event::SelfTestRequested(who));
+			Ok(())
+		}
+
+		/// Report the result of a requested self test.
+		/// This should only be called by offchain workers.
+		/// 
+		/// * `passed`: Whether every step of the self test succeeded
+		/// 
+		#[pallet::weight(100)]
+		pub fn submit_self_test_result(
+			origin: OriginFor<T>,
+			passed: bool,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(SelfTestRequests::<T>::take(who.clone()), Error::<T>::NoSelfTestRequested);
+			if passed {
+				Self::deposit_event(Event::SelfTestPassed(who));
+			} else {
+				Self::deposit_event(Event::SelfTestFailed(who));
+			}
+			Ok(())
+		}
+
+		/// Cancel a pending reencryption request. Cancellation is only possible 
+		/// before any capsule fragments have been delivered to the caller.
+		/// 
+		/// * `data_public_key`: The public key of the data the reencryption was requested for
+		/// 
+		#[pallet::weight(100)]
+		pub fn cancel_reencryption(
+			origin: OriginFor<T>,
+			data_public_key: Vec<u8>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				EncryptedCapsuleFrags::<T>::get(who.clone(), data_public_key.clone()).is_empty(),
+				Error::<T>::FragmentsAlreadyDelivered
+			);
+			let is_request = |caller: &T::AccountId, pk: &Vec<u8>| *caller == who && *pk == data_public_key;
+			let mut cancelled = false;
+			// the proxy has not yet generated key fragments
+			if let Some(artifact) = EncryptionArtifacts::<T>::get(data_public_key.clone()) {
+				KeyFragGenerationRequests::<T>::mutate(artifact.proxy, |reqs| {
+					let pending = reqs.len();
+					reqs.retain(|r| !is_request(&r.caller, &r.data_public_key));
+					cancelled |= reqs.len() != pending;
+				});
+			}
+			// key fragments were generated but no capsule fragments were delivered
+			if let Some(artifact) = ReencryptionArtifacts::<T>::take(who.clone(), data_public_key.clone()) {
+				for (holder, _) in artifact.verified_kfrags.iter() {
+					CapsuleFragmentGenerationRequests::<T>::mutate(holder, |reqs| {
+						reqs.retain(|r| !is_request(&r.caller, &r.data_public_key));
+					});
+				}
+				cancelled = true;
+			}
+			ensure!(cancelled, Error::<T>::NoSuchReencryptionRequest);
+			GrantedAssets::<T>::remove(who.clone(), data_public_key.clone());
+			if let Some(expires_at) = GrantExpiries::<T>::take(who.clone(), data_public_key.clone()) {
+				Self::unschedule_expiry(&who, &data_public_key, expires_at);
+			}
+			Self::deposit_event(Event::ReencryptionCancelled(who, data_public_key));
+			Ok(())
+		}
+
+		/// Report the result of verifying a capsule fragment a proxy delivered to the caller,
+		/// counting towards the proxy's reputation. Fragments are encrypted for the consumer, so
+		/// the caller submits the fragment once opened and it is verified against the capsule
+		/// and the reencryption keys on chain. Each delivered fragment can be reported once.
+		/// 
+		/// * `data_public_key`: The public key of the data the fragment was delivered for
+		/// * `proxy`: The proxy that delivered the fragment
+		/// * `capsule_fragment`: The opened capsule fragment
+		/// 
+		#[pallet::weight(100)]
+		pub fn report_capsule_fragment(
+			origin: OriginFor<T>,
+			data_public_key: Vec<u8>,
+			proxy: T::AccountId,
+			capsule_fragment: Vec<u8>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			EncryptedCapsuleFrags::<T>::try_mutate(who.clone(), data_public_key.clone(), |cfrags| {
+				let fragment = cfrags.iter_mut()
+					.find(|f| f.proxy == proxy)
+					.ok_or(Error::<T>::NoSuchCapsuleFragment)?;
+				ensure!(!fragment.reported, Error::<T>::FragmentAlreadyReported);
+				fragment.reported = true;
+				Ok::<(), Error<T>>(())
+			})?;
+			let encryption_artifact = EncryptionArtifacts::<T>::get(data_public_key.clone())
+				.ok_or(Error::<T>::MalformedArtifacts)?;
+			let reencryption_artifact = ReencryptionArtifacts::<T>::get(who.clone(), data_public_key.clone())
+				.ok_or(Error::<T>::MalformedArtifacts)?;
+			let capsule = Capsule::from_bytes(&encryption_artifact.capsule)
+				.map_err(|_| Error::<T>::MalformedArtifacts)?;
+			let delegating_pk = PublicKey::from_bytes(data_public_key.clone())
+				.map_err(|_| Error::<T>::MalformedArtifacts)?;
+			let ephemeral_pk = PublicKey::from_bytes(reencryption_artifact.ephemeral_public_key)
+				.map_err(|_| Error::<T>::MalformedArtifacts)?;
+			let verifying_pk = PublicKey::from_bytes(reencryption_artifact.verifying_key)
+				.map_err(|_| Error::<T>::MalformedArtifacts)?;
+			let valid = Self::verify_capsule_fragment(
+				capsule_fragment, &capsule, &verifying_pk, &delegating_pk, &ephemeral_pk,
+			).is_some();
+			Self::record_fragment_verification(&proxy, valid);
+			Self::deposit_event(Event::CapsuleFragmentReported(who, data_public_key, proxy, valid));
+			Ok(())
+		}
+
 	}
 }
 
 impl<T: Config> Pallet<T> {
 
+	/// Get everything a consumer needs to decrypt a data asset: the capsule created
+	/// when the data was encrypted, the cid and size of the ciphertext in IPFS, and
+	/// the plaintext checksum attested by the owner to verify the decrypted data with.
+	/// 
+	/// * `asset_id`: The asset id associated with the data asset
+	/// 
+	pub fn decryption_context(asset_id: u32) -> Option<DecryptionContext> {
+		let metadata = T::MetadataProvider::get(asset_id)?;
+		let artifact = EncryptionArtifacts::<T>::get(metadata.public_key)?;
+		Some(DecryptionContext {
+			capsule: artifact.capsule,
+			ciphertext_cid: metadata.ciphertext_cid,
+			ciphertext_size: metadata.ciphertext_size,
+			plaintext_checksum: metadata.plaintext_checksum,
+		})
+	}
+
+	/// Check whether a consumer could decrypt a data asset, without needing their secret key.
+	/// Delivered capsule fragments are counted but can't be verified here. A consumer whose
+	/// access grant expired is not authorized, even if their fragments weren't removed yet.
+	/// 
+	/// * `asset_id`: The asset to decrypt
+	/// * `account`: The consumer
+	/// 
+	pub fn decrypt_precheck(asset_id: u32, account: T::AccountId) -> DecryptionReadiness {
+		let mut report = DecryptionReadiness {
+			fragments_needed: KFRAG_THRESHOLD as u32,
+			..Default::default()
+		};
+		let metadata = match T::MetadataProvider::get(asset_id) {
+			Some(metadata) => metadata,
+			None => return report,
+		};
+		report.asset_exists = true;
+		report.capsule_present = EncryptionArtifacts::<T>::contains_key(&metadata.public_key);
+		report.reencryption_artifact_present =
+			ReencryptionArtifacts::<T>::contains_key(&account, &metadata.public_key);
+		report.authorized = GrantedAssets::<T>::contains_key(&account, &metadata.public_key)
+			&& !Self::access_expired(&account, &metadata.public_key);
+		report.fragments_delivered =
+			EncryptedCapsuleFrags::<T>::get(&account, &metadata.public_key).len() as u32;
+		report.pinned = T::MetadataProvider::replica_count(asset_id) > 0;
+		report.frozen = T::MetadataProvider::is_frozen(asset_id);
+		report.ready = report.capsule_present
+			&& report.reencryption_artifact_present
+			&& report.authorized
+			&& report.fragments_delivered >= report.fragments_needed
+			&& report.pinned
+			&& !report.frozen;
+		report
+	}
+
 	///
 	/// Attempt to decrypt the ciphertext.
 	/// Decryption will only be successful if the caller has sufficiently many capsule fragments.
@@ -386,6 +860,17 @@ impl<T: Config> Pallet<T> {
 	/// * `asset_id`: The unique asset id that identifies the on-chain asset associated with the data
 	/// * `secret_key`: An X25519 secret key whose public key was used when authorization was granted 
 	///                 and capsule fragments were created.
+	/// * `use_fragments`: The number of verified capsule fragments to decrypt with. Must be at least
+	///                    `KFRAG_THRESHOLD`. If not specified, all available fragments are used.
+	/// 
+	/// Returns `DecryptionFailure::TooFewFragmentsRequested` if `use_fragments` is below
+	/// `KFRAG_THRESHOLD`, `DecryptionFailure::NotEnoughFragments` if fewer verified capsule fragments
+	/// than the threshold have been delivered to the caller so far, `DecryptionFailure::DecryptionFailed`
+	/// if the ciphertext fails authentication, e.g. because it doesn't match the asset's capsule,
+	/// and `DecryptionFailure::InvalidSecretKey` if the secret key is not exactly 32 bytes.
+	/// The ciphertext is decrypted with the scheme recorded in the asset's metadata. Umbral is
+	/// the only scheme so far, and `DecryptionFailure::UnsupportedScheme` is reserved for
+	/// schemes a runtime records but can't decrypt.
 	/// 
 	pub fn decrypt(
 		signature: Bytes,
@@ -394,17 +879,11 @@ impl<T: Config> Pallet<T> {
 		ciphertext: Bytes,
 		asset_id: u32,
 		secret_key: Bytes,
-	) -> Option<Bytes> {
-		
-        let acct_bytes: [u8;32] = signer.to_vec().try_into().unwrap();
-        let acct_pubkey = Public::from_raw(acct_bytes);
-        let sig: Signature = Signature::from_slice(signature.to_vec().as_ref()).unwrap();
-        let msg: Vec<u8> = message.to_vec();
-        let acct_id: T::AccountId = T::AccountId::decode(&mut &acct_bytes[..]).unwrap();
-
-        if sig.verify(msg.as_slice(), &acct_pubkey) {
-			let sk_vec = secret_key.to_vec();
-			let sk_slice = iris_primitives::slice_to_array_32(&sk_vec).unwrap();
+		use_fragments: Option<u32>,
+	) -> Result<Bytes, DecryptionFailure> {
+		let sk_slice = iris_primitives::slice_to_array_32(&secret_key)
+			.ok_or(DecryptionFailure::InvalidSecretKey)?;
+		if let Some(acct_id) = Self::verified_signer(signature, signer, message) {
 			let sk = BoxSecretKey::from(*sk_slice);
 			// map asset_id to public_key 
             // let asset_id_as_type = TryInto::<T::AssetId>::try_into(asset_id).ok().unwrap();
@@ -412,15 +891,138 @@ impl<T: Config> Pallet<T> {
 			// let metadata = T::MetadataProvider::get(asset_id_as_type.clone()).unwrap();
 			let metadata = T::MetadataProvider::get(asset_id).unwrap();
 			// decrypt secret key
-			return Some(Self::do_decrypt(
-				acct_id, 
-				ciphertext.to_vec(),
-				metadata.public_key,
-				sk,
-			));
+			return match metadata.encryption_scheme {
+				EncryptionScheme::UmbralSalsaBox => Self::do_decrypt(
+					acct_id, 
+					ciphertext.to_vec(),
+					metadata.public_key,
+					sk,
+					use_fragments,
+				),
+			};
+		}
+
+		Ok(Bytes::from(Vec::new()))
+	}
+
+	/// Recover the key material needed to decrypt an asset, so that repeated reads can skip
+	/// opening and verifying the capsule fragments. The arguments are as for `decrypt`.
+	/// 
+	/// Returns None if the signature is invalid, `DecryptionFailure::TooFewFragmentsRequested` if
+	/// `use_fragments` is below `KFRAG_THRESHOLD`, and `DecryptionFailure::NotEnoughFragments` if
+	/// too few fragments have been delivered so far.
+	/// 
+	pub fn decryption_key(
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+		asset_id: u32,
+		secret_key: Bytes,
+		use_fragments: Option<u32>,
+	) -> Result<Option<DecryptionKey>, DecryptionFailure> {
+		let acct_id = match Self::verified_signer(signature, signer, message) {
+			Some(acct_id) => acct_id,
+			None => return Ok(None),
+		};
+		let fragment_limit = Self::fragment_limit(use_fragments)?;
+		let sk_slice = match iris_primitives::slice_to_array_32(&secret_key) {
+			Some(sk_slice) => sk_slice,
+			None => return Ok(None),
+		};
+		let metadata = match T::MetadataProvider::get(asset_id) {
+			Some(metadata) => metadata,
+			None => return Ok(None),
+		};
+		Self::recover_decryption_key(
+			acct_id,
+			metadata.public_key,
+			BoxSecretKey::from(*sk_slice),
+			fragment_limit,
+		).map(Some)
+	}
+
+	/// The maximum number of verified capsule fragments to decrypt with
+	/// 
+	/// * `use_fragments`: The number of fragments the caller asked for, or all available if None
+	/// 
+	/// Returns `DecryptionFailure::TooFewFragmentsRequested` if fewer than `KFRAG_THRESHOLD`
+	/// fragments were asked for, since decryption could never succeed.
+	/// 
+	fn fragment_limit(use_fragments: Option<u32>) -> Result<usize, DecryptionFailure> {
+		match use_fragments {
+			Some(n) if (n as usize) < KFRAG_THRESHOLD => Err(DecryptionFailure::TooFewFragmentsRequested {
+				requested: n,
+				need: KFRAG_THRESHOLD as u32,
+			}),
+			Some(n) => Ok(n as usize),
+			None => Ok(usize::MAX),
 		}
+	}
 
-		Some(Bytes::from(Vec::new()))
+	/// Decrypt ciphertext with key material recovered by `decryption_key`.
+	/// 
+	/// * `ciphertext`: The ciphertext to be decrypted
+	/// * `signature`: The signature created to sign the message
+	/// * `signer`: The account the key was recovered for
+	/// * `message`: A signed message
+	/// * `asset_id`: The asset id associated with the data asset
+	/// * `key`: The recovered key material
+	/// 
+	/// Returns None if the signature is invalid or the key is no longer valid, i.e. the signer
+	/// was issued new reencryption artifacts or its capsule fragments expired, or if the
+	/// ciphertext fails authentication with the key.
+	/// 
+	pub fn decrypt_with_key(
+		ciphertext: Bytes,
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+		asset_id: u32,
+		key: DecryptionKey,
+	) -> Option<Bytes> {
+		let acct_id = Self::verified_signer(signature, signer, message)?;
+		let metadata = T::MetadataProvider::get(asset_id)?;
+		Self::apply_decryption_key(acct_id, ciphertext.to_vec(), metadata.public_key, &key)?.ok()
+	}
+
+	/// Check a consumer's proof that it controls the box public key it submits with a
+	/// reencryption request: a signature by the consumer's account key over
+	/// `BOX_KEY_PROOF_CONTEXT` followed by the box public key.
+	/// 
+	/// * `consumer`: The account requesting reencryption
+	/// * `box_public_key`: The box public key the capsule fragments are encrypted for
+	/// * `proof`: The consumer's signature
+	/// 
+	pub fn verify_box_key_proof(
+		consumer: &T::AccountId,
+		box_public_key: &[u8],
+		proof: &[u8],
+	) -> bool {
+		let acct_bytes: [u8; 32] = match consumer.encode().try_into() {
+			Ok(bytes) => bytes,
+			Err(_) => return false,
+		};
+		let sig = match Signature::from_slice(proof) {
+			Some(sig) => sig,
+			None => return false,
+		};
+		let message = [BOX_KEY_PROOF_CONTEXT, box_public_key].concat();
+		sig.verify(message.as_slice(), &Public::from_raw(acct_bytes))
+	}
+
+	/// The account that signed a message, if the signature is valid
+	fn verified_signer(
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+	) -> Option<T::AccountId> {
+		let acct_bytes: [u8; 32] = signer.to_vec().try_into().ok()?;
+		let acct_pubkey = Public::from_raw(acct_bytes);
+		let sig: Signature = Signature::from_slice(signature.to_vec().as_ref())?;
+		if !sig.verify(message.as_ref(), &acct_pubkey) {
+			return None;
+		}
+		T::AccountId::decode(&mut &acct_bytes[..]).ok()
 	}
 
 	/// decrypt reencrypted data
@@ -431,25 +1033,61 @@ impl<T: Config> Pallet<T> {
 	///                            to the entity that encrypted the data (i.e. data owner)
 	/// * `x25518_sk`: An x25519 secret whose public key was passed via a rule executor (by the same account id
 	///                passed to this function) when requesting decryption rights. 
+	/// * `use_fragments`: The number of capsule fragments to decrypt with, or all available if None.
+	/// 
+	/// Fragments that can't be opened or verified are skipped. If fewer than the threshold remain,
+	/// decryption is not attempted and the number of verified fragments is reported instead.
 	/// 
 	fn do_decrypt(
 		account_id: T::AccountId,
 		ciphertext: Vec<u8>,
 		delegating_public_key: Vec<u8>,
 		x25519_sk: BoxSecretKey,
-	) -> Bytes {
+		use_fragments: Option<u32>,
+	) -> Result<Bytes, DecryptionFailure> {
+		let fragment_limit = Self::fragment_limit(use_fragments)?;
+		let key = Self::recover_decryption_key(
+			account_id.clone(), delegating_public_key.clone(), x25519_sk, fragment_limit,
+		)?;
+		Self::apply_decryption_key(account_id, ciphertext, delegating_public_key, &key)
+			.unwrap_or_else(|| Ok(Bytes::from(Vec::new())))
+	}
+
+	/// Open and verify a caller's capsule fragments, and recover the secret key
+	/// the proxy generated for it. Fragments delivered under an expired access grant are
+	/// treated as if they had already been removed.
+	/// 
+	/// * `account_id`: The account id of the caller requesting decryption.
+	/// * `delegating_public_key`: The public key of the encrypted data
+	/// * `x25519_sk`: The caller's x25519 secret key, which the fragments and secret key are encrypted for
+	/// * `fragment_limit`: The maximum number of verified fragments to recover
+	/// 
+	fn recover_decryption_key(
+		account_id: T::AccountId,
+		delegating_public_key: Vec<u8>,
+		x25519_sk: BoxSecretKey,
+		fragment_limit: usize,
+	) -> Result<DecryptionKey, DecryptionFailure> {
 		// read runtime storage items
 		// capsule
 		let encryption_artifact = EncryptionArtifacts::<T>::get(delegating_public_key.clone()).unwrap();
 		// 
-		let reencryption_artifact = ReencryptionArtifacts::<T>::get(
+		// without a reencryption artifact no fragments have been delivered yet
+		let reencryption_artifact = match ReencryptionArtifacts::<T>::get(
 			account_id.clone(), delegating_public_key.clone(),
-		).unwrap();
+		) {
+			Some(artifact) if !Self::access_expired(&account_id, &delegating_public_key) => artifact,
+			_ => return Err(DecryptionFailure::NotEnoughFragments {
+				have: 0,
+				need: KFRAG_THRESHOLD as u32,
+			}),
+		};
 		let encrypted_capsule_fragments = EncryptedCapsuleFrags::<T>::get(
 			account_id, delegating_public_key.clone()
 		);
 
 		// gathering + formatting data
+		let fingerprint = Self::reencryption_fingerprint(&delegating_public_key, &reencryption_artifact);
 		let delegating_pk = PublicKey::from_bytes(delegating_public_key).unwrap();
 		let ephemeral_pk = PublicKey::from_bytes(reencryption_artifact.ephemeral_public_key.clone()).unwrap();
 		let verifying_pk = PublicKey::from_bytes(reencryption_artifact.verifying_key.clone()).unwrap();
@@ -458,27 +1096,58 @@ impl<T: Config> Pallet<T> {
 		let capsule = Capsule::from_bytes(&capsule_data).unwrap();
 		// TODO: refactor this completely, it's pretty bad... at least move to new function
 		let mut verified_capsule_fragments: Vec<VerifiedCapsuleFrag> = Vec::new();
-		for enc_cap_frag in encrypted_capsule_fragments.into_iter() {
+		for enc_cap_frag in encrypted_capsule_fragments.into_iter().map(|f| f.encrypted_cfrag) {
+			if verified_capsule_fragments.len() >= fragment_limit {
+				break;
+			}
 			let raw_pk = enc_cap_frag.public_key.clone();
-			let pk_array = iris_primitives::slice_to_array_32(&raw_pk).unwrap();
+			match pallet_authorities::Pallet::<T>::proxy_of_box_key(&raw_pk) {
+				Some(proxy) => log::info!("Verifying a capsule fragment encrypted by {:?}", proxy),
+				None => log::warn!("Verifying a capsule fragment encrypted with an unregistered box key"),
+			}
+			let pk_array = match iris_primitives::slice_to_array_32(&raw_pk) {
+				Some(pk_array) => pk_array,
+				None => {
+					log::warn!("Skipping a capsule fragment with a malformed box key");
+					continue;
+				}
+			};
 			let cap_pk = BoxPublicKey::from(*pk_array);
-			let decrypted_capsule_vec = iris_primitives::decrypt_x25519(
+			let decrypted_capsule_vec = match iris_primitives::decrypt_x25519(
 				cap_pk,
 				x25519_sk.clone(),
 				enc_cap_frag.ciphertext.clone(),
 				enc_cap_frag.nonce.clone(),
-			).unwrap();
-			let cfrag = CapsuleFrag::from_bytes(decrypted_capsule_vec).unwrap();
+			) {
+				Ok(bytes) => bytes,
+				Err(_) => {
+					log::warn!("Skipping a capsule fragment that could not be opened");
+					continue;
+				}
+			};
 			// verify each capsule fragment
-			let verified_cfrag = cfrag
-				.verify(&capsule, &verifying_pk, &delegating_pk, &ephemeral_pk)
-				.unwrap();
+			let verified_cfrag = match Self::verify_capsule_fragment(
+				decrypted_capsule_vec, &capsule, &verifying_pk, &delegating_pk, &ephemeral_pk,
+			) {
+				Some(verified_cfrag) => verified_cfrag,
+				None => {
+					log::warn!("Skipping a capsule fragment that failed verification");
+					continue;
+				}
+			};
 			verified_capsule_fragments.push(verified_cfrag);
 		}
+		if verified_capsule_fragments.len() < KFRAG_THRESHOLD {
+			return Err(DecryptionFailure::NotEnoughFragments {
+				have: verified_capsule_fragments.len() as u32,
+				need: KFRAG_THRESHOLD as u32,
+			});
+		}
 		// ----------------
 		// the public key associated with secret that encrypted the cfrags
 		// TODO: need to make better utility funcs to shorten this
-		let pk = iris_primitives::vec_to_box_public_key(&reencryption_artifact.secret.public_key);
+		let pk = iris_primitives::vec_to_box_public_key(&reencryption_artifact.secret.public_key)
+			.ok_or(DecryptionFailure::DecryptionFailed)?;
 		// recover secret key created by PROXY node and whose PK was used to generate kfrags
 		let decrypted_tpre_sk_bytes = iris_primitives::decrypt_x25519(
 			pk,
@@ -486,24 +1155,169 @@ impl<T: Config> Pallet<T> {
 			reencryption_artifact.secret.ciphertext.clone(),
 			reencryption_artifact.secret.nonce,
 		).unwrap();
-		let decrypted_sk = SecretKey::from_bytes(decrypted_tpre_sk_bytes).unwrap();
+		Ok(DecryptionKey {
+			secret_key: decrypted_tpre_sk_bytes,
+			capsule_fragments: verified_capsule_fragments.iter()
+				.map(|cfrag| cfrag.to_array().as_slice().to_vec())
+				.collect(),
+			fingerprint,
+		})
+	}
+
+	/// Verify an opened capsule fragment against the capsule and the keys used to generate it
+	/// 
+	/// * `capsule_fragment`: The opened capsule fragment
+	/// * `capsule`: The capsule created when the data was encrypted
+	/// * `verifying_pk`: The public key of the proxy's signer that generated the key fragments
+	/// * `delegating_pk`: The public key of the encrypted data
+	/// * `receiving_pk`: The public key the fragment was reencrypted for
+	/// 
+	fn verify_capsule_fragment(
+		capsule_fragment: Vec<u8>,
+		capsule: &Capsule,
+		verifying_pk: &PublicKey,
+		delegating_pk: &PublicKey,
+		receiving_pk: &PublicKey,
+	) -> Option<VerifiedCapsuleFrag> {
+		CapsuleFrag::from_bytes(capsule_fragment)
+			.ok()
+			.and_then(|cfrag| cfrag.verify(capsule, verifying_pk, delegating_pk, receiving_pk).ok())
+	}
+
+	/// Count a reported capsule fragment towards the reputation of the proxy that delivered it
+	/// 
+	/// * `proxy`: The proxy that delivered the fragment
+	/// * `valid`: Whether the fragment passed verification
+	/// 
+	fn record_fragment_verification(proxy: &T::AccountId, valid: bool) {
+		ProxyReputation::<T>::mutate(proxy, |stats| {
+			if valid {
+				stats.valid_fragments = stats.valid_fragments.saturating_add(1);
+			} else {
+				stats.invalid_fragments = stats.invalid_fragments.saturating_add(1);
+			}
+		});
+	}
+
+	/// Decrypt reencrypted data with key material recovered by `recover_decryption_key`.
+	/// Returns None if the key no longer matches the caller's reencryption artifacts,
+	/// if fewer than the threshold of the caller's capsule fragments remain on chain, or if
+	/// the caller's access grant expired, and `DecryptionFailure::DecryptionFailed` if the
+	/// ciphertext fails authentication.
+	/// 
+	/// * `account_id`: The account id of the caller requesting decryption.
+	/// * `ciphertext`: The ciphertext to be decrypted
+	/// * `delegating_public_key`: The public key of the encrypted data
+	/// * `key`: The recovered key material
+	/// 
+	fn apply_decryption_key(
+		account_id: T::AccountId,
+		ciphertext: Vec<u8>,
+		delegating_public_key: Vec<u8>,
+		key: &DecryptionKey,
+	) -> Option<Result<Bytes, DecryptionFailure>> {
+		if Self::access_expired(&account_id, &delegating_public_key) {
+			return None;
+		}
+		let reencryption_artifact = ReencryptionArtifacts::<T>::get(
+			account_id.clone(), delegating_public_key.clone(),
+		)?;
+		if key.fingerprint != Self::reencryption_fingerprint(&delegating_public_key, &reencryption_artifact) {
+			return None;
+		}
+		let delivered = EncryptedCapsuleFrags::<T>::decode_len(account_id, delegating_public_key.clone())
+			.unwrap_or(0);
+		if delivered < KFRAG_THRESHOLD {
+			return None;
+		}
+		let encryption_artifact = EncryptionArtifacts::<T>::get(delegating_public_key.clone())?;
+		let capsule = Capsule::from_bytes(&encryption_artifact.capsule).ok()?;
+		let delegating_pk = PublicKey::from_bytes(delegating_public_key).ok()?;
+		let decrypted_sk = SecretKey::from_bytes(key.secret_key.clone()).ok()?;
+		let verified_capsule_fragments = key.capsule_fragments.iter()
+			.map(|cfrag| VerifiedCapsuleFrag::from_verified_bytes(cfrag).ok())
+			.collect::<Option<Vec<_>>>()?;
 		// ----------------
 		// here, the secret key should be the secret key whose pk was used to generate kfrags
 		// and the pub key should be the one whose sk created the frags
-		let plaintext = match umbral_pre::decrypt_reencrypted(
+		Some(Self::decrypt_with_fragments(
 			&decrypted_sk,
 			&delegating_pk,
 			&capsule,
-			verified_capsule_fragments, 
+			verified_capsule_fragments,
 			ciphertext,
-		) {
-			Ok(plaintext) => plaintext.to_vec(),
-			Err(e) => {
-				log::error!("An error occurred while decrypting the provided ciphertext: {:?}", e);
-				"".as_bytes().to_vec()
-			}
-		};
-		Bytes::from(plaintext)
+		).map(Bytes::from))
+	}
+
+	/// Decrypt reencrypted data with verified capsule fragments. The ciphertext is
+	/// authenticated, so a key, capsule or ciphertext that don't belong together
+	/// fail with `DecryptionFailure::DecryptionFailed` rather than produce garbage.
+	/// 
+	/// * `receiving_sk`: The secret key whose public key the key fragments were generated for
+	/// * `delegating_pk`: The public key of the encrypted data
+	/// * `capsule`: The capsule created when the data was encrypted
+	/// * `capsule_fragments`: The verified capsule fragments
+	/// * `ciphertext`: The ciphertext to be decrypted
+	/// 
+	fn decrypt_with_fragments(
+		receiving_sk: &SecretKey,
+		delegating_pk: &PublicKey,
+		capsule: &Capsule,
+		capsule_fragments: Vec<VerifiedCapsuleFrag>,
+		ciphertext: Vec<u8>,
+	) -> Result<Vec<u8>, DecryptionFailure> {
+		umbral_pre::decrypt_reencrypted(
+			receiving_sk,
+			delegating_pk,
+			capsule,
+			capsule_fragments,
+			ciphertext,
+		)
+		.map(|plaintext| plaintext.to_vec())
+		.map_err(|e| {
+			log::error!("An error occurred while decrypting the provided ciphertext: {:?}", e);
+			DecryptionFailure::DecryptionFailed
+		})
+	}
+
+	/// A hash of the reencryption artifacts issued to a caller for some data. It changes
+	/// whenever the caller is issued new artifacts.
+	fn reencryption_fingerprint(
+		delegating_public_key: &[u8],
+		artifact: &ReencryptionArtifact<T::AccountId>,
+	) -> [u8; 32] {
+		sp_io::hashing::blake2_256(&(
+			delegating_public_key,
+			&artifact.verifying_key,
+			&artifact.ephemeral_public_key,
+			&artifact.secret,
+		).encode())
+	}
+
+	/// Stage the data public key for ingestion and encode the encryption artifacts on chain
+	/// 
+	/// * `owner`: The owner of the encrypted data
+	/// * `proxy`: The proxy assigned to process reencryption requests
+	/// * `capsule`: The capsule created when the data was encrypted
+	/// * `public_key`: The data public key
+	/// * `encrypted_sk_box`: The data secret key, encrypted for the proxy
+	/// 
+	fn stage_encryption_artifacts(
+		owner: T::AccountId,
+		proxy: T::AccountId,
+		capsule: Vec<u8>,
+		public_key: Vec<u8>,
+		encrypted_sk_box: EncryptedBox,
+	) -> DispatchResult {
+		// rejects malformed public keys, so stage first
+		T::QueueManager::add_ingestion_staging(owner, public_key.clone())?;
+		EncryptionArtifacts::<T>::insert(public_key.clone(), TPREEncryptionArtifact {
+			capsule,
+			proxy: proxy.clone(),
+		});
+		ProxyCodes::<T>::insert(proxy, public_key, encrypted_sk_box);
+		Self::deposit_event(Event::EncryptionArtifactsSubmitted);
+		Ok(())
 	}
 
 	/// TODO: look at client\network\src\config.rs for sk generation/storage + write to file
@@ -514,6 +1328,7 @@ impl<T: Config> Pallet<T> {
 	/// * `signer`: The signing account id
 	/// * `message`: The signed message
 	/// * `proxy`: A proxy node's account id
+	/// * `seed`: A seed drawn from the node's entropy source, used to generate the data keys
 	/// 
     pub fn encrypt(
         plaintext: Bytes,
@@ -521,43 +1336,88 @@ impl<T: Config> Pallet<T> {
         signer: Bytes,
         message: Bytes,
         proxy: Bytes,
-    ) -> Bytes {
+        seed: [u8; 32],
+    ) -> Result<EncryptionResult, EncryptionFailure> {
+        // never derive keys from a missing seed
+        if seed == [0u8; 32] {
+            return Err(EncryptionFailure::MissingSeed);
+        }
+
         let proxy_acct_bytes: [u8;32] = proxy.to_vec().try_into().unwrap();
         let proxy_acct_id: T::AccountId = T::AccountId::decode(&mut &proxy_acct_bytes[..]).unwrap();
 
-        let acct_bytes: [u8;32] = signer.to_vec().try_into().unwrap();
-        let acct_pubkey = Public::from_raw(acct_bytes);
-
-        let sig: Signature = Signature::from_slice(signature.to_vec().as_ref()).unwrap();
-        let msg: Vec<u8> = message.to_vec();
-
-        let acct_id: T::AccountId = T::AccountId::decode(&mut &acct_bytes[..]).unwrap();
-
-        if sig.verify(msg.as_slice(), &acct_pubkey) {
-            let plaintext_as_slice: &[u8] = &plaintext;
-			return Self::do_encrypt(plaintext_as_slice, acct_id, proxy_acct_id);
-        }
-
-        Bytes::from(b"Signature verification failed".to_vec())
+        let acct_id = Self::verified_signer(signature, signer, message)
+            .ok_or(EncryptionFailure::InvalidSignature)?;
+        let plaintext_as_slice: &[u8] = &plaintext;
+        Self::do_encrypt(plaintext_as_slice, acct_id, proxy_acct_id, seed)
     }
 
+	/// Recover the signing account and encrypt the data as `encrypt` does, but without staging
+	/// anything on chain. The signer stages the returned artifacts along with their ingestion
+	/// request with `encrypt_and_request`. The arguments are as for `encrypt`.
+	/// 
+	pub fn encrypt_for_request(
+		plaintext: Bytes,
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+		proxy: Bytes,
+		seed: [u8; 32],
+	) -> Result<UnstagedEncryption, EncryptionFailure> {
+		if seed == [0u8; 32] {
+			return Err(EncryptionFailure::MissingSeed);
+		}
+		let proxy_acct_bytes: [u8; 32] = proxy.to_vec().try_into()
+			.map_err(|_| EncryptionFailure::NoProxyKey)?;
+		let proxy_acct_id = T::AccountId::decode(&mut &proxy_acct_bytes[..])
+			.map_err(|_| EncryptionFailure::NoProxyKey)?;
+		Self::verified_signer(signature, signer, message)
+			.ok_or(EncryptionFailure::InvalidSignature)?;
+		Self::do_encrypt_unstaged(&plaintext, proxy_acct_id, seed)
+	}
+
 	fn do_encrypt(
 		plaintext: &[u8],
 		owner_account_id: T::AccountId,
 		proxy_account_id: T::AccountId,
-	) -> Bytes {
+		seed: [u8; 32],
+	) -> Result<EncryptionResult, EncryptionFailure> {
+		let unstaged = Self::do_encrypt_unstaged(plaintext, proxy_account_id.clone(), seed)?;
+		let call = Call::submit_encryption_artifacts { 
+			owner: owner_account_id,
+			proxy: proxy_account_id,
+			capsule: unstaged.result.capsule.clone(),
+			public_key: unstaged.result.public_key.clone(),
+			encrypted_sk_box: unstaged.encrypted_sk_box,
+		};
+
+		match SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into()) {
+			Ok(_) => Ok(unstaged.result),
+			Err(e) => {
+				log::error!("Something went wrong while submitting the unsigned transaction: {:?}", e);
+				Err(EncryptionFailure::SubmissionFailed)
+			}
+		}
+	}
+
+	/// Encrypt the data with freshly generated keys, and the data secret key for the proxy
+	/// 
+	/// * `plaintext`: the plaintext to encrypt
+	/// * `proxy_account_id`: the proxy whose x25519 public key the data secret key is encrypted for
+	/// * `seed`: the seed to generate the data keys from
+	/// 
+	fn do_encrypt_unstaged(
+		plaintext: &[u8],
+		proxy_account_id: T::AccountId,
+		seed: [u8; 32],
+	) -> Result<UnstagedEncryption, EncryptionFailure> {
 		let proxy_pk_vec = pallet_authorities::Pallet::<T>::x25519_public_keys(proxy_account_id.clone());
-		// TODO: Error handling! Only works if proxy has a key
-		let proxy_pk_slice = iris_primitives::slice_to_array_32(&proxy_pk_vec).unwrap();
+		let proxy_pk_slice = iris_primitives::slice_to_array_32(&proxy_pk_vec)
+			.ok_or(EncryptionFailure::NoProxyKey)?;
 		let proxy_pk = BoxPublicKey::from(*proxy_pk_slice);
 		
-		let phrase = b"iris encryption";
-		let (seed, _) = T::Randomness::random(phrase);
-		// seed needs to be guaranteed to be 32 bytes.
-		let seed = <[u8; 32]>::decode(&mut TrailingZeroInput::new(seed.as_ref()))
-			.expect("input is padded with zeroes; qed");
 		let mut rng = ChaCha20Rng::from_seed(seed);
-		let sk = SecretKey::random_with_rng(rng.clone());
+		let sk = SecretKey::random_with_rng(&mut rng);
 		let pk = sk.public_key();
 		let (capsule, ciphertext) = match umbral_pre::encrypt_with_rng(
 			&mut rng, &pk, plaintext)
@@ -565,45 +1425,41 @@ impl<T: Config> Pallet<T> {
 			Ok((capsule, ciphertext)) => (capsule, ciphertext),
 			Err(e) => {
 				log::error!("Something went wrong while encrypting the data: {:?}", e);
-				return Bytes::from("".as_bytes().to_vec());
-				// return Err(error);
+				return Err(EncryptionFailure::EncryptionFailed);
 			}
 		};
 	
 		let sk_bytes = sk.to_secret_array().as_secret().to_vec();
-		let encrypted_sk = encrypt_x25519(proxy_pk, sk_bytes);
-		let call = Call::submit_encryption_artifacts { 
-			owner: owner_account_id,
-			proxy: proxy_account_id,
-			capsule: capsule.clone().to_array().as_slice().to_vec(),
-			public_key: pk.clone().to_array().as_slice().to_vec(),
-			encrypted_sk_box: encrypted_sk,
-		};
-
-		match SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into()) {
-			Ok(_) => {
-				Bytes::from(ciphertext.to_vec())
+		let encrypted_sk = encrypt_x25519(proxy_pk, sk_bytes, &mut rng);
+		let capsule = capsule.to_array().as_slice().to_vec();
+		let public_key = pk.to_array().as_slice().to_vec();
+		Ok(UnstagedEncryption {
+			result: EncryptionResult {
+				ciphertext: ciphertext.to_vec(),
+				public_key,
+				capsule,
 			},
-			Err(e) => {
-				log::error!("Something went wrong while submitting the unsigned transaction: {:?}", e);
-				Bytes::from(b"An error occurred while submitting the unsigned tx. Please try again. If the issue persists, restart your node and try again.".to_vec())
-			}
-		}
-	
+			encrypted_sk_box: encrypted_sk,
+		})
 	}
 
 	/// A proxy processes requests to generate kfrags for an authorized caller
 	/// 
 	/// * `account`: The account of the proxy node to execute commands and submit results
+	/// * `candidates`: The authorities the key fragments may be assigned to
+	/// * `seed`: Secure entropy for the generated keys and fragments
 	///
 	fn proxy_process_kfrag_generation_requests(
 		account: T::AccountId,
 		candidates: Vec<T::AccountId>,
+		seed: [u8; 32],
 	) -> Result<(), Error<T>> {
+		ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
 		let kfrag_generation_requests = KeyFragGenerationRequests::<T>::get(account.clone());
 		let secret_storage = StorageValueRef::persistent(b"iris::x25519");
 		if let Ok(Some(local_sk)) = secret_storage.get::<[u8;32]>() {
 			let local_secret_key: BoxSecretKey = BoxSecretKey::from(local_sk);
+			let mut rng = ChaCha20Rng::from_seed(seed);
 			for request in kfrag_generation_requests.into_iter() {
 				// ---------
 				// 1. recover secret key needed to generate kfrags
@@ -611,11 +1467,10 @@ impl<T: Config> Pallet<T> {
 					account.clone(), 
 					request.data_public_key.clone()
 				).unwrap();
-				ensure!(encrypted_delegating_sk.public_key.len() == 32, Error::<T>::InvalidPublicKeyLength);
 				// convert pk vec to BoxPublicKey
 				let encrypted_sk_pub_key: BoxPublicKey = iris_primitives::vec_to_box_public_key(
-					&encrypted_delegating_sk.public_key.clone()
-				);
+					&encrypted_delegating_sk.public_key
+				).ok_or(Error::<T>::InvalidPublicKeyLength)?;
 				// TODO ERROR HANDLING?
 				let delegating_sk_bytes = iris_primitives::decrypt_x25519(
 					encrypted_sk_pub_key.clone(),
@@ -626,55 +1481,51 @@ impl<T: Config> Pallet<T> {
 				let delegating_secret_key = SecretKey::from_bytes(delegating_sk_bytes).unwrap();
 				// ---------
 				// generate new key pair 
-				
-				let phrase = b"iris reencapsulation";
-				let (seed, _) = T::Randomness::random(phrase);
-				// seed needs to be guaranteed to be 32 bytes.
-				let seed = <[u8; 32]>::decode(&mut TrailingZeroInput::new(seed.as_ref()))
-					.expect("input is padded with zeroes; qed");
-				let mut rng = ChaCha20Rng::from_seed(seed);
-
 				let signer = umbral_pre::Signer::new(delegating_secret_key.clone());
-				let receiving_sk = SecretKey::random_with_rng(rng.clone());
+				let receiving_sk = SecretKey::random_with_rng(&mut rng);
 				let receiving_pk = receiving_sk.public_key();
 				// generate kfrags
-				// TODO: store/pass threshold + shares values? hard coded at {3, 2} currently
+				// TODO: store/pass threshold + shares values per request?
 				let kfrags = generate_kfrags_with_rng(
 				    &mut rng, 
 					&delegating_secret_key.clone(), // this is the original SK generated by the data owner
 					&receiving_pk.clone(), // newly generated ephemeral public key
 					&signer, 
-					2, 3, true, true
+					KFRAG_THRESHOLD, KFRAG_SHARES, true, true
 				);
 				// ----------
 				let mut assignments = Vec::new();
 				let required_authorities_count = kfrags.len();
+				let request_id = Self::reencryption_request_id(&request.caller, &request.data_public_key);
+				let holders = T::AssignmentStrategy::assign_reencryption(
+					&request_id, candidates.clone(), required_authorities_count,
+				);
 				// this seems like it should come a lot earlier, or not be made here at all
 				// still need to refactor
-				ensure!(candidates.len() >= required_authorities_count, Error::<T>::InsufficientAuthorities);
-				for i in 0..required_authorities_count {
-					let candidate = candidates[i].clone();
+				ensure!(holders.len() >= required_authorities_count, Error::<T>::InsufficientAuthorities);
+				for (i, candidate) in holders.into_iter().take(required_authorities_count).enumerate() {
 					// get x25519 pk
 					let recipient_pk_vec = pallet_authorities::Pallet::<T>::x25519_public_keys(candidate.clone());
-					let recipient_pk = iris_primitives::vec_to_box_public_key(&recipient_pk_vec);
+					let recipient_pk = iris_primitives::vec_to_box_public_key(&recipient_pk_vec)
+						.ok_or(Error::<T>::InvalidPublicKeyLength)?;
 					let key_fragment = kfrags[i].clone()
 						.unverify().to_array()
 						.as_slice().to_vec();
 					// Do I really need to do this?
 					let encrypted_kfrag_data = iris_primitives::encrypt_x25519(
-						recipient_pk.clone(), key_fragment,
+						recipient_pk.clone(), key_fragment, &mut rng,
 					);
 					assignments.push((candidate.clone(), encrypted_kfrag_data.clone()));
 				}
 				// passed as arg
 				let recipient_pk: BoxPublicKey = iris_primitives::vec_to_box_public_key(
-					&request.consumer_public_key.clone()
-				);
+					&request.consumer_public_key
+				).ok_or(Error::<T>::InvalidPublicKeyLength)?;
 				let receiving_sk_bytes = receiving_sk.to_secret_array()
 					.as_secret()
 					.to_vec();
 				let encrypted_ephem_sk_artifacts = iris_primitives::encrypt_x25519(
-					recipient_pk, receiving_sk_bytes,
+					recipient_pk, receiving_sk_bytes, &mut rng,
 				);
 				// ----------
 				// send signed tx to encode this on chain (potentially acting in capacity of proxy (substrate version))
@@ -701,16 +1552,24 @@ impl<T: Config> Pallet<T> {
 	}
 
 	/// kfrag holders execute this logic to reencrypt for a caller
+	/// 
+	/// * `account`: The account of the kfrag holder
+	/// * `seed`: Secure entropy for the reencryption and the encrypted capsule fragments
+	/// 
 	fn kfrag_holder_process_reencryption_requests(
 		account: T::AccountId,
+		seed: [u8; 32],
 	) -> Result<(), Error<T>> {
+		ensure!(!T::QueueManager::is_halted(), Error::<T>::Halted);
 		let reencryption_requests = 
 			CapsuleFragmentGenerationRequests::<T>::get(account.clone());
 		
 		let secret_storage = StorageValueRef::persistent(b"iris::x25519");
 		// only proceed if we have the secret key
 		if let Ok(Some(local_sk)) = secret_storage.get::<[u8;32]>() {
+			let mut fragments = Vec::new();
 			let local_secret_key: BoxSecretKey = BoxSecretKey::from(local_sk);
+			let mut rng = ChaCha20Rng::from_seed(seed);
 			// each request contains (caller (consumer), data_public_key, caller_public_key)
 			for request in reencryption_requests.iter() {
 				// ----------
@@ -751,12 +1610,6 @@ impl<T: Config> Pallet<T> {
 				let verified_kfrag = kfrag.verify(&verifying_pk, Some(&data_public_key), Some(&consumer_public_key)).unwrap();
 				// ----------
 				// create capsule fragment
-				let phrase = b"iris reencryption";
-				let (seed, _) = T::Randomness::random(phrase);
-				// seed needs to be guaranteed to be 32 bytes.
-				let seed = <[u8; 32]>::decode(&mut TrailingZeroInput::new(seed.as_ref()))
-					.expect("input is padded with zeroes; qed");
-				let mut rng = ChaCha20Rng::from_seed(seed);
 				// recover capsule and pk (created by data owner)
 				let capsule_data = encryption_artifacts.capsule.clone();
 				let capsule = Capsule::from_bytes(&capsule_data).unwrap();
@@ -769,27 +1622,30 @@ impl<T: Config> Pallet<T> {
 				let caller_pk = BoxPublicKey::from(*caller_pk_array);
 
 				let encrypted_cfrag_data = iris_primitives::encrypt_x25519(
-					caller_pk, cfrag_bytes,
+					caller_pk, cfrag_bytes, &mut rng,
 				);
-				// ----------
-				// send signed tx to encode this on chain (potentially acting in capacity of proxy (substrate version))
-				let tx_signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
-				if !tx_signer.can_sign() {
-					log::error!(
-						"No local accounts available. Consider adding one via `author_insertKey` RPC.",
-					);
-				}
-				let results = tx_signer.send_signed_transaction(|_acct| { 
-					Call::submit_capsule_fragment {
-						data_consumer: request.caller.clone(),
-						public_key: request.data_public_key.clone(),
-						encrypted_cfrag_data: encrypted_cfrag_data.clone(),
-					}
+				fragments.push((request.caller.clone(), request.data_public_key.clone(), encrypted_cfrag_data));
+			}
+			if fragments.is_empty() {
+				return Ok(());
+			}
+			// ----------
+			// send signed txs to encode the fragments on chain, batched to save transactions
+			// (potentially acting in capacity of proxy (substrate version))
+			let tx_signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
+			if !tx_signer.can_sign() {
+				log::error!(
+					"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+				);
+			}
+			let batch_size = T::MaxFragmentBatch::get().max(1) as usize;
+			for batch in fragments.chunks(batch_size) {
+				let results = tx_signer.send_signed_transaction(|_acct| {
+					Call::submit_fragments { fragments: batch.to_vec() }
 				});
-			
 				for (_, res) in &results {
 					match res {
-						Ok(()) => log::info!("Submitted results successfully"),
+						Ok(()) => log::info!("Submitted {} capsule fragments successfully", batch.len()),
 						Err(e) => log::error!("Failed to submit transaction: {:?}",  e),
 					}
 				}
@@ -799,6 +1655,162 @@ impl<T: Config> Pallet<T> {
 		Ok(())
 	}
 
+	/// Run the self test if the proxy requested one and report the result on chain.
+	/// 
+	/// * `account`: The proxy account of the local node
+	/// * `seed`: Secure entropy for the self test's keys
+	/// 
+	fn proxy_process_self_test(account: T::AccountId, seed: [u8; 32]) {
+		if !SelfTestRequests::<T>::get(account.clone()) {
+			return;
+		}
+		let passed = match Self::do_self_test(account, seed) {
+			Ok(()) => true,
+			Err(e) => {
+				log::error!("The proxy self test failed: {:?}", e);
+				false
+			},
+		};
+		let tx_signer = Signer::<T, <T as pallet::Config>::AuthorityId>::all_accounts();
+		if !tx_signer.can_sign() {
+			log::error!(
+				"No local accounts available. Consider adding one via `author_insertKey` RPC.",
+			);
+		}
+		let results = tx_signer.send_signed_transaction(|_acct| {
+			Call::submit_self_test_result { passed }
+		});
+		for (_, res) in &results {
+			if let Err(e) = res {
+				log::error!("Failed to submit transaction: {:?}",  e);
+			}
+		}
+	}
+
+	/// Execute the proxy self test: round trip some plaintext through the node's x25519 
+	/// secret key and through umbral encryption, kfrag generation, reencryption,
+	/// capsule fragment verification and decryption. Must be called from an offchain context.
+	/// 
+	/// * `who`: The proxy running the self test
+	/// * `seed`: Secure entropy for the self test's keys
+	/// 
+	fn do_self_test(who: T::AccountId, seed: [u8; 32]) -> Result<(), Error<T>> {
+		// the node's x25519 secret key must match the public key the proxy published
+		let box_pk_vec = pallet_authorities::Pallet::<T>::x25519_public_keys(who);
+		ensure!(box_pk_vec.len() == BOX_PUBLIC_KEY_SIZE, Error::<T>::SelfTestFailed);
+		let secret_storage = StorageValueRef::persistent(b"iris::x25519");
+		let local_sk = secret_storage.get::<[u8; 32]>()
+			.ok()
+			.flatten()
+			.ok_or(Error::<T>::SelfTestFailed)?;
+		let box_sk = BoxSecretKey::from(local_sk);
+		ensure!(box_sk.public_key().as_bytes().to_vec() == box_pk_vec, Error::<T>::SelfTestFailed);
+
+		let mut rng = ChaCha20Rng::from_seed(seed);
+		let plaintext = b"iris proxy self test".to_vec();
+
+		// box keys
+		let box_pk = iris_primitives::vec_to_box_public_key(&box_pk_vec).ok_or(Error::<T>::SelfTestFailed)?;
+		let encrypted = encrypt_x25519(box_pk, plaintext.clone(), &mut rng);
+		let sender_pk_array = iris_primitives::slice_to_array_32(&encrypted.public_key)
+			.ok_or(Error::<T>::SelfTestFailed)?;
+		let recovered_box_plaintext = iris_primitives::decrypt_x25519(
+			BoxPublicKey::from(*sender_pk_array),
+			box_sk,
+			encrypted.ciphertext.clone(),
+			encrypted.nonce.clone(),
+		).map_err(|_| Error::<T>::SelfTestFailed)?;
+		ensure!(recovered_box_plaintext == plaintext, Error::<T>::SelfTestFailed);
+
+		// umbral
+		let delegating_sk = SecretKey::random_with_rng(&mut rng);
+		let delegating_pk = delegating_sk.public_key();
+		let receiving_sk = SecretKey::random_with_rng(&mut rng);
+		let receiving_pk = receiving_sk.public_key();
+		let signer = umbral_pre::Signer::new(delegating_sk.clone());
+		let (capsule, ciphertext) = umbral_pre::encrypt_with_rng(&mut rng, &delegating_pk, &plaintext)
+			.map_err(|_| Error::<T>::SelfTestFailed)?;
+		let kfrags = generate_kfrags_with_rng(
+			&mut rng, &delegating_sk, &receiving_pk, &signer, 1, 1, true, true,
+		);
+		let mut verified_capsule_fragments: Vec<VerifiedCapsuleFrag> = Vec::new();
+		for kfrag in kfrags.iter() {
+			let cfrag_bytes = reencrypt_with_rng(&mut rng, &capsule, kfrag.clone())
+				.to_array().as_slice().to_vec();
+			let cfrag = CapsuleFrag::from_bytes(cfrag_bytes)
+				.map_err(|_| Error::<T>::SelfTestFailed)?;
+			let verified_cfrag = cfrag
+				.verify(&capsule, &signer.verifying_key(), &delegating_pk, &receiving_pk)
+				.map_err(|_| Error::<T>::SelfTestFailed)?;
+			verified_capsule_fragments.push(verified_cfrag);
+		}
+		let recovered_plaintext = umbral_pre::decrypt_reencrypted(
+			&receiving_sk,
+			&delegating_pk,
+			&capsule,
+			verified_capsule_fragments,
+			ciphertext,
+		).map_err(|_| Error::<T>::SelfTestFailed)?;
+		ensure!(recovered_plaintext.to_vec() == plaintext, Error::<T>::SelfTestFailed);
+		Ok(())
+	}
+
+	/// Remove capsule fragments that expire at the given block, along with every fragment
+	/// delivered under an access grant that expires at it. At most `MaxExpiredFragmentsPerBlock`
+	/// entries are processed, any entries that can't be processed are carried over to the next block.
+	/// 
+	/// * `now`: The current block number
+	/// * `remaining_weight`: The weight available to the sweep
+	/// 
+	fn expire_capsule_fragments(now: T::BlockNumber, remaining_weight: Weight) -> Weight {
+		let db_weight = T::DbWeight::get();
+		let mut consumed = db_weight.reads(1);
+		let expiring: Vec<(T::AccountId, Vec<u8>)> = FragmentExpiries::<T>::drain_prefix(now)
+			.map(|(entry, _)| entry)
+			.collect();
+		if expiring.is_empty() {
+			return consumed;
+		}
+		consumed = consumed.saturating_add(db_weight.writes(expiring.len() as u64));
+
+		let max_entries = T::MaxExpiredFragmentsPerBlock::get() as usize;
+		let entry_weight = db_weight.reads_writes(2, 3);
+		let mut carry_over = Vec::new();
+		for (index, (consumer, public_key)) in expiring.into_iter().enumerate() {
+			if index >= max_entries || consumed.saturating_add(entry_weight) > remaining_weight {
+				carry_over.push((consumer, public_key));
+				continue;
+			}
+			consumed = consumed.saturating_add(entry_weight);
+			if Self::access_expired_at(&consumer, &public_key, now) {
+				// the grant may have been revoked when an earlier entry for it expired
+				if Self::revoke_access(&consumer, &public_key) {
+					Self::deposit_event(Event::AccessExpired(consumer, public_key));
+				}
+				continue;
+			}
+			let cfrags = EncryptedCapsuleFrags::<T>::get(consumer.clone(), public_key.clone());
+			let unexpired: Vec<CapsuleFragment<T::AccountId, T::BlockNumber>> = cfrags.iter()
+				.filter(|f| f.expires_at > now)
+				.cloned()
+				.collect();
+			if unexpired.len() < cfrags.len() {
+				if unexpired.is_empty() {
+					EncryptedCapsuleFrags::<T>::remove(consumer.clone(), public_key.clone());
+				} else {
+					EncryptedCapsuleFrags::<T>::insert(consumer.clone(), public_key.clone(), unexpired);
+				}
+				Self::deposit_event(Event::FragmentsExpired(consumer, public_key));
+			}
+		}
+
+		consumed = consumed.saturating_add(db_weight.writes(carry_over.len() as u64));
+		for entry in carry_over {
+			FragmentExpiries::<T>::insert(now + One::one(), entry, ());
+		}
+		consumed
+	}
+
 	fn validate_transaction_parameters() -> TransactionValidity {
 		ValidTransaction::with_tag_prefix("iris")
 			.priority(2 << 20)
@@ -807,23 +1819,202 @@ impl<T: Config> Pallet<T> {
 			.build()
 	}
 
+	/// Validate and store a capsule fragment delivered by a proxy. Every check is made
+	/// before anything is written, so a rejected fragment leaves storage untouched.
+	/// 
+	/// * `who`: The proxy delivering the fragment
+	/// * `data_consumer`: The account for which the capsule fragment has been created
+	/// * `public_key`: The unique public key that identifies the encrypted data
+	/// * `encrypted_cfrag_data`: The encrypted capsule fragment
+	/// 
+	fn do_submit_capsule_fragment(
+		who: T::AccountId,
+		data_consumer: T::AccountId,
+		public_key: Vec<u8>,
+		encrypted_cfrag_data: EncryptedBox,
+	) -> DispatchResult {
+		ensure!(!Self::access_expired(&data_consumer, &public_key), Error::<T>::AccessExpired);
+		let holder = ReencryptionArtifacts::<T>::get(&data_consumer, &public_key)
+			.map(|artifact| artifact.verified_kfrags.iter().any(|(proxy, _)| *proxy == who))
+			.unwrap_or(false);
+		ensure!(holder, Error::<T>::NotAKfragHolder);
+		let mut expires_at = <frame_system::Pallet<T>>::block_number() + T::FragmentTTL::get();
+		if let Some(grant_expires_at) = GrantExpiries::<T>::get(&data_consumer, &public_key) {
+			expires_at = expires_at.min(grant_expires_at);
+		}
+		// should there be any verification that this public key was generated via the encryption? probably
+		let box_key_version = pallet_authorities::Pallet::<T>::box_key_version(who.clone());
+		let fragment = CapsuleFragment {
+			encrypted_cfrag: encrypted_cfrag_data,
+			proxy: who.clone(),
+			box_key_version,
+			expires_at,
+			reported: false,
+		};
+		let (delivered, replaced) = EncryptedCapsuleFrags::<T>::try_mutate(data_consumer.clone(), public_key.clone(), |cfrags| -> Result<_, Error<T>> {
+			// a fragment under an older box key is replaced rather than counted again
+			match cfrags.iter_mut().find(|f| f.proxy == who && f.box_key_version != box_key_version) {
+				Some(stale) => {
+					let stale_expires_at = stale.expires_at;
+					*stale = fragment;
+					Ok((cfrags.len(), Some(stale_expires_at)))
+				},
+				None => {
+					ensure!(!cfrags.iter().any(|f| f.proxy == who), Error::<T>::FragmentAlreadyDelivered);
+					ensure!(
+						cfrags.len() < T::MaxFragmentsPerRequest::get() as usize,
+						Error::<T>::FragmentLimitReached
+					);
+					cfrags.push(fragment);
+					Ok((cfrags.len(), None))
+				},
+			}
+		})?;
+		let redelivered = replaced.is_some();
+		if let Some(stale_expires_at) = replaced {
+			Self::unschedule_expiry(&data_consumer, &public_key, stale_expires_at);
+		}
+		if redelivered {
+			Self::deposit_event(Event::CapsuleFragmentRedelivered(
+				data_consumer.clone(), public_key.clone(), who.clone(),
+			));
+		}
+		// access is granted once the consumer holds enough fragments to decrypt
+		if !redelivered && delivered == KFRAG_THRESHOLD {
+			if let Some(asset_id) = GrantedAssets::<T>::get(data_consumer.clone(), public_key.clone()) {
+				AccessCount::<T>::mutate(asset_id, |count| *count = count.saturating_add(1));
+				T::AssetHistory::record(asset_id, AssetLifecycleEvent::Accessed, Some(data_consumer.clone()));
+			}
+		}
+		FragmentExpiries::<T>::insert(expires_at, (data_consumer, public_key.clone()), ());
+		// cleanup
+		CapsuleFragmentGenerationRequests::<T>::mutate(who, |reqs| {
+			reqs.retain(|r| *r.data_public_key != public_key.clone());
+		});
+		Self::deposit_event(Event::ReencapsulationComplete);
+		Ok(())
+	}
+
+	/// Whether a consumer's access grant for a public key has expired.
+	/// Grants without an expiry never expire.
+	/// 
+	/// * `consumer`: The consumer the grant was made to
+	/// * `public_key`: The public key of the encrypted data
+	/// 
+	pub fn access_expired(consumer: &T::AccountId, public_key: &Vec<u8>) -> bool {
+		Self::access_expired_at(consumer, public_key, <frame_system::Pallet<T>>::block_number())
+	}
+
+	fn access_expired_at(consumer: &T::AccountId, public_key: &Vec<u8>, now: T::BlockNumber) -> bool {
+		GrantExpiries::<T>::get(consumer, public_key)
+			.map(|expires_at| expires_at <= now)
+			.unwrap_or(false)
+	}
+
+	/// Remove a consumer's entry in the fragment expiry index at a block, unless one of its
+	/// fragments or its access grant still expires at that block
+	/// 
+	/// * `consumer`: The consumer the fragments were delivered to
+	/// * `public_key`: The public key of the encrypted data
+	/// * `expires_at`: The block the entry was scheduled for
+	/// 
+	fn unschedule_expiry(consumer: &T::AccountId, public_key: &Vec<u8>, expires_at: T::BlockNumber) {
+		let still_expiring = GrantExpiries::<T>::get(consumer, public_key) == Some(expires_at)
+			|| EncryptedCapsuleFrags::<T>::get(consumer, public_key).iter().any(|f| f.expires_at == expires_at);
+		if !still_expiring {
+			FragmentExpiries::<T>::remove(expires_at, (consumer.clone(), public_key.clone()));
+		}
+	}
+
+	/// Remove the capsule fragments delivered under an expired access grant, along with any
+	/// fragments still to be generated for it. The expiry is kept so that fragments delivered
+	/// late are rejected until the consumer is granted access again. Returns false if there
+	/// was no grant to revoke.
+	/// 
+	/// * `consumer`: The consumer the grant was made to
+	/// * `public_key`: The public key of the encrypted data
+	/// 
+	fn revoke_access(consumer: &T::AccountId, public_key: &Vec<u8>) -> bool {
+		for fragment in EncryptedCapsuleFrags::<T>::take(consumer, public_key) {
+			Self::unschedule_expiry(consumer, public_key, fragment.expires_at);
+		}
+		let granted = GrantedAssets::<T>::take(consumer, public_key).is_some();
+		if let Some(artifact) = ReencryptionArtifacts::<T>::get(consumer, public_key) {
+			for (holder, _) in artifact.verified_kfrags.iter() {
+				CapsuleFragmentGenerationRequests::<T>::mutate(holder, |reqs| {
+					reqs.retain(|r| r.caller != *consumer || r.data_public_key != *public_key);
+				});
+			}
+		}
+		granted
+	}
+
 	// what if public_key dne?
+	/// Grant a consumer access to an asset and ask its proxy to generate key fragments for them.
+	/// 
+	/// * `account`: The consumer
+	/// * `asset_id`: The asset access is granted to
+	/// * `data_public_key`: The public key of the encrypted data
+	/// * `consumer_public_key`: The consumer's x25519 public key
+	/// * `expires_at`: The block at which the consumer's access expires, or None if it doesn't
+	/// 
 	pub fn add_kfrag_request(
         account: T::AccountId,
+        asset_id: T::AssetId,
         data_public_key: Vec<u8>,
 		consumer_public_key: Vec<u8>,
+		expires_at: Option<T::BlockNumber>,
     ) {
         // NOTE: this assumes there's at least one proxy available.
         // TODO: revisit this when testing
         let proxy = EncryptionArtifacts::<T>::get(data_public_key.clone()).unwrap().proxy;
+        GrantedAssets::<T>::insert(account.clone(), data_public_key.clone(), asset_id);
+		let previous_expiry = GrantExpiries::<T>::take(account.clone(), data_public_key.clone());
+		if let Some(expires_at) = expires_at {
+			GrantExpiries::<T>::insert(account.clone(), data_public_key.clone(), expires_at);
+			FragmentExpiries::<T>::insert(expires_at, (account.clone(), data_public_key.clone()), ());
+		}
+		// the previous grant no longer expires
+		if let Some(previous_expiry) = previous_expiry {
+			Self::unschedule_expiry(&account, &data_public_key, previous_expiry);
+		}
         KeyFragGenerationRequests::<T>::mutate(proxy, |pks| {
             pks.push(KeyFragGenerationRequest {
                 caller: account,
                 data_public_key: data_public_key.clone(),
 				consumer_public_key: consumer_public_key.clone(),
+				asset_id,
             });
         });
     }
+
+	/// The id of a reencryption request. A caller has at most one pending request
+	/// per public key, so the pair identifies it.
+	/// 
+	/// * `caller`: The account that requested reencryption
+	/// * `data_public_key`: The public key of the data to reencrypt
+	/// 
+	pub fn reencryption_request_id(caller: &T::AccountId, data_public_key: &[u8]) -> [u8; 32] {
+		sp_io::hashing::blake2_256(&(caller, data_public_key).encode())
+	}
+
+	/// The reencryption requests queued with a proxy that it has not yet served,
+	/// as (request id, caller, asset id) in the order they were queued.
+	/// 
+	/// * `proxy`: The proxy the requests are assigned to
+	/// 
+	pub fn pending_reencryptions(
+		proxy: T::AccountId,
+	) -> Vec<([u8; 32], T::AccountId, T::AssetId)> {
+		KeyFragGenerationRequests::<T>::get(proxy)
+			.into_iter()
+			.map(|r| (
+				Self::reencryption_request_id(&r.caller, &r.data_public_key),
+				r.caller,
+				r.asset_id,
+			))
+			.collect()
+	}
 }
 
 pub trait OffchainKeyManager<AccountId> {
@@ -832,20 +2023,35 @@ pub trait OffchainKeyManager<AccountId> {
 		candidates: Vec<AccountId>,
 	);
 	fn process_reencryption_requests(account: AccountId);
+	fn process_self_test(account: AccountId);
 }
 
+// offchain workers are the only callers, so the node's entropy source is available to seed
+// the keys and fragments they generate
 impl<T: Config> OffchainKeyManager<T::AccountId> for Pallet<T> {
 	fn process_decryption_delegation(
 		account: T::AccountId,
 		candidates: Vec<T::AccountId>
 	) {
+		if T::QueueManager::is_halted() {
+			log::info!("Reencryption is halted, skipping kfrag generation requests");
+			return;
+		}
 		// TODO: proper error handling
-		Self::proxy_process_kfrag_generation_requests(account, candidates)
+		Self::proxy_process_kfrag_generation_requests(account, candidates, sp_io::offchain::random_seed())
 			.expect("reencryption should work");
 	}
 
 	fn process_reencryption_requests(account: T::AccountId) {
-		Self::kfrag_holder_process_reencryption_requests(account)
+		if T::QueueManager::is_halted() {
+			log::info!("Reencryption is halted, skipping reencryption requests");
+			return;
+		}
+		Self::kfrag_holder_process_reencryption_requests(account, sp_io::offchain::random_seed())
 			.expect("reencapsulation should work");
 	}
+
+	fn process_self_test(account: T::AccountId) {
+		Self::proxy_process_self_test(account, sp_io::offchain::random_seed());
+	}
 }
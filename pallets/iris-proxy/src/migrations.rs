@@ -0,0 +1,99 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Storage migrations for the proxy pallet
+
+use super::*;
+
+/// Version 1 records the proxy and expiry of each capsule fragment and the asset
+/// each key fragment generation request was authorized for
+pub mod v1 {
+	use super::*;
+
+	/// the key fragment generation request stored before version 1
+	#[derive(Encode, Decode, RuntimeDebug, PartialEq, TypeInfo)]
+	pub struct OldKeyFragGenerationRequest<AccountId> {
+		pub caller: AccountId,
+		pub data_public_key: Vec<u8>,
+		pub consumer_public_key: Vec<u8>,
+	}
+
+	/// Migrate the proxy pallet's storage to version 1.
+	///
+	/// Fragments stored before version 1 were encrypted as raw boxes that do not
+	/// record the proxy that delivered them, so they can be neither verified nor
+	/// expired and are removed; consumers request reencryption again. Pending key
+	/// fragment generation requests do not record the asset they were authorized
+	/// for and are removed as well; callers resubmit them.
+	/// 
+	pub fn migrate<T: Config>() -> Weight {
+		let on_chain = StorageVersion::get::<Pallet<T>>();
+		if on_chain >= 1 {
+			return T::DbWeight::get().reads(1);
+		}
+		let mut fragments: u64 = 0;
+		EncryptedCapsuleFrags::<T>::translate::<Vec<EncryptedBox>, _>(|_, _, old| {
+			fragments += old.len() as u64;
+			None
+		});
+		let mut requests: u64 = 0;
+		KeyFragGenerationRequests::<T>::translate::<Vec<OldKeyFragGenerationRequest<T::AccountId>>, _>(|_, old| {
+			requests += old.len() as u64;
+			None
+		});
+		StorageVersion::new(1).put::<Pallet<T>>();
+		log::info!(
+			"Removed {:?} unattributed capsule fragments and {:?} key fragment generation requests in version 1",
+			fragments, requests,
+		);
+		T::DbWeight::get().reads_writes(fragments + requests + 1, fragments + requests + 1)
+	}
+}
+
+/// Version 2 keys the fragment expiry index by block and then by (consumer, public key),
+/// so that an entry can be removed once the fragment or grant it was scheduled for is
+/// replaced or cancelled
+pub mod v2 {
+	use super::*;
+	use frame_support::{storage::migration::storage_key_iter, traits::PalletInfoAccess};
+
+	/// Migrate the proxy pallet's storage to version 2. Each list of (consumer, public key)
+	/// pairs expiring at a block becomes one entry per pair.
+	pub fn migrate<T: Config>() -> Weight {
+		let on_chain = StorageVersion::get::<Pallet<T>>();
+		if on_chain >= 2 {
+			return T::DbWeight::get().reads(1);
+		}
+		// the old and new entries share a prefix, so the old ones are drained first
+		let old: Vec<(T::BlockNumber, Vec<(T::AccountId, Vec<u8>)>)> = storage_key_iter::<
+			T::BlockNumber, Vec<(T::AccountId, Vec<u8>)>, Blake2_128Concat,
+		>(<Pallet<T> as PalletInfoAccess>::name().as_bytes(), b"FragmentExpiries")
+			.drain()
+			.collect();
+		let blocks = old.len() as u64;
+		let mut entries: u64 = 0;
+		for (expires_at, pairs) in old {
+			for pair in pairs {
+				entries += 1;
+				FragmentExpiries::<T>::insert(expires_at, pair, ());
+			}
+		}
+		StorageVersion::new(2).put::<Pallet<T>>();
+		log::info!("Keyed {:?} fragment expiries by block and consumer in version 2", entries);
+		T::DbWeight::get().reads_writes(blocks + 1, blocks + entries + 1)
+	}
+}
@@ -22,7 +22,7 @@ use crate::{self as pallet_iris_proxy, Config};
 // use pallet_data_assets;
 use frame_support::{
 	parameter_types, 
-	traits::{GenesisBuild, ConstU32},
+	traits::{GenesisBuild, ConstBool, ConstU32, ConstU64},
 	BasicExternalities,
 };
 
@@ -277,6 +277,22 @@ impl pallet_data_assets::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = ConstU32<2>;
+	type MaxPinReportSize = ConstU32<10>;
+	type MaxIngestionAttempts = ConstU32<3>;
+	type IngestionBackoff = ConstU32<2>;
+	type MaxAssetNameLength = ConstU32<8>;
+	type MaxAssetDescriptionLength = ConstU32<16>;
+	type FreezeUnavailableAssets = ConstBool<true>;
+	type AllowSelfGateway = ConstBool<true>;
+	type SetAssetMetadata = ConstBool<true>;
+	type MaxDatasetFiles = ConstU32<4>;
+	type ClaimDuration = ConstU32<5>;
+	type MaxAssetHistory = ConstU32<16>;
+	type GatewayPricing = ();
+	type GatewayProvider = ();
+	type ProxyPrefs = ();
+	type PeerIdProvider = ();
 }
 
 parameter_types! {
@@ -298,6 +314,12 @@ impl Config for Test {
 	type QueueManager = DataAssets;
 	type MetadataProvider = DataAssets;
 	type Randomness = TestRandomness<Self>;
+	type FragmentTTL = ConstU64<10>;
+	type MaxExpiredFragmentsPerBlock = ConstU32<10>;
+	type MaxFragmentsPerRequest = ConstU32<3>;
+	type MaxFragmentBatch = ConstU32<4>;
+	type AssetHistory = DataAssets;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
 }
 
 pub type Extrinsic = TestXt<Call, ()>;
@@ -20,7 +20,7 @@
 use super::*;
 use crate::mock::*;
 use frame_support::{
-	assert_ok, pallet_prelude::*
+	assert_noop, assert_ok, pallet_prelude::*
 };
 use sp_runtime::{
 	testing::UintAuthorityId,
@@ -96,6 +96,334 @@ fn can_submit_encryption_artifacts() {
 	});
 }
 
+#[test]
+fn can_submit_precomputed_artifacts() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I encrypted my data client-side for a proxy's x25519 public key
+		let owner = test_data.owner.clone().public();
+		let proxy = test_data.proxy.clone().public();
+		let pairs = vec![(owner.clone(), 10), (proxy.clone(), 10)];
+		let mut rng = ChaCha20Rng::seed_from_u64(11);
+		let data_sk = umbral_pre::SecretKey::random_with_rng(&mut rng);
+		let data_pk = data_sk.public_key();
+		let (capsule, _) = umbral_pre::encrypt_with_rng(&mut rng, &data_pk, &test_data.plaintext).unwrap();
+		let capsule = capsule.to_array().as_slice().to_vec();
+		let public_key = data_pk.to_array().as_slice().to_vec();
+		let proxy_pk: [u8; 32] = test_data.x25519_pk.clone().try_into().unwrap();
+		let encrypted_sk_box = encrypt_x25519(
+			crypto_box::PublicKey::from(proxy_pk),
+			data_sk.to_secret_array().as_secret().to_vec(),
+			&mut rng,
+		);
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I submit the artifacts before the proxy has published its x25519 public key
+			// Then: they are rejected
+			assert_noop!(IrisProxy::submit_precomputed_artifacts(
+				Origin::signed(owner.clone()), proxy.clone(), capsule.clone(), public_key.clone(), encrypted_sk_box.clone(),
+			), Error::<Test>::NoProxyKey);
+			assert_ok!(Authorities::insert_key(Origin::signed(proxy.clone()), test_data.x25519_pk.clone()));
+			// When: I submit the artifacts
+			assert_ok!(IrisProxy::submit_precomputed_artifacts(
+				Origin::signed(owner.clone()), proxy.clone(), capsule.clone(), public_key.clone(), encrypted_sk_box.clone(),
+			));
+			// Then: they are staged as the encrypt rpc stages them
+			let encryption_artifacts = EncryptionArtifacts::<Test>::get(public_key.clone()).unwrap();
+			assert_eq!(encryption_artifacts.capsule, capsule);
+			assert_eq!(encryption_artifacts.proxy, proxy);
+			assert_eq!(ProxyCodes::<Test>::get(proxy.clone(), public_key.clone()), Some(encrypted_sk_box));
+			assert_eq!(Some(public_key), DataAssets::ingestion_staging(owner.clone()));
+		});
+	});
+}
+
+#[test]
+fn submit_precomputed_artifacts_rejects_malformed_artifacts() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: a proxy that has published its x25519 public key
+		let owner = test_data.owner.clone().public();
+		let proxy = test_data.proxy.clone().public();
+		let pairs = vec![(owner.clone(), 10), (proxy.clone(), 10)];
+		let mut rng = ChaCha20Rng::seed_from_u64(11);
+		let data_sk = umbral_pre::SecretKey::random_with_rng(&mut rng);
+		let data_pk = data_sk.public_key();
+		let (capsule, _) = umbral_pre::encrypt_with_rng(&mut rng, &data_pk, &test_data.plaintext).unwrap();
+		let capsule = capsule.to_array().as_slice().to_vec();
+		let public_key = data_pk.to_array().as_slice().to_vec();
+		let proxy_pk: [u8; 32] = test_data.x25519_pk.clone().try_into().unwrap();
+		let encrypted_sk_box = encrypt_x25519(
+			crypto_box::PublicKey::from(proxy_pk),
+			data_sk.to_secret_array().as_secret().to_vec(),
+			&mut rng,
+		);
+		let submit = |capsule: Vec<u8>, public_key: Vec<u8>, encrypted_sk_box: EncryptedBox| {
+			IrisProxy::submit_precomputed_artifacts(
+				Origin::signed(owner.clone()), proxy.clone(), capsule, public_key, encrypted_sk_box,
+			)
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			assert_ok!(Authorities::insert_key(Origin::signed(proxy.clone()), test_data.x25519_pk.clone()));
+			// When: I submit a truncated capsule, or bytes that don't encode a capsule
+			// Then: it is rejected
+			assert_noop!(submit(capsule[1..].to_vec(), public_key.clone(), encrypted_sk_box.clone()), Error::<Test>::MalformedCapsule);
+			assert_noop!(submit(vec![0; capsule.len()], public_key.clone(), encrypted_sk_box.clone()), Error::<Test>::MalformedCapsule);
+			// When: I submit a malformed data public key
+			// Then: it is rejected
+			assert_noop!(submit(capsule.clone(), public_key[1..].to_vec(), encrypted_sk_box.clone()), Error::<Test>::MalformedDataPublicKey);
+			assert_noop!(submit(capsule.clone(), vec![0; public_key.len()], encrypted_sk_box.clone()), Error::<Test>::MalformedDataPublicKey);
+			// When: I submit an encrypted secret key with a short nonce, public key or ciphertext
+			// Then: it is rejected
+			let mut short_nonce = encrypted_sk_box.clone();
+			short_nonce.nonce.pop();
+			assert_noop!(submit(capsule.clone(), public_key.clone(), short_nonce), Error::<Test>::MalformedSecretBox);
+			let mut short_key = encrypted_sk_box.clone();
+			short_key.public_key.pop();
+			assert_noop!(submit(capsule.clone(), public_key.clone(), short_key), Error::<Test>::MalformedSecretBox);
+			let mut short_ciphertext = encrypted_sk_box.clone();
+			short_ciphertext.ciphertext.pop();
+			assert_noop!(submit(capsule.clone(), public_key.clone(), short_ciphertext), Error::<Test>::MalformedSecretBox);
+			// And: nothing is staged
+			assert_eq!(None, DataAssets::ingestion_staging(owner.clone()));
+		});
+	});
+}
+
+#[test]
+fn decryption_context_pairs_capsule_with_ciphertext() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a valid node with a positive balance
+		let owner = test_data.owner.clone().public();
+		let pairs = vec![(owner.clone(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+		let multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// And: I have encrypted some data
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			// And: the ciphertext has been ingested by a gateway
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				1,
+				cid.clone(),
+				multiaddr.clone(),
+				1_000,
+				1,
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(owner.clone())[0].clone();
+			assert_ok!(<DataAssets as pallet_data_assets::ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 1, test_data.ciphertext.len() as u128,
+			));
+			// When: I fetch the decryption context for the asset
+			let context = IrisProxy::decryption_context(1).unwrap();
+			// Then: it pairs the capsule with the ciphertext pinned in IPFS
+			assert_eq!(context.capsule, test_data.capsule.clone());
+			assert_eq!(context.ciphertext_cid, cid);
+			assert_eq!(context.ciphertext_size, test_data.ciphertext.len() as u128);
+			assert_eq!(context.plaintext_checksum, None);
+			// When: I attest the checksum of the plaintext
+			let checksum = sp_io::hashing::sha2_256(&test_data.plaintext).to_vec();
+			assert_ok!(DataAssets::set_plaintext_checksum(Origin::signed(owner.clone()), 1, checksum.clone()));
+			// Then: consumers can verify the decrypted data against it
+			let context = IrisProxy::decryption_context(1).unwrap();
+			assert_eq!(context.plaintext_checksum, Some(checksum));
+			// And: it survives an encode/decode round trip
+			let decoded = DecryptionContext::decode(&mut &context.encode()[..]).unwrap();
+			assert_eq!(decoded, context);
+			// And: there is no context for an unknown asset
+			assert_eq!(IrisProxy::decryption_context(2), None);
+		});
+	});
+}
+
+#[test]
+fn decrypt_precheck_reports_what_is_missing() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a valid node with a positive balance
+		let owner = test_data.owner.clone().public();
+		let consumer = test_data.consumer.clone().public();
+		let pairs = vec![(owner.clone(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+		let multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// And: I have encrypted some data and a gateway has ingested the ciphertext
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			assert_ok!(DataAssets::create_request(
+				Origin::signed(owner.clone()),
+				owner.clone(),
+				1,
+				cid.clone(),
+				multiaddr.clone(),
+				1_000,
+				1,
+				1,
+				1,
+				None,
+			));
+			let cmd = DataAssets::ingestion_commands(owner.clone())[0].clone();
+			assert_ok!(<DataAssets as pallet_data_assets::ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 1, test_data.ciphertext.len() as u128,
+			));
+			// When: a consumer prechecks decryption before any proxy has served them
+			let report = IrisProxy::decrypt_precheck(1, consumer.clone());
+			// Then: the capsule is there but the consumer has no artifact or fragments yet
+			assert_eq!(report, DecryptionReadiness {
+				asset_exists: true,
+				capsule_present: true,
+				reencryption_artifact_present: false,
+				authorized: false,
+				fragments_delivered: 0,
+				fragments_needed: KFRAG_THRESHOLD as u32,
+				pinned: true,
+				frozen: false,
+				ready: false,
+			});
+			// When: a proxy delivers a capsule fragment and the owner freezes the asset
+			assign_kfrag_holders(consumer.clone(), test_data.public_key.clone(), vec![test_data.proxy.clone().public()]);
+			assert_ok!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(test_data.proxy.clone().public()),
+				consumer.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			assert_ok!(Assets::freeze_asset(Origin::signed(owner.clone()), 1));
+			// Then: the fragment is counted and the asset is reported frozen
+			let report = IrisProxy::decrypt_precheck(1, consumer.clone());
+			assert_eq!(report.fragments_delivered, 1);
+			assert!(report.frozen);
+			assert!(!report.ready);
+			// When: the consumer is granted access until the next block
+			IrisProxy::add_kfrag_request(
+				consumer.clone(), 1, test_data.public_key.clone(), test_data.x25519_pk.clone(), Some(1),
+			);
+			// Then: they are authorized
+			assert!(IrisProxy::decrypt_precheck(1, consumer.clone()).authorized);
+			// And: they are no longer authorized once the grant expires
+			System::set_block_number(1);
+			let report = IrisProxy::decrypt_precheck(1, consumer.clone());
+			assert!(!report.authorized);
+			assert!(!report.ready);
+			// And: an unknown asset is reported as missing
+			assert_eq!(IrisProxy::decrypt_precheck(2, consumer.clone()), DecryptionReadiness {
+				fragments_needed: KFRAG_THRESHOLD as u32,
+				..Default::default()
+			});
+		});
+	});
+}
+
+#[test]
+fn encrypt_and_request_stages_artifacts_and_queues_the_request() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a valid node with a positive balance
+		let owner = test_data.owner.clone().public();
+		let pairs = vec![(owner.clone(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+		let multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I stage my encryption artifacts and request ingestion together
+			assert_ok!(IrisProxy::encrypt_and_request(
+				Origin::signed(owner.clone()),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+				owner.clone(),
+				1,
+				cid.clone(),
+				multiaddr.clone(),
+				1_000,
+				1,
+				1,
+			));
+			// Then: the artifacts are staged
+			let artifacts = EncryptionArtifacts::<Test>::get(test_data.public_key.clone()).unwrap();
+			assert_eq!(artifacts.capsule, test_data.capsule.clone());
+			assert_eq!(
+				Some(test_data.public_key.clone()),
+				DataAssets::ingestion_staging(owner.clone()),
+			);
+			// And: the request is queued with the gateway
+			let cmd = DataAssets::ingestion_commands(owner.clone())[0].clone();
+			assert_eq!(cmd.cid, cid);
+			assert_eq!(cmd.owner, owner);
+		});
+	});
+}
+
+#[test]
+fn encrypt_and_request_rolls_back_the_staged_artifacts_when_the_request_fails() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a valid node with a positive balance
+		let owner = test_data.owner.clone().public();
+		let pairs = vec![(owner.clone(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+		let multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I stage my encryption artifacts along with a request that can't be queued
+			// Then: the request fails and nothing is left staged
+			assert_noop!(IrisProxy::encrypt_and_request(
+				Origin::signed(owner.clone()),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+				owner.clone(),
+				1,
+				cid.clone(),
+				multiaddr.clone(),
+				1_000,
+				1,
+				0,
+			), pallet_data_assets::Error::<Test>::InvalidReplication);
+			assert!(EncryptionArtifacts::<Test>::get(test_data.public_key.clone()).is_none());
+			assert!(ProxyCodes::<Test>::get(test_data.proxy.clone().public(), test_data.public_key.clone()).is_none());
+			assert_eq!(None, DataAssets::ingestion_staging(owner.clone()));
+		});
+	});
+}
+
 #[test]
 fn can_submit_capsule_fragment() {
 	TEST_CONSTANTS.with(|test_data| {
@@ -107,6 +435,9 @@ fn can_submit_capsule_fragment() {
 		};
 
 		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			assign_kfrag_holders(
+				test_data.consumer.clone().public(), test_data.public_key.clone(), vec![test_data.proxy.clone().public()],
+			);
 			assert_ok!(IrisProxy::submit_capsule_fragment(
 				Origin::signed(test_data.proxy.clone().public()),
 				test_data.consumer.clone().public(),
@@ -117,51 +448,963 @@ fn can_submit_capsule_fragment() {
 			let verified_cfrags = EncryptedCapsuleFrags::<Test>::get(
 				test_data.consumer.clone().public(), test_data.public_key.clone()
 			);
-			assert_eq!(verified_cfrags.len(), 1);
-			assert_eq!(verified_cfrags[0], encrypted_capsule_fragment.clone());
+			assert_eq!(verified_cfrags.len(), 1);
+			assert_eq!(verified_cfrags[0].encrypted_cfrag, encrypted_capsule_fragment.clone());
+		});
+	});
+}
+
+#[test]
+fn capsule_fragments_are_accepted_once_from_each_kfrag_holder() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_capsule_fragment = iris_primitives::EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let consumer = test_data.consumer.clone().public();
+		let deliver = |who: sp_core::sr25519::Public| IrisProxy::submit_capsule_fragment(
+			Origin::signed(who),
+			consumer.clone(),
+			test_data.public_key.clone(),
+			encrypted_capsule_fragment.clone(),
+		);
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: a proxy delivers a fragment before any key fragments were generated
+			// Then: it is rejected
+			assert_noop!(deliver(test_data.proxy.clone().public()), Error::<Test>::NotAKfragHolder);
+			// Given: key fragments were assigned to the proxy
+			assign_kfrag_holders(consumer.clone(), test_data.public_key.clone(), vec![test_data.proxy.clone().public()]);
+			// When: an account that holds no key fragment delivers a fragment
+			// Then: it is rejected
+			assert_noop!(deliver(test_data.owner.clone().public()), Error::<Test>::NotAKfragHolder);
+			// When: the proxy delivers its fragment
+			assert_ok!(deliver(test_data.proxy.clone().public()));
+			// And: it delivers another one under the same box key
+			// Then: the second fragment is rejected
+			assert_noop!(deliver(test_data.proxy.clone().public()), Error::<Test>::FragmentAlreadyDelivered);
+			assert_eq!(1, EncryptedCapsuleFrags::<Test>::get(consumer.clone(), test_data.public_key.clone()).len());
+		});
+	});
+}
+
+#[test]
+fn capsule_fragment_deliveries_are_limited_per_request() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_capsule_fragment = iris_primitives::EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let deliver = |who: sp_core::sr25519::Public| IrisProxy::submit_capsule_fragment(
+			Origin::signed(who),
+			test_data.consumer.clone().public(),
+			test_data.public_key.clone(),
+			encrypted_capsule_fragment.clone(),
+		);
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: more key fragments were assigned for a public key than fragments can be delivered
+			let max = <Test as crate::Config>::MaxFragmentsPerRequest::get();
+			let holders = kfrag_holders(max as usize + 1);
+			assign_kfrag_holders(test_data.consumer.clone().public(), test_data.public_key.clone(), holders.clone());
+			// And: a consumer has been delivered the maximum number of fragments for the public key
+			for holder in holders.iter().take(max as usize) {
+				assert_ok!(deliver(holder.clone()));
+			}
+			// When: another fragment is delivered
+			// Then: it is rejected
+			assert_noop!(deliver(holders[max as usize].clone()), Error::<Test>::FragmentLimitReached);
+			assert_eq!(max as usize, EncryptedCapsuleFrags::<Test>::get(
+				test_data.consumer.clone().public(), test_data.public_key.clone()
+			).len());
+			// And: fragments can still be delivered to the consumer for other public keys
+			assign_kfrag_holders(
+				test_data.consumer.clone().public(), test_data.capsule.clone(), vec![test_data.proxy.clone().public()],
+			);
+			assert_ok!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(test_data.proxy.clone().public()),
+				test_data.consumer.clone().public(),
+				test_data.capsule.clone(),
+				encrypted_capsule_fragment.clone(),
+			));
+		});
+	});
+}
+
+#[test]
+fn capsule_fragments_can_be_submitted_in_batches() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_capsule_fragment = iris_primitives::EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let consumer = test_data.consumer.clone().public();
+		let proxy = test_data.proxy.clone().public();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(2);
+			// Given: the proxy holds key fragments for three public keys
+			let other_key = vec![3u8; 33];
+			for public_key in [test_data.public_key.clone(), test_data.capsule.clone(), other_key.clone()] {
+				assign_kfrag_holders(consumer.clone(), public_key, vec![proxy.clone()]);
+			}
+			// And: the consumer's access grant for one public key has expired
+			GrantExpiries::<Test>::insert(consumer.clone(), test_data.capsule.clone(), 1);
+			// When: a proxy submits a batch with a fragment for that public key and a duplicate
+			// among two valid ones
+			assert_ok!(IrisProxy::submit_fragments(Origin::signed(proxy.clone()), vec![
+				(consumer.clone(), test_data.public_key.clone(), encrypted_capsule_fragment.clone()),
+				(consumer.clone(), test_data.capsule.clone(), encrypted_capsule_fragment.clone()),
+				(consumer.clone(), test_data.public_key.clone(), encrypted_capsule_fragment.clone()),
+				(consumer.clone(), other_key.clone(), encrypted_capsule_fragment.clone()),
+			]));
+			// Then: only the invalid fragments are rejected
+			assert_eq!(1, EncryptedCapsuleFrags::<Test>::get(consumer.clone(), test_data.public_key.clone()).len());
+			assert_eq!(1, EncryptedCapsuleFrags::<Test>::get(consumer.clone(), other_key.clone()).len());
+			assert!(EncryptedCapsuleFrags::<Test>::get(consumer.clone(), test_data.capsule.clone()).is_empty());
+			// And: the summary counts the stored and rejected fragments
+			System::assert_last_event(mock::Event::IrisProxy(crate::Event::CapsuleFragmentsSubmitted(
+				proxy.clone(), 2, 2,
+			)));
+			// When: a proxy submits more than the maximum number of fragments in a batch
+			let max = <Test as crate::Config>::MaxFragmentBatch::get() as usize;
+			let too_many = vec![
+				(consumer.clone(), test_data.public_key.clone(), encrypted_capsule_fragment.clone()); max + 1
+			];
+			// Then: the whole batch is rejected
+			assert_noop!(
+				IrisProxy::submit_fragments(Origin::signed(proxy.clone()), too_many),
+				Error::<Test>::FragmentBatchTooLarge,
+			);
+		});
+	});
+}
+
+#[test]
+fn capsule_fragments_are_removed_after_ttl() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_capsule_fragment = iris_primitives::EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: a capsule fragment has been submitted for a consumer
+			assign_kfrag_holders(
+				test_data.consumer.clone().public(), test_data.public_key.clone(), vec![test_data.proxy.clone().public()],
+			);
+			assert_ok!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(test_data.proxy.clone().public()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				encrypted_capsule_fragment.clone(),
+			));
+			let expires_at = 1 + <Test as crate::Config>::FragmentTTL::get();
+			let cfrags = EncryptedCapsuleFrags::<Test>::get(
+				test_data.consumer.clone().public(), test_data.public_key.clone()
+			);
+			assert_eq!(cfrags[0].expires_at, expires_at);
+			// When: the sweep runs before the TTL has passed
+			IrisProxy::on_idle(expires_at - 1, Weight::max_value());
+			// Then: the fragment is kept
+			assert_eq!(EncryptedCapsuleFrags::<Test>::get(
+				test_data.consumer.clone().public(), test_data.public_key.clone()
+			).len(), 1);
+			// When: the sweep runs once the TTL has passed
+			System::set_block_number(expires_at);
+			IrisProxy::on_idle(expires_at, Weight::max_value());
+			// Then: the fragment is removed
+			assert!(!EncryptedCapsuleFrags::<Test>::contains_key(
+				test_data.consumer.clone().public(), test_data.public_key.clone()
+			));
+			assert!(FragmentExpiries::<Test>::iter_prefix(expires_at).next().is_none());
+			System::assert_last_event(mock::Event::IrisProxy(crate::Event::FragmentsExpired(
+				test_data.consumer.clone().public(), test_data.public_key.clone(),
+			)));
+		});
+	});
+}
+
+// #[test]
+// fn submit_capsule_fragment_fails_if_public_key_unknown() {
+
+// }
+
+#[test]
+fn can_submit_reencryption_keys() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+
+		let encrypted_sk = EncryptedBox {
+			ciphertext: test_data.ciphertext.clone(),
+			nonce: test_data.nonce.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		let kfrag_assignments = vec![(test_data.proxy.clone().public(), encrypted_sk.clone())];
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			assert_ok!(IrisProxy::submit_reencryption_keys(
+				Origin::signed(test_data.proxy.public().clone()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				kfrag_assignments.clone(),
+				encrypted_sk.clone(),
+			));
+
+			let reencryption_artifact = ReencryptionArtifacts::<Test>::get(
+				test_data.consumer.clone().public(), test_data.public_key.clone(),
+			).unwrap();
+			assert_eq!(reencryption_artifact.verifying_key, test_data.public_key.clone());
+			assert_eq!(reencryption_artifact.ephemeral_public_key, test_data.public_key.clone());
+			assert_eq!(reencryption_artifact.secret, encrypted_sk);
+			assert_eq!(reencryption_artifact.verified_kfrags, kfrag_assignments.clone());
+
+
+		});
+	});
+}
+
+#[test]
+fn reencryption_is_rejected_while_halted() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+		let encrypted_sk = EncryptedBox {
+			ciphertext: test_data.ciphertext.clone(),
+			nonce: test_data.nonce.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: encrypted data is assigned to a proxy
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_sk.clone(),
+			));
+			// And: reencryption has been halted
+			assert_ok!(DataAssets::set_halted(Origin::root(), true));
+			// When: a proxy submits reencryption keys
+			// Then: they are rejected
+			assert_noop!(IrisProxy::submit_reencryption_keys(
+				Origin::signed(test_data.proxy.public().clone()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				vec![(test_data.proxy.clone().public(), encrypted_sk.clone())],
+				encrypted_sk.clone(),
+			), Error::<Test>::Halted);
+			// And: capsule fragments are rejected
+			assert_noop!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(test_data.proxy.clone().public()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				encrypted_sk.clone(),
+			), Error::<Test>::Halted);
+			// And: offchain reencryption work is skipped
+			assert!(matches!(
+				IrisProxy::kfrag_holder_process_reencryption_requests(test_data.proxy.clone().public(), [1u8; 32]),
+				Err(Error::<Test>::Halted),
+			));
+			// But: the encryption artifacts can still be read
+			let artifact = EncryptionArtifacts::<Test>::get(test_data.public_key.clone()).unwrap();
+			assert_eq!(artifact.capsule, test_data.capsule.clone());
+		});
+	});
+}
+
+#[test]
+fn consumer_can_cancel_reencryption_before_fragments_delivered() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			// Given: encrypted data is assigned to a proxy
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			// And: I have requested reencryption which the proxy has not yet served
+			IrisProxy::add_kfrag_request(
+				test_data.consumer.clone().public(),
+				1,
+				test_data.public_key.clone(),
+				test_data.x25519_pk.clone(),
+				None,
+			);
+			// When: I cancel the request
+			assert_ok!(IrisProxy::cancel_reencryption(
+				Origin::signed(test_data.consumer.clone().public()),
+				test_data.public_key.clone(),
+			));
+			// Then: the request is removed from the proxy's queue
+			assert!(KeyFragGenerationRequests::<Test>::get(test_data.proxy.clone().public()).is_empty());
+			System::assert_last_event(mock::Event::IrisProxy(crate::Event::ReencryptionCancelled(
+				test_data.consumer.clone().public(), test_data.public_key.clone(),
+			)));
+			// And: it can't be cancelled twice
+			assert_noop!(IrisProxy::cancel_reencryption(
+				Origin::signed(test_data.consumer.clone().public()),
+				test_data.public_key.clone(),
+			), Error::<Test>::NoSuchReencryptionRequest);
+		});
+	});
+}
+
+#[test]
+fn superseded_and_cancelled_grants_leave_no_scheduled_expiry() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let consumer = test_data.consumer.clone().public();
+			let entry = (consumer.clone(), test_data.public_key.clone());
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			// Given: I was granted access until block 10
+			IrisProxy::add_kfrag_request(
+				consumer.clone(), 1, test_data.public_key.clone(), test_data.x25519_pk.clone(), Some(10),
+			);
+			assert_eq!(Some(()), FragmentExpiries::<Test>::get(10, entry.clone()));
+			// When: I am granted access again until block 20
+			IrisProxy::add_kfrag_request(
+				consumer.clone(), 1, test_data.public_key.clone(), test_data.x25519_pk.clone(), Some(20),
+			);
+			// Then: only the new grant's expiry is scheduled
+			assert_eq!(None, FragmentExpiries::<Test>::get(10, entry.clone()));
+			assert_eq!(Some(()), FragmentExpiries::<Test>::get(20, entry.clone()));
+			// When: I cancel the request before any fragment is delivered
+			assert_ok!(IrisProxy::cancel_reencryption(
+				Origin::signed(consumer.clone()),
+				test_data.public_key.clone(),
+			));
+			// Then: no expiry is scheduled for it
+			assert_eq!(None, FragmentExpiries::<Test>::get(20, entry));
+		});
+	});
+}
+
+#[test]
+fn proxy_lists_pending_reencryption_requests() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: encrypted data is assigned to a proxy
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			// And: two consumers have requested reencryption
+			IrisProxy::add_kfrag_request(
+				test_data.consumer.clone().public(),
+				1,
+				test_data.public_key.clone(),
+				test_data.x25519_pk.clone(),
+				None,
+			);
+			IrisProxy::add_kfrag_request(
+				test_data.owner.clone().public(),
+				1,
+				test_data.public_key.clone(),
+				test_data.x25519_pk.clone(),
+				None,
+			);
+			// When: I list the proxy's pending reencryptions
+			let pending = IrisProxy::pending_reencryptions(test_data.proxy.clone().public());
+			// Then: both requests are listed in the order they were queued
+			assert_eq!(vec![
+				(
+					IrisProxy::reencryption_request_id(
+						&test_data.consumer.clone().public(), &test_data.public_key,
+					),
+					test_data.consumer.clone().public(),
+					1,
+				),
+				(
+					IrisProxy::reencryption_request_id(
+						&test_data.owner.clone().public(), &test_data.public_key,
+					),
+					test_data.owner.clone().public(),
+					1,
+				),
+			], pending);
+			// And: no other proxy has pending reencryptions
+			assert!(IrisProxy::pending_reencryptions(test_data.consumer.clone().public()).is_empty());
+			// When: a consumer cancels its request
+			assert_ok!(IrisProxy::cancel_reencryption(
+				Origin::signed(test_data.consumer.clone().public()),
+				test_data.public_key.clone(),
+			));
+			// Then: it is no longer pending
+			let pending = IrisProxy::pending_reencryptions(test_data.proxy.clone().public());
+			assert_eq!(1, pending.len());
+			assert_eq!(test_data.owner.clone().public(), pending[0].1);
+		});
+	});
+}
+
+#[test]
+fn access_count_increments_once_per_granted_access() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: encrypted data is assigned to a proxy
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			// And: two consumers were authorized to decrypt asset 1
+			for consumer in [test_data.consumer.clone().public(), test_data.owner.clone().public()] {
+				IrisProxy::add_kfrag_request(
+					consumer,
+					1,
+					test_data.public_key.clone(),
+					test_data.x25519_pk.clone(),
+					None,
+				);
+			}
+			// And: key fragments were assigned to one more proxy than needed to decrypt
+			let holders = kfrag_holders(crate::KFRAG_THRESHOLD + 1);
+			for consumer in [test_data.consumer.clone().public(), test_data.owner.clone().public()] {
+				assign_kfrag_holders(consumer, test_data.public_key.clone(), holders.clone());
+			}
+			let deliver = |holder: &sp_core::sr25519::Public, consumer: sp_core::sr25519::Public| {
+				assert_ok!(IrisProxy::submit_capsule_fragment(
+					Origin::signed(holder.clone()),
+					consumer,
+					test_data.public_key.clone(),
+					encrypted_key.clone(),
+				));
+			};
+			// When: the first consumer is delivered fewer fragments than the threshold
+			for holder in holders.iter().take(crate::KFRAG_THRESHOLD - 1) {
+				deliver(holder, test_data.consumer.clone().public());
+			}
+			// Then: access has not been granted yet
+			assert_eq!(0, IrisProxy::access_count(1));
+			// When: it is delivered enough fragments to decrypt, and then some
+			for holder in holders.iter().skip(crate::KFRAG_THRESHOLD - 1) {
+				deliver(holder, test_data.consumer.clone().public());
+			}
+			// Then: access was granted once
+			assert_eq!(1, IrisProxy::access_count(1));
+			// When: the second consumer is delivered enough fragments to decrypt
+			for holder in holders.iter().take(crate::KFRAG_THRESHOLD) {
+				deliver(holder, test_data.owner.clone().public());
+			}
+			// Then: access was granted twice
+			assert_eq!(2, IrisProxy::access_count(1));
+		});
+	});
+}
+
+#[test]
+fn asset_history_records_rekeying_and_access() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let consumer = test_data.consumer.clone().public();
+			let proxy = test_data.proxy.clone().public();
+			// Given: a consumer was authorized to decrypt asset 1
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()),
+				test_data.owner.clone().public(),
+				proxy.clone(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+			));
+			IrisProxy::add_kfrag_request(
+				consumer.clone(), 1, test_data.public_key.clone(), test_data.x25519_pk.clone(), None,
+			);
+			// When: the proxy generates reencryption keys for the consumer
+			let holders = kfrag_holders(crate::KFRAG_THRESHOLD);
+			assert_ok!(IrisProxy::submit_reencryption_keys(
+				Origin::signed(proxy.clone()),
+				consumer.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.x25519_pk.clone(),
+				test_data.public_key.clone(),
+				holders.iter().map(|holder| (holder.clone(), encrypted_key.clone())).collect(),
+				encrypted_key.clone(),
+			));
+			// And: the consumer is delivered enough fragments to decrypt
+			for holder in holders {
+				assert_ok!(IrisProxy::submit_capsule_fragment(
+					Origin::signed(holder),
+					consumer.clone(),
+					test_data.public_key.clone(),
+					encrypted_key.clone(),
+				));
+			}
+			// Then: the asset's history records both
+			assert_eq!(vec![
+				(1, AssetLifecycleEvent::Rekeyed, Some(consumer.clone())),
+				(1, AssetLifecycleEvent::Accessed, Some(consumer.clone())),
+			], DataAssets::asset_history(1));
+		});
+	});
+}
+
+#[test]
+fn proxy_can_redeliver_capsule_fragment_after_rotating_box_key() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		let fragment = |nonce: Vec<u8>| EncryptedBox {
+			nonce,
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			let consumer = test_data.consumer.clone().public();
+			let proxy = test_data.proxy.clone().public();
+			let deliver = |who: sp_core::sr25519::Public, cfrag: EncryptedBox| {
+				assert_ok!(IrisProxy::submit_capsule_fragment(
+					Origin::signed(who), consumer.clone(), test_data.public_key.clone(), cfrag,
+				));
+			};
+			// Given: a consumer was authorized to decrypt asset 1
+			IrisProxy::add_kfrag_request(
+				consumer.clone(), 1, test_data.public_key.clone(), test_data.x25519_pk.clone(), None,
+			);
+			assign_kfrag_holders(
+				consumer.clone(), test_data.public_key.clone(), vec![proxy.clone(), test_data.owner.clone().public()],
+			);
+			// And: a proxy delivered a fragment encrypted with its first box key
+			assert_ok!(Authorities::insert_key(Origin::signed(proxy.clone()), vec![1u8; 32]));
+			deliver(proxy.clone(), fragment(test_data.nonce.clone()));
+			// When: the proxy rotates its box key and delivers a fresh fragment later on
+			System::set_block_number(3);
+			assert_ok!(Authorities::insert_key(Origin::signed(proxy.clone()), vec![2u8; 32]));
+			let fresh = fragment(vec![9u8; 24]);
+			deliver(proxy.clone(), fresh.clone());
+			// Then: the fresh fragment replaced the old one instead of counting twice
+			let cfrags = EncryptedCapsuleFrags::<Test>::get(consumer.clone(), test_data.public_key.clone());
+			assert_eq!(1, cfrags.len());
+			assert_eq!(fresh, cfrags[0].encrypted_cfrag);
+			assert_eq!(2, cfrags[0].box_key_version);
+			// And: only the fresh fragment's expiry is scheduled
+			let ttl = <Test as crate::Config>::FragmentTTL::get();
+			let entry = (consumer.clone(), test_data.public_key.clone());
+			assert_eq!(None, FragmentExpiries::<Test>::get(1 + ttl, entry.clone()));
+			assert_eq!(Some(()), FragmentExpiries::<Test>::get(3 + ttl, entry));
+			assert_eq!(0, IrisProxy::access_count(1));
+			System::assert_last_event(mock::Event::IrisProxy(crate::Event::ReencapsulationComplete));
+			assert!(System::events().iter().any(|r| r.event == mock::Event::IrisProxy(
+				crate::Event::CapsuleFragmentRedelivered(consumer.clone(), test_data.public_key.clone(), proxy.clone())
+			)));
+			// When: another proxy delivers its fragment
+			deliver(test_data.owner.clone().public(), fragment(test_data.nonce.clone()));
+			// Then: the consumer holds enough fragments and access was granted once
+			assert_eq!(crate::KFRAG_THRESHOLD, EncryptedCapsuleFrags::<Test>::get(
+				consumer.clone(), test_data.public_key.clone()
+			).len());
+			assert_eq!(1, IrisProxy::access_count(1));
+		});
+	});
+}
+
+#[test]
+fn consumer_cannot_cancel_reencryption_after_fragments_delivered() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+		let encrypted_sk = EncryptedBox {
+			ciphertext: test_data.ciphertext.clone(),
+			nonce: test_data.nonce.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let kfrag_assignments = vec![
+			(test_data.proxy.clone().public(), encrypted_sk.clone()),
+			(test_data.owner.clone().public(), encrypted_sk.clone()),
+		];
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: key fragments were generated for my reencryption request
+			assert_ok!(IrisProxy::submit_reencryption_keys(
+				Origin::signed(test_data.proxy.public().clone()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				test_data.public_key.clone(),
+				kfrag_assignments.clone(),
+				encrypted_sk.clone(),
+			));
+			// And: one of the fragment holders has delivered a capsule fragment
+			assert_ok!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(test_data.proxy.clone().public()),
+				test_data.consumer.clone().public(),
+				test_data.public_key.clone(),
+				encrypted_sk.clone(),
+			));
+			// When: I try to cancel the request
+			// Then: the request can no longer be cancelled
+			assert_noop!(IrisProxy::cancel_reencryption(
+				Origin::signed(test_data.consumer.clone().public()),
+				test_data.public_key.clone(),
+			), Error::<Test>::FragmentsAlreadyDelivered);
+			// And: the remaining fragment holder's request is still queued
+			assert_eq!(CapsuleFragmentGenerationRequests::<Test>::get(test_data.owner.clone().public()).len(), 1);
+		});
+	});
+}
+
+#[test]
+fn decryption_fails_for_mismatched_capsule_and_ciphertext() {
+	// Given: two plaintexts encrypted for the same delegating key
+	let mut rng = ChaCha20Rng::seed_from_u64(7);
+	let delegating_sk = umbral_pre::SecretKey::random_with_rng(&mut rng);
+	let delegating_pk = delegating_sk.public_key();
+	let receiving_sk = umbral_pre::SecretKey::random_with_rng(&mut rng);
+	let signer = umbral_pre::Signer::new(delegating_sk.clone());
+	let (capsule, ciphertext) = umbral_pre::encrypt_with_rng(&mut rng, &delegating_pk, b"first").unwrap();
+	let (_, other_ciphertext) = umbral_pre::encrypt_with_rng(&mut rng, &delegating_pk, b"second").unwrap();
+	// And: capsule fragments for the first capsule
+	let kfrags = umbral_pre::generate_kfrags_with_rng(
+		&mut rng, &delegating_sk, &receiving_sk.public_key(), &signer, 1, 1, true, true,
+	);
+	let cfrags: Vec<umbral_pre::VerifiedCapsuleFrag> = kfrags.iter()
+		.map(|kfrag| umbral_pre::reencrypt_with_rng(&mut rng, &capsule, kfrag.clone()))
+		.collect();
+	// When: I decrypt the ciphertext that belongs to the capsule
+	// Then: the plaintext is recovered
+	assert_eq!(Ok(b"first".to_vec()), IrisProxy::decrypt_with_fragments(
+		&receiving_sk, &delegating_pk, &capsule, cfrags.clone(), ciphertext.to_vec(),
+	));
+	// When: I decrypt the other ciphertext with the same capsule
+	// Then: authentication fails instead of returning garbage
+	assert_eq!(Err(DecryptionFailure::DecryptionFailed), IrisProxy::decrypt_with_fragments(
+		&receiving_sk, &delegating_pk, &capsule, cfrags, other_ciphertext.to_vec(),
+	));
+}
+
+#[test]
+fn decrypt_rejects_secret_keys_that_are_not_32_bytes() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.consumer.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: a consumer that signed a message
+			let message = b"decrypt".to_vec();
+			let signature = test_data.consumer.sign(&message);
+			// When: they try to decrypt with a 16 byte secret key
+			let result = IrisProxy::decrypt(
+				Bytes::from(signature.0.to_vec()),
+				Bytes::from(test_data.consumer.public().0.to_vec()),
+				Bytes::from(message),
+				Bytes::from(test_data.ciphertext.clone()),
+				1,
+				Bytes::from(vec![1u8; 16]),
+				None,
+			);
+			// Then: decryption fails instead of panicking
+			assert_eq!(Err(DecryptionFailure::InvalidSecretKey), result);
+		});
+	});
+}
+
+#[test]
+fn decrypt_uses_umbral_for_newly_created_assets() {
+	TEST_CONSTANTS.with(|test_data| {
+		let owner = test_data.owner.clone().public();
+		let pairs = vec![(owner.clone(), 10)];
+		let encrypted_key = EncryptedBox {
+			nonce: test_data.nonce.clone(),
+			ciphertext: test_data.ciphertext.clone(),
+			public_key: test_data.public_key.clone(),
+		};
+		let cid = "QmPZv7P8nQUSh2CpqTvUeYemFyjvMjgWEs8H1Tm8b3zAm9".as_bytes().to_vec();
+		let multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".as_bytes().to_vec();
+
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// Given: an asset class created from data I encrypted
+			assert_ok!(IrisProxy::encrypt_and_request(
+				Origin::signed(owner.clone()),
+				test_data.proxy.clone().public(),
+				test_data.capsule.clone(),
+				test_data.public_key.clone(),
+				encrypted_key.clone(),
+				owner.clone(),
+				1,
+				cid.clone(),
+				multiaddr.clone(),
+				1_000,
+				1,
+				1,
+			));
+			let cmd = DataAssets::ingestion_commands(owner.clone())[0].clone();
+			assert_ok!(<DataAssets as pallet_data_assets::ResultsHandler<Test, _, _, _>>::create_asset_class(
+				Origin::signed(owner.clone()), cmd, 1, test_data.ciphertext.len() as u128,
+			));
+			// Then: its metadata records the default scheme
+			let metadata = <DataAssets as pallet_data_assets::MetadataProvider<u32>>::get(1).unwrap();
+			assert_eq!(EncryptionScheme::UmbralSalsaBox, metadata.encryption_scheme);
+			// When: a consumer without capsule fragments tries to decrypt it
+			let message = b"decrypt".to_vec();
+			let signature = test_data.consumer.sign(&message);
+			let result = IrisProxy::decrypt(
+				Bytes::from(signature.0.to_vec()),
+				Bytes::from(test_data.consumer.public().0.to_vec()),
+				Bytes::from(message),
+				Bytes::from(test_data.ciphertext.clone()),
+				1,
+				Bytes::from(vec![1u8; 32]),
+				None,
+			);
+			// Then: decryption takes the umbral path and waits for fragments
+			assert_eq!(Err(DecryptionFailure::NotEnoughFragments { have: 0, need: 2 }), result);
+		});
+	});
+}
+
+#[test]
+fn proxy_can_pass_self_test() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a proxy that has published an x25519 public key
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			System::set_block_number(1);
+			assert_ok!(Authorities::insert_key(
+				Origin::signed(test_data.proxy.clone().public()), test_data.x25519_pk.clone(),
+			));
+			// When: I request a self test
+			assert_ok!(IrisProxy::proxy_self_test(
+				Origin::signed(test_data.proxy.clone().public()),
+			));
+			// Then: a self test is flagged for my node
+			assert_eq!(true, crate::SelfTestRequests::<Test>::get(test_data.proxy.clone().public()));
+			System::assert_last_event(mock::Event::IrisProxy(
+				crate::Event::SelfTestRequested(test_data.proxy.clone().public())
+			));
+			// When: my node reports that the self test passed
+			assert_ok!(IrisProxy::submit_self_test_result(
+				Origin::signed(test_data.proxy.clone().public()), true,
+			));
+			// Then: the self test passes and the request is cleared
+			System::assert_last_event(mock::Event::IrisProxy(
+				crate::Event::SelfTestPassed(test_data.proxy.clone().public())
+			));
+			assert_eq!(false, crate::SelfTestRequests::<Test>::get(test_data.proxy.clone().public()));
+			// And: a result can't be reported without a request
+			assert_noop!(IrisProxy::submit_self_test_result(
+				Origin::signed(test_data.proxy.clone().public()), false,
+			), Error::<Test>::NoSelfTestRequested);
+		});
+	});
+}
+
+#[test]
+fn proxy_offchain_runs_requested_self_test_with_local_key() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+		let proxy = test_data.proxy.clone().public();
+		let mut t = new_test_ext_funded(pairs, validators());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/tony1", PHRASE)),
+		).unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		t.execute_with(|| {
+			// Given: I am a proxy whose published x25519 key matches my node's local key
+			let pk: Vec<u8> = Authorities::local_x25519_public_key([1u8; 32]);
+			assert_ok!(Authorities::insert_key(Origin::signed(proxy.clone()), pk.clone()));
+			// When: my node runs without a pending self test request
+			IrisProxy::process_self_test(proxy.clone());
+			// Then: nothing is submitted
+			assert!(pool_state.read().transactions.is_empty());
+			// Given: I have requested a self test
+			assert_ok!(IrisProxy::proxy_self_test(Origin::signed(proxy.clone())));
+			// When: my node processes the request
+			IrisProxy::process_self_test(proxy.clone());
+			// Then: it reports that the self test passed
+			let tx = pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.call, mock::Call::IrisProxy(Call::submit_self_test_result { passed: true }));
+			// Given: I published an x25519 key my node doesn't hold
+			assert_ok!(Authorities::insert_key(
+				Origin::signed(proxy.clone()), test_data.x25519_pk.clone(),
+			));
+			// When: my node processes the request
+			IrisProxy::process_self_test(proxy.clone());
+			// Then: it reports that the self test failed
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+			assert_eq!(tx.call, mock::Call::IrisProxy(Call::submit_self_test_result { passed: false }));
+		});
+	});
+}
+
+#[test]
+fn proxy_self_test_fails_without_x25519_public_key() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: I am a proxy that has not published an x25519 public key
+		let pairs = vec![(test_data.proxy.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I run the self test
+			// Then: the self test fails
+			assert_noop!(IrisProxy::proxy_self_test(
+				Origin::signed(test_data.proxy.clone().public()),
+			), Error::<Test>::SelfTestFailed);
+		});
+	});
+}
+
+#[test]
+fn encrypt_fails_without_proxy_x25519_public_key() {
+	TEST_CONSTANTS.with(|test_data| {
+		// Given: a proxy that has not published an x25519 public key
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			// When: I encrypt data for the proxy
+			let result = IrisProxy::do_encrypt(
+				&test_data.plaintext.clone(),
+				test_data.owner.clone().public(),
+				test_data.proxy.clone().public(),
+				[7u8; 32],
+			);
+			// Then: I'm told the proxy has no key
+			assert_eq!(Err(EncryptionFailure::NoProxyKey), result);
 		});
 	});
 }
 
-// #[test]
-// fn submit_capsule_fragment_fails_if_public_key_unknown() {
-
-// }
-
 #[test]
-fn can_submit_reencryption_keys() {
+fn proxy_migrates_baseline_storage_to_v1() {
 	TEST_CONSTANTS.with(|test_data| {
-		let pairs = vec![(test_data.proxy.clone().public(), 10)];
-
-		let encrypted_sk = EncryptedBox {
-			ciphertext: test_data.ciphertext.clone(),
-			nonce: test_data.nonce.clone(),
-			public_key: test_data.public_key.clone(),
-		};
-
-		let kfrag_assignments = vec![(test_data.proxy.clone().public(), encrypted_sk.clone())];
-
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
 		new_test_ext_funded(pairs, validators()).execute_with(|| {
-			assert_ok!(IrisProxy::submit_reencryption_keys(
-				Origin::signed(test_data.proxy.public().clone()),
-				test_data.consumer.clone().public(),
-				test_data.public_key.clone(),
-				test_data.public_key.clone(),
-				test_data.public_key.clone(),
-				test_data.public_key.clone(),
-				kfrag_assignments.clone(),
-				encrypted_sk.clone(),
-			));
-
-			let reencryption_artifact = ReencryptionArtifacts::<Test>::get(
-				test_data.consumer.clone().public(), test_data.public_key.clone(),
-			).unwrap();
-			assert_eq!(reencryption_artifact.verifying_key, test_data.public_key.clone());
-			assert_eq!(reencryption_artifact.ephemeral_public_key, test_data.public_key.clone());
-			assert_eq!(reencryption_artifact.secret, encrypted_sk);
-			assert_eq!(reencryption_artifact.verified_kfrags, kfrag_assignments.clone());
-
+			let consumer = test_data.consumer.clone().public();
+			let proxy = test_data.proxy.clone().public();
+			// Given: a consumer holds a capsule fragment stored as a raw (nonce, ciphertext, public key) box
+			frame_support::storage::unhashed::put(
+				&crate::EncryptedCapsuleFrags::<Test>::hashed_key_for(&consumer, &vec![1u8, 2, 3]),
+				&vec![(vec![0u8; 24], vec![4u8, 5, 6], vec![7u8; 32])],
+			);
+			// And: a proxy holds a request stored as (caller, data public key, consumer public key)
+			frame_support::storage::unhashed::put(
+				&crate::KeyFragGenerationRequests::<Test>::hashed_key_for(&proxy),
+				&vec![(consumer.clone(), vec![1u8, 2, 3], vec![8u8; 32])],
+			);
+			frame_support::traits::StorageVersion::new(0).put::<IrisProxy>();
+			// When: the runtime is upgraded
+			<IrisProxy as frame_support::traits::Hooks<u64>>::on_runtime_upgrade();
+			// Then: the unattributed fragment and request are removed
+			assert!(crate::EncryptedCapsuleFrags::<Test>::get(&consumer, &vec![1u8, 2, 3]).is_empty());
+			assert!(crate::KeyFragGenerationRequests::<Test>::get(&proxy).is_empty());
+			assert!(frame_support::storage::unhashed::get_raw(
+				&crate::KeyFragGenerationRequests::<Test>::hashed_key_for(&proxy),
+			).is_none());
+			// And: the storage version is current
+			assert_eq!(2, frame_support::traits::StorageVersion::get::<IrisProxy>());
+		});
+	});
+}
 
+#[test]
+fn proxy_migrates_fragment_expiries_to_v2() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![(test_data.owner.clone().public(), 10)];
+		new_test_ext_funded(pairs, validators()).execute_with(|| {
+			use frame_support::{storage::StoragePrefixedMap, StorageHasher};
+			let consumer = test_data.consumer.clone().public();
+			let owner = test_data.owner.clone().public();
+			// Given: two consumers' fragments were listed as expiring at block 5 before the index was keyed
+			let mut key = crate::FragmentExpiries::<Test>::final_prefix().to_vec();
+			key.extend(5u64.using_encoded(frame_support::Blake2_128Concat::hash));
+			frame_support::storage::unhashed::put(
+				&key,
+				&vec![(consumer.clone(), vec![1u8, 2, 3]), (owner.clone(), vec![4u8, 5, 6])],
+			);
+			frame_support::traits::StorageVersion::new(1).put::<IrisProxy>();
+			// When: the migration runs
+			crate::migrations::v2::migrate::<Test>();
+			// Then: each consumer's entry is keyed by the block and the pair
+			assert!(frame_support::storage::unhashed::get_raw(&key).is_none());
+			assert_eq!(Some(()), crate::FragmentExpiries::<Test>::get(5, (consumer, vec![1u8, 2, 3])));
+			assert_eq!(Some(()), crate::FragmentExpiries::<Test>::get(5, (owner, vec![4u8, 5, 6])));
+			// And: the storage version is bumped
+			assert_eq!(2, frame_support::traits::StorageVersion::get::<IrisProxy>());
 		});
 	});
 }
@@ -208,20 +1451,17 @@ fn test_iris_protocol_happy_path() {
 
 		t.execute_with(|| {
 
-			// generate new public key
-			let mut rng = ChaCha20Rng::seed_from_u64(31u64);
-			let secret_key = SecretKey::generate(&mut rng);
-			let pk: Vec<u8> = secret_key.public_key().as_bytes().to_vec();
-
-			Authorities::update_x25519();
+			// the x25519 keys are drawn from the offchain entropy source, so every
+			// artifact below is read back from the transactions the workers submit
+			Authorities::update_x25519([1u8; 32]);
 			pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
+			let pk: Vec<u8> = Authorities::local_x25519_public_key([1u8; 32]);
 			
 			assert_ok!(Authorities::insert_key(
 				Origin::signed(test_data.owner.public().clone()), pk.clone(),
 			));
 
-		
 			assert_ok!(Authorities::insert_key(
 				Origin::signed(proxy.0.clone()), pk.clone(),
 			));
@@ -232,213 +1472,521 @@ fn test_iris_protocol_happy_path() {
 				));
 			}	
 
-			let sk_box = EncryptedBox {
-				nonce: test_data.nonce.clone(),
-				ciphertext: vec![155, 157, 182, 50, 148, 238, 223, 196, 62, 153, 134, 37, 58, 199, 71, 176, 83, 180, 73, 235, 143, 230, 221, 40, 9, 182, 4, 129, 230, 192, 13, 6, 47, 52, 14, 161, 121, 219, 204, 224, 237, 21, 139, 241, 15, 168, 189, 181], 
-				public_key: test_data.x25519_pk.clone() ,
-			};
-
-			let submit_encryption_artifacts_call = mock::Call::IrisProxy(Call::submit_encryption_artifacts { 
-				owner: test_data.owner.clone().public(),
-				proxy: proxy.clone().0, 
-				capsule: test_data.capsule.clone(), 
-				public_key: test_data.public_key.clone(), 
-				encrypted_sk_box: sk_box.clone(),
-			});
-
 			// GIVEN: Some data has been encrypted and added to the ingestion staging map
-			let ciphertext_bytes = IrisProxy::do_encrypt(
+			let encryption = IrisProxy::do_encrypt(
 				&test_data.plaintext.clone(),
 				test_data.owner.clone().public(), // owner
 				proxy.clone().0, // proxy
-			);
+				[7u8; 32], // seed
+			).unwrap();
+			let ciphertext_bytes = encryption.ciphertext.clone();
 			
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
 			assert_eq!(tx.signature, None);
-			assert_eq!(submit_encryption_artifacts_call, tx.call);
-
+			let (capsule, public_key, sk_box) = match tx.call {
+				mock::Call::IrisProxy(Call::submit_encryption_artifacts {
+					owner, proxy: call_proxy, capsule, public_key, encrypted_sk_box,
+				}) => {
+					assert_eq!(test_data.owner.clone().public(), owner);
+					assert_eq!(proxy.clone().0, call_proxy);
+					(capsule, public_key, encrypted_sk_box)
+				},
+				_ => panic!("expected submit_encryption_artifacts"),
+			};
+			// AND: the encryption result references the submitted artifacts
+			assert_eq!(public_key, encryption.public_key);
+			assert_eq!(capsule, encryption.capsule);
 
 			// now we want to simulate the extrinsic being executed
 			assert_ok!(IrisProxy::submit_encryption_artifacts(
 				Origin::signed(test_data.owner.clone().public()), 
 				test_data.owner.clone().public(),  // owner
 				proxy.clone().0, // proxy
-				test_data.capsule.clone(), // capsule 
-				test_data.public_key.clone(), // umbral pk
+				capsule.clone(), // capsule 
+				public_key.clone(), // umbral pk
 				sk_box.clone(), // encrypted sk to decrypt umbral sk 
 			));
 
 			// bypassing Authorization module
 			IrisProxy::add_kfrag_request(
 				test_data.consumer.clone().public(),
-				test_data.public_key.clone(),
+				1,
+				public_key.clone(),
 				consumer_ephemeral_pk.as_bytes().to_vec().clone(),
+				None,
 			);
 
-			let ephemeral_pk_vec = vec![2, 63, 144, 248, 248, 201, 242, 155, 168, 30, 228, 157, 83, 19, 180, 139, 103, 158, 42, 198, 120, 113, 17, 126, 29, 187, 84, 22, 248, 203, 182, 239, 145];
-
-			let frag_0 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![247, 18, 198, 82, 236, 106, 20, 226, 111, 154, 109, 98, 90, 145, 233, 183, 250, 145, 197, 218, 77, 93, 23, 33, 41, 89, 56, 73, 233, 29, 31, 5, 72, 101, 73, 57, 174, 163, 157, 218, 185, 105, 169, 67, 48, 140, 51, 68, 247, 126, 112, 228, 20, 103, 209, 216, 242, 115, 247, 167, 201, 127, 2, 115, 121, 4, 131, 146, 8, 79, 235, 4, 115, 55, 154, 39, 54, 219, 224, 107, 201, 201, 124, 251, 240, 59, 127, 24, 174, 76, 27, 7, 75, 118, 84, 76, 118, 200, 142, 74, 178, 88, 229, 8, 122, 77, 2, 229, 195, 142, 184, 169, 54, 145, 87, 200, 179, 235, 44, 137, 74, 138, 56, 2, 199, 179, 132, 58, 51, 31, 151, 53, 253, 106, 52, 81, 184, 113, 240, 26, 173, 149, 199, 220, 170, 181, 88, 112, 44, 98, 254, 252, 155, 117, 128, 254, 162, 226, 130, 244, 90, 15, 167, 34, 232, 78, 53, 69, 117, 24, 10, 212, 56, 248, 197, 180, 234, 21, 2, 159, 219, 116, 253, 10, 68, 170, 54, 73, 225, 236, 175, 209, 170, 2, 245, 47, 228, 58, 176, 33, 243, 144, 178, 135, 18, 191, 186, 29, 75, 53, 133, 75, 189, 43, 10, 202, 34, 24, 41, 53, 208, 103, 56, 12, 67, 222, 3, 109, 196, 200, 148, 33, 66, 180, 43, 189, 151, 98, 222, 42, 47, 200, 52, 54, 27, 115, 151, 244, 153, 77, 139, 243, 21, 178, 68, 254, 61, 233, 155, 204, 149, 191, 87, 193, 119, 168, 244, 83, 227, 74, 84, 93, 115, 26, 246, 96], 
-			};
-
-			let frag_1 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![161, 216, 252, 203, 193, 155, 29, 193, 13, 15, 118, 62, 186, 245, 143, 124, 250, 203, 30, 24, 34, 76, 33, 238, 39, 196, 246, 81, 71, 140, 222, 40, 161, 194, 194, 132, 115, 193, 239, 109, 75, 88, 145, 161, 147, 195, 14, 204, 12, 89, 253, 228, 167, 104, 220, 101, 66, 48, 123, 8, 107, 130, 8, 188, 119, 241, 174, 220, 217, 8, 182, 173, 17, 252, 242, 7, 144, 171, 199, 199, 201, 201, 124, 251, 240, 59, 127, 24, 174, 76, 27, 7, 75, 118, 84, 76, 118, 200, 142, 74, 178, 88, 229, 8, 122, 77, 2, 229, 195, 142, 184, 169, 54, 144, 144, 6, 171, 18, 22, 190, 11, 16, 230, 10, 164, 192, 4, 70, 221, 206, 71, 160, 176, 159, 26, 188, 74, 21, 139, 26, 81, 141, 171, 67, 164, 145, 80, 157, 24, 5, 170, 195, 41, 53, 19, 73, 239, 114, 54, 37, 40, 223, 183, 245, 29, 190, 71, 111, 52, 57, 254, 42, 189, 147, 127, 198, 173, 109, 108, 95, 76, 109, 221, 82, 181, 228, 82, 247, 221, 84, 221, 212, 19, 201, 111, 207, 40, 41, 61, 168, 92, 5, 95, 240, 255, 104, 225, 135, 110, 249, 95, 126, 122, 27, 90, 239, 217, 255, 80, 20, 81, 172, 114, 4, 151, 48, 190, 116, 86, 103, 20, 169, 217, 162, 108, 162, 101, 130, 63, 17, 80, 197, 24, 77, 105, 176, 66, 246, 26, 61, 159, 147, 99, 25, 174, 120, 39, 116, 144, 40, 11, 30, 190, 151, 19, 140, 214, 90, 237, 149, 80, 21, 118, 94, 246, 96], 
-			};
-
-			let frag_2 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![24, 178, 131, 14, 248, 232, 28, 29, 14, 243, 139, 159, 99, 6, 53, 174, 195, 131, 241, 161, 124, 3, 107, 207, 167, 94, 29, 216, 88, 187, 223, 26, 148, 98, 1, 154, 77, 253, 181, 49, 232, 160, 212, 5, 79, 192, 169, 92, 233, 145, 170, 134, 102, 7, 238, 7, 115, 89, 132, 173, 232, 29, 67, 133, 98, 182, 151, 166, 154, 174, 173, 83, 114, 232, 252, 100, 80, 73, 191, 120, 201, 201, 124, 251, 240, 59, 127, 24, 174, 76, 27, 7, 75, 118, 84, 76, 118, 200, 142, 74, 178, 88, 229, 8, 122, 77, 2, 229, 195, 142, 184, 169, 54, 144, 151, 91, 134, 166, 48, 215, 226, 20, 54, 118, 226, 245, 109, 96, 82, 180, 150, 19, 253, 199, 24, 220, 59, 160, 70, 162, 181, 153, 186, 165, 107, 147, 149, 112, 47, 114, 162, 91, 229, 18, 131, 219, 125, 82, 189, 103, 249, 242, 50, 171, 171, 37, 86, 192, 168, 141, 82, 226, 16, 56, 101, 172, 161, 81, 94, 120, 195, 23, 241, 180, 64, 215, 160, 148, 200, 35, 111, 0, 157, 83, 222, 61, 41, 216, 142, 229, 178, 234, 131, 61, 52, 147, 214, 223, 83, 212, 4, 212, 45, 97, 55, 72, 241, 205, 44, 230, 127, 50, 252, 154, 113, 151, 138, 17, 139, 227, 241, 87, 174, 145, 56, 144, 195, 72, 36, 227, 212, 217, 72, 174, 42, 16, 27, 73, 62, 30, 76, 227, 246, 181, 153, 206, 209, 188, 150, 146, 72, 30, 118, 141, 20, 36, 16, 137, 134, 75, 69, 84, 152, 207, 246, 96], 
-			};
-			let consumer_pk = vec![136, 127, 175, 150, 142, 160, 194, 185, 24, 43, 243, 37, 77, 126, 183, 5, 114, 157, 167, 133, 183, 81, 29, 217, 53, 237, 240, 233, 111, 29, 9, 84];
-
-			let encrypted_receiving_sk = EncryptedBox {
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![103, 77, 91, 193, 13, 150, 184, 196, 151, 197, 24, 130, 29, 254, 134, 97, 33, 235, 189, 114, 226, 177, 224, 84, 255, 131, 160, 36, 156, 222, 195, 90, 68, 90, 162, 11, 66, 238, 90, 83, 232, 118, 116, 104, 115, 97, 161, 104],
-			};
-
-			let call = mock::Call::IrisProxy(Call::submit_reencryption_keys { 
-				consumer: test_data.consumer.clone().public(), 
-				receiving_public_key: ephemeral_pk_vec.clone(), 
-				delegating_public_key: test_data.public_key.clone(), 
-				kfrag_assignments: vec![
-					(validators[0].clone().0, frag_0.clone()), 
-					(validators[1].clone().0, frag_1.clone()),
-					(validators[2].clone().0, frag_2.clone()),
-				],
-				encrypted_receiving_sk: encrypted_receiving_sk.clone(),
-				consumer_public_key: consumer_pk.clone(),
-				verifying_public_key: test_data.public_key.clone(),
-			});
-
 			let candidates = validators.clone().iter().map(|v| v.0).collect::<Vec<_>>();
 			// THEN: I can generate new key fragments for the caller
 			assert_ok!(IrisProxy::proxy_process_kfrag_generation_requests(
 				proxy.clone().0,
 				candidates.clone(),
+				[1u8; 32],
 			));
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
 			assert_eq!(tx.signature.unwrap().0, 1);
-			assert_eq!(call, tx.call);
+			let (
+				receiving_public_key, kfrag_assignments, encrypted_receiving_sk, verifying_public_key,
+			) = match tx.call {
+				mock::Call::IrisProxy(Call::submit_reencryption_keys {
+					consumer, receiving_public_key, delegating_public_key, kfrag_assignments,
+					encrypted_receiving_sk, consumer_public_key, verifying_public_key,
+				}) => {
+					assert_eq!(test_data.consumer.clone().public(), consumer);
+					assert_eq!(public_key.clone(), delegating_public_key);
+					assert_eq!(consumer_ephemeral_pk.as_bytes().to_vec(), consumer_public_key);
+					(receiving_public_key, kfrag_assignments, encrypted_receiving_sk, verifying_public_key)
+				},
+				_ => panic!("expected submit_reencryption_keys"),
+			};
+			// each validator is assigned a key fragment
+			let mut holders = kfrag_assignments.iter().map(|a| a.0).collect::<Vec<_>>();
+			holders.sort();
+			let mut expected = candidates.clone();
+			expected.sort();
+			assert_eq!(expected, holders);
 			// // // Then: When the extrinsic is executed
 			assert_ok!(IrisProxy::submit_reencryption_keys(
 				Origin::signed(proxy.clone().0),
 				test_data.consumer.clone().public(),
-				ephemeral_pk_vec.clone(),
-				test_data.public_key.clone(),
+				receiving_public_key.clone(),
+				public_key.clone(),
 				consumer_ephemeral_pk.as_bytes().to_vec().clone(),
-				test_data.public_key.clone(),
-				vec![
-					(validators[0].clone().0, frag_0.clone()), 
-					(validators[1].clone().0, frag_1.clone()),
-					(validators[2].clone().0, frag_2.clone()),
-				],
+				verifying_public_key.clone(),
+				kfrag_assignments.clone(),
 				encrypted_receiving_sk.clone(),
 			));
-			// AND: I process reencryption requests
-			assert_ok!(IrisProxy::kfrag_holder_process_reencryption_requests(
-				validators[0].clone().0,
-			));
 
-			let encrypted_cfrag_0 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![205, 30, 94, 241, 154, 185, 205, 232, 31, 134, 110, 188, 95, 157, 125, 44, 10, 53, 108, 82, 95, 227, 60, 59, 72, 135, 194, 21, 216, 98, 202, 179, 236, 225, 199, 78, 187, 202, 30, 5, 57, 100, 41, 160, 25, 17, 146, 228, 17, 190, 136, 103, 146, 188, 158, 252, 142, 231, 127, 148, 223, 226, 177, 9, 69, 100, 56, 103, 131, 206, 82, 33, 54, 250, 69, 147, 203, 140, 222, 32, 179, 206, 31, 88, 217, 180, 89, 24, 147, 243, 195, 124, 47, 46, 13, 218, 51, 16, 130, 175, 159, 143, 0, 13, 124, 237, 96, 225, 43, 224, 80, 236, 83, 7, 49, 92, 92, 239, 167, 129, 44, 69, 177, 2, 150, 156, 127, 54, 253, 127, 60, 230, 110, 253, 20, 148, 130, 34, 142, 100, 100, 163, 59, 99, 177, 53, 67, 248, 222, 67, 255, 236, 159, 109, 238, 110, 96, 214, 53, 159, 24, 16, 44, 18, 203, 157, 86, 217, 131, 164, 57, 104, 26, 152, 66, 38, 249, 213, 158, 197, 235, 209, 179, 221, 120, 68, 246, 13, 3, 155, 201, 235, 188, 154, 232, 221, 52, 212, 23, 75, 238, 125, 102, 26, 85, 218, 200, 175, 84, 212, 46, 150, 105, 141, 14, 116, 139, 152, 238, 58, 71, 26, 155, 35, 180, 113, 229, 91, 38, 14, 211, 148, 112, 202, 44, 78, 98, 201, 57, 96, 91, 185, 32, 42, 47, 136, 28, 80, 39, 140, 138, 183, 170, 196, 221, 172, 29, 165, 104, 229, 33, 201, 8, 34, 232, 60, 113, 151, 20, 68, 34, 158, 56, 164, 245, 21, 3, 251, 30, 10, 120, 52, 128, 160, 51, 92, 215, 125, 68, 22, 146, 238, 105, 168, 27, 243, 32, 67, 202, 192, 28, 195, 179, 87, 62, 104, 253, 147, 30, 195, 232, 60, 9, 199, 232, 40, 82, 41, 47, 209, 93, 251, 52, 93, 74, 124, 228, 3, 41, 170, 143, 225, 102, 206, 41, 212, 73, 35, 203, 220, 11, 41, 207, 39, 23, 26, 207, 158, 223, 98, 227, 70, 183, 98, 157, 175, 217, 190, 143, 245, 108, 72, 22, 13, 218, 110, 245, 176, 219, 107, 235, 221, 133, 25, 146], 
-			};
+			for (i, v) in validators.iter().enumerate() {
+				// AND: I process reencryption requests
+				assert_ok!(IrisProxy::kfrag_holder_process_reencryption_requests(
+					v.clone().0,
+					[1u8; 32],
+				));
+				let tx = pool_state.write().transactions.pop().unwrap();
+				assert!(pool_state.read().transactions.is_empty());
+				let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+				assert_eq!(tx.signature.unwrap().0, 2 + i as u64);
+				let encrypted_cfrag = match tx.call {
+					mock::Call::IrisProxy(Call::submit_fragments { mut fragments }) => {
+						// the proxy holds a single request, so its batch holds a single fragment
+						assert_eq!(1, fragments.len());
+						let (data_consumer, cfrag_public_key, encrypted_cfrag_data) = fragments.remove(0);
+						assert_eq!(test_data.consumer.public().clone(), data_consumer);
+						assert_eq!(public_key.clone(), cfrag_public_key);
+						encrypted_cfrag_data
+					},
+					_ => panic!("expected submit_fragments"),
+				};
+				// And: I submit capsule fragments 
+				assert_ok!(IrisProxy::submit_capsule_fragment(
+					Origin::signed(v.0.clone()),
+					test_data.consumer.public().clone(),
+					public_key.clone(),
+					encrypted_cfrag.clone(),
+				));
+			}
 
-			let v_0_call = mock::Call::IrisProxy(Call::submit_capsule_fragment {
-				data_consumer: test_data.consumer.public().clone(), 
-				public_key: test_data.public_key.clone(), 
-				encrypted_cfrag_data: encrypted_cfrag_0.clone()
-			});
+			// When: I try to decrypt data with all available fragments
+			let plaintext = IrisProxy::do_decrypt(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				None,
+			).unwrap();
+			// Then: the recovered plaintext matches the input plaintext
+			assert_eq!(test_data.plaintext.clone(), plaintext.to_vec());
 
-			let tx = pool_state.write().transactions.pop().unwrap();
-			assert!(pool_state.read().transactions.is_empty());
-			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 2);
-			assert_eq!(v_0_call, tx.call);
-			// // And: I submit capsule fragments 
-			assert_ok!(IrisProxy::submit_capsule_fragment(
-				Origin::signed(validators[0].0.clone()),
+			// When: I try to decrypt data with exactly the threshold number of fragments
+			let plaintext = IrisProxy::do_decrypt(
 				test_data.consumer.public().clone(),
-				test_data.public_key.clone(),
-				encrypted_cfrag_0.clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				Some(crate::KFRAG_THRESHOLD as u32),
+			).unwrap();
+			// Then: the recovered plaintext matches the input plaintext
+			assert_eq!(test_data.plaintext.clone(), plaintext.to_vec());
+
+			// When: I try to decrypt data with fewer fragments than the threshold
+			let result = IrisProxy::do_decrypt(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				Some(crate::KFRAG_THRESHOLD as u32 - 1),
+			);
+			// Then: I'm told how many fragments I asked for and how many are needed
+			assert_eq!(Err(DecryptionFailure::TooFewFragmentsRequested {
+				requested: crate::KFRAG_THRESHOLD as u32 - 1,
+				need: crate::KFRAG_THRESHOLD as u32,
+			}), result);
+
+			// When: only one fewer than the threshold number of fragments has been delivered
+			let delivered = crate::EncryptedCapsuleFrags::<Test>::get(
+				test_data.consumer.public().clone(), public_key.clone(),
+			);
+			crate::EncryptedCapsuleFrags::<Test>::mutate(
+				test_data.consumer.public().clone(), public_key.clone(),
+				|frags| frags.truncate(crate::KFRAG_THRESHOLD - 1),
+			);
+			let result = IrisProxy::do_decrypt(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				None,
+			);
+			// Then: I'm told how many fragments are available and how many are needed
+			assert_eq!(Err(DecryptionFailure::NotEnoughFragments {
+				have: crate::KFRAG_THRESHOLD as u32 - 1,
+				need: crate::KFRAG_THRESHOLD as u32,
+			}), result);
+			crate::EncryptedCapsuleFrags::<Test>::insert(
+				test_data.consumer.public().clone(), public_key.clone(), delivered,
+			);
+
+			// When: I recover the decryption key from the fragments
+			let key = IrisProxy::recover_decryption_key(
+				test_data.consumer.public().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				usize::MAX,
+			).unwrap();
+			// Then: the key decrypts the data without the fragments being verified again
+			let plaintext = IrisProxy::apply_decryption_key(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				&key,
+			).unwrap();
+			assert_eq!(test_data.plaintext.clone(), plaintext.to_vec());
+
+			// When: I report the fragment the first proxy delivered once opened
+			assert_ok!(IrisProxy::report_capsule_fragment(
+				Origin::signed(test_data.consumer.public().clone()),
+				public_key.clone(),
+				validators[0].0.clone(),
+				key.capsule_fragments[0].clone(),
+			));
+			// Then: it passed verification and counts towards the proxy's reputation
+			assert_eq!(
+				ProxyStats { valid_fragments: 1, invalid_fragments: 0 },
+				IrisProxy::proxy_reputation(validators[0].0.clone()),
+			);
+			// And: it can't be reported again
+			assert_noop!(IrisProxy::report_capsule_fragment(
+				Origin::signed(test_data.consumer.public().clone()),
+				public_key.clone(),
+				validators[0].0.clone(),
+				key.capsule_fragments[0].clone(),
+			), Error::<Test>::FragmentAlreadyReported);
+			// When: I report a fragment from another proxy that fails verification
+			assert_ok!(IrisProxy::report_capsule_fragment(
+				Origin::signed(test_data.consumer.public().clone()),
+				public_key.clone(),
+				validators[1].0.clone(),
+				vec![1, 2, 3],
 			));
+			// Then: it counts against that proxy's reputation
+			assert_eq!(
+				ProxyStats { valid_fragments: 0, invalid_fragments: 1 },
+				IrisProxy::proxy_reputation(validators[1].0.clone()),
+			);
+			// And: a proxy that delivered no fragment can't be reported
+			assert_noop!(IrisProxy::report_capsule_fragment(
+				Origin::signed(test_data.consumer.public().clone()),
+				public_key.clone(),
+				test_data.owner.public().clone(),
+				vec![1, 2, 3],
+			), Error::<Test>::NoSuchCapsuleFragment);
 
-			let encrypted_cfrag_1 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![255, 31, 73, 99, 158, 132, 255, 175, 231, 180, 90, 179, 21, 128, 106, 185, 11, 251, 169, 105, 105, 223, 90, 229, 102, 5, 220, 2, 98, 26, 242, 253, 123, 172, 22, 98, 55, 86, 134, 97, 195, 130, 196, 114, 197, 192, 215, 31, 148, 190, 228, 66, 253, 170, 101, 248, 235, 224, 29, 93, 75, 163, 109, 234, 103, 176, 109, 205, 132, 12, 118, 172, 150, 156, 35, 15, 57, 151, 202, 233, 149, 131, 31, 2, 2, 118, 54, 9, 165, 60, 205, 225, 225, 54, 163, 75, 242, 61, 107, 8, 20, 50, 221, 111, 14, 90, 146, 208, 19, 2, 243, 163, 110, 143, 49, 92, 92, 239, 167, 129, 44, 69, 177, 2, 150, 156, 127, 54, 253, 127, 60, 230, 110, 253, 20, 148, 130, 34, 142, 100, 100, 163, 59, 99, 177, 53, 67, 248, 222, 67, 255, 236, 159, 109, 238, 110, 96, 214, 53, 159, 24, 16, 44, 18, 203, 157, 86, 217, 131, 164, 57, 104, 26, 152, 66, 38, 249, 213, 158, 197, 235, 209, 179, 221, 120, 68, 246, 13, 3, 155, 201, 235, 188, 154, 232, 221, 52, 212, 23, 75, 238, 125, 102, 26, 85, 218, 200, 175, 84, 212, 46, 150, 105, 140, 201, 186, 147, 97, 212, 13, 6, 128, 69, 43, 215, 2, 101, 39, 200, 223, 3, 1, 61, 63, 2, 163, 144, 173, 66, 96, 167, 161, 76, 181, 33, 172, 28, 80, 39, 140, 138, 183, 170, 196, 221, 172, 29, 165, 104, 229, 33, 201, 8, 34, 232, 60, 113, 151, 20, 68, 34, 158, 56, 164, 245, 21, 3, 251, 30, 128, 91, 103, 217, 76, 30, 149, 168, 129, 59, 198, 79, 56, 108, 152, 227, 52, 153, 209, 110, 84, 192, 140, 255, 218, 82, 40, 77, 175, 128, 156, 170, 230, 60, 0, 216, 120, 119, 210, 200, 168, 124, 122, 255, 23, 66, 168, 10, 190, 48, 56, 32, 97, 238, 85, 63, 147, 86, 209, 43, 61, 48, 86, 194, 11, 108, 104, 12, 75, 221, 225, 147, 82, 215, 20, 54, 69, 95, 164, 18, 254, 136, 214, 183, 228, 140, 10, 209, 146, 210, 101, 52, 217, 205, 28, 214], 
-			};
+			// When: the consumer is issued new reencryption artifacts
+			let artifact = crate::ReencryptionArtifacts::<Test>::get(
+				test_data.consumer.public().clone(), public_key.clone(),
+			).unwrap();
+			crate::ReencryptionArtifacts::<Test>::mutate(
+				test_data.consumer.public().clone(), public_key.clone(),
+				|artifact| artifact.as_mut().unwrap().ephemeral_public_key = consumer_ephemeral_pk.as_bytes().to_vec(),
+			);
+			// Then: the recovered key is stale
+			assert_eq!(None, IrisProxy::apply_decryption_key(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				&key,
+			));
+			crate::ReencryptionArtifacts::<Test>::insert(
+				test_data.consumer.public().clone(), public_key.clone(), artifact,
+			);
 
-			let v_1_call = mock::Call::IrisProxy(Call::submit_capsule_fragment {
-				data_consumer: test_data.consumer.public().clone(), 
-				public_key: test_data.public_key.clone(), 
-				encrypted_cfrag_data: encrypted_cfrag_1.clone()
-			});
+			// When: the capsule fragments expire
+			let expires_at = <Test as crate::Config>::FragmentTTL::get();
+			IrisProxy::on_idle(expires_at, Weight::max_value());
+			// Then: the recovered key can no longer be used
+			assert_eq!(None, IrisProxy::apply_decryption_key(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				&key,
+			));
+			// Then: decryption fails
+			let result = IrisProxy::do_decrypt(
+				test_data.consumer.public().clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				None,
+			);
+			assert_eq!(Err(DecryptionFailure::NotEnoughFragments {
+				have: 0,
+				need: crate::KFRAG_THRESHOLD as u32,
+			}), result);
+		});
+	});
+}
+
+#[test]
+fn test_decryption_fails_once_access_expires() {
+	TEST_CONSTANTS.with(|test_data| {
+		let pairs = vec![
+			(test_data.owner.clone().public(), 10),
+			(test_data.consumer.clone().public(), 10),
+			(test_data.proxy.clone().public(), 10)
+		];
+
+		let mut rng = ChaCha20Rng::seed_from_u64(31u64);
+
+		// this is the new ephemeral keypair created by consumer 
+		let consumer_sk = SecretKey::generate(&mut rng);
+		let consumer_ephemeral_pk = consumer_sk.public_key();
+
+		// let proxy_sk = SecretKey::generate(&mut rng);
+
+		let validators = validators();
+		let proxy = validators[0].clone();
+		let mut t = new_test_ext_funded(pairs, validators.clone());
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+
+		let keystore = KeyStore::new();
+		const PHRASE: &str =
+			"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+		SyncCryptoStore::sr25519_generate_new(
+			&keystore,
+			crate::crypto::Public::ID,
+			Some(&format!("{}/tony1", PHRASE)),
+		).unwrap();
+
+		t.register_extension(OffchainWorkerExt::new(offchain.clone()));
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt(Arc::new(keystore)));
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			// access is granted for fewer blocks than capsule fragments live
+			let expires_at = 6;
+
+			// the x25519 keys are drawn from the offchain entropy source, so every
+			// artifact below is read back from the transactions the workers submit
+			Authorities::update_x25519([1u8; 32]);
+			pool_state.write().transactions.pop().unwrap();
+			assert!(pool_state.read().transactions.is_empty());
+			let pk: Vec<u8> = Authorities::local_x25519_public_key([1u8; 32]);
+			
+			assert_ok!(Authorities::insert_key(
+				Origin::signed(test_data.owner.public().clone()), pk.clone(),
+			));
 
-			assert_ok!(IrisProxy::kfrag_holder_process_reencryption_requests(
-				validators[1].clone().0,
+			assert_ok!(Authorities::insert_key(
+				Origin::signed(proxy.0.clone()), pk.clone(),
 			));
+
+			for v in validators.clone() {
+				assert_ok!(Authorities::insert_key(
+					Origin::signed(v.0.clone()), pk.clone(),
+				));
+			}	
+
+			// GIVEN: Some data has been encrypted and added to the ingestion staging map
+			let encryption = IrisProxy::do_encrypt(
+				&test_data.plaintext.clone(),
+				test_data.owner.clone().public(), // owner
+				proxy.clone().0, // proxy
+				[7u8; 32], // seed
+			).unwrap();
+			let ciphertext_bytes = encryption.ciphertext.clone();
+			
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 3);
-			assert_eq!(v_1_call, tx.call);
-			// And: I submit capsule fragments 
-			assert_ok!(IrisProxy::submit_capsule_fragment(
-				Origin::signed(validators[1].0.clone()),
-				test_data.consumer.public().clone(),
-				test_data.public_key.clone(),
-				encrypted_cfrag_1.clone(),
-			));
+			assert_eq!(tx.signature, None);
+			let (capsule, public_key, sk_box) = match tx.call {
+				mock::Call::IrisProxy(Call::submit_encryption_artifacts {
+					owner, proxy: call_proxy, capsule, public_key, encrypted_sk_box,
+				}) => {
+					assert_eq!(test_data.owner.clone().public(), owner);
+					assert_eq!(proxy.clone().0, call_proxy);
+					(capsule, public_key, encrypted_sk_box)
+				},
+				_ => panic!("expected submit_encryption_artifacts"),
+			};
+			// AND: the encryption result references the submitted artifacts
+			assert_eq!(public_key, encryption.public_key);
+			assert_eq!(capsule, encryption.capsule);
 
+			// now we want to simulate the extrinsic being executed
+			assert_ok!(IrisProxy::submit_encryption_artifacts(
+				Origin::signed(test_data.owner.clone().public()), 
+				test_data.owner.clone().public(),  // owner
+				proxy.clone().0, // proxy
+				capsule.clone(), // capsule 
+				public_key.clone(), // umbral pk
+				sk_box.clone(), // encrypted sk to decrypt umbral sk 
+			));
 
-			let encrypted_cfrag_2 = EncryptedBox { 
-				nonce: test_data.nonce.clone(), 
-				public_key: test_data.x25519_pk.clone(),
-				ciphertext: vec![31, 252, 9, 61, 32, 38, 32, 113, 130, 128, 11, 189, 252, 226, 34, 185, 10, 94, 114, 186, 49, 45, 18, 94, 54, 186, 86, 241, 171, 40, 6, 221, 19, 212, 243, 96, 175, 200, 215, 141, 126, 16, 160, 76, 51, 49, 251, 214, 24, 191, 204, 98, 190, 250, 164, 74, 220, 63, 220, 241, 24, 105, 198, 153, 226, 11, 221, 252, 178, 112, 116, 53, 172, 87, 243, 217, 28, 246, 17, 214, 90, 208, 38, 74, 237, 207, 104, 70, 239, 29, 77, 123, 10, 191, 188, 124, 243, 15, 94, 168, 215, 44, 227, 83, 84, 6, 49, 40, 86, 166, 47, 160, 201, 31, 49, 92, 92, 239, 167, 129, 44, 69, 177, 2, 150, 156, 127, 54, 253, 127, 60, 230, 110, 253, 20, 148, 130, 34, 142, 100, 100, 163, 59, 99, 177, 53, 67, 248, 222, 67, 255, 236, 159, 109, 238, 110, 96, 214, 53, 159, 24, 16, 44, 18, 203, 157, 86, 217, 131, 164, 57, 104, 26, 152, 66, 38, 249, 213, 158, 197, 235, 209, 179, 221, 120, 68, 246, 13, 3, 155, 201, 235, 188, 154, 232, 221, 52, 212, 23, 75, 238, 125, 102, 26, 85, 218, 200, 175, 84, 212, 46, 150, 105, 140, 206, 231, 190, 213, 242, 100, 239, 132, 149, 87, 145, 55, 12, 1, 71, 165, 210, 178, 112, 103, 0, 195, 225, 24, 143, 216, 67, 181, 93, 83, 238, 174, 28, 80, 39, 140, 138, 183, 170, 196, 221, 172, 29, 165, 104, 229, 33, 201, 8, 34, 232, 60, 113, 151, 20, 68, 34, 158, 56, 164, 245, 21, 3, 251, 30, 80, 63, 30, 232, 209, 133, 177, 36, 29, 6, 114, 201, 187, 231, 52, 129, 77, 71, 192, 118, 92, 146, 225, 235, 231, 65, 143, 110, 163, 15, 59, 89, 189, 150, 87, 162, 21, 208, 250, 250, 212, 142, 84, 97, 153, 220, 78, 173, 138, 85, 229, 164, 132, 16, 34, 12, 199, 100, 119, 225, 38, 194, 210, 222, 91, 143, 43, 172, 18, 98, 197, 176, 129, 167, 129, 154, 114, 233, 82, 218, 248, 50, 149, 183, 44, 150, 13, 121, 84, 1, 14, 234, 204, 140, 242, 71], 
-			};
-			let v_2_call = mock::Call::IrisProxy(Call::submit_capsule_fragment {
-				data_consumer: test_data.consumer.public().clone(), 
-				public_key: test_data.public_key.clone(), 
-				encrypted_cfrag_data: encrypted_cfrag_2.clone()
-			});
+			// bypassing Authorization module, the consumer is granted access until `expires_at`
+			IrisProxy::add_kfrag_request(
+				test_data.consumer.clone().public(),
+				1,
+				public_key.clone(),
+				consumer_ephemeral_pk.as_bytes().to_vec().clone(),
+				Some(expires_at),
+			);
 
-			assert_ok!(IrisProxy::kfrag_holder_process_reencryption_requests(
-				validators[2].clone().0,
+			let candidates = validators.clone().iter().map(|v| v.0).collect::<Vec<_>>();
+			// THEN: I can generate new key fragments for the caller
+			assert_ok!(IrisProxy::proxy_process_kfrag_generation_requests(
+				proxy.clone().0,
+				candidates.clone(),
+				[1u8; 32],
 			));
 			let tx = pool_state.write().transactions.pop().unwrap();
 			assert!(pool_state.read().transactions.is_empty());
 			let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
-			assert_eq!(tx.signature.unwrap().0, 4);
-			assert_eq!(v_2_call, tx.call);
-			// And: I submit capsule fragments 
-			assert_ok!(IrisProxy::submit_capsule_fragment(
-				Origin::signed(validators[2].0.clone()),
-				test_data.consumer.public().clone(),
-				test_data.public_key.clone(),
-				encrypted_cfrag_2.clone(),
+			assert_eq!(tx.signature.unwrap().0, 1);
+			let (
+				receiving_public_key, kfrag_assignments, encrypted_receiving_sk, verifying_public_key,
+			) = match tx.call {
+				mock::Call::IrisProxy(Call::submit_reencryption_keys {
+					consumer, receiving_public_key, delegating_public_key, kfrag_assignments,
+					encrypted_receiving_sk, consumer_public_key, verifying_public_key,
+				}) => {
+					assert_eq!(test_data.consumer.clone().public(), consumer);
+					assert_eq!(public_key.clone(), delegating_public_key);
+					assert_eq!(consumer_ephemeral_pk.as_bytes().to_vec(), consumer_public_key);
+					(receiving_public_key, kfrag_assignments, encrypted_receiving_sk, verifying_public_key)
+				},
+				_ => panic!("expected submit_reencryption_keys"),
+			};
+			// each validator is assigned a key fragment
+			let mut holders = kfrag_assignments.iter().map(|a| a.0).collect::<Vec<_>>();
+			holders.sort();
+			let mut expected = candidates.clone();
+			expected.sort();
+			assert_eq!(expected, holders);
+			// // // Then: When the extrinsic is executed
+			assert_ok!(IrisProxy::submit_reencryption_keys(
+				Origin::signed(proxy.clone().0),
+				test_data.consumer.clone().public(),
+				receiving_public_key.clone(),
+				public_key.clone(),
+				consumer_ephemeral_pk.as_bytes().to_vec().clone(),
+				verifying_public_key.clone(),
+				kfrag_assignments.clone(),
+				encrypted_receiving_sk.clone(),
 			));
 
-			// When: I try to decrypt data
+			for (i, v) in validators.iter().enumerate() {
+				// AND: I process reencryption requests
+				assert_ok!(IrisProxy::kfrag_holder_process_reencryption_requests(
+					v.clone().0,
+					[1u8; 32],
+				));
+				let tx = pool_state.write().transactions.pop().unwrap();
+				assert!(pool_state.read().transactions.is_empty());
+				let tx = mock::Extrinsic::decode(&mut &*tx).unwrap();
+				assert_eq!(tx.signature.unwrap().0, 2 + i as u64);
+				let encrypted_cfrag = match tx.call {
+					mock::Call::IrisProxy(Call::submit_fragments { mut fragments }) => {
+						// the proxy holds a single request, so its batch holds a single fragment
+						assert_eq!(1, fragments.len());
+						let (data_consumer, cfrag_public_key, encrypted_cfrag_data) = fragments.remove(0);
+						assert_eq!(test_data.consumer.public().clone(), data_consumer);
+						assert_eq!(public_key.clone(), cfrag_public_key);
+						encrypted_cfrag_data
+					},
+					_ => panic!("expected submit_fragments"),
+				};
+				// And: I submit capsule fragments 
+				assert_ok!(IrisProxy::submit_capsule_fragment(
+					Origin::signed(v.0.clone()),
+					test_data.consumer.public().clone(),
+					public_key.clone(),
+					encrypted_cfrag.clone(),
+				));
+			}
+
+			// Then: the fragments expire with the grant rather than after their TTL
+			let consumer = test_data.consumer.public().clone();
+			assert!(crate::EncryptedCapsuleFrags::<Test>::get(consumer.clone(), public_key.clone())
+				.iter()
+				.all(|f| f.expires_at == expires_at));
+
+			// When: I decrypt the data before access expires
+			System::set_block_number(expires_at - 1);
 			let plaintext = IrisProxy::do_decrypt(
-				test_data.consumer.public().clone(),
+				consumer.clone(),
 				ciphertext_bytes.to_vec().clone(),
-				test_data.public_key.clone(),
+				public_key.clone(),
 				consumer_sk.clone(),
-			);
+				None,
+			).unwrap();
 			// Then: the recovered plaintext matches the input plaintext
 			assert_eq!(test_data.plaintext.clone(), plaintext.to_vec());
+			let key = IrisProxy::recover_decryption_key(
+				consumer.clone(), public_key.clone(), consumer_sk.clone(), usize::MAX,
+			).unwrap();
+
+			// When: access expires but the fragments haven't been swept yet
+			System::set_block_number(expires_at);
+			// Then: the fragments can't be used to decrypt the data
+			assert_eq!(Err(DecryptionFailure::NotEnoughFragments {
+				have: 0,
+				need: crate::KFRAG_THRESHOLD as u32,
+			}), IrisProxy::do_decrypt(
+				consumer.clone(),
+				ciphertext_bytes.to_vec().clone(),
+				public_key.clone(),
+				consumer_sk.clone(),
+				None,
+			));
+			// And: neither can a key recovered before access expired
+			assert_eq!(None, IrisProxy::apply_decryption_key(
+				consumer.clone(), ciphertext_bytes.to_vec().clone(), public_key.clone(), &key,
+			));
+
+			// When: the fragment expiry sweep runs
+			IrisProxy::on_idle(expires_at, Weight::max_value());
+			// Then: the consumer's fragments and grant are removed
+			System::assert_last_event(mock::Event::IrisProxy(crate::Event::AccessExpired(
+				consumer.clone(), public_key.clone(),
+			)));
+			assert!(crate::EncryptedCapsuleFrags::<Test>::get(consumer.clone(), public_key.clone()).is_empty());
+			assert_eq!(None, crate::GrantedAssets::<Test>::get(consumer.clone(), public_key.clone()));
+			// And: fragments delivered late are rejected
+			assert_noop!(IrisProxy::submit_capsule_fragment(
+				Origin::signed(proxy.clone().0),
+				consumer.clone(),
+				public_key.clone(),
+				sk_box.clone(),
+			), Error::<Test>::AccessExpired);
 		});
 	});
 }
@@ -462,6 +2010,27 @@ fn test_iris_protocol_happy_path() {
 
 // }
 
+/// Record the proxies assigned key fragments for a consumer's reencryption request,
+/// as `submit_reencryption_keys` does, so that they can deliver capsule fragments
+fn assign_kfrag_holders(
+	consumer: sp_core::sr25519::Public,
+	public_key: Vec<u8>,
+	holders: Vec<sp_core::sr25519::Public>,
+) {
+	let encrypted_kfrag = EncryptedBox { nonce: Vec::new(), ciphertext: Vec::new(), public_key: Vec::new() };
+	crate::ReencryptionArtifacts::<Test>::insert(consumer, public_key, ReencryptionArtifact {
+		verifying_key: Vec::new(),
+		secret: encrypted_kfrag.clone(),
+		ephemeral_public_key: Vec::new(),
+		verified_kfrags: holders.into_iter().map(|holder| (holder, encrypted_kfrag.clone())).collect(),
+	});
+}
+
+/// Distinct accounts to act as key fragment holders
+fn kfrag_holders(count: usize) -> Vec<sp_core::sr25519::Public> {
+	(0..count).map(|i| sp_core::sr25519::Public::from_raw([i as u8 + 1; 32])).collect()
+}
+
 // TODO: move this into TEST_DATA
 fn validators() -> Vec<(sp_core::sr25519::Public, UintAuthorityId)> {
 	let v0: (sp_core::sr25519::Public, UintAuthorityId) = (
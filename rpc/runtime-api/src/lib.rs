@@ -20,6 +20,12 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use sp_core::Bytes;
+use iris_primitives::{
+	AssetHistoryEntry, AssetPreview, CommandAssignment, DecryptionContext, DecryptionFailure, DecryptionReadiness,
+	DecryptionKey, DelayInfo, EncryptionFailure, EncryptionResult, GatewayStats, PendingReencryption, PendingReserves, ProxyStats, RequestStatus,
+	UnstagedEncryption,
+};
+use sp_std::vec::Vec;
 
 use codec::Codec;
 use sp_runtime::{
@@ -27,6 +33,11 @@ use sp_runtime::{
 };
 
 sp_api::decl_runtime_apis! {
+	/// Version history:
+	/// 1. encrypt, decrypt
+	/// 2. encrypt takes a seed and returns the data public key and capsule, decrypt takes the
+	///    number of fragments to use and reports why it failed, and the query methods below
+	#[api_version(2)]
 	pub trait EncryptionApi<Balance> 
 		where Balance: Codec + MaybeDisplay,
 	{
@@ -36,7 +47,8 @@ sp_api::decl_runtime_apis! {
 			signer: Bytes,
 			message: Bytes,
 			proxy: Bytes,
-		) -> Bytes;
+			seed: [u8; 32],
+		) -> Result<EncryptionResult, EncryptionFailure>;
 
 		fn decrypt(
 			ciphertext: Bytes,
@@ -45,7 +57,102 @@ sp_api::decl_runtime_apis! {
 			message: Bytes,
 			asset_id: u32,
 			secret_key: Bytes,
+			use_fragments: Option<u32>,
+		) -> Result<Bytes, DecryptionFailure>;
+
+		fn peek(
+			asset_id: u32,
+			signer: Bytes,
+		) -> Option<AssetPreview>;
+
+		fn estimate_reserve(
+			gateway: Bytes,
+			estimated_size: u128,
+		) -> Balance;
+
+		fn ingested_assets(
+			gateway: Bytes,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32>;
+
+		fn gateway_stats(
+			gateway: Bytes,
+		) -> GatewayStats;
+
+		fn request_status(
+			request_id: [u8; 32],
+		) -> Option<RequestStatus>;
+
+		fn recommend_gateways(
+			estimated_size: u128,
+			count: u32,
+		) -> Vec<Bytes>;
+
+		fn decryption_context(
+			asset_id: u32,
+		) -> Option<DecryptionContext>;
+
+		fn pending_reserves(
+			gateway: Bytes,
+		) -> PendingReserves<Balance>;
+
+		fn pending_reencryptions(
+			proxy: Bytes,
+		) -> Vec<PendingReencryption>;
+
+		fn decryption_key(
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			asset_id: u32,
+			secret_key: Bytes,
+			use_fragments: Option<u32>,
+		) -> Result<Option<DecryptionKey>, DecryptionFailure>;
+
+		fn decrypt_with_key(
+			ciphertext: Bytes,
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			asset_id: u32,
+			key: DecryptionKey,
 		) -> Option<Bytes>;
 
+		fn current_delay() -> DelayInfo;
+
+		fn assets_in_dataspace(
+			dataspace_id: u32,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32>;
+
+		fn asset_exists(asset_id: u32) -> bool;
+
+		fn command_assignments(owner: Bytes, cid: Bytes) -> Vec<CommandAssignment>;
+
+		fn proxy_reputation(proxy: Bytes) -> ProxyStats;
+
+		fn encrypt_for_request(
+			plaintext: Bytes,
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			proxy: Bytes,
+			seed: [u8; 32],
+		) -> Result<UnstagedEncryption, EncryptionFailure>;
+
+		fn vesting_target_block(estimated_size: u128) -> u32;
+
+		fn decrypt_precheck(asset_id: u32, account: Bytes) -> DecryptionReadiness;
+
+		fn assets_created_between(
+			from_block: u32,
+			to_block: u32,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32>;
+
+		fn asset_history(asset_id: u32) -> Vec<AssetHistoryEntry>;
 	}
 }
@@ -0,0 +1,197 @@
+// This file is part of Iris.
+//
+// Copyright (C) 2022 Ideal Labs.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! A node-local cache of the key material consumers recover when decrypting
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+use sp_core::Bytes;
+
+/// a cached value, along with what's needed to decide whether it may be served
+struct Entry<V> {
+	/// a hash of the secret key the value was recovered with
+	secret_hash: [u8; 32],
+	/// when the value was recovered
+	inserted: Instant,
+	/// when the value was last served, in cache ticks
+	last_used: u64,
+	value: V,
+}
+
+/// An in-memory, least recently used cache keyed by (signer, asset id).
+/// Entries are only served to callers presenting the same secret key they were
+/// recovered with, and expire after a fixed time to live. Nothing is persisted,
+/// so cached key material is gone when the node restarts.
+pub struct DecryptionCache<V> {
+	capacity: usize,
+	ttl: Duration,
+	tick: u64,
+	entries: HashMap<(Bytes, u32), Entry<V>>,
+}
+
+impl<V: Clone> DecryptionCache<V> {
+	/// create a new cache
+	///
+	/// * `capacity`: The maximum number of entries to hold
+	/// * `ttl`: How long an entry may be served after it is inserted
+	///
+	pub fn new(capacity: usize, ttl: Duration) -> Self {
+		Self { capacity, ttl, tick: 0, entries: HashMap::new() }
+	}
+
+	/// Get the value cached for a signer and asset, if it exists, hasn't expired
+	/// and was recovered with the given secret key
+	///
+	/// * `signer`: The address the value was recovered for
+	/// * `asset_id`: The asset the value was recovered for
+	/// * `secret_hash`: A hash of the caller's secret key
+	/// * `now`: The current time
+	///
+	pub fn get(
+		&mut self,
+		signer: &Bytes,
+		asset_id: u32,
+		secret_hash: &[u8; 32],
+		now: Instant,
+	) -> Option<V> {
+		let key = (signer.clone(), asset_id);
+		let expired = match self.entries.get(&key) {
+			Some(entry) => now.saturating_duration_since(entry.inserted) > self.ttl,
+			None => return None,
+		};
+		if expired {
+			self.entries.remove(&key);
+			return None;
+		}
+		self.tick += 1;
+		let tick = self.tick;
+		self.entries.get_mut(&key)
+			.filter(|entry| &entry.secret_hash == secret_hash)
+			.map(|entry| {
+				entry.last_used = tick;
+				entry.value.clone()
+			})
+	}
+
+	/// Cache a value for a signer and asset, evicting the least recently used
+	/// entry if the cache is full
+	///
+	/// * `signer`: The address the value was recovered for
+	/// * `asset_id`: The asset the value was recovered for
+	/// * `secret_hash`: A hash of the secret key the value was recovered with
+	/// * `value`: The value to cache
+	/// * `now`: The current time
+	///
+	pub fn insert(
+		&mut self,
+		signer: Bytes,
+		asset_id: u32,
+		secret_hash: [u8; 32],
+		value: V,
+		now: Instant,
+	) {
+		if self.capacity == 0 {
+			return;
+		}
+		let key = (signer, asset_id);
+		if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+			let oldest = self.entries.iter()
+				.min_by_key(|(_, entry)| entry.last_used)
+				.map(|(key, _)| key.clone());
+			if let Some(oldest) = oldest {
+				self.entries.remove(&oldest);
+			}
+		}
+		self.tick += 1;
+		self.entries.insert(key, Entry { secret_hash, inserted: now, last_used: self.tick, value });
+	}
+
+	/// Remove the value cached for a signer and asset
+	///
+	/// * `signer`: The address the value was recovered for
+	/// * `asset_id`: The asset the value was recovered for
+	///
+	pub fn remove(&mut self, signer: &Bytes, asset_id: u32) {
+		self.entries.remove(&(signer.clone(), asset_id));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn signer(b: u8) -> Bytes {
+		Bytes::from(vec![b; 32])
+	}
+
+	#[test]
+	fn cached_values_are_only_served_for_the_same_secret() {
+		// Given: a value cached for a signer and asset
+		let now = Instant::now();
+		let mut cache = DecryptionCache::new(2, Duration::from_secs(60));
+		cache.insert(signer(1), 1, [1u8; 32], 10u32, now);
+		// When: I get it with the secret it was recovered with
+		// Then: it is served
+		assert_eq!(Some(10), cache.get(&signer(1), 1, &[1u8; 32], now));
+		// When: I get it with another secret, or for another asset
+		// Then: it is not served
+		assert_eq!(None, cache.get(&signer(1), 1, &[2u8; 32], now));
+		assert_eq!(None, cache.get(&signer(1), 2, &[1u8; 32], now));
+	}
+
+	#[test]
+	fn cached_values_expire_after_their_ttl() {
+		// Given: a value cached with a one minute ttl
+		let now = Instant::now();
+		let mut cache = DecryptionCache::new(2, Duration::from_secs(60));
+		cache.insert(signer(1), 1, [1u8; 32], 10u32, now);
+		// When: I get it after the ttl has passed
+		let later = now + Duration::from_secs(61);
+		// Then: it is not served
+		assert_eq!(None, cache.get(&signer(1), 1, &[1u8; 32], later));
+	}
+
+	#[test]
+	fn full_cache_evicts_the_least_recently_used_value() {
+		// Given: a full cache whose first entry was used most recently
+		let now = Instant::now();
+		let mut cache = DecryptionCache::new(2, Duration::from_secs(60));
+		cache.insert(signer(1), 1, [1u8; 32], 10u32, now);
+		cache.insert(signer(2), 1, [2u8; 32], 20u32, now);
+		assert_eq!(Some(10), cache.get(&signer(1), 1, &[1u8; 32], now));
+		// When: I cache another value
+		cache.insert(signer(3), 1, [3u8; 32], 30u32, now);
+		// Then: the least recently used entry was evicted
+		assert_eq!(Some(10), cache.get(&signer(1), 1, &[1u8; 32], now));
+		assert_eq!(None, cache.get(&signer(2), 1, &[2u8; 32], now));
+		assert_eq!(Some(30), cache.get(&signer(3), 1, &[3u8; 32], now));
+	}
+
+	#[test]
+	fn removed_values_are_not_served() {
+		// Given: a cached value
+		let now = Instant::now();
+		let mut cache = DecryptionCache::new(2, Duration::from_secs(60));
+		cache.insert(signer(1), 1, [1u8; 32], 10u32, now);
+		// When: I remove it
+		cache.remove(&signer(1), 1);
+		// Then: it is not served
+		assert_eq!(None, cache.get(&signer(1), 1, &[1u8; 32], now));
+	}
+}
@@ -15,31 +15,180 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use futures::{future, FutureExt, Stream, StreamExt};
 use jsonrpsee::{
 	core::{async_trait, RpcResult},
 	proc_macros::rpc,
 	types::error::{CallError, ErrorObject},
+	ws_server::SubscriptionSink,
 };
-use sp_api::ProvideRuntimeApi;
+use parking_lot::{Mutex, RwLock};
+use sc_client_api::BlockchainEvents;
+use sc_rpc_api::DenyUnsafe;
+use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
 use sp_core::{
+	offchain::OffchainStorage,
+	traits::SpawnNamed,
 	Bytes,
+	H256,
 };
 use sp_runtime::{
 	generic::BlockId,
 	traits::{Block as BlockT, MaybeDisplay},
+	AccountId32,
 };
 use sp_rpc::number::NumberOrHex;
-use std::sync::Arc;
-use codec::Codec;
+use serde::{Deserialize, Serialize};
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use rand::{rngs::OsRng, RngCore};
+use codec::{Codec, Decode, DecodeAll, Encode};
+use iris_primitives::{
+	AssetLifecycleEvent, AssetPreview, DecryptionContext, DecryptionFailure, DecryptionKey, DecryptionReadiness, DelayInfo, EncryptionResult,
+	GatewayStats, IngestionCommand, MultiaddressError, ProxyStats, RequestStatus, UnstagedEncryption, INGESTION_PAUSED_KEY,
+	LOCAL_PINS_KEY, PROCESS_QUEUE_NOW_KEY, parse_multiaddress,
+};
+
+mod cache;
+
+pub use cache::DecryptionCache;
 
 pub use encryption_rpc_runtime_api::EncryptionApi as EncryptionRuntimeApi;
 
+/// the number of asset ids returned per page when no page size is given
+const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// the EncryptionApi version this node's methods call, refusing runtimes that predate it
+const ENCRYPTION_API_VERSION: u32 = 2;
+
+/// the number of gateways recommended when no count is given
+const DEFAULT_RECOMMENDATION_COUNT: u32 = 5;
+
+/// the balance reserved toward a gateway by a single queued ingestion command
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PendingReserve {
+	/// the id of the command
+	pub command_id: H256,
+	/// the balance the command reserves
+	pub balance: NumberOrHex,
+}
+
+/// the balances reserved toward a gateway by the ingestion commands queued with it
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PendingReservesInfo {
+	/// the sum of the balances of all queued commands
+	pub total: NumberOrHex,
+	/// the balance of each queued command
+	pub commands: Vec<PendingReserve>,
+}
+
+/// a reencryption request queued with a proxy that it has not yet served
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PendingReencryptionInfo {
+	/// the id of the request
+	pub request_id: H256,
+	/// the address of the account that requested reencryption
+	pub caller: Bytes,
+	/// the asset the caller was authorized to decrypt
+	pub asset_id: u32,
+}
+
+/// a transition in the lifecycle of an asset
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AssetHistoryEntryInfo {
+	/// the block at which the transition happened
+	pub block: u32,
+	/// the transition
+	pub event: AssetLifecycleEvent,
+	/// the address of the gateway or consumer involved, if any
+	pub account: Option<Bytes>,
+}
+
+/// a gateway assigned an ingestion command
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CommandAssignmentInfo {
+	/// the address of the gateway
+	pub gateway: Bytes,
+	/// whether the gateway has ingested the data
+	pub completed: bool,
+}
+
+/// the data attached to an error raised by a failed runtime api call
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CallErrorData<P> {
+	/// the rpc method whose runtime api call failed
+	pub method: String,
+	/// the parameters the method was called with, excluding data and secrets
+	pub params: P,
+	/// the error returned by the runtime api
+	pub error: String,
+}
+
+/// the parameters of a failed `iris_encrypt` or `iris_encryptForRequest` call
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EncryptParams {
+	pub signer: Bytes,
+	pub proxy: Bytes,
+}
+
+/// the parameters of a failed `iris_decrypt` call
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DecryptParams {
+	pub signer: Bytes,
+	pub asset_id: u32,
+	pub use_fragments: Option<u32>,
+}
+
+/// a scale-encoded `IngestionCommand` decoded into readable fields
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct IngestionCommandInfo {
+	/// the id of the command
+	pub id: H256,
+	/// the ss58 address of the owner of the data to be ingested
+	pub owner: String,
+	/// the CID of the data, as UTF-8 if valid and hex otherwise
+	pub cid: String,
+	/// the multiaddress of the ipfs node holding the data, as UTF-8 if valid and hex otherwise
+	pub multiaddress: String,
+	/// the balance offered for the ingestion
+	pub balance: NumberOrHex,
+	/// the block number at which the command was queued
+	pub queued_at: u32,
+}
+
+/// a protocol of a multiaddress and its value, if it takes one
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MultiaddressComponentInfo {
+	/// the protocol, e.g. ip4
+	pub protocol: String,
+	/// the protocol's value, e.g. 127.0.0.1
+	pub value: Option<String>,
+}
+
+/// whether a multiaddress is one `create_request` accepts, and its components if so
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MultiaddressValidity {
+	/// true if the multiaddress parsed
+	pub valid: bool,
+	/// why the multiaddress didn't parse, if it didn't
+	pub error: Option<String>,
+	/// the protocols of a valid multiaddress, in order
+	pub components: Vec<MultiaddressComponentInfo>,
+	/// the peer id a valid multiaddress addresses, if any
+	pub peer_id: Option<String>,
+}
+
+/// Runtimes that don't expose version 2 of the EncryptionApi are refused with a `MethodUnsupported` error
 #[rpc(client, server)]
 pub trait EncryptionApi<BlockHash, Balance> {
 
 	/// Encrypts the ciphertext using the signer's public keys
-	/// and delegates reencryption rights to the proxy
+	/// and delegates reencryption rights to the proxy.
+	/// The data keys are seeded from the node's entropy source, so runtimes
+	/// that can't accept a seed are refused rather than fall back to a predictable one.
 	/// 
 	/// * `plaintext`: The plaintext to be encrypted
 	/// * `signature`: The signature created to sign the message
@@ -47,6 +196,15 @@ pub trait EncryptionApi<BlockHash, Balance> {
 	/// * `signer`: The address of the node who signed the message
 	/// * `proxy`: The proxy to delegate reencryption rights to
 	///  
+	/// Returns the ciphertext along with the data public key, which identifies the data when
+	/// requesting reencryption, and the capsule. If the data can't be encrypted the call fails
+	/// with an `EncryptionFailed` error whose data holds the reason. If the runtime api call
+	/// itself fails, the `RuntimeError`'s data holds the method, the signer and proxy, and the error.
+	/// 
+	/// Nodes may bound the size of the plaintext they encrypt, in which case larger plaintexts
+	/// are refused with a `PlaintextTooLarge` error before any keys are generated. Large data
+	/// should be split into chunks and encrypted separately, or stored externally.
+	/// 
 	#[method(name = "iris_encrypt")]
 	fn encrypt(
 		&self,
@@ -56,7 +214,25 @@ pub trait EncryptionApi<BlockHash, Balance> {
         message: Bytes,
 		proxy: Bytes,
 		at: Option<BlockHash>,
-	) -> RpcResult<Bytes>;
+	) -> RpcResult<EncryptionResult>;
+
+	/// Encrypts the plaintext as `iris_encrypt` does, but stages nothing on chain. Instead,
+	/// the data secret key encrypted for the proxy is returned along with the encryption result,
+	/// and once the ciphertext is added to ipfs the signer submits both with the iris-proxy
+	/// pallet's `encrypt_and_request` extrinsic, which stages the encryption artifacts and
+	/// queues the ingestion request in a single transaction.
+	/// The parameters and errors are as for `iris_encrypt`.
+	/// 
+	#[method(name = "iris_encryptForRequest")]
+	fn encrypt_for_request(
+		&self,
+		plaintext: Bytes,
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+		proxy: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<UnstagedEncryption>;
 
 	/// Decrypts ciphertext encrypted with Iris that the signer has access to.
 	/// 
@@ -66,6 +242,23 @@ pub trait EncryptionApi<BlockHash, Balance> {
 	/// * `signer`: The address of the node who signed the mess
 	/// * `asset_id`: The asset id associated with the data asset
 	/// * `secret_key`: A secret key whose corresponding public key was passed to the rule executor
+	/// * `use_fragments`: The number of capsule fragments to decrypt with (at least the threshold).
+	///                    All available fragments are used if not specified.
+	/// 
+	/// If `use_fragments` is below the threshold, the call fails with a `TooFewFragmentsRequested`
+	/// error whose data holds `{ requested, need }`. If fewer verified capsule fragments than the threshold have been delivered so far, the call
+	/// fails with a `NotEnoughFragments` error whose data holds `{ have, need }`, so clients can
+	/// show progress and retry later. If the ciphertext fails authentication, e.g. because it
+	/// doesn't match the asset's capsule, the call fails with a `DecryptionFailed` error, and if
+	/// the secret key is not exactly 32 bytes, it fails with an `InvalidSecretKey` error.
+	/// 
+	/// If the node enables its decryption cache, the key material recovered from the capsule
+	/// fragments is held in the node's memory, so repeated reads of an asset skip verifying
+	/// the fragments again. Cached keys are only used with the secret key they were recovered
+	/// with, and are dropped once the signer's reencryption artifacts change.
+	/// 
+	/// If a runtime api call fails, the `RuntimeError`'s data holds the method, the signer,
+	/// asset id and `use_fragments`, and the error.
 	/// 
 	#[method(name = "iris_decrypt")]
 	fn decrypt(
@@ -76,20 +269,435 @@ pub trait EncryptionApi<BlockHash, Balance> {
 		message: Bytes,
 		asset_id: u32,
 		secret_key: Bytes,
+		use_fragments: Option<u32>,
 		at: Option<BlockHash>,
 	) -> RpcResult<Option<Bytes>>;
+
+	/// Preview a data asset without retrieving or decrypting it. Unlike `iris_decrypt`
+	/// this only reads on-chain metadata and performs no IPFS or crypto I/O.
+	/// 
+	/// * `asset_id`: The asset id associated with the data asset
+	/// * `signer`: The address to check authorization for
+	/// 
+	/// The preview includes the ipfs peer ids of the gateways pinning the data, so clients can
+	/// connect to them directly, the number of times access to the asset was granted, and the
+	/// cids of the other files of a multi-file dataset, along with the size of the ciphertext
+	/// so clients can plan the download. Unknown assets fail with an `AssetNotFound` error.
+	/// 
+	#[method(name = "iris_peek")]
+	fn peek(
+		&self,
+		asset_id: u32,
+		signer: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<Option<AssetPreview>>;
+
+	/// Check whether an asset exists, without reading its metadata.
+	/// 
+	/// * `asset_id`: The asset id
+	/// 
+	#[method(name = "iris_assetExists")]
+	fn asset_exists(
+		&self,
+		asset_id: u32,
+		at: Option<BlockHash>,
+	) -> RpcResult<bool>;
+
+	/// Estimate the minimum reserve a gateway will accept to ingest some data.
+	/// If the gateway hasn't set pricing, the minimum reserve per size unit is used.
+	/// 
+	/// * `gateway`: The address of the gateway
+	/// * `estimated_size`: The estimated size of the data to be ingested, in bytes
+	/// 
+	#[method(name = "iris_estimateReserve")]
+	fn estimate_reserve(
+		&self,
+		gateway: Bytes,
+		estimated_size: u128,
+		at: Option<BlockHash>,
+	) -> RpcResult<NumberOrHex>;
+
+	/// List the asset ids of the asset classes a gateway has ingested.
+	/// 
+	/// * `gateway`: The address of the gateway
+	/// * `page`: The page to fetch, starting from 0 (default 0)
+	/// * `page_size`: The number of asset ids per page (default 100)
+	/// 
+	#[method(name = "iris_ingestedAssets")]
+	fn ingested_assets(
+		&self,
+		gateway: Bytes,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<u32>>;
+
+	/// Get ingestion statistics for a gateway: the number of completed commands
+	/// and the average number of blocks between a command being queued and completed.
+	/// 
+	/// * `gateway`: The address of the gateway
+	/// 
+	#[method(name = "iris_gatewayStats")]
+	fn gateway_stats(
+		&self,
+		gateway: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<GatewayStats>;
+
+	/// Recommend gateways with enough remaining storage for some data, best first.
+	/// Gateways are scored by their remaining storage, their ingestion reliability,
+	/// and how recently they were active on chain.
+	/// 
+	/// * `estimated_size`: The estimated size of the data to be ingested, in bytes
+	/// * `count`: The maximum number of gateways to recommend (default 5)
+	/// 
+	#[method(name = "iris_recommendGateway")]
+	fn recommend_gateway(
+		&self,
+		estimated_size: u128,
+		count: Option<u32>,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<Bytes>>;
+
+	/// Get the capsule of a data asset along with the cid and size (in bytes) of its
+	/// ciphertext, so the ciphertext can be fetched from IPFS and paired with the capsule.
+	/// The context also holds the sha-256 checksum of the plaintext if the owner attested one,
+	/// so the decrypted data can be verified against it. Fails with an `AssetNotFound` error
+	/// for unknown assets.
+	/// 
+	/// * `asset_id`: The asset id associated with the data asset
+	/// 
+	#[method(name = "iris_decryptionContext")]
+	fn decryption_context(
+		&self,
+		asset_id: u32,
+		at: Option<BlockHash>,
+	) -> RpcResult<Option<DecryptionContext>>;
+
+	/// Get the balances reserved toward a gateway by the ingestion commands queued with it,
+	/// i.e. the revenue the gateway can expect once it completes them. Returns the total
+	/// along with the balance of each queued command.
+	/// 
+	/// * `gateway`: The address of the gateway
+	/// 
+	#[method(name = "iris_pendingReserves")]
+	fn pending_reserves(
+		&self,
+		gateway: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<PendingReservesInfo>;
+
+	/// Get the reencryption requests assigned to a proxy that it has not yet served,
+	/// in the order they were queued.
+	/// 
+	/// * `proxy`: The address of the proxy
+	/// 
+	#[method(name = "iris_pendingReencryptions")]
+	fn pending_reencryptions(
+		&self,
+		proxy: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<PendingReencryptionInfo>>;
+
+	/// Get the gateways assigned an owner's ingestion requests for some data, along with
+	/// whether each has completed ingestion. Empty if the owner has no such request.
+	/// 
+	/// * `owner`: The address of the owner of the request
+	/// * `cid`: The cid of the data to be ingested
+	/// 
+	#[method(name = "iris_commandAssignments")]
+	fn command_assignments(
+		&self,
+		owner: Bytes,
+		cid: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<CommandAssignmentInfo>>;
+
+	/// Get the number of capsule fragments a proxy delivered that consumers reported
+	/// as passing or failing verification.
+	/// 
+	/// * `proxy`: The address of the proxy
+	/// 
+	#[method(name = "iris_proxyReputation")]
+	fn proxy_reputation(
+		&self,
+		proxy: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<ProxyStats>;
+
+	/// Get the number of blocks after which the reserve offered in `create_request` vests
+	/// with the gateways, along with the number of blocks between gateway config syncs.
+	/// 
+	#[method(name = "iris_currentDelay")]
+	fn current_delay(
+		&self,
+		at: Option<BlockHash>,
+	) -> RpcResult<DelayInfo>;
+
+	/// Get the number of blocks between gateway config syncs. Gateways sync their ipfs config
+	/// and process their ingestion queue on blocks that are a multiple of the interval, so the
+	/// next sync is expected at the next such block.
+	/// 
+	#[method(name = "iris_configSyncInterval")]
+	fn config_sync_interval(
+		&self,
+		at: Option<BlockHash>,
+	) -> RpcResult<u32>;
+
+	/// Get the block at which the reserve of a request created in the next block would vest
+	/// with its gateways. This is the block announced by the `CreatedIngestionRequest` event.
+	/// 
+	/// * `estimated_size`: The size of the data in bytes, as given to `create_request`
+	/// 
+	#[method(name = "iris_vestingTargetBlock")]
+	fn vesting_target_block(
+		&self,
+		estimated_size: u128,
+		at: Option<BlockHash>,
+	) -> RpcResult<u32>;
+
+	/// Check whether an account has everything on chain it needs to decrypt an asset,
+	/// without handing over a secret key. Delivered capsule fragments are counted but not
+	/// verified, since verifying them takes the account's secret key. Access grants can
+	/// expire, after which the account is no longer authorized to decrypt.
+	/// 
+	/// * `asset_id`: The asset to decrypt
+	/// * `account`: The address of the consumer
+	/// 
+	#[method(name = "iris_decryptPrecheck")]
+	fn decrypt_precheck(
+		&self,
+		asset_id: u32,
+		account: Bytes,
+		at: Option<BlockHash>,
+	) -> RpcResult<DecryptionReadiness>;
+
+	/// List the asset ids of the asset classes bonded to a dataspace.
+	/// Unknown dataspaces have no assets.
+	/// 
+	/// * `dataspace_id`: The dataspace
+	/// * `page`: The page to fetch, starting from 0 (default 0)
+	/// * `page_size`: The number of asset ids per page (default 100)
+	/// 
+	#[method(name = "iris_assetsInDataspace")]
+	fn assets_in_dataspace(
+		&self,
+		dataspace_id: u32,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<u32>>;
+
+	/// List the asset ids of the asset classes created between two blocks (inclusive),
+	/// in creation order. The runtime searches at most 100,000 blocks from `from_block`
+	/// and returns at most 1,000 asset ids per page, so wider ranges should be split.
+	/// 
+	/// * `from_block`: The first block of the range
+	/// * `to_block`: The last block of the range
+	/// * `page`: The page to fetch, starting from 0 (default 0)
+	/// * `page_size`: The number of asset ids per page (default 100)
+	/// 
+	#[method(name = "iris_assetsCreatedBetween")]
+	fn assets_created_between(
+		&self,
+		from_block: u32,
+		to_block: u32,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<u32>>;
+
+	/// Get the most recent lifecycle events of an asset, oldest first: its creation, the
+	/// reencryption keys generated for consumers, consumers gaining access, replicas being
+	/// ejected, and the asset being frozen or thawed. The runtime keeps a bounded number of
+	/// events per asset, dropping the oldest first. Unknown assets have no history.
+	/// 
+	/// * `asset_id`: The asset id
+	/// 
+	#[method(name = "iris_assetHistory")]
+	fn asset_history(
+		&self,
+		asset_id: u32,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<AssetHistoryEntryInfo>>;
+
+	/// Decode a scale-encoded ingestion command, e.g. one read from raw storage,
+	/// into readable fields. Doesn't touch chain state.
+	/// 
+	/// * `command`: The scale-encoded command
+	/// 
+	#[method(name = "iris_decodeCommand")]
+	fn decode_command(
+		&self,
+		command: Bytes,
+	) -> RpcResult<IngestionCommandInfo>;
+
+	/// Check a multiaddress with the same parser `create_request` validates multiaddresses
+	/// with, so malformed ones can be caught before submitting a request. Doesn't touch
+	/// chain state.
+	/// 
+	/// * `multiaddress`: The multiaddress, e.g. /ip4/127.0.0.1/tcp/4001/p2p/12D3KooW...
+	/// 
+	#[method(name = "iris_validateMultiaddress")]
+	fn validate_multiaddress(
+		&self,
+		multiaddress: String,
+	) -> RpcResult<MultiaddressValidity>;
+
+	/// Subscribe to the progress of an ingestion request. The current status is sent
+	/// immediately (if the request exists), then again each time it changes in a new
+	/// best block. The subscription ends once the request is complete.
+	/// 
+	/// * `request_id`: The request id announced in the `CreatedIngestionRequest` event
+	/// 
+	#[subscription(
+		name = "iris_subscribeRequest",
+		unsubscribe = "iris_unsubscribeRequest",
+		item = RequestStatus,
+	)]
+	fn subscribe_request(&self, request_id: H256);
+}
+
+/// Node-local controls over how this node processes ingestion commands, and a view of what it stores
+#[rpc(client, server)]
+pub trait IngestionControlApi {
+
+	/// Pause or resume ingestion processing on this node. While paused, the node's
+	/// offchain worker leaves its ingestion queue untouched. This is an unsafe method.
+	/// 
+	/// * `paused`: Whether ingestion processing should be paused
+	/// 
+	#[method(name = "iris_pauseIngestion")]
+	fn pause_ingestion(&self, paused: bool) -> RpcResult<()>;
+
+	/// Ask this node's offchain worker to process its ingestion queue on its next run,
+	/// instead of waiting for the next scheduled block. This is an operational convenience
+	/// for testing and maintenance that only affects the local node, and it has no effect
+	/// while ingestion is paused. This is an unsafe method.
+	/// 
+	#[method(name = "iris_processQueueNow")]
+	fn process_queue_now(&self) -> RpcResult<()>;
+
+	/// List the cids this node's ipfs daemon has pinned, to reconcile them against the
+	/// gateway's on-chain obligations (`IngestedByGateway`). The list is taken by the
+	/// offchain worker whenever it processes the ingestion queue, so it can lag the daemon
+	/// by up to a queue interval. If the daemon couldn't be reached when it was last asked,
+	/// or hasn't been asked yet, the call fails with an `IpfsNotAvailable` error.
+	/// This is an unsafe method.
+	/// 
+	#[method(name = "iris_localPins")]
+	fn local_pins(&self) -> RpcResult<Vec<Bytes>>;
+}
+
+/// A struct that implements IngestionControlApi using the node's offchain storage
+pub struct IngestionControl<S> {
+	storage: Arc<RwLock<S>>,
+	deny_unsafe: DenyUnsafe,
+}
+
+impl<S: OffchainStorage> IngestionControl<S> {
+	/// create a new 'IngestionControl' instance with the given offchain storage
+	pub fn new(storage: S, deny_unsafe: DenyUnsafe) -> Self {
+		Self { storage: Arc::new(RwLock::new(storage)), deny_unsafe }
+	}
+}
+
+impl<S: OffchainStorage + 'static> IngestionControlApiServer for IngestionControl<S> {
+
+	fn pause_ingestion(&self, paused: bool) -> RpcResult<()> {
+		self.deny_unsafe.check_if_safe().map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::UnsafeCall.into(),
+				"Unable to pause ingestion.",
+				Some(e.to_string())
+			))
+		})?;
+		self.storage.write().set(
+			sp_offchain::STORAGE_PREFIX,
+			INGESTION_PAUSED_KEY,
+			&paused.encode(),
+		);
+		Ok(())
+	}
+
+	fn process_queue_now(&self) -> RpcResult<()> {
+		self.deny_unsafe.check_if_safe().map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::UnsafeCall.into(),
+				"Unable to process the ingestion queue.",
+				Some(e.to_string())
+			))
+		})?;
+		self.storage.write().set(
+			sp_offchain::STORAGE_PREFIX,
+			PROCESS_QUEUE_NOW_KEY,
+			&true.encode(),
+		);
+		Ok(())
+	}
+
+	fn local_pins(&self) -> RpcResult<Vec<Bytes>> {
+		self.deny_unsafe.check_if_safe().map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::UnsafeCall.into(),
+				"Unable to list local pins.",
+				Some(e.to_string())
+			))
+		})?;
+		let pins = self.storage.read()
+			.get(sp_offchain::STORAGE_PREFIX, LOCAL_PINS_KEY)
+			.and_then(|bytes| Option::<Vec<Vec<u8>>>::decode(&mut &bytes[..]).ok())
+			.flatten();
+		match pins {
+			Some(pins) => Ok(pins.into_iter().map(Bytes::from).collect()),
+			None => Err(CallError::Custom(ErrorObject::owned(
+				Error::IpfsNotAvailable.into(),
+				"The ipfs daemon could not be reached.",
+				None::<()>,
+			)).into()),
+		}
+	}
 }
 
 /// A struct that implements EncryptionRpc
 pub struct Encryption<C, P> {
 	client: Arc<C>,
+	executor: Arc<dyn SpawnNamed>,
+	decryption_cache: Option<Mutex<DecryptionCache<DecryptionKey>>>,
+	max_plaintext_size: Option<usize>,
 	_marker: std::marker::PhantomData<P>,
 }
 
 impl<C, P> Encryption<C, P> {
 	/// create new 'Encrypt' instance with the given reference to the client
-	pub fn new(client: Arc<C>) -> Self {
-		Self { client, _marker: Default::default() }
+	/// and an executor to drive subscriptions
+	pub fn new(client: Arc<C>, executor: Arc<dyn SpawnNamed>) -> Self {
+		Self {
+			client,
+			executor,
+			decryption_cache: None,
+			max_plaintext_size: None,
+			_marker: Default::default(),
+		}
+	}
+
+	/// cache the key material recovered by decrypt calls in memory
+	/// 
+	/// * `capacity`: The maximum number of (signer, asset) keys to hold
+	/// * `ttl`: How long a key may be used after it is recovered
+	/// 
+	pub fn with_decryption_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+		self.decryption_cache = Some(Mutex::new(DecryptionCache::new(capacity, ttl)));
+		self
+	}
+
+	/// refuse to encrypt plaintexts larger than the given size
+	/// 
+	/// * `max_plaintext_size`: The maximum number of plaintext bytes an encrypt call accepts
+	/// 
+	pub fn with_max_plaintext_size(mut self, max_plaintext_size: usize) -> Self {
+		self.max_plaintext_size = Some(max_plaintext_size);
+		self
 	}
 }
 
@@ -97,22 +705,363 @@ impl<C, P> Encryption<C, P> {
 pub enum Error {
 	/// the call to runtime failed
 	RuntimeError,
+	/// the value could not be converted to the expected type
+	DecodeError,
+	/// the runtime's EncryptionApi version does not support the method
+	MethodUnsupported,
+	/// the method is unsafe and unsafe methods are denied
+	UnsafeCall,
+	/// fewer capsule fragments than the threshold are available
+	NotEnoughFragments,
+	/// the runtime could not encrypt the data
+	EncryptionFailed,
+	/// the asset does not exist
+	AssetNotFound,
+	/// the ciphertext failed authentication when decrypting
+	DecryptionFailed,
+	/// the plaintext is larger than the node accepts
+	PlaintextTooLarge,
+	/// the secret key is not a 32 byte x25519 secret key
+	InvalidSecretKey,
+	/// the node's ipfs daemon could not be reached
+	IpfsNotAvailable,
+	/// the asset was encrypted with a scheme the runtime can't decrypt
+	UnsupportedScheme,
+	/// fewer capsule fragments than the threshold were requested
+	TooFewFragmentsRequested,
 }
 
 impl From<Error> for i32 {
 	fn from(e: Error) -> i32 {
 		match e {
 			Error::RuntimeError => 1,
+			Error::DecodeError => 2,
+			Error::MethodUnsupported => 3,
+			Error::UnsafeCall => 4,
+			Error::NotEnoughFragments => 5,
+			Error::EncryptionFailed => 6,
+			Error::AssetNotFound => 7,
+			Error::DecryptionFailed => 8,
+			Error::PlaintextTooLarge => 9,
+			Error::InvalidSecretKey => 10,
+			Error::IpfsNotAvailable => 11,
+			Error::UnsupportedScheme => 12,
+			Error::TooFewFragmentsRequested => 13,
 		}
 	}
 }
 
+/// Check that a runtime's EncryptionApi version supports a method
+/// 
+/// * `version`: The EncryptionApi version exposed by the runtime, if any
+/// * `required`: The EncryptionApi version that introduced the method
+/// 
+fn ensure_api_version(version: Option<u32>, required: u32) -> RpcResult<()> {
+	match version {
+		Some(v) if v >= required => Ok(()),
+		_ => Err(CallError::Custom(ErrorObject::owned(
+			Error::MethodUnsupported.into(),
+			"Method unsupported on this runtime.",
+			Some(format!("requires EncryptionApi version {}, found {:?}", required, version)),
+		)).into()),
+	}
+}
+
+/// Check that a plaintext is no larger than the node is willing to encrypt
+/// 
+/// * `len`: The size of the plaintext in bytes
+/// * `max`: The maximum size the node accepts, if it sets one
+/// 
+fn ensure_plaintext_size(len: usize, max: Option<usize>) -> RpcResult<()> {
+	match max {
+		Some(max) if len > max => Err(CallError::Custom(ErrorObject::owned(
+			Error::PlaintextTooLarge.into(),
+			"The plaintext is too large to encrypt.",
+			Some(format!("plaintext is {} bytes, at most {} are accepted", len, max)),
+		)).into()),
+		_ => Ok(()),
+	}
+}
+
+/// Draw a seed for the data keys from the node's entropy source
+/// 
+fn entropy_seed() -> RpcResult<[u8; 32]> {
+	let mut seed = [0u8; 32];
+	OsRng.try_fill_bytes(&mut seed).map_err(|e| {
+		CallError::Custom(ErrorObject::owned(
+			Error::RuntimeError.into(),
+			"Unable to read from the node's entropy source.",
+			Some(e.to_string())
+		))
+	})?;
+	Ok(seed)
+}
+
+/// Build the error for a failed runtime api call, identifying the method
+/// and the parameters it was called with
+/// 
+/// * `message`: The error message
+/// * `method`: The rpc method that made the call
+/// * `params`: The parameters the method was called with
+/// * `error`: The error returned by the runtime api
+/// 
+fn runtime_error<P: Serialize>(
+	message: &'static str,
+	method: &str,
+	params: P,
+	error: impl ToString,
+) -> CallError {
+	CallError::Custom(ErrorObject::owned(
+		Error::RuntimeError.into(),
+		message,
+		Some(CallErrorData { method: method.into(), params, error: error.to_string() }),
+	))
+}
+
+/// Convert a decryption failure into its RPC error
+/// 
+/// * `failure`: The reason the runtime could not decrypt
+/// 
+fn decryption_failure(failure: DecryptionFailure) -> CallError {
+	match failure {
+		DecryptionFailure::NotEnoughFragments { .. } => CallError::Custom(ErrorObject::owned(
+			Error::NotEnoughFragments.into(),
+			"Not enough capsule fragments are available yet.",
+			Some(failure),
+		)),
+		DecryptionFailure::DecryptionFailed => CallError::Custom(ErrorObject::owned(
+			Error::DecryptionFailed.into(),
+			"The ciphertext could not be authenticated with the recovered key.",
+			None::<()>,
+		)),
+		DecryptionFailure::InvalidSecretKey => CallError::Custom(ErrorObject::owned(
+			Error::InvalidSecretKey.into(),
+			"The secret key must be exactly 32 bytes.",
+			None::<()>,
+		)),
+		DecryptionFailure::UnsupportedScheme => CallError::Custom(ErrorObject::owned(
+			Error::UnsupportedScheme.into(),
+			"The asset was encrypted with a scheme this runtime can't decrypt.",
+			None::<()>,
+		)),
+		DecryptionFailure::TooFewFragmentsRequested { .. } => CallError::Custom(ErrorObject::owned(
+			Error::TooFewFragmentsRequested.into(),
+			"Fewer capsule fragments were requested than decryption requires.",
+			Some(failure),
+		)),
+	}
+}
+
+/// Convert a runtime balance into its RPC representation
+/// 
+/// * `value`: The balance to convert
+/// 
+fn to_rpc_balance<Balance>(value: Balance) -> Result<NumberOrHex, CallError>
+where
+	Balance: MaybeDisplay + Copy + TryInto<NumberOrHex>,
+{
+	value.try_into().map_err(|_| {
+		CallError::Custom(ErrorObject::owned(
+			Error::DecodeError.into(),
+			format!("{} doesn't fit in NumberOrHex representation", value),
+			None::<()>,
+		))
+	})
+}
+
+/// Show bytes as UTF-8 if they are valid UTF-8, and as hex otherwise
+/// 
+/// * `bytes`: The bytes to show
+/// 
+fn readable_bytes(bytes: Vec<u8>) -> String {
+	String::from_utf8(bytes).unwrap_or_else(|e| sp_core::bytes::to_hex(e.as_bytes(), false))
+}
+
+/// Decode a scale-encoded ingestion command into its RPC representation,
+/// rejecting input that is malformed or has trailing bytes
+/// 
+/// * `command`: The scale-encoded command
+/// 
+fn decode_ingestion_command<Balance>(command: &[u8]) -> Result<IngestionCommandInfo, CallError>
+where
+	Balance: Decode + MaybeDisplay + Copy + TryInto<NumberOrHex>,
+{
+	let cmd = IngestionCommand::<AccountId32, Balance>::decode_all(&mut &command[..]).map_err(|e| {
+		CallError::Custom(ErrorObject::owned(
+			Error::DecodeError.into(),
+			"Unable to decode the ingestion command.",
+			Some(e.to_string()),
+		))
+	})?;
+	Ok(IngestionCommandInfo {
+		id: H256::from(cmd.id),
+		owner: cmd.owner.to_string(),
+		cid: readable_bytes(cmd.cid),
+		multiaddress: readable_bytes(cmd.multiaddress),
+		balance: to_rpc_balance(cmd.balance)?,
+		queued_at: cmd.queued_at,
+	})
+}
+
+/// Parse a multiaddress into its RPC validity report
+/// 
+/// * `multiaddress`: The multiaddress
+/// 
+fn multiaddress_validity(multiaddress: &str) -> MultiaddressValidity {
+	match parse_multiaddress(multiaddress.as_bytes()) {
+		Ok(parsed) => MultiaddressValidity {
+			valid: true,
+			error: None,
+			components: parsed.components.into_iter().map(|(protocol, value)| MultiaddressComponentInfo {
+				protocol: readable_bytes(protocol),
+				value: value.map(readable_bytes),
+			}).collect(),
+			peer_id: parsed.peer_id.map(readable_bytes),
+		},
+		Err(e) => MultiaddressValidity {
+			valid: false,
+			error: Some(match e {
+				MultiaddressError::Empty => "The multiaddress is empty.".into(),
+				MultiaddressError::InvalidUtf8 => "The multiaddress is not valid UTF-8.".into(),
+				MultiaddressError::MissingLeadingSlash => "The multiaddress must start with '/'.".into(),
+				MultiaddressError::UnknownProtocol(protocol) =>
+					format!("Unknown protocol '{}'.", readable_bytes(protocol)),
+				MultiaddressError::MissingValue(protocol) =>
+					format!("The '{}' protocol is missing its value.", readable_bytes(protocol)),
+				MultiaddressError::InvalidValue(protocol) =>
+					format!("The value of the '{}' protocol is malformed.", readable_bytes(protocol)),
+			}),
+			components: Vec::new(),
+			peer_id: None,
+		},
+	}
+}
+
+/// Check that the runtime at the given block exposes the EncryptionApi version this node calls
+/// 
+/// * `client`: The client used to query the runtime
+/// * `at`: The block to check
+/// 
+fn ensure_supported<C, Block, Balance>(
+	client: &C,
+	at: &BlockId<Block>,
+) -> RpcResult<()>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block>,
+	C::Api: EncryptionRuntimeApi<Block, Balance>,
+	Balance: Codec + MaybeDisplay,
+{
+	let version = client.runtime_api()
+		.api_version::<dyn EncryptionRuntimeApi<Block, Balance>>(at)
+		.map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch the runtime api version.",
+				Some(e.to_string())
+			))
+		})?;
+	ensure_api_version(version, ENCRYPTION_API_VERSION)
+}
+
+/// Fail with an `AssetNotFound` error unless an asset exists
+/// 
+/// * `exists`: Whether the asset exists
+/// * `asset_id`: The asset id
+/// 
+fn check_asset_exists(exists: bool, asset_id: u32) -> RpcResult<()> {
+	if exists {
+		return Ok(());
+	}
+	Err(CallError::Custom(ErrorObject::owned(
+		Error::AssetNotFound.into(),
+		"The asset does not exist.",
+		Some(asset_id),
+	)).into())
+}
+
+/// Check that an asset exists before running a heavier query for it
+/// 
+/// * `client`: The client used to query the runtime
+/// * `at`: The block to check
+/// * `asset_id`: The asset id
+/// 
+fn ensure_asset_exists<C, Block, Balance>(
+	client: &C,
+	at: &BlockId<Block>,
+	asset_id: u32,
+) -> RpcResult<()>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block>,
+	C::Api: EncryptionRuntimeApi<Block, Balance>,
+	Balance: Codec + MaybeDisplay,
+{
+	let exists = client.runtime_api().asset_exists(at, asset_id).map_err(|e| {
+		CallError::Custom(ErrorObject::owned(
+			Error::RuntimeError.into(),
+			"Unable to check whether the asset exists.",
+			Some(e.to_string())
+		))
+	})?;
+	check_asset_exists(exists, asset_id)
+}
+
+/// Get the status of an ingestion request at a block, if it exists
+/// 
+/// * `client`: The client used to query the runtime
+/// * `hash`: The hash of the block to query
+/// * `request_id`: The id of the ingestion request
+/// 
+fn request_status_at<C, Block, Balance>(
+	client: &C,
+	hash: <Block as BlockT>::Hash,
+	request_id: [u8; 32],
+) -> Option<RequestStatus>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block>,
+	C::Api: EncryptionRuntimeApi<Block, Balance>,
+	Balance: Codec + MaybeDisplay,
+{
+	client.runtime_api()
+		.request_status(&BlockId::hash(hash), request_id)
+		.ok()
+		.flatten()
+}
+
+/// Reduce a stream of request statuses to the changes in status,
+/// ending the stream once a final status has been sent
+/// 
+/// * `statuses`: The status of a request, e.g. once per block
+/// 
+fn status_changes<S>(statuses: S) -> impl Stream<Item = RequestStatus>
+where
+	S: Stream<Item = RequestStatus>,
+{
+	let mut last: Option<RequestStatus> = None;
+	statuses
+		.filter(move |status| {
+			let changed = last.as_ref() != Some(status);
+			last = Some(status.clone());
+			future::ready(changed)
+		})
+		// follow a final status with an end marker so the stream ends without waiting on more blocks
+		.flat_map(|status| {
+			let end = if status.is_final() { Some(None) } else { None };
+			futures::stream::iter(Some(Some(status)).into_iter().chain(end))
+		})
+		.take_while(|status| future::ready(status.is_some()))
+		.filter_map(future::ready)
+}
+
 #[async_trait]
 impl<C, Block, Balance> 
 	EncryptionApiServer<<Block as BlockT>::Hash, Balance> for Encryption<C, Block>
 where 
 	Block: BlockT,
-	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + BlockchainEvents<Block>,
+	C: Send + Sync + 'static,
 	C::Api: EncryptionRuntimeApi<Block, Balance>,
 	Balance: Codec + MaybeDisplay + Copy + TryInto<NumberOrHex> + Send + Sync + 'static,
 {
@@ -125,42 +1074,1124 @@ where
         message: Bytes,
 		proxy: Bytes,
 		at: Option<<Block as BlockT>::Hash>
-	) -> RpcResult<Bytes> {
+	) -> RpcResult<EncryptionResult> {
 		let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
 			self.client.info().best_hash
 		));
-		api.encrypt(&at, plaintext, signature, signer, message, proxy)
-			.map_err(|e| {
-				CallError::Custom(ErrorObject::owned(
-					Error::RuntimeError.into(),
-					"Unable to add bytes.",
-					Some(e.to_string())
-				)
-			).into()
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		ensure_plaintext_size(plaintext.len(), self.max_plaintext_size)?;
+		let seed = entropy_seed()?;
+		let params = EncryptParams { signer: signer.clone(), proxy: proxy.clone() };
+		let result = api.encrypt(&at, plaintext, signature, signer, message, proxy, seed)
+			.map_err(|e| runtime_error("Unable to add bytes.", "iris_encrypt", params, e))?;
+		result.map_err(|failure| {
+			CallError::Custom(ErrorObject::owned(
+				Error::EncryptionFailed.into(),
+				"Unable to encrypt the data.",
+				Some(failure),
+			)).into()
 		})
 	}
 
-	fn decrypt(
+	fn encrypt_for_request(
 		&self,
-		ciphertext: Bytes,
+		plaintext: Bytes,
 		signature: Bytes,
 		signer: Bytes,
 		message: Bytes,
-		asset_id: u32,
-		secret_key: Bytes,
+		proxy: Bytes,
 		at: Option<<Block as BlockT>::Hash>
-	) -> RpcResult<Option<Bytes>> {
+	) -> RpcResult<UnstagedEncryption> {
 		let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
 			self.client.info().best_hash
 		));
-		api.decrypt(&at, ciphertext, signature, signer, message, asset_id, secret_key).map_err(|e| {
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		ensure_plaintext_size(plaintext.len(), self.max_plaintext_size)?;
+		let seed = entropy_seed()?;
+		let params = EncryptParams { signer: signer.clone(), proxy: proxy.clone() };
+		let result = api.encrypt_for_request(&at, plaintext, signature, signer, message, proxy, seed)
+			.map_err(|e| runtime_error("Unable to encrypt bytes.", "iris_encryptForRequest", params, e))?;
+		result.map_err(|failure| {
 			CallError::Custom(ErrorObject::owned(
-				Error::RuntimeError.into(),
-				"Unable to retrieve bytes.",
+				Error::EncryptionFailed.into(),
+				"Unable to encrypt the data.",
+				Some(failure),
+			)).into()
+		})
+	}
+
+	fn decrypt(
+		&self,
+		ciphertext: Bytes,
+		signature: Bytes,
+		signer: Bytes,
+		message: Bytes,
+		asset_id: u32,
+		secret_key: Bytes,
+		use_fragments: Option<u32>,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Option<Bytes>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let params = || DecryptParams { signer: signer.clone(), asset_id, use_fragments };
+		let decrypt_error = |e: sp_api::ApiError| runtime_error("Unable to retrieve bytes.", "iris_decrypt", params(), e);
+		if use_fragments.is_none() {
+			if let Some(cache) = &self.decryption_cache {
+				let secret_hash = sp_core::hashing::blake2_256(&secret_key);
+				let cached = cache.lock().get(&signer, asset_id, &secret_hash, Instant::now());
+				let key = match cached {
+					Some(key) => Some(key),
+					None => {
+						let key = api.decryption_key(
+							&at, signature.clone(), signer.clone(), message.clone(),
+							asset_id, secret_key.clone(), None,
+						).map_err(decrypt_error)?.map_err(decryption_failure)?;
+						if let Some(key) = &key {
+							cache.lock().insert(
+								signer.clone(), asset_id, secret_hash, key.clone(), Instant::now(),
+							);
+						}
+						key
+					}
+				};
+				if let Some(key) = key {
+					let plaintext = api.decrypt_with_key(
+						&at, ciphertext.clone(), signature.clone(), signer.clone(),
+						message.clone(), asset_id, key,
+					).map_err(decrypt_error)?;
+					if plaintext.is_some() {
+						return Ok(plaintext);
+					}
+					// the key is stale, so recover the plaintext from the fragments instead
+					cache.lock().remove(&signer, asset_id);
+				}
+			}
+		}
+		let result = api.decrypt(
+			&at, ciphertext, signature, signer.clone(), message, asset_id, secret_key, use_fragments,
+		).map_err(decrypt_error)?;
+		result.map(Some).map_err(|failure| decryption_failure(failure).into())
+	}
+
+	fn peek(
+		&self,
+		asset_id: u32,
+		signer: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Option<AssetPreview>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		ensure_asset_exists::<_, _, Balance>(&*self.client, &at, asset_id)?;
+		api.peek(&at, asset_id, signer).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to preview asset.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn asset_exists(
+		&self,
+		asset_id: u32,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<bool> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.asset_exists(&at, asset_id).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to check whether the asset exists.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn estimate_reserve(
+		&self,
+		gateway: Bytes,
+		estimated_size: u128,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<NumberOrHex> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let reserve = api.estimate_reserve(&at, gateway, estimated_size).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to estimate reserve.",
+				Some(e.to_string())
+			))
+		})?;
+		Ok(to_rpc_balance(reserve)?)
+	}
+
+	fn ingested_assets(
+		&self,
+		gateway: Bytes,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<u32>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let page = page.unwrap_or(0);
+		let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+		api.ingested_assets(&at, gateway, page, page_size).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to list ingested assets.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn gateway_stats(
+		&self,
+		gateway: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<GatewayStats> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.gateway_stats(&at, gateway).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch gateway stats.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn recommend_gateway(
+		&self,
+		estimated_size: u128,
+		count: Option<u32>,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<Bytes>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let count = count.unwrap_or(DEFAULT_RECOMMENDATION_COUNT);
+		api.recommend_gateways(&at, estimated_size, count).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to recommend gateways.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn decryption_context(
+		&self,
+		asset_id: u32,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Option<DecryptionContext>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		ensure_asset_exists::<_, _, Balance>(&*self.client, &at, asset_id)?;
+		api.decryption_context(&at, asset_id).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch decryption context.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn pending_reserves(
+		&self,
+		gateway: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<PendingReservesInfo> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let reserves = api.pending_reserves(&at, gateway).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch pending reserves.",
+				Some(e.to_string())
+			))
+		})?;
+		let mut commands = Vec::with_capacity(reserves.commands.len());
+		for (id, balance) in reserves.commands {
+			commands.push(PendingReserve {
+				command_id: H256::from(id),
+				balance: to_rpc_balance(balance)?,
+			});
+		}
+		Ok(PendingReservesInfo {
+			total: to_rpc_balance(reserves.total)?,
+			commands,
+		})
+	}
+
+	fn pending_reencryptions(
+		&self,
+		proxy: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<PendingReencryptionInfo>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let requests = api.pending_reencryptions(&at, proxy).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch pending reencryptions.",
+				Some(e.to_string())
+			))
+		})?;
+		Ok(requests.into_iter().map(|r| PendingReencryptionInfo {
+			request_id: H256::from(r.request_id),
+			caller: r.caller.into(),
+			asset_id: r.asset_id,
+		}).collect())
+	}
+
+	fn command_assignments(
+		&self,
+		owner: Bytes,
+		cid: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<CommandAssignmentInfo>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let assignments = api.command_assignments(&at, owner, cid).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to query command assignments.",
+				Some(e.to_string())
+			))
+		})?;
+		Ok(assignments.into_iter().map(|a| CommandAssignmentInfo {
+			gateway: a.gateway.into(),
+			completed: a.completed,
+		}).collect())
+	}
+
+	fn proxy_reputation(
+		&self,
+		proxy: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<ProxyStats> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.proxy_reputation(&at, proxy).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch proxy reputation.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn current_delay(
+		&self,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<DelayInfo> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.current_delay(&at).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch the current delay.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn config_sync_interval(
+		&self,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<u32> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.current_delay(&at)
+			.map(|info| info.config_sync_interval)
+			.map_err(|e| {
+				CallError::Custom(ErrorObject::owned(
+					Error::RuntimeError.into(),
+					"Unable to fetch the config sync interval.",
+					Some(e.to_string())
+				)).into()
+			})
+	}
+
+	fn vesting_target_block(
+		&self,
+		estimated_size: u128,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<u32> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.vesting_target_block(&at, estimated_size).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to compute the vesting target block.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn decrypt_precheck(
+		&self,
+		asset_id: u32,
+		account: Bytes,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<DecryptionReadiness> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		api.decrypt_precheck(&at, asset_id, account).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to precheck decryption.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn assets_in_dataspace(
+		&self,
+		dataspace_id: u32,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<u32>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let page = page.unwrap_or(0);
+		let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+		api.assets_in_dataspace(&at, dataspace_id, page, page_size).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to list dataspace assets.",
+				Some(e.to_string())
+			)).into()
+		})
+	}
+
+	fn assets_created_between(
+		&self,
+		from_block: u32,
+		to_block: u32,
+		page: Option<u32>,
+		page_size: Option<u32>,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<u32>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let page = page.unwrap_or(0);
+		let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+		api.assets_created_between(&at, from_block, to_block, page, page_size).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to list the assets created in the block range.",
 				Some(e.to_string())
 			)).into()
 		})
 	}
-}
\ No newline at end of file
+
+	fn asset_history(
+		&self,
+		asset_id: u32,
+		at: Option<<Block as BlockT>::Hash>
+	) -> RpcResult<Vec<AssetHistoryEntryInfo>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			self.client.info().best_hash
+		));
+		ensure_supported::<_, _, Balance>(&*self.client, &at)?;
+		let history = api.asset_history(&at, asset_id).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::RuntimeError.into(),
+				"Unable to fetch the asset history.",
+				Some(e.to_string())
+			))
+		})?;
+		Ok(history.into_iter().map(|entry| AssetHistoryEntryInfo {
+			block: entry.block,
+			event: entry.event,
+			account: entry.account.map(Into::into),
+		}).collect())
+	}
+
+	fn decode_command(
+		&self,
+		command: Bytes,
+	) -> RpcResult<IngestionCommandInfo> {
+		Ok(decode_ingestion_command::<Balance>(&command)?)
+	}
+
+	fn validate_multiaddress(
+		&self,
+		multiaddress: String,
+	) -> RpcResult<MultiaddressValidity> {
+		Ok(multiaddress_validity(&multiaddress))
+	}
+
+	fn subscribe_request(
+		&self,
+		sink: SubscriptionSink,
+		request_id: H256,
+	) -> RpcResult<()> {
+		let best_hash = self.client.info().best_hash;
+		ensure_supported::<_, _, Balance>(&*self.client, &BlockId::hash(best_hash))?;
+		let request_id = request_id.to_fixed_bytes();
+		// start from the status at the current best block, then follow new best blocks
+		let initial = request_status_at::<_, Block, Balance>(&*self.client, best_hash, request_id);
+		let client = self.client.clone();
+		let updates = self.client.import_notification_stream()
+			.filter(|notification| future::ready(notification.is_new_best))
+			.filter_map(move |notification| future::ready(
+				request_status_at::<_, Block, Balance>(&*client, notification.hash, request_id)
+			));
+		let stream = status_changes(futures::stream::iter(initial).chain(updates)).boxed();
+
+		let fut = async move {
+			sink.pipe_from_stream(stream).await;
+		};
+		self.executor.spawn("iris-rpc-request-subscription", Some("rpc"), fut.boxed());
+		Ok(())
+	}
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jsonrpsee::core::Error as JsonRpseeError;
+	use iris_primitives::{
+		AssetHistoryEntry, CommandAssignment, EncryptionFailure, PendingReencryption,
+		PendingReserves,
+	};
+	use sp_core::storage::StorageKey;
+	use sp_runtime::{
+		testing::{Block as TestBlock, ExtrinsicWrapper},
+		traits::NumberFor,
+	};
+
+	type Block = TestBlock<ExtrinsicWrapper<u64>>;
+
+	/// a runtime whose EncryptionApi answers from fixed data
+	#[derive(Clone, Default)]
+	struct MockRuntime {
+		/// the assets that exist and their previews
+		assets: Vec<(u32, AssetPreview)>,
+		/// the lifecycle of every asset
+		history: Vec<AssetHistoryEntry>,
+		/// the reserves queued with every gateway
+		reserves: Option<PendingReserves<u64>>,
+	}
+
+	sp_api::mock_impl_runtime_apis! {
+		impl encryption_rpc_runtime_api::EncryptionApi<Block, u64> for MockRuntime {
+			fn encrypt(
+				_plaintext: Bytes,
+				_signature: Bytes,
+				_signer: Bytes,
+				_message: Bytes,
+				_proxy: Bytes,
+				_seed: [u8; 32],
+			) -> Result<EncryptionResult, EncryptionFailure> {
+				unimplemented!()
+			}
+
+			fn decrypt(
+				_ciphertext: Bytes,
+				_signature: Bytes,
+				_signer: Bytes,
+				_message: Bytes,
+				_asset_id: u32,
+				_secret_key: Bytes,
+				use_fragments: Option<u32>,
+			) -> Result<Bytes, DecryptionFailure> {
+				Err(DecryptionFailure::TooFewFragmentsRequested { requested: use_fragments.unwrap_or(0), need: 2 })
+			}
+
+			fn peek(&self, asset_id: u32, _signer: Bytes) -> Option<AssetPreview> {
+				self.assets.iter().find(|(id, _)| *id == asset_id).map(|(_, preview)| preview.clone())
+			}
+
+			fn estimate_reserve(_gateway: Bytes, estimated_size: u128) -> u64 {
+				u64::try_from(estimated_size).unwrap_or(u64::MAX)
+			}
+
+			fn ingested_assets(_gateway: Bytes, page: u32, page_size: u32) -> Vec<u32> {
+				vec![page, page_size]
+			}
+
+			fn gateway_stats(_gateway: Bytes) -> GatewayStats {
+				unimplemented!()
+			}
+
+			fn request_status(_request_id: [u8; 32]) -> Option<RequestStatus> {
+				unimplemented!()
+			}
+
+			fn recommend_gateways(_estimated_size: u128, _count: u32) -> Vec<Bytes> {
+				unimplemented!()
+			}
+
+			fn decryption_context(_asset_id: u32) -> Option<DecryptionContext> {
+				unimplemented!()
+			}
+
+			fn pending_reserves(&self, _gateway: Bytes) -> PendingReserves<u64> {
+				self.reserves.clone().unwrap()
+			}
+
+			fn pending_reencryptions(_proxy: Bytes) -> Vec<PendingReencryption> {
+				unimplemented!()
+			}
+
+			fn decryption_key(
+				_signature: Bytes,
+				_signer: Bytes,
+				_message: Bytes,
+				_asset_id: u32,
+				_secret_key: Bytes,
+				_use_fragments: Option<u32>,
+			) -> Result<Option<DecryptionKey>, DecryptionFailure> {
+				unimplemented!()
+			}
+
+			fn decrypt_with_key(
+				_ciphertext: Bytes,
+				_signature: Bytes,
+				_signer: Bytes,
+				_message: Bytes,
+				_asset_id: u32,
+				_key: DecryptionKey,
+			) -> Option<Bytes> {
+				unimplemented!()
+			}
+
+			fn current_delay() -> DelayInfo {
+				unimplemented!()
+			}
+
+			fn assets_in_dataspace(_dataspace_id: u32, _page: u32, _page_size: u32) -> Vec<u32> {
+				unimplemented!()
+			}
+
+			fn asset_exists(&self, asset_id: u32) -> bool {
+				self.assets.iter().any(|(id, _)| *id == asset_id)
+			}
+
+			fn command_assignments(_owner: Bytes, _cid: Bytes) -> Vec<CommandAssignment> {
+				unimplemented!()
+			}
+
+			fn proxy_reputation(_proxy: Bytes) -> ProxyStats {
+				unimplemented!()
+			}
+
+			fn encrypt_for_request(
+				_plaintext: Bytes,
+				_signature: Bytes,
+				_signer: Bytes,
+				_message: Bytes,
+				_proxy: Bytes,
+				_seed: [u8; 32],
+			) -> Result<UnstagedEncryption, EncryptionFailure> {
+				unimplemented!()
+			}
+
+			fn vesting_target_block(_estimated_size: u128) -> u32 {
+				unimplemented!()
+			}
+
+			fn decrypt_precheck(_asset_id: u32, _account: Bytes) -> DecryptionReadiness {
+				unimplemented!()
+			}
+
+			fn assets_created_between(_from_block: u32, _to_block: u32, _page: u32, _page_size: u32) -> Vec<u32> {
+				unimplemented!()
+			}
+
+			fn asset_history(&self, _asset_id: u32) -> Vec<AssetHistoryEntry> {
+				self.history.clone()
+			}
+		}
+	}
+
+	/// a client that serves the mock runtime. Tests query an explicit block,
+	/// so the client is never asked for its best block
+	struct MockClient {
+		runtime: MockRuntime,
+	}
+
+	impl ProvideRuntimeApi<Block> for MockClient {
+		type Api = MockRuntime;
+
+		fn runtime_api<'a>(&'a self) -> sp_api::ApiRef<'a, Self::Api> {
+			self.runtime.clone().into()
+		}
+	}
+
+	impl HeaderBackend<Block> for MockClient {
+		fn header(&self, _id: BlockId<Block>) -> sp_blockchain::Result<Option<<Block as BlockT>::Header>> {
+			unimplemented!()
+		}
+
+		fn info(&self) -> sp_blockchain::Info<Block> {
+			unimplemented!()
+		}
+
+		fn status(&self, _id: BlockId<Block>) -> sp_blockchain::Result<sp_blockchain::BlockStatus> {
+			unimplemented!()
+		}
+
+		fn number(&self, _hash: <Block as BlockT>::Hash) -> sp_blockchain::Result<Option<NumberFor<Block>>> {
+			unimplemented!()
+		}
+
+		fn hash(&self, _number: NumberFor<Block>) -> sp_blockchain::Result<Option<<Block as BlockT>::Hash>> {
+			unimplemented!()
+		}
+	}
+
+	impl BlockchainEvents<Block> for MockClient {
+		fn import_notification_stream(&self) -> sc_client_api::ImportNotifications<Block> {
+			unimplemented!()
+		}
+
+		fn finality_notification_stream(&self) -> sc_client_api::FinalityNotifications<Block> {
+			unimplemented!()
+		}
+
+		fn storage_changes_notification_stream(
+			&self,
+			_filter_keys: Option<&[StorageKey]>,
+			_child_filter_keys: Option<&[(StorageKey, Option<Vec<StorageKey>>)]>,
+		) -> sp_blockchain::Result<sc_client_api::StorageEventStream<<Block as BlockT>::Hash>> {
+			unimplemented!()
+		}
+	}
+
+	fn encryption(runtime: MockRuntime) -> Encryption<MockClient, Block> {
+		Encryption::new(
+			Arc::new(MockClient { runtime }),
+			Arc::new(sp_core::testing::TaskExecutor::new()),
+		)
+	}
+
+	fn preview(cid: &[u8]) -> AssetPreview {
+		AssetPreview {
+			cid: cid.to_vec(),
+			owner: vec![1u8; 32],
+			authorized: true,
+			name: None,
+			description: None,
+			providers: Vec::new(),
+			access_count: 0,
+			extra_cids: Vec::new(),
+			ciphertext_size: 64,
+		}
+	}
+
+	fn error_code(result: RpcResult<()>) -> Option<i32> {
+		match result {
+			Err(JsonRpseeError::Call(CallError::Custom(err))) => Some(err.code()),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn runtimes_must_expose_the_required_encryption_api_version() {
+		// Given: runtimes without the EncryptionApi, before, at and after the required version
+		let cases = [
+			(None, false),
+			(Some(ENCRYPTION_API_VERSION - 1), false),
+			(Some(ENCRYPTION_API_VERSION), true),
+			(Some(ENCRYPTION_API_VERSION + 1), true),
+		];
+		for (version, supported) in cases {
+			// When: I check whether the runtime supports this node's methods
+			let result = ensure_api_version(version, ENCRYPTION_API_VERSION);
+			// Then: only runtimes at or after the required version are supported
+			if supported {
+				assert!(result.is_ok(), "version {:?} should be supported", version);
+			} else {
+				assert_eq!(Some(Error::MethodUnsupported.into()), error_code(result), "version {:?}", version);
+			}
+		}
+	}
+
+	#[test]
+	fn peek_previews_known_assets_and_fails_for_unknown_ones() {
+		// Given: a runtime with a single asset
+		let rpc = encryption(MockRuntime { assets: vec![(1, preview(b"QmA"))], ..Default::default() });
+		let at = Some(H256::zero());
+		// When: I peek at it
+		let result = EncryptionApiServer::<_, u64>::peek(&rpc, 1, Bytes(vec![2u8; 32]), at);
+		// Then: its preview is returned
+		assert_eq!(Some(preview(b"QmA")), result.unwrap());
+		// When: I peek at an asset that doesn't exist
+		let result = EncryptionApiServer::<_, u64>::peek(&rpc, 2, Bytes(vec![2u8; 32]), at);
+		// Then: the asset is not found
+		assert_eq!(Some(Error::AssetNotFound.into()), error_code(result.map(|_| ())));
+	}
+
+	#[test]
+	fn encrypt_refuses_large_plaintexts_without_calling_the_runtime() {
+		// Given: a node that encrypts at most 4 bytes
+		let rpc = encryption(MockRuntime::default()).with_max_plaintext_size(4);
+		// When: I encrypt 5 bytes
+		let result = EncryptionApiServer::<_, u64>::encrypt(
+			&rpc, Bytes(vec![0u8; 5]), Bytes(vec![]), Bytes(vec![]), Bytes(vec![]), Bytes(vec![]),
+			Some(H256::zero()),
+		);
+		// Then: the plaintext is too large
+		assert_eq!(Some(Error::PlaintextTooLarge.into()), error_code(result.map(|_| ())));
+	}
+
+	#[test]
+	fn decrypt_reports_the_runtime_decryption_failure() {
+		// Given: a runtime that refuses to decrypt with fewer fragments than the threshold
+		let rpc = encryption(MockRuntime { assets: vec![(1, preview(b"QmA"))], ..Default::default() });
+		// When: I decrypt with a single fragment
+		let result = EncryptionApiServer::<_, u64>::decrypt(
+			&rpc, Bytes(vec![]), Bytes(vec![]), Bytes(vec![]), Bytes(vec![]), 1, Bytes(vec![0u8; 32]),
+			Some(1), Some(H256::zero()),
+		);
+		// Then: the client sees a TooFewFragmentsRequested error
+		assert_eq!(Some(Error::TooFewFragmentsRequested.into()), error_code(result.map(|_| ())));
+	}
+
+	#[test]
+	fn paged_queries_use_the_default_page_when_none_is_given() {
+		// Given: a runtime that echoes the page it was asked for
+		let rpc = encryption(MockRuntime::default());
+		let at = Some(H256::zero());
+		// When: I list a gateway's assets without a page
+		let result = EncryptionApiServer::<_, u64>::ingested_assets(&rpc, Bytes(vec![]), None, None, at);
+		// Then: the first page of the default size is requested
+		assert_eq!(vec![0, DEFAULT_PAGE_SIZE], result.unwrap());
+		// When: I ask for a specific page
+		let result = EncryptionApiServer::<_, u64>::ingested_assets(&rpc, Bytes(vec![]), Some(3), Some(10), at);
+		// Then: that page is requested
+		assert_eq!(vec![3, 10], result.unwrap());
+	}
+
+	#[test]
+	fn balances_are_returned_as_numbers() {
+		// Given: a gateway with two queued commands
+		let rpc = encryption(MockRuntime {
+			reserves: Some(PendingReserves { total: 5, commands: vec![([1u8; 32], 2), ([2u8; 32], 3)] }),
+			..Default::default()
+		});
+		let at = Some(H256::zero());
+		// When: I list its pending reserves
+		let reserves = EncryptionApiServer::<_, u64>::pending_reserves(&rpc, Bytes(vec![]), at).unwrap();
+		// Then: the total and each command's balance are returned
+		assert_eq!(PendingReservesInfo {
+			total: NumberOrHex::from(5u64),
+			commands: vec![
+				PendingReserve { command_id: H256::from([1u8; 32]), balance: NumberOrHex::from(2u64) },
+				PendingReserve { command_id: H256::from([2u8; 32]), balance: NumberOrHex::from(3u64) },
+			],
+		}, reserves);
+		// When: I estimate a reserve
+		let reserve = EncryptionApiServer::<_, u64>::estimate_reserve(&rpc, Bytes(vec![]), 7, at).unwrap();
+		// Then: the runtime's estimate is returned
+		assert_eq!(NumberOrHex::from(7u64), reserve);
+	}
+
+	#[test]
+	fn asset_history_is_returned_in_order() {
+		// Given: an asset that was created and then accessed
+		let history = vec![
+			AssetHistoryEntry { block: 3, event: AssetLifecycleEvent::Created, account: Some(vec![1u8]) },
+			AssetHistoryEntry { block: 5, event: AssetLifecycleEvent::Accessed, account: None },
+		];
+		let rpc = encryption(MockRuntime { history, ..Default::default() });
+		// When: I fetch its history
+		let history = EncryptionApiServer::<_, u64>::asset_history(&rpc, 1, Some(H256::zero())).unwrap();
+		// Then: each transition is returned in order
+		assert_eq!(
+			vec![(3, AssetLifecycleEvent::Created, Some(Bytes(vec![1u8]))), (5, AssetLifecycleEvent::Accessed, None)],
+			history.into_iter().map(|e| (e.block, e.event, e.account)).collect::<Vec<_>>(),
+		);
+	}
+
+	#[test]
+	fn encrypt_refuses_plaintexts_larger_than_the_maximum() {
+		// Given: a node that encrypts at most 1024 bytes
+		let max = Some(1024);
+		// When: I encrypt exactly 1024 bytes
+		// Then: the plaintext is accepted
+		assert!(ensure_plaintext_size(1024, max).is_ok());
+		// When: I encrypt one more byte
+		let result = ensure_plaintext_size(1025, max);
+		// Then: the plaintext is too large
+		assert_eq!(Some(Error::PlaintextTooLarge.into()), error_code(result));
+		// And: nodes without a maximum accept it
+		assert!(ensure_plaintext_size(1025, None).is_ok());
+	}
+
+	#[test]
+	fn decrypt_reports_invalid_secret_keys() {
+		// Given: the runtime rejected a secret key that isn't 32 bytes
+		let failure = DecryptionFailure::InvalidSecretKey;
+		// When: the failure is returned to the client
+		let result: RpcResult<()> = Err(decryption_failure(failure).into());
+		// Then: the client sees an InvalidSecretKey error
+		assert_eq!(Some(Error::InvalidSecretKey.into()), error_code(result));
+	}
+
+	#[test]
+	fn decrypt_reports_too_few_requested_fragments() {
+		// Given: the runtime refused to decrypt with fewer fragments than the threshold
+		let failure = DecryptionFailure::TooFewFragmentsRequested { requested: 1, need: 2 };
+		// When: the failure is returned to the client
+		let result: RpcResult<()> = Err(decryption_failure(failure).into());
+		// Then: the client sees a TooFewFragmentsRequested error
+		assert_eq!(Some(Error::TooFewFragmentsRequested.into()), error_code(result));
+	}
+
+	#[test]
+	fn unknown_assets_are_not_found() {
+		// Given: an asset that exists and one that doesn't
+		// When: I check them before a heavier query
+		// Then: only the unknown asset fails, with a not found error
+		assert!(check_asset_exists(true, 1).is_ok());
+		assert_eq!(Some(Error::AssetNotFound.into()), error_code(check_asset_exists(false, 2)));
+	}
+
+	#[test]
+	fn ingestion_commands_decode_into_readable_fields() {
+		// Given: a scale-encoded ingestion command with a binary multiaddress
+		let owner = AccountId32::new([7u8; 32]);
+		let cmd = IngestionCommand::<AccountId32, u128> {
+			id: [1u8; 32],
+			owner: owner.clone(),
+			cid: b"QmTest".to_vec(),
+			multiaddress: vec![0xff, 0x00],
+			balance: 10,
+			queued_at: 3,
+		};
+		// When: I decode it
+		let info = decode_ingestion_command::<u128>(&cmd.encode()).unwrap();
+		// Then: the fields are readable, with invalid UTF-8 shown as hex
+		assert_eq!(H256::from([1u8; 32]), info.id);
+		assert_eq!(owner.to_string(), info.owner);
+		assert_eq!("QmTest", info.cid);
+		assert_eq!("0xff00", info.multiaddress);
+		assert_eq!(NumberOrHex::from(10u128), info.balance);
+		assert_eq!(3, info.queued_at);
+		// When: I decode a truncated command, or one with trailing bytes
+		let encoded = cmd.encode();
+		for malformed in [&encoded[..10], &[&encoded[..], &[0u8][..]].concat()[..]] {
+			let result = decode_ingestion_command::<u128>(malformed).map(|_| ()).map_err(Into::into);
+			// Then: a decode error is returned
+			assert_eq!(Some(Error::DecodeError.into()), error_code(result));
+		}
+	}
+
+	#[test]
+	fn multiaddresses_are_validated_into_their_components() {
+		// Given: a multiaddress addressing a peer over tcp
+		// When: I validate it
+		let validity = multiaddress_validity("/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp");
+		// Then: it is valid and its protocols and peer id are listed
+		assert!(validity.valid);
+		assert_eq!(None, validity.error);
+		assert_eq!(
+			vec!["ip4", "tcp", "p2p"],
+			validity.components.iter().map(|c| c.protocol.as_str()).collect::<Vec<_>>(),
+		);
+		assert_eq!(Some("4001".to_string()), validity.components[1].value);
+		assert_eq!(Some("12D3KooWMvyvKxYcy9mjbFbXcogFSCvENzQ62ogRxHKZaksFCkAp".to_string()), validity.peer_id);
+		// When: I validate a multiaddress with a misspelled protocol
+		let validity = multiaddress_validity("/ip4/127.0.0.1/tpc/4001");
+		// Then: it is invalid and the error names the protocol
+		assert!(!validity.valid);
+		assert_eq!(Some("Unknown protocol 'tpc'.".to_string()), validity.error);
+		assert!(validity.components.is_empty());
+	}
+
+	#[test]
+	fn runtime_errors_identify_the_failed_call() {
+		// Given: a decrypt call whose runtime api call failed
+		let params = DecryptParams { signer: Bytes(vec![1, 2]), asset_id: 3, use_fragments: None };
+		// When: I build its error
+		let error = runtime_error("Unable to retrieve bytes.", "iris_decrypt", params, "Codec error");
+		// Then: the code and message are unchanged and the data identifies the call
+		match error {
+			CallError::Custom(err) => {
+				assert_eq!(i32::from(Error::RuntimeError), err.code());
+				assert_eq!("Unable to retrieve bytes.", err.message());
+				assert_eq!(
+					Some(r#"{"method":"iris_decrypt","params":{"signer":"0x0102","asset_id":3,"use_fragments":null},"error":"Codec error"}"#),
+					err.data().map(|data| data.get()),
+				);
+			},
+			_ => panic!("expected a custom error"),
+		}
+	}
+
+	#[test]
+	fn request_status_changes_skip_repeats_and_end_once_complete() {
+		// Given: the status of a request in each of several blocks
+		let statuses = vec![
+			RequestStatus::Queued,
+			RequestStatus::Queued,
+			RequestStatus::Ingesting { pinned: 1, replication: 2 },
+			RequestStatus::Ingesting { pinned: 1, replication: 2 },
+			RequestStatus::Complete { asset_id: 1 },
+			RequestStatus::Complete { asset_id: 1 },
+		];
+		// When: I reduce them to status changes
+		let changes: Vec<RequestStatus> = futures::executor::block_on(
+			status_changes(futures::stream::iter(statuses)).collect()
+		);
+		// Then: each change is sent once, and nothing after completion
+		assert_eq!(vec![
+			RequestStatus::Queued,
+			RequestStatus::Ingesting { pinned: 1, replication: 2 },
+			RequestStatus::Complete { asset_id: 1 },
+		], changes);
+	}
+
+	#[test]
+	fn pause_ingestion_sets_the_offchain_flag() {
+		// Given: a node that allows unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::No,
+		);
+		// When: I pause ingestion
+		assert!(control.pause_ingestion(true).is_ok());
+		// Then: the flag is set in persistent offchain storage
+		let flag = control.storage.read().get(sp_offchain::STORAGE_PREFIX, INGESTION_PAUSED_KEY);
+		assert_eq!(Some(true.encode()), flag);
+		// When: I resume ingestion
+		assert!(control.pause_ingestion(false).is_ok());
+		// Then: the flag is cleared
+		let flag = control.storage.read().get(sp_offchain::STORAGE_PREFIX, INGESTION_PAUSED_KEY);
+		assert_eq!(Some(false.encode()), flag);
+	}
+
+	#[test]
+	fn pause_ingestion_is_unsafe() {
+		// Given: a node that denies unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::Yes,
+		);
+		// When: I pause ingestion
+		// Then: the call is denied
+		assert_eq!(Some(Error::UnsafeCall.into()), error_code(control.pause_ingestion(true)));
+	}
+
+	#[test]
+	fn process_queue_now_sets_the_offchain_flag() {
+		// Given: a node that allows unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::No,
+		);
+		// When: I ask for the ingestion queue to be processed now
+		assert!(control.process_queue_now().is_ok());
+		// Then: the flag is set in persistent offchain storage
+		let flag = control.storage.read().get(sp_offchain::STORAGE_PREFIX, PROCESS_QUEUE_NOW_KEY);
+		assert_eq!(Some(true.encode()), flag);
+	}
+
+	#[test]
+	fn process_queue_now_is_unsafe() {
+		// Given: a node that denies unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::Yes,
+		);
+		// When: I ask for the ingestion queue to be processed now
+		// Then: the call is denied
+		assert_eq!(Some(Error::UnsafeCall.into()), error_code(control.process_queue_now()));
+	}
+
+	#[test]
+	fn local_pins_lists_the_recorded_cids() {
+		// Given: a node that allows unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::No,
+		);
+		// When: I list local pins before the offchain worker has recorded any
+		// Then: the daemon is reported as unavailable
+		let result = control.local_pins().map(|_| ());
+		assert_eq!(Some(Error::IpfsNotAvailable.into()), error_code(result));
+		// Given: the offchain worker recorded the daemon's pins
+		let pins = Some(vec![b"QmA".to_vec(), b"QmB".to_vec()]);
+		control.storage.write().set(sp_offchain::STORAGE_PREFIX, LOCAL_PINS_KEY, &pins.encode());
+		// When: I list local pins
+		// Then: the recorded cids are returned
+		assert_eq!(
+			vec![Bytes::from(b"QmA".to_vec()), Bytes::from(b"QmB".to_vec())],
+			control.local_pins().unwrap(),
+		);
+		// Given: the daemon could not be reached on the offchain worker's last run
+		control.storage.write().set(sp_offchain::STORAGE_PREFIX, LOCAL_PINS_KEY, &None::<Vec<Vec<u8>>>.encode());
+		// When: I list local pins
+		// Then: the daemon is reported as unavailable
+		let result = control.local_pins().map(|_| ());
+		assert_eq!(Some(Error::IpfsNotAvailable.into()), error_code(result));
+	}
+
+	#[test]
+	fn local_pins_is_unsafe() {
+		// Given: a node that denies unsafe rpc calls
+		let control = IngestionControl::new(
+			sp_core::offchain::storage::InMemOffchainStorage::default(), DenyUnsafe::Yes,
+		);
+		// When: I list local pins
+		// Then: the call is denied
+		assert_eq!(Some(Error::UnsafeCall.into()), error_code(control.local_pins().map(|_| ())));
+	}
+}
@@ -43,7 +43,7 @@ use sp_std::prelude::*;
 use sp_version::NativeVersion;
 use sp_version::RuntimeVersion;
 use pallet_contracts::{weights::WeightInfo, DefaultContractAccessWeight};
-use codec::Encode;
+use codec::{Decode, Encode};
 use sp_runtime::traits::StaticLookup;
 
 // A few exports that help ease life for downstream crates.
@@ -72,7 +72,7 @@ pub use pallet_assets::Call as AssetsCall;
 use pallet_transaction_payment::CurrencyAdapter;
 #[cfg(any(feature = "std", test))]
 pub use sp_runtime::BuildStorage;
-pub use sp_runtime::{Perbill, Permill};
+pub use sp_runtime::{Perbill, Percent, Permill};
 
 use pallet_im_online::sr25519::AuthorityId as ImOnlineId;
 use pallet_authorities::EraIndex;
@@ -454,12 +454,44 @@ impl pallet_assets::Config for Runtime {
 	type WeightInfo = pallet_assets::weights::SubstrateWeight<Runtime>;
 }
 
+parameter_types! {
+	pub const MaxPendingRequests: u32 = 10;
+	pub const MaxPinReportSize: u32 = 500;
+	pub const MaxIngestionAttempts: u32 = 5;
+	pub const IngestionBackoff: u32 = 10;
+	pub const MaxAssetNameLength: u32 = 64;
+	pub const MaxAssetDescriptionLength: u32 = 512;
+	pub const FreezeUnavailableAssets: bool = true;
+	pub const AllowSelfGateway: bool = false;
+	pub const SetAssetMetadata: bool = true;
+	pub const MaxDatasetFiles: u32 = 64;
+	// roughly 10 minutes
+	pub const ClaimDuration: u32 = 100;
+	pub const MaxAssetHistory: u32 = 64;
+}
+
 /// configure the iris assets pallet
 impl pallet_data_assets::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
 	type Currency = Balances;
 	type AuthorityId = pallet_authorities::crypto::TestAuthId;
+	type MaxPendingRequests = MaxPendingRequests;
+	type MaxPinReportSize = MaxPinReportSize;
+	type MaxIngestionAttempts = MaxIngestionAttempts;
+	type IngestionBackoff = IngestionBackoff;
+	type MaxAssetNameLength = MaxAssetNameLength;
+	type MaxAssetDescriptionLength = MaxAssetDescriptionLength;
+	type FreezeUnavailableAssets = FreezeUnavailableAssets;
+	type AllowSelfGateway = AllowSelfGateway;
+	type SetAssetMetadata = SetAssetMetadata;
+	type MaxDatasetFiles = MaxDatasetFiles;
+	type ClaimDuration = ClaimDuration;
+	type MaxAssetHistory = MaxAssetHistory;
+	type GatewayPricing = Gateway;
+	type GatewayProvider = Gateway;
+	type ProxyPrefs = Gateway;
+	type PeerIdProvider = Ipfs;
 }
 
 // parameter_types! {
@@ -467,6 +499,14 @@ impl pallet_data_assets::Config for Runtime {
 // 	pub const Rng: ChaCha20Rng = ChaCha20Rng::seed_from_u64(17u64);
 // }
 
+parameter_types! {
+	pub const FragmentTTL: BlockNumber = 7 * DAYS;
+	pub const MaxExpiredFragmentsPerBlock: u32 = 50;
+	// one fragment per key fragment share
+	pub const MaxFragmentsPerRequest: u32 = pallet_iris_proxy::KFRAG_SHARES as u32;
+	pub const MaxFragmentBatch: u32 = 32;
+}
+
 impl pallet_iris_proxy::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
@@ -474,6 +514,12 @@ impl pallet_iris_proxy::Config for Runtime {
 	type QueueManager = DataAssets;
 	type MetadataProvider = DataAssets;
 	type Randomness = RandomnessCollectiveFlip;
+	type FragmentTTL = FragmentTTL;
+	type MaxExpiredFragmentsPerBlock = MaxExpiredFragmentsPerBlock;
+	type MaxFragmentsPerRequest = MaxFragmentsPerRequest;
+	type MaxFragmentBatch = MaxFragmentBatch;
+	type AssetHistory = DataAssets;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
 }
 
 impl pallet_authorization::Config for Runtime {
@@ -518,6 +564,9 @@ impl pallet_im_online::Config for Runtime {
 
 parameter_types! {
 	pub const BondingDuration: EraIndex = 3;
+	// gateways price ingestion per gb, prorated to the byte
+	pub const SizeUnit: u128 = pallet_gateway::BYTES_PER_GB;
+	pub const MinReservePerUnit: Balance = CENTS;
 }
 
 impl pallet_gateway::Config for Runtime {
@@ -527,11 +576,24 @@ impl pallet_gateway::Config for Runtime {
 	type Balance = <Self as pallet_balances::Config>::Balance ;
 	type BondingDuration = BondingDuration;
 	type EraProvider = Authorities;
+	type SizeUnit = SizeUnit;
+	type MinReservePerUnit = MinReservePerUnit;
+	type StorageReporter = Ipfs;
 }
 
 parameter_types! {
 	// roughly 35 seconds
 	pub const NodeConfigBlockDuration: u32 = 2;
+	pub const MaxSubmissionAttempts: u32 = 3;
+	pub const MaxSignedRetries: u32 = 2;
+	pub const MaxAssetIdRetries: u32 = 3;
+	pub const UnsignedIpfsSubmissions: bool = false;
+	pub const MaxBridgeSweepPerBlock: u32 = 50;
+	pub const MaxIdentitiesPerCall: u32 = 8;
+	pub const ConfigDriftTolerance: Percent = Percent::from_percent(10);
+	pub const ConfigDriftThreshold: u32 = 5;
+	pub const DeregistrationPolicy: pallet_data_assets::OrphanedCommandPolicy =
+		pallet_data_assets::OrphanedCommandPolicy::Reroute;
 }
 
 impl pallet_ipfs::Config for Runtime {
@@ -545,6 +607,19 @@ impl pallet_ipfs::Config for Runtime {
 	type MetadataProvider = DataAssets;
 	type ResultsHandler = DataAssets;
 	type OffchainKeyManager = IrisProxy;
+	type CompletionOrigin = frame_system::EnsureSigned<AccountId>;
+	type MaxSubmissionAttempts = MaxSubmissionAttempts;
+	type MaxSignedRetries = MaxSignedRetries;
+	type MaxAssetIdRetries = MaxAssetIdRetries;
+	type UnsignedSubmissions = UnsignedIpfsSubmissions;
+	type MaxBridgeSweepPerBlock = MaxBridgeSweepPerBlock;
+	type MaxIdentitiesPerCall = MaxIdentitiesPerCall;
+	type AssignmentStrategy = iris_primitives::HashAssignment;
+	type DeregistrationPolicy = DeregistrationPolicy;
+	type ContentValidator = ();
+	type ConfigDriftTolerance = ConfigDriftTolerance;
+	type ConfigDriftThreshold = ConfigDriftThreshold;
+	type ReportConfigDrift = Authorities;
 }
 
 parameter_types! {
@@ -878,13 +953,15 @@ impl_runtime_apis! {
 			signer: Bytes,
 			message: Bytes,
 			proxy: Bytes,
-		) -> Bytes {
+			seed: [u8; 32],
+		) -> Result<iris_primitives::EncryptionResult, iris_primitives::EncryptionFailure> {
 			IrisProxy::encrypt(
 				plaintext, 
 				signature, 
 				signer, 
 				message,
 				proxy,
+				seed,
 			)
 		}
 
@@ -895,7 +972,8 @@ impl_runtime_apis! {
 			message: Bytes,
 			asset_id: u32,
 			secret_key: Bytes,
-		) -> Option<Bytes> {
+			use_fragments: Option<u32>,
+		) -> Result<Bytes, iris_primitives::DecryptionFailure> {
 			IrisProxy::decrypt(
 				signature,
 				signer,
@@ -903,8 +981,218 @@ impl_runtime_apis! {
 				ciphertext,
 				asset_id,
 				secret_key,
+				use_fragments,
 			)
 		}
+
+		fn peek(
+			asset_id: u32,
+			signer: Bytes,
+		) -> Option<iris_primitives::AssetPreview> {
+			let acct_id = AccountId::decode(&mut &signer.to_vec()[..]).ok()?;
+			Authorization::peek(asset_id, acct_id)
+		}
+
+		fn estimate_reserve(
+			gateway: Bytes,
+			estimated_size: u128,
+		) -> Balance {
+			let gateway_id = AccountId::decode(&mut &gateway.to_vec()[..]).ok();
+			Gateway::estimate_reserve(gateway_id, estimated_size)
+		}
+
+		fn ingested_assets(
+			gateway: Bytes,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32> {
+			match AccountId::decode(&mut &gateway.to_vec()[..]) {
+				Ok(gateway_id) => DataAssets::ingested_assets(gateway_id, page, page_size),
+				Err(_) => Vec::new(),
+			}
+		}
+
+		fn gateway_stats(
+			gateway: Bytes,
+		) -> iris_primitives::GatewayStats {
+			match AccountId::decode(&mut &gateway.to_vec()[..]) {
+				Ok(gateway_id) => DataAssets::gateway_stats(gateway_id),
+				Err(_) => Default::default(),
+			}
+		}
+
+		fn request_status(
+			request_id: [u8; 32],
+		) -> Option<iris_primitives::RequestStatus> {
+			DataAssets::replication(request_id).map(|status| {
+				let pinned = status.pinned_by.len() as u32;
+				match status.asset_id {
+					Some(asset_id) if pinned >= status.replication =>
+						iris_primitives::RequestStatus::Complete { asset_id },
+					_ if pinned == 0 => iris_primitives::RequestStatus::Queued,
+					_ => iris_primitives::RequestStatus::Ingesting {
+						pinned,
+						replication: status.replication,
+					},
+				}
+			})
+		}
+
+		fn recommend_gateways(
+			estimated_size: u128,
+			count: u32,
+		) -> Vec<Bytes> {
+			Ipfs::recommend_gateways(estimated_size, count, |gateway| {
+				DataAssets::gateway_stats(gateway)
+			})
+			.into_iter()
+			.map(|gateway| gateway.encode().into())
+			.collect()
+		}
+
+		fn decryption_context(
+			asset_id: u32,
+		) -> Option<iris_primitives::DecryptionContext> {
+			IrisProxy::decryption_context(asset_id)
+		}
+
+		fn pending_reserves(
+			gateway: Bytes,
+		) -> iris_primitives::PendingReserves<Balance> {
+			match AccountId::decode(&mut &gateway.to_vec()[..]) {
+				Ok(gateway_id) => DataAssets::pending_reserves(gateway_id),
+				Err(_) => Default::default(),
+			}
+		}
+
+		fn pending_reencryptions(
+			proxy: Bytes,
+		) -> Vec<iris_primitives::PendingReencryption> {
+			match AccountId::decode(&mut &proxy.to_vec()[..]) {
+				Ok(proxy_id) => IrisProxy::pending_reencryptions(proxy_id)
+					.into_iter()
+					.map(|(request_id, caller, asset_id)| iris_primitives::PendingReencryption {
+						request_id,
+						caller: caller.encode(),
+						asset_id,
+					})
+					.collect(),
+				Err(_) => Vec::new(),
+			}
+		}
+
+		fn decryption_key(
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			asset_id: u32,
+			secret_key: Bytes,
+			use_fragments: Option<u32>,
+		) -> Result<Option<iris_primitives::DecryptionKey>, iris_primitives::DecryptionFailure> {
+			IrisProxy::decryption_key(
+				signature,
+				signer,
+				message,
+				asset_id,
+				secret_key,
+				use_fragments,
+			)
+		}
+
+		fn decrypt_with_key(
+			ciphertext: Bytes,
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			asset_id: u32,
+			key: iris_primitives::DecryptionKey,
+		) -> Option<Bytes> {
+			IrisProxy::decrypt_with_key(ciphertext, signature, signer, message, asset_id, key)
+		}
+
+		fn current_delay() -> iris_primitives::DelayInfo {
+			iris_primitives::DelayInfo {
+				delay: DataAssets::delay(),
+				config_sync_interval: Ipfs::config_sync_interval(),
+			}
+		}
+
+		fn assets_in_dataspace(
+			dataspace_id: u32,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32> {
+			DataSpaces::assets_in_dataspace(dataspace_id, page, page_size)
+		}
+
+		fn asset_exists(asset_id: u32) -> bool {
+			DataAssets::asset_exists(asset_id)
+		}
+
+		fn command_assignments(
+			owner: Bytes,
+			cid: Bytes,
+		) -> Vec<iris_primitives::CommandAssignment> {
+			match AccountId::decode(&mut &owner.to_vec()[..]) {
+				Ok(owner_id) => DataAssets::command_assignments(owner_id, cid.to_vec())
+					.into_iter()
+					.map(|(gateway, completed)| iris_primitives::CommandAssignment {
+						gateway: gateway.encode(),
+						completed,
+					})
+					.collect(),
+				Err(_) => Vec::new(),
+			}
+		}
+
+		fn proxy_reputation(proxy: Bytes) -> iris_primitives::ProxyStats {
+			match AccountId::decode(&mut &proxy.to_vec()[..]) {
+				Ok(proxy_id) => IrisProxy::proxy_reputation(proxy_id),
+				Err(_) => Default::default(),
+			}
+		}
+
+		fn encrypt_for_request(
+			plaintext: Bytes,
+			signature: Bytes,
+			signer: Bytes,
+			message: Bytes,
+			proxy: Bytes,
+			seed: [u8; 32],
+		) -> Result<iris_primitives::UnstagedEncryption, iris_primitives::EncryptionFailure> {
+			IrisProxy::encrypt_for_request(plaintext, signature, signer, message, proxy, seed)
+		}
+
+		fn vesting_target_block(estimated_size: u128) -> u32 {
+			DataAssets::vesting_target_block(estimated_size)
+		}
+
+		fn decrypt_precheck(asset_id: u32, account: Bytes) -> iris_primitives::DecryptionReadiness {
+			match AccountId::decode(&mut &account.to_vec()[..]) {
+				Ok(account_id) => IrisProxy::decrypt_precheck(asset_id, account_id),
+				Err(_) => Default::default(),
+			}
+		}
+
+		fn assets_created_between(
+			from_block: u32,
+			to_block: u32,
+			page: u32,
+			page_size: u32,
+		) -> Vec<u32> {
+			DataAssets::assets_created_between(from_block, to_block, page, page_size)
+		}
+
+		fn asset_history(asset_id: u32) -> Vec<iris_primitives::AssetHistoryEntry> {
+			DataAssets::asset_history(asset_id)
+				.into_iter()
+				.map(|(block, event, account)| iris_primitives::AssetHistoryEntry {
+					block,
+					event,
+					account: account.map(|a| a.encode()),
+				})
+				.collect()
+		}
 	}
 
 	#[cfg(feature = "runtime-benchmarks")]
@@ -1073,12 +1361,12 @@ impl ChainExtension<Runtime> for IrisExtension {
 			// IrisEjection: submit execution results from a rule executor
 			4 => {
 				let mut env = env.buf_in_buf_out();
-				let (caller_account, target_account, asset_id, result, public_key): 
-					(AccountId, AccountId, u32, bool, [u8;32]) = env.read_as()?;
+				let (caller_account, target_account, asset_id, result, public_key, box_key_proof): 
+					(AccountId, AccountId, u32, bool, [u8;32], [u8;64]) = env.read_as()?;
 				let origin: Origin = system::RawOrigin::Signed(caller_account).into();
 
 				crate::Authorization::submit_execution_results(
-					origin, asset_id, target_account, result, public_key.to_vec()
+					origin, asset_id, target_account, result, public_key.to_vec(), box_key_proof.to_vec(),
 				)?;
 				Ok(RetVal::Converging(func_id))
 			},